@@ -90,10 +90,10 @@ DEPS_term := std log
 DEPS_getopts := std
 DEPS_collections := core alloc unicode
 DEPS_num := std
-DEPS_test := std getopts serialize rbml term time regex native:rust_test_helpers
+DEPS_test := std getopts serialize rbml term regex native:rust_test_helpers
 DEPS_time := std serialize
 DEPS_rand := core
-DEPS_log := std regex
+DEPS_log := std regex libc
 DEPS_regex := std
 DEPS_regex_macros = rustc syntax std regex
 DEPS_fmt_macros = std
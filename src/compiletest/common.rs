@@ -110,6 +110,9 @@ pub struct Config {
     // Write out a parseable log of tests that were run
     pub logfile: Option<Path>,
 
+    // Write out a JUnit XML report of the tests that were run
+    pub junit: Option<Path>,
+
     // Write out a json file containing any metrics of the run
     pub save_metrics: Option<Path>,
 
@@ -72,6 +72,7 @@ pub fn parse_config(args: Vec<String> ) -> Config {
           optopt("", "target-rustcflags", "flags to pass to rustc for target", "FLAGS"),
           optflag("", "verbose", "run tests verbosely, showing all output"),
           optopt("", "logfile", "file to log test execution to", "FILE"),
+          optopt("", "junit", "file to write a JUnit XML report to", "FILE"),
           optopt("", "save-metrics", "file to save metrics to", "FILE"),
           optopt("", "ratchet-metrics", "file to ratchet metrics against", "FILE"),
           optopt("", "ratchet-noise-percent",
@@ -147,6 +148,7 @@ pub fn parse_config(args: Vec<String> ) -> Config {
         filter: filter,
         cfail_regex: Regex::new(errors::EXPECTED_PATTERN).unwrap(),
         logfile: matches.opt_str("logfile").map(|s| Path::new(s)),
+        junit: matches.opt_str("junit").map(|s| Path::new(s)),
         save_metrics: matches.opt_str("save-metrics").map(|s| Path::new(s)),
         ratchet_metrics:
             matches.opt_str("ratchet-metrics").map(|s| Path::new(s)),
@@ -279,14 +281,20 @@ pub fn test_opts(config: &Config) -> test::TestOpts {
         },
         run_ignored: config.run_ignored,
         logfile: config.logfile.clone(),
+        junit: config.junit.clone(),
+        timeout: None,
+        slow_threshold: None,
         run_tests: true,
         run_benchmarks: true,
         ratchet_metrics: config.ratchet_metrics.clone(),
         ratchet_noise_percent: config.ratchet_noise_percent.clone(),
         save_metrics: config.save_metrics.clone(),
+        baseline: None,
+        compare: None,
         test_shard: config.test_shard.clone(),
         nocapture: false,
         color: test::AutoColor,
+        format: test::HumanFormat,
         show_boxplot: false,
         boxplot_width: 50,
         show_all_stats: false,
@@ -348,6 +356,7 @@ pub fn make_test(config: &Config, testfile: &Path, f: || -> test::TestFn)
             name: make_test_name(config, testfile),
             ignore: header::is_test_ignored(config, testfile),
             should_fail: test::ShouldFail::No,
+            timeout: None,
         },
         testfn: f(),
     }
@@ -76,6 +76,53 @@
 //!     do_work(input.as_slice(), output);
 //! }
 //! ```
+//!
+//! # Subcommands
+//!
+//! Programs like `git` or `cargo` take a subcommand, with options of its own,
+//! after their own options. Describe them as a tree of `Command`s and parse
+//! the arguments with `getopts_command`; the matches of the subcommand given
+//! are in the `subcommand` field of the matches of its parent.
+//!
+//! ```{.rust}
+//! extern crate getopts;
+//! use getopts::{command, getopts_command, optflag, optopt};
+//!
+//! fn main() {
+//!     let cmd = command("vcs", "A version control system",
+//!                       &[optflag("v", "verbose", "print more")])
+//!         .subcommand(command("commit", "Record changes",
+//!                             &[optopt("m", "message", "the commit message", "MSG")]));
+//!
+//!     let args = vec!["-v".to_string(), "commit".to_string(),
+//!                     "-m".to_string(), "fix".to_string()];
+//!     let matches = getopts_command(args.as_slice(), &cmd).unwrap();
+//!     assert!(matches.opt_present("v"));
+//!     let (ref name, ref commit) = *matches.subcommand.as_ref().unwrap();
+//!     assert_eq!(name.as_slice(), "commit");
+//!     assert_eq!(commit.opt_str("m").unwrap().as_slice(), "fix");
+//! }
+//! ```
+//!
+//! `bash_completion` and `zsh_completion` generate shell completion scripts
+//! for a command and its subcommands.
+//!
+//! # Environment variables and typed values
+//!
+//! An option can take its value from an environment variable when it isn't
+//! given on the command line, with `OptGroup::env`, and `Matches::opt_get`
+//! parses the value of an option with `FromStr`:
+//!
+//! ```{.rust}
+//! extern crate getopts;
+//! use getopts::{getopts, optopt};
+//!
+//! fn main() {
+//!     let opts = &[optopt("j", "jobs", "number of jobs to run", "N").env("JOBS")];
+//!     let matches = getopts(&["-j".to_string(), "4".to_string()], opts).unwrap();
+//!     assert_eq!(matches.opt_get_default("j", 1u), Ok(4u));
+//! }
+//! ```
 
 #![crate_name = "getopts"]
 #![experimental = "use the crates.io `getopts` library instead"]
@@ -101,8 +148,10 @@ use self::Whitespace::*;
 use self::LengthLimit::*;
 
 use std::fmt;
+use std::os;
 use std::result::Result::{Err, Ok};
 use std::result;
+use std::str::FromStr;
 use std::string::String;
 
 /// Name of an option. Either a string or a single char.
@@ -166,7 +215,25 @@ pub struct OptGroup {
     /// Whether option has an argument
     pub hasarg: HasArg,
     /// How often it can occur
-    pub occur: Occur
+    pub occur: Occur,
+    // Environment variable the value of the option is taken from when the
+    // option isn't given on the command line, set with `env`
+    env: Option<String>,
+}
+
+/// A subcommand, e.g. `commit` in `git commit`, which has its own options,
+/// usage text and subcommands. A program is itself the command at the root
+/// of the tree of its subcommands.
+#[deriving(Clone, PartialEq, Eq)]
+pub struct Command {
+    /// Name of the command, as given on the command line
+    pub name: String,
+    /// Description for usage help text
+    pub desc: String,
+    /// Options of the command, which are given after its name
+    pub opts: Vec<OptGroup>,
+    /// Subcommands of the command
+    pub subcommands: Vec<Command>,
 }
 
 /// Describes whether an option is given at all or has a value.
@@ -186,6 +253,9 @@ pub struct Matches {
     vals: Vec<Vec<Optval>>,
     /// Free string fragments
     pub free: Vec<String>,
+    /// The subcommand given and the matches of its arguments, when parsing
+    /// with `getopts_command` a command that has subcommands
+    pub subcommand: Option<(String, Box<Matches>)>,
 }
 
 /// The type returned when the command line does not conform to the
@@ -203,6 +273,11 @@ pub enum Fail {
     OptionDuplicated(String),
     /// There's an argument being passed to a non-argument option.
     UnexpectedArgument(String),
+    /// The argument of an option can't be parsed as a value of the type
+    /// asked for: the name of the option, and the argument.
+    InvalidValue(String, String),
+    /// The subcommand given is not among the subcommands of its command.
+    UnrecognizedCommand(String),
 }
 
 /// The type of failure that occurred.
@@ -214,6 +289,8 @@ pub enum FailType {
     OptionMissing_,
     OptionDuplicated_,
     UnexpectedArgument_,
+    InvalidValue_,
+    UnrecognizedCommand_,
 }
 
 /// The result of parsing a command line with a set of options.
@@ -237,6 +314,14 @@ impl Name {
 }
 
 impl OptGroup {
+    /// Take the value of the option from the environment variable `var`
+    /// when the option isn't given on the command line. A flag is given when
+    /// the variable is set to anything but an empty string or `0`.
+    pub fn env(mut self, var: &str) -> OptGroup {
+        self.env = Some(var.to_string());
+        self
+    }
+
     /// Translate OptGroup into Opt.
     /// (Both short and long names correspond to different Opts).
     pub fn long_to_short(&self) -> Opt {
@@ -359,6 +444,41 @@ impl Matches {
     }
 
 
+    /// Returns the argument supplied to a matching option parsed as a `T`,
+    /// or `None` if the option is not present.
+    ///
+    /// Fails with `InvalidValue` if the argument can't be parsed.
+    pub fn opt_get<T: FromStr>(&self, nm: &str) -> result::Result<Option<T>, Fail> {
+        match self.opt_str(nm) {
+            None => Ok(None),
+            Some(s) => match FromStr::from_str(s.as_slice()) {
+                Some(t) => Ok(Some(t)),
+                None => Err(InvalidValue(nm.to_string(), s)),
+            }
+        }
+    }
+
+    /// Returns the argument supplied to a matching option parsed as a `T`,
+    /// or `def` if the option is not present.
+    ///
+    /// Fails with `InvalidValue` if the argument can't be parsed.
+    pub fn opt_get_default<T: FromStr>(&self, nm: &str, def: T) -> result::Result<T, Fail> {
+        self.opt_get(nm).map(|t| t.unwrap_or(def))
+    }
+
+    /// Returns the arguments supplied to all matches of the given option,
+    /// parsed as `T`s.
+    ///
+    /// Fails with `InvalidValue` if any of the arguments can't be parsed.
+    pub fn opt_gets<T: FromStr>(&self, nm: &str) -> result::Result<Vec<T>, Fail> {
+        self.opt_strs(nm).into_iter().map(|s| {
+            match FromStr::from_str(s.as_slice()) {
+                Some(t) => Ok(t),
+                None => Err(InvalidValue(nm.to_string(), s)),
+            }
+        }).collect()
+    }
+
     /// Returns the matching string, a default, or none.
     ///
     /// Returns none if the option was not present, `def` if the option was
@@ -415,7 +535,8 @@ pub fn reqopt(short_name: &str, long_name: &str, desc: &str, hint: &str) -> OptG
         hint: hint.to_string(),
         desc: desc.to_string(),
         hasarg: Yes,
-        occur: Req
+        occur: Req,
+        env: None,
     }
 }
 
@@ -435,7 +556,8 @@ pub fn optopt(short_name: &str, long_name: &str, desc: &str, hint: &str) -> OptG
         hint: hint.to_string(),
         desc: desc.to_string(),
         hasarg: Yes,
-        occur: Optional
+        occur: Optional,
+        env: None,
     }
 }
 
@@ -453,7 +575,8 @@ pub fn optflag(short_name: &str, long_name: &str, desc: &str) -> OptGroup {
         hint: "".to_string(),
         desc: desc.to_string(),
         hasarg: No,
-        occur: Optional
+        occur: Optional,
+        env: None,
     }
 }
 
@@ -472,7 +595,8 @@ pub fn optflagmulti(short_name: &str, long_name: &str, desc: &str) -> OptGroup {
         hint: "".to_string(),
         desc: desc.to_string(),
         hasarg: No,
-        occur: Multi
+        occur: Multi,
+        env: None,
     }
 }
 
@@ -492,7 +616,8 @@ pub fn optflagopt(short_name: &str, long_name: &str, desc: &str, hint: &str) ->
         hint: hint.to_string(),
         desc: desc.to_string(),
         hasarg: Maybe,
-        occur: Optional
+        occur: Optional,
+        env: None,
     }
 }
 
@@ -513,7 +638,8 @@ pub fn optmulti(short_name: &str, long_name: &str, desc: &str, hint: &str) -> Op
         hint: hint.to_string(),
         desc: desc.to_string(),
         hasarg: Yes,
-        occur: Multi
+        occur: Multi,
+        env: None,
     }
 }
 
@@ -532,7 +658,8 @@ pub fn opt(short_name: &str,
         hint: hint.to_string(),
         desc: desc.to_string(),
         hasarg: hasarg,
-        occur: occur
+        occur: occur,
+        env: None,
     }
 }
 
@@ -562,6 +689,12 @@ impl fmt::Show for Fail {
             UnexpectedArgument(ref nm) => {
                 write!(f, "Option '{}' does not take an argument.", *nm)
             }
+            InvalidValue(ref nm, ref val) => {
+                write!(f, "Invalid value '{}' for option '{}'.", *val, *nm)
+            }
+            UnrecognizedCommand(ref nm) => {
+                write!(f, "Unrecognized command: '{}'.", *nm)
+            }
         }
     }
 }
@@ -682,6 +815,19 @@ pub fn getopts(args: &[String], optgrps: &[OptGroup]) -> Result {
         }
         i += 1;
     }
+    for (i, optgrp) in optgrps.iter().enumerate() {
+        if !vals[i].is_empty() {
+            continue;
+        }
+        match optgrp.env.as_ref().and_then(|var| os::getenv(var.as_slice())) {
+            Some(val) => match optgrp.hasarg {
+                No if val.is_empty() || val == "0" => {}
+                No => vals[i].push(Given),
+                Yes | Maybe => vals[i].push(Val(val)),
+            },
+            None => {}
+        }
+    }
     for i in range(0u, n_opts) {
         let n = vals[i].len();
         let occ = opts[i].occur;
@@ -695,21 +841,128 @@ pub fn getopts(args: &[String], optgrps: &[OptGroup]) -> Result {
     Ok(Matches {
         opts: opts,
         vals: vals,
-        free: free
+        free: free,
+        subcommand: None,
     })
 }
 
-/// Derive a usage message from a set of long options.
-pub fn usage(brief: &str, opts: &[OptGroup]) -> String {
+/// Create a command, e.g. `commit` for `git commit`, with its options.
+///
+/// * `name` - Name of the command, as given on the command line
+/// * `desc` - Description for usage help
+/// * `opts` - The options of the command
+pub fn command(name: &str, desc: &str, opts: &[OptGroup]) -> Command {
+    Command {
+        name: name.to_string(),
+        desc: desc.to_string(),
+        opts: opts.to_vec(),
+        subcommands: Vec::new(),
+    }
+}
 
-    let desc_sep = format!("\n{}", " ".repeat(24));
+impl Command {
+    /// Add a subcommand to the command.
+    pub fn subcommand(mut self, cmd: Command) -> Command {
+        self.subcommands.push(cmd);
+        self
+    }
+
+    /// Derive a usage message for the command, from its options and
+    /// subcommands.
+    pub fn usage(&self, brief: &str) -> String {
+        let mut text = usage(brief, self.opts.as_slice());
+        if !self.subcommands.is_empty() {
+            let rows: Vec<String> = self.subcommands.iter().map(|cmd| {
+                let row = format!("    {} ", cmd.name);
+                push_desc(row, cmd.desc.as_slice())
+            }).collect();
+            text.push_str(format!("\nCommands:\n{}\n", rows.connect("\n")).as_slice());
+        }
+        text
+    }
+}
+
+/// Parse command line arguments according to the options of a command and
+/// of its subcommands.
+///
+/// The options of the command come first, then the name of a subcommand,
+/// and the arguments of the subcommand, which are parsed the same way. The
+/// matches of the subcommand are in the `subcommand` field of the returned
+/// matches, which is `None` if no arguments are left for a subcommand. Note
+/// that the optional argument of an option must follow an equals sign if the
+/// option comes before the name of a subcommand.
+///
+/// Returns `Err(UnrecognizedCommand)` if the command has subcommands and the
+/// first free argument isn't one of them.
+pub fn getopts_command(args: &[String], cmd: &Command) -> Result {
+    if cmd.subcommands.is_empty() {
+        return getopts(args, cmd.opts.as_slice());
+    }
+    let i = match first_free(args, cmd.opts.as_slice()) {
+        Some(i) => i,
+        None => return getopts(args, cmd.opts.as_slice()),
+    };
+    let name = &args[i];
+    let sub = match cmd.subcommands.iter().find(|sub| sub.name == *name) {
+        Some(sub) => sub,
+        None => return Err(UnrecognizedCommand(name.clone())),
+    };
+    let mut matches = try!(getopts(args.slice_to(i), cmd.opts.as_slice()));
+    let sub_matches = try!(getopts_command(args.slice_from(i + 1), sub));
+    matches.subcommand = Some((name.clone(), box sub_matches));
+    Ok(matches)
+}
+
+// The position of the first argument which is neither an option nor the
+// argument of one.
+fn first_free(args: &[String], optgrps: &[OptGroup]) -> Option<uint> {
+    let opts: Vec<Opt> = optgrps.iter().map(|x| x.long_to_short()).collect();
+    let takes_arg = |nm: Name| {
+        match find_opt(opts.as_slice(), nm) {
+            Some(id) => opts[id].hasarg == Yes,
+            None => false,
+        }
+    };
+    let mut i = 0;
+    while i < args.len() {
+        let cur = args[i].as_slice();
+        if cur == "--" {
+            return if i + 1 < args.len() { Some(i + 1) } else { None };
+        } else if !is_arg(cur) {
+            return Some(i);
+        } else if cur.as_bytes()[1] == b'-' {
+            if !cur.contains_char('=') && takes_arg(Long(cur.slice_from(2).to_string())) {
+                i += 1;
+            }
+        } else {
+            // As in `getopts`, the first short option which takes an
+            // argument takes the rest of the word, or the next one.
+            let mut j = 1;
+            while j < cur.len() {
+                let range = cur.char_range_at(j);
+                if takes_arg(Short(range.ch)) {
+                    if range.next == cur.len() {
+                        i += 1;
+                    }
+                    break;
+                }
+                j = range.next;
+            }
+        }
+        i += 1;
+    }
+    None
+}
 
+/// Derive a usage message from a set of long options.
+pub fn usage(brief: &str, opts: &[OptGroup]) -> String {
     let rows = opts.iter().map(|optref| {
         let OptGroup{short_name,
                      long_name,
                      hint,
                      desc,
                      hasarg,
+                     env,
                      ..} = (*optref).clone();
 
         let mut row = " ".repeat(4);
@@ -746,42 +999,52 @@ pub fn usage(brief: &str, opts: &[OptGroup]) -> String {
             }
         }
 
-        // FIXME: #5516 should be graphemes not codepoints
-        // here we just need to indent the start of the description
-        let rowlen = row.char_len();
-        if rowlen < 24 {
-            for _ in range(0, 24 - rowlen) {
-                row.push(' ');
-            }
-        } else {
-            row.push_str(desc_sep.as_slice())
+        match env {
+            Some(var) => push_desc(row, format!("{} [env: {}]", desc, var).as_slice()),
+            None => push_desc(row, desc.as_slice()),
         }
+    });
 
-        // Normalize desc to contain words separated by one space character
-        let mut desc_normalized_whitespace = String::new();
-        for word in desc.words() {
-            desc_normalized_whitespace.push_str(word);
-            desc_normalized_whitespace.push(' ');
-        }
+    format!("{}\n\nOptions:\n{}\n", brief,
+            rows.collect::<Vec<String>>().connect("\n"))
+}
 
-        // FIXME: #5516 should be graphemes not codepoints
-        let mut desc_rows = Vec::new();
-        each_split_within(desc_normalized_whitespace.as_slice(),
-                          54,
-                          |substr| {
-            desc_rows.push(substr.to_string());
-            true
-        });
+// Appends a description to a row of a usage message, wrapped and indented.
+fn push_desc(mut row: String, desc: &str) -> String {
+    let desc_sep = format!("\n{}", " ".repeat(24));
+
+    // FIXME: #5516 should be graphemes not codepoints
+    // here we just need to indent the start of the description
+    let rowlen = row.char_len();
+    if rowlen < 24 {
+        for _ in range(0, 24 - rowlen) {
+            row.push(' ');
+        }
+    } else {
+        row.push_str(desc_sep.as_slice())
+    }
 
-        // FIXME: #5516 should be graphemes not codepoints
-        // wrapped description
-        row.push_str(desc_rows.connect(desc_sep.as_slice()).as_slice());
+    // Normalize desc to contain words separated by one space character
+    let mut desc_normalized_whitespace = String::new();
+    for word in desc.words() {
+        desc_normalized_whitespace.push_str(word);
+        desc_normalized_whitespace.push(' ');
+    }
 
-        row
+    // FIXME: #5516 should be graphemes not codepoints
+    let mut desc_rows = Vec::new();
+    each_split_within(desc_normalized_whitespace.as_slice(),
+                      54,
+                      |substr| {
+        desc_rows.push(substr.to_string());
+        true
     });
 
-    format!("{}\n\nOptions:\n{}\n", brief,
-            rows.collect::<Vec<String>>().connect("\n"))
+    // FIXME: #5516 should be graphemes not codepoints
+    // wrapped description
+    row.push_str(desc_rows.connect(desc_sep.as_slice()).as_slice());
+
+    row
 }
 
 fn format_option(opt: &OptGroup) -> String {
@@ -832,6 +1095,206 @@ pub fn short_usage(program_name: &str, opts: &[OptGroup]) -> String {
     line
 }
 
+// A command of a tree of subcommands, with the names of the shell functions
+// or states for it and for its subcommands, which are made of the names of
+// the commands leading to it.
+struct CommandPath<'a> {
+    path: String,
+    cmd: &'a Command,
+    subpaths: Vec<String>,
+}
+
+fn command_paths<'a>(cmd: &'a Command, path: String, paths: &mut Vec<CommandPath<'a>>) {
+    let subpaths: Vec<String> = cmd.subcommands.iter().map(|sub| {
+        format!("{}_{}", path, shell_name(sub.name.as_slice()))
+    }).collect();
+    paths.push(CommandPath { path: path.clone(), cmd: cmd, subpaths: subpaths.clone() });
+    for (sub, subpath) in cmd.subcommands.iter().zip(subpaths.into_iter()) {
+        command_paths(sub, subpath, paths);
+    }
+}
+
+fn shell_name(name: &str) -> String {
+    name.chars().map(|c| if c.is_alphanumeric() { c } else { '_' }).collect()
+}
+
+// The words of the command line that give an option: `-o` and `--output`.
+fn option_words(opt: &OptGroup) -> Vec<String> {
+    let mut words = Vec::new();
+    if opt.short_name.len() > 0 {
+        words.push(format!("-{}", opt.short_name));
+    }
+    if opt.long_name.len() > 0 {
+        words.push(format!("--{}", opt.long_name));
+    }
+    words
+}
+
+/// Generate a bash completion script for a program whose options and
+/// subcommands are those of `cmd`, and whose name is the name of `cmd`.
+///
+/// The script completes the options and subcommands of the command being
+/// typed, and file names for the arguments of options and for free
+/// arguments.
+pub fn bash_completion(cmd: &Command) -> String {
+    let func = format!("_{}", shell_name(cmd.name.as_slice()));
+    let mut paths = Vec::new();
+    command_paths(cmd, func.clone(), &mut paths);
+
+    let mut script = format!("{}() {{\n", func);
+    script.push_str("    local cur prev cmd i\n");
+    script.push_str("    cur=\"${COMP_WORDS[COMP_CWORD]}\"\n");
+    script.push_str("    prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n");
+    script.push_str(format!("    cmd=\"{}\"\n", func).as_slice());
+
+    // Find the subcommand being typed.
+    script.push_str("    for ((i = 1; i < COMP_CWORD; i++)); do\n");
+    script.push_str("        case \"${cmd},${COMP_WORDS[i]}\" in\n");
+    for p in paths.iter() {
+        for (sub, subpath) in p.cmd.subcommands.iter().zip(p.subpaths.iter()) {
+            script.push_str(format!("            \"{},{}\") cmd=\"{}\" ;;\n",
+                                    p.path, sub.name, *subpath).as_slice());
+        }
+    }
+    script.push_str("        esac\n");
+    script.push_str("    done\n");
+
+    // The arguments of options are file names.
+    script.push_str("    case \"${cmd},${prev}\" in\n");
+    for p in paths.iter() {
+        let patterns: Vec<String> = p.cmd.opts.iter().filter(|opt| opt.hasarg == Yes)
+            .flat_map(|opt| option_words(opt).into_iter())
+            .map(|word| format!("\"{},{}\"", p.path, word))
+            .collect();
+        if !patterns.is_empty() {
+            script.push_str(format!("        {})\n", patterns.connect("|")).as_slice());
+            script.push_str("            COMPREPLY=($(compgen -f -- \"${cur}\"))\n");
+            script.push_str("            return 0 ;;\n");
+        }
+    }
+    script.push_str("    esac\n");
+
+    script.push_str("    case \"${cmd}\" in\n");
+    for p in paths.iter() {
+        let mut words: Vec<String> = p.cmd.opts.iter()
+            .flat_map(|opt| option_words(opt).into_iter())
+            .collect();
+        words.extend(p.cmd.subcommands.iter().map(|sub| sub.name.clone()));
+        // Commands without subcommands take file names.
+        let files = if p.cmd.subcommands.is_empty() { " -f" } else { "" };
+        script.push_str(format!("        {})\n", p.path).as_slice());
+        script.push_str(format!("            COMPREPLY=($(compgen -W \"{}\"{} -- \"${{cur}}\")) ;;\n",
+                                words.connect(" "), files).as_slice());
+    }
+    script.push_str("    esac\n");
+    script.push_str("}\n");
+    script.push_str(format!("complete -F {} {}\n", func, cmd.name).as_slice());
+    script
+}
+
+/// Generate a zsh completion script for a program whose options and
+/// subcommands are those of `cmd`, and whose name is the name of `cmd`.
+///
+/// The script completes options with their descriptions, subcommands with
+/// theirs, and file names for the arguments of options and for free
+/// arguments. It is meant to be installed as `_NAME` in a directory of
+/// `$fpath`.
+pub fn zsh_completion(cmd: &Command) -> String {
+    let func = format!("_{}", shell_name(cmd.name.as_slice()));
+    let mut paths = Vec::new();
+    command_paths(cmd, func.clone(), &mut paths);
+
+    let mut script = format!("#compdef {}\n", cmd.name);
+    for p in paths.iter() {
+        let cmd = p.cmd;
+        script.push_str(format!("\n{}() {{\n", p.path).as_slice());
+        let mut specs: Vec<String> = cmd.opts.iter().map(zsh_option_spec).collect();
+        if cmd.subcommands.is_empty() {
+            specs.push("'*:file:_files'".to_string());
+        } else {
+            script.push_str("    local context state state_descr line\n");
+            script.push_str("    typeset -A opt_args\n");
+            specs.push("': :->command'".to_string());
+            specs.push("'*:: :->args'".to_string());
+        }
+        let flag = if cmd.subcommands.is_empty() { "" } else { " -C" };
+        script.push_str(format!("    _arguments{} \\\n        {}\n", flag,
+                                specs.connect(" \\\n        ")).as_slice());
+        if cmd.subcommands.is_empty() {
+            script.push_str("}\n");
+            continue;
+        }
+
+        script.push_str("    case $state in\n");
+        script.push_str("        command)\n");
+        script.push_str("            local -a commands\n");
+        script.push_str("            commands=(\n");
+        for sub in cmd.subcommands.iter() {
+            let name = sub.name.replace(":", "\\:");
+            script.push_str(format!("                {}\n",
+                                    zsh_quote(format!("{}:{}", name, sub.desc).as_slice()))
+                            .as_slice());
+        }
+        script.push_str("            )\n");
+        script.push_str("            _describe -t commands 'command' commands ;;\n");
+        script.push_str("        args)\n");
+        script.push_str("            case $line[1] in\n");
+        for (sub, subpath) in cmd.subcommands.iter().zip(p.subpaths.iter()) {
+            script.push_str(format!("                {}) {} ;;\n",
+                                    zsh_quote(sub.name.as_slice()), *subpath).as_slice());
+        }
+        script.push_str("            esac ;;\n");
+        script.push_str("    esac\n");
+        script.push_str("}\n");
+    }
+    script.push_str(format!("\n{} \"$@\"\n", func).as_slice());
+    script
+}
+
+// Quotes a string for zsh, within single quotes.
+fn zsh_quote(s: &str) -> String {
+    format!("'{}'", s.replace("'", "'\\''"))
+}
+
+// The `_arguments` spec of an option, e.g.
+// `'(-o --output)'{-o+,--output=}'[write output to FILE]:FILE:_files'`.
+fn zsh_option_spec(opt: &OptGroup) -> String {
+    let (short_suffix, long_suffix) = match opt.hasarg {
+        No => ("", ""),
+        Yes => ("+", "="),
+        Maybe => ("-", "=-"),
+    };
+    let mut names = Vec::new();
+    if opt.short_name.len() > 0 {
+        names.push(format!("-{}{}", opt.short_name, short_suffix));
+    }
+    if opt.long_name.len() > 0 {
+        names.push(format!("--{}{}", opt.long_name, long_suffix));
+    }
+
+    let desc = opt.desc.replace("\\", "\\\\").replace("[", "\\[").replace("]", "\\]");
+    let mut rest = format!("[{}]", desc);
+    match opt.hasarg {
+        No => {}
+        Yes => rest.push_str(format!(":{}:_files", opt.hint.replace(":", "\\:")).as_slice()),
+        Maybe => rest.push_str(format!("::{}:_files", opt.hint.replace(":", "\\:")).as_slice()),
+    }
+
+    let exclusion = if opt.occur == Multi {
+        "'*'".to_string()
+    } else if names.len() > 1 {
+        let words: Vec<String> = option_words(opt);
+        zsh_quote(format!("({})", words.connect(" ")).as_slice())
+    } else {
+        String::new()
+    };
+    if names.len() > 1 {
+        format!("{}{{{}}}{}", exclusion, names.connect(","), zsh_quote(rest.as_slice()))
+    } else {
+        format!("{}{}", exclusion, zsh_quote(format!("{}{}", names[0], rest).as_slice()))
+    }
+}
+
 #[deriving(Copy)]
 enum SplitWithinState {
     A,  // leading whitespace, initial state
@@ -964,6 +1427,7 @@ mod tests {
     use super::*;
     use super::Fail::*;
 
+    use std::os;
     use std::result::Result::{Err, Ok};
     use std::result;
 
@@ -1603,4 +2067,162 @@ Options:
         debug!("generated: <<{}>>", generated_usage);
         assert_eq!(generated_usage, expected);
     }
+
+    #[test]
+    fn test_env_fallback() {
+        os::setenv("GETOPTS_TEST_ENV_JOBS", "3");
+        os::setenv("GETOPTS_TEST_ENV_VERBOSE", "1");
+        os::setenv("GETOPTS_TEST_ENV_QUIET", "0");
+        os::setenv("GETOPTS_TEST_ENV_OUTPUT", "out.txt");
+        let opts = vec!(optopt("j", "jobs", "Desc", "N").env("GETOPTS_TEST_ENV_JOBS"),
+                        optflag("v", "verbose", "Desc").env("GETOPTS_TEST_ENV_VERBOSE"),
+                        optflag("q", "quiet", "Desc").env("GETOPTS_TEST_ENV_QUIET"),
+                        reqopt("o", "output", "Desc", "FILE").env("GETOPTS_TEST_ENV_OUTPUT"),
+                        optopt("k", "", "Desc", "K").env("GETOPTS_TEST_ENV_UNSET"));
+
+        let m = getopts(&[], opts.as_slice()).unwrap();
+        assert_eq!(m.opt_str("j").unwrap(), "3");
+        assert!(m.opt_present("v"));
+        assert!(!m.opt_present("q"));
+        assert_eq!(m.opt_str("o").unwrap(), "out.txt");
+        assert!(!m.opt_present("k"));
+
+        let args = vec!("-j".to_string(), "5".to_string());
+        let m = getopts(args.as_slice(), opts.as_slice()).unwrap();
+        assert_eq!(m.opt_str("j").unwrap(), "5");
+    }
+
+    #[test]
+    fn test_opt_get() {
+        let args = vec!("-j".to_string(), "4".to_string(),
+                        "-n".to_string(), "x".to_string(),
+                        "-l".to_string(), "1".to_string(),
+                        "-l".to_string(), "-2".to_string());
+        let opts = vec!(optopt("j", "", "Desc", "N"),
+                        optopt("n", "", "Desc", "N"),
+                        optopt("m", "", "Desc", "N"),
+                        optmulti("l", "", "Desc", "N"));
+        let m = getopts(args.as_slice(), opts.as_slice()).unwrap();
+        assert_eq!(m.opt_get::<uint>("j"), Ok(Some(4)));
+        assert_eq!(m.opt_get::<uint>("m"), Ok(None));
+        assert_eq!(m.opt_get_default("m", 8u), Ok(8));
+        assert_eq!(m.opt_gets::<int>("l"), Ok(vec!(1, -2)));
+
+        let err = m.opt_get::<uint>("n").unwrap_err();
+        assert_eq!(err, InvalidValue("n".to_string(), "x".to_string()));
+        assert_eq!(err.to_string(), "Invalid value 'x' for option 'n'.");
+    }
+
+    fn vcs() -> Command {
+        command("vcs", "A version control system",
+                &[optflag("v", "verbose", "Print more"),
+                  optopt("C", "", "Run in DIR", "DIR")])
+            .subcommand(command("commit", "Record changes to the repository",
+                                &[optmulti("m", "message", "Use MSG as the commit message",
+                                           "MSG")]))
+            .subcommand(command("remote", "Manage the set of tracked repositories", &[])
+                .subcommand(command("add", "Add a remote",
+                                    &[optflag("f", "fetch", "Fetch the remote")])))
+    }
+
+    #[test]
+    fn test_subcommands() {
+        let args: Vec<String> = vec!("-C", "repo", "remote", "add", "-f", "origin")
+            .into_iter().map(|s| s.to_string()).collect();
+        let m = getopts_command(args.as_slice(), &vcs()).unwrap();
+        assert_eq!(m.opt_str("C").unwrap(), "repo");
+        assert!(m.free.is_empty());
+        let (ref name, ref remote) = *m.subcommand.as_ref().unwrap();
+        assert_eq!(*name, "remote");
+        let (ref name, ref add) = *remote.subcommand.as_ref().unwrap();
+        assert_eq!(*name, "add");
+        assert!(add.opt_present("fetch"));
+        assert_eq!(add.free, vec!("origin".to_string()));
+        assert!(add.subcommand.is_none());
+
+        // Options of a command don't apply to its subcommands.
+        let args: Vec<String> = vec!("commit", "-v")
+            .into_iter().map(|s| s.to_string()).collect();
+        match getopts_command(args.as_slice(), &vcs()) {
+            Err(UnrecognizedOption(_)) => {}
+            _ => panic!()
+        }
+
+        let args = vec!("-v".to_string());
+        let m = getopts_command(args.as_slice(), &vcs()).unwrap();
+        assert!(m.opt_present("v"));
+        assert!(m.subcommand.is_none());
+
+        let args = vec!("-v".to_string(), "frob".to_string());
+        match getopts_command(args.as_slice(), &vcs()) {
+            Err(f) => assert_eq!(f, UnrecognizedCommand("frob".to_string())),
+            _ => panic!()
+        }
+    }
+
+    #[test]
+    fn test_command_usage() {
+        let expected =
+"Usage: vcs [OPTIONS] COMMAND
+
+Options:
+    -v --verbose        Print more
+    -C DIR              Run in DIR
+
+Commands:
+    commit              Record changes to the repository
+    remote              Manage the set of tracked repositories
+";
+        let generated_usage = vcs().usage("Usage: vcs [OPTIONS] COMMAND");
+        debug!("expected: <<{}>>", expected);
+        debug!("generated: <<{}>>", generated_usage);
+        assert_eq!(generated_usage, expected);
+
+        let opts = vec!(optopt("j", "jobs", "Number of jobs", "N").env("JOBS"));
+        let expected =
+"Usage: make
+
+Options:
+    -j --jobs N         Number of jobs [env: JOBS]
+";
+        assert_eq!(usage("Usage: make", opts.as_slice()), expected);
+    }
+
+    #[test]
+    fn test_bash_completion() {
+        let script = bash_completion(&vcs());
+        debug!("generated: <<{}>>", script);
+        assert!(script.starts_with("_vcs() {\n"));
+        assert!(script.contains("            \"_vcs,remote\") cmd=\"_vcs_remote\" ;;\n"));
+        assert!(script.contains("            \"_vcs_remote,add\") cmd=\"_vcs_remote_add\" ;;\n"));
+        assert!(script.contains("        \"_vcs,-C\")\n\
+                                 \x20           COMPREPLY=($(compgen -f -- \"${cur}\"))\n"));
+        assert!(script.contains("        \"_vcs_commit,-m\"|\"_vcs_commit,--message\")\n"));
+        assert!(script.contains("        _vcs)\n\
+                                 \x20           COMPREPLY=($(compgen -W \
+                                 \"-v --verbose -C commit remote\" -- \"${cur}\")) ;;\n"));
+        assert!(script.contains("        _vcs_remote_add)\n\
+                                 \x20           COMPREPLY=($(compgen -W \
+                                 \"-f --fetch\" -f -- \"${cur}\")) ;;\n"));
+        assert!(script.ends_with("complete -F _vcs vcs\n"));
+    }
+
+    #[test]
+    fn test_zsh_completion() {
+        let script = zsh_completion(&vcs());
+        debug!("generated: <<{}>>", script);
+        assert!(script.starts_with("#compdef vcs\n\n_vcs() {\n"));
+        assert!(script.contains("    _arguments -C \\\n\
+                                 \x20       '(-v --verbose)'{-v,--verbose}'[Print more]' \\\n\
+                                 \x20       '-C+[Run in DIR]:DIR:_files' \\\n\
+                                 \x20       ': :->command' \\\n\
+                                 \x20       '*:: :->args'\n"));
+        assert!(script.contains("                'commit:Record changes to the repository'\n"));
+        assert!(script.contains("                'remote') _vcs_remote ;;\n"));
+        assert!(script.contains("\n_vcs_commit() {\n    _arguments \\\n\
+                                 \x20       '*'{-m+,--message=}\
+                                 '[Use MSG as the commit message]:MSG:_files' \\\n\
+                                 \x20       '*:file:_files'\n}\n"));
+        assert!(script.ends_with("\n_vcs \"$@\"\n"));
+    }
 }
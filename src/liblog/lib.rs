@@ -85,6 +85,35 @@
 //!
 //! * `log_enabled!(level)` - returns true if logging of the given level is enabled
 //!
+//! # Structured logging
+//!
+//! Each of the logging macros can attach key-value pairs to a log line by
+//! listing them in braces before the format string:
+//!
+//! ```
+//! #![feature(phase)]
+//! #[phase(plugin, link)] extern crate log;
+//!
+//! fn main() {
+//!     let _request = log::push_context("request_id", "f81d4fae");
+//!     info!({ status = 200u, bytes = 1024u }, "served {}", "/index.html");
+//! }
+//! ```
+//!
+//! Pairs added with `push_context` are attached to every line logged by the
+//! current task until the returned guard goes out of scope. The default logger
+//! appends both kinds of pairs to the message:
+//!
+//! ```{.bash}
+//! $ RUST_LOG=info ./main
+//! INFO:main: served /index.html request_id=f81d4fae status=200 bytes=1024
+//! ```
+//!
+//! A task can use a different format by installing another logger with
+//! `set_logger`: `TextLogger` writes the same lines as the default logger to
+//! any `Writer`, optionally prefixed with a timestamp, and `JsonLogger` writes
+//! each record as a JSON object on its own line.
+//!
 //! # Enabling logging
 //!
 //! Log levels are controlled on a per-module basis, and by default all logging is
@@ -142,6 +171,19 @@
 //!  hello. In both cases the log message must include a single digit number
 //!  followed by 'scopes'
 //!
+//! # Changing the configuration at runtime
+//!
+//! The directives of `RUST_LOG` are read when the first message is logged, and
+//! can later be replaced by passing a string of the same syntax to
+//! `set_directives`, for example when a daemon is told to reload its
+//! configuration.
+//!
+//! Records of tasks which have not installed their own logger are written to
+//! stderr, or, once `set_sinks` or `add_sink` has been called, to each of the
+//! process-wide sinks. A `Sink` pairs a logger with its own maximum level and
+//! regex filter, so that, say, warnings go to stderr while everything enabled
+//! goes to a `RotatingFileLogger` and errors also go to a `SyslogLogger`.
+//!
 //! # Performance and Side Effects
 //!
 //! Each of these macros will expand to code similar to:
@@ -164,27 +206,35 @@
        html_favicon_url = "http://www.rust-lang.org/favicon.ico",
        html_root_url = "http://doc.rust-lang.org/nightly/",
        html_playground_url = "http://play.rust-lang.org/")]
-#![feature(macro_rules, unboxed_closures)]
+#![feature(macro_rules, unboxed_closures, unsafe_destructor)]
 #![deny(missing_docs)]
 
+extern crate libc;
 extern crate regex;
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::fmt;
 use std::io::LineBufferedWriter;
 use std::io;
+use std::kinds::marker;
 use std::mem;
 use std::os;
 use std::rt;
 use std::slice;
-use std::sync::{Once, ONCE_INIT};
+use std::sync::{Arc, Condvar, Mutex, Once, ONCE_INIT, StaticRWLock, RWLOCK_INIT};
+use std::time::SystemTime;
 
 use regex::Regex;
 
 use directive::LOG_LEVEL_NAMES;
 
+pub use loggers::{JsonLogger, RotatingFileLogger, TextLogger};
+#[cfg(unix)]
+pub use loggers::{Facility, SyslogLogger};
+
 pub mod macros;
 mod directive;
+mod loggers;
 
 /// Maximum logging level of a module that can be specified. Common logging
 /// levels are found in the DEBUG/INFO/WARN/ERROR constants.
@@ -198,12 +248,22 @@ const DEFAULT_LOG_LEVEL: u32 = 1;
 /// logging statement should be run.
 static mut LOG_LEVEL: u32 = MAX_LOG_LEVEL;
 
+/// Guards `DIRECTIVES`, `FILTER` and `SINKS`, which may be replaced at
+/// runtime.
+static LOCK: StaticRWLock = RWLOCK_INIT;
+
+/// Initializes the globals below from `RUST_LOG`.
+static INIT: Once = ONCE_INIT;
+
 static mut DIRECTIVES: *const Vec<directive::LogDirective> =
     0 as *const Vec<directive::LogDirective>;
 
 /// Optional regex filter.
 static mut FILTER: *const Regex = 0 as *const _;
 
+/// The process-wide sinks used by tasks without a logger of their own.
+static mut SINKS: *const Vec<Arc<Sink>> = 0 as *const Vec<Arc<Sink>>;
+
 /// Debug log level
 pub const DEBUG: u32 = 4;
 /// Info log level
@@ -219,6 +279,26 @@ thread_local! {
     }
 }
 
+thread_local! {
+    static CONTEXT: RefCell<Context> = {
+        RefCell::new(Context { pairs: Vec::new(), ids: Vec::new(), next_id: 0 })
+    }
+}
+
+// The key-value pairs pushed by the live `ContextGuard`s of a task, with the
+// id of the guard of each pair.
+struct Context {
+    pairs: Vec<(String, String)>,
+    ids: Vec<uint>,
+    next_id: uint,
+}
+
+thread_local! {
+    // Set while this task writes to the sinks, so that a sink which logs does
+    // not try to lock itself again.
+    static IN_SINK: Cell<bool> = Cell::new(false)
+}
+
 /// A trait used to represent an interface to a task-local logger. Each task
 /// can have its own custom logger which can respond to logging messages
 /// however it likes.
@@ -247,11 +327,7 @@ impl fmt::Show for LogLevel {
 
 impl Logger for DefaultLogger {
     fn log(&mut self, record: &LogRecord) {
-        match writeln!(&mut self.handle,
-                       "{}:{}: {}",
-                       record.level,
-                       record.module_path,
-                       record.args) {
+        match loggers::write_text(&mut self.handle, record, false) {
             Err(e) => panic!("failed to log: {}", e),
             Ok(()) => {}
         }
@@ -277,29 +353,70 @@ impl Drop for DefaultLogger {
 /// invoked through the logging family of macros.
 #[doc(hidden)]
 pub fn log(level: u32, loc: &'static LogLocation, args: &fmt::Arguments) {
+    log_with_fields(level, loc, &[], args)
+}
+
+/// Like `log`, but also attaches the structured `fields` to the record. This
+/// is called by the logging macros when they are given key-value pairs.
+#[doc(hidden)]
+pub fn log_with_fields(level: u32, loc: &'static LogLocation, fields: &[LogField],
+                       args: &fmt::Arguments) {
     // Test the literal string from args against the current filter, if there
-    // is one.
-    match unsafe { FILTER.as_ref() } {
-        Some(filter) if !filter.is_match(args.to_string().as_slice()) => return,
-        _ => {}
+    // is one. The lock is released before calling any logger, which may log
+    // itself.
+    {
+        let _g = LOCK.read();
+        match unsafe { FILTER.as_ref() } {
+            Some(filter) if !filter.is_match(args.to_string().as_slice()) => return,
+            _ => {}
+        }
     }
 
     // Completely remove the local logger from TLS in case anyone attempts to
     // frob the slot while we're doing the logging. This will destroy any logger
     // set during logging.
-    let mut logger = LOCAL_LOGGER.with(|s| {
+    let logger = LOCAL_LOGGER.with(|s| {
         s.borrow_mut().take()
-    }).unwrap_or_else(|| {
-        box DefaultLogger { handle: io::stderr() } as Box<Logger + Send>
     });
-    logger.log(&LogRecord {
-        level: LogLevel(level),
-        args: args,
-        file: loc.file,
-        module_path: loc.module_path,
-        line: loc.line,
+    let sinks = match logger {
+        Some(..) => Vec::new(),
+        None if IN_SINK.with(|s| s.get()) => Vec::new(),
+        None => {
+            let _g = LOCK.read();
+            unsafe { SINKS.as_ref().map(|s| s.clone()).unwrap_or(Vec::new()) }
+        }
+    };
+
+    let time = SystemTime::now();
+    CONTEXT.with(move |context| {
+        let context = context.borrow();
+        let record = LogRecord {
+            level: LogLevel(level),
+            args: args,
+            file: loc.file,
+            module_path: loc.module_path,
+            line: loc.line,
+            fields: fields,
+            context: context.pairs.as_slice(),
+            time: time,
+        };
+        match logger {
+            Some(mut logger) => {
+                logger.log(&record);
+                set_logger(logger);
+            }
+            None if sinks.is_empty() => {
+                DefaultLogger { handle: io::stderr() }.log(&record);
+            }
+            None => {
+                IN_SINK.with(|s| s.set(true));
+                for sink in sinks.iter() {
+                    sink.log(&record);
+                }
+                IN_SINK.with(|s| s.set(false));
+            }
+        }
     });
-    set_logger(logger);
 }
 
 /// Getter for the global log level. This is a function so that it can be called
@@ -317,6 +434,158 @@ pub fn set_logger(logger: Box<Logger + Send>) -> Option<Box<Logger + Send>> {
     })
 }
 
+/// A destination for the records of every task which has not installed a
+/// logger of its own with `set_logger`.
+///
+/// Each sink wraps a logger with its own maximum level and an optional regex
+/// filter on the message. These only narrow what the logging directives
+/// enable: a record that `RUST_LOG` disables reaches no sink at all.
+///
+/// # Example
+///
+/// ```
+/// extern crate log;
+/// extern crate regex;
+///
+/// use std::io;
+/// use log::{JsonLogger, Sink, TextLogger};
+/// use regex::Regex;
+///
+/// fn main() {
+///     let requests = Regex::new("^request").unwrap();
+///     log::set_sinks(vec![
+///         Sink::new(box TextLogger::new(io::stderr())).level(log::WARN),
+///         Sink::new(box JsonLogger::new(io::stdout())).filter(requests),
+///     ]);
+/// }
+/// ```
+pub struct Sink {
+    level: u32,
+    filter: Option<Regex>,
+    // The logger is taken out while a task logs to it, so that the lock isn't
+    // held, and left poisoned, if the logger panics.
+    logger: Mutex<Option<Box<Logger + Send>>>,
+    // Notified when the logger is put back.
+    returned: Condvar,
+}
+
+impl Sink {
+    /// Creates a sink passing every record to `logger`.
+    pub fn new(logger: Box<Logger + Send>) -> Sink {
+        Sink {
+            level: MAX_LOG_LEVEL,
+            filter: None,
+            logger: Mutex::new(Some(logger)),
+            returned: Condvar::new(),
+        }
+    }
+
+    /// Sets the maximum level of the records passed to the logger.
+    pub fn level(mut self, level: u32) -> Sink {
+        self.level = level;
+        self
+    }
+
+    /// Only passes the records whose message matches `filter` to the logger.
+    pub fn filter(mut self, filter: Regex) -> Sink {
+        self.filter = Some(filter);
+        self
+    }
+
+    fn log(&self, record: &LogRecord) {
+        let LogLevel(level) = record.level;
+        if level > self.level { return }
+        match self.filter {
+            Some(ref filter) if !filter.is_match(record.args.to_string().as_slice()) => return,
+            _ => {}
+        }
+        let mut borrowed = {
+            let mut logger = self.logger.lock();
+            while logger.is_none() {
+                self.returned.wait(&logger);
+            }
+            let taken = logger.take();
+            BorrowedLogger { sink: self, logger: taken }
+        };
+        borrowed.logger.as_mut().unwrap().log(record);
+    }
+}
+
+// The logger of a sink while a task logs to it, which is put back when this
+// is dropped, even if the logger panicked.
+struct BorrowedLogger<'a> {
+    sink: &'a Sink,
+    logger: Option<Box<Logger + Send>>,
+}
+
+#[unsafe_destructor]
+impl<'a> Drop for BorrowedLogger<'a> {
+    fn drop(&mut self) {
+        *self.sink.logger.lock() = self.logger.take();
+        self.sink.returned.notify_one();
+    }
+}
+
+/// Replaces the process-wide sinks. Records of tasks without a logger of
+/// their own are written to each of `sinks`, or to stderr if there are none.
+pub fn set_sinks(sinks: Vec<Sink>) {
+    INIT.doit(init);
+    let sinks = box sinks.into_iter().map(|s| Arc::new(s)).collect::<Vec<_>>();
+    let _g = LOCK.write();
+    unsafe {
+        // Logging has been torn down at exit.
+        if SINKS.is_null() {
+            return;
+        }
+        let _old: Box<Vec<Arc<Sink>>> = mem::transmute(SINKS);
+        SINKS = mem::transmute(sinks);
+    }
+}
+
+/// Adds a process-wide sink to those already set.
+pub fn add_sink(sink: Sink) {
+    INIT.doit(init);
+    let _g = LOCK.write();
+    unsafe {
+        // The sinks are gone once the at_exit hook of `init` has run.
+        if SINKS.is_null() {
+            return;
+        }
+        let sinks = &mut *(SINKS as *mut Vec<Arc<Sink>>);
+        sinks.push(Arc::new(sink));
+    }
+}
+
+/// Replaces the logging directives of the whole process with those parsed
+/// from `spec`, which has the same syntax as `RUST_LOG`. Invalid directives
+/// are reported on stdout and ignored, as they are for `RUST_LOG`.
+///
+/// This allows a long-running program to change what it logs, as when it is
+/// told to reload its configuration. Tasks logging concurrently may briefly
+/// see the old directives.
+pub fn set_directives(spec: &str) {
+    INIT.doit(init);
+    let (directives, filter) = parse_spec(spec);
+    let _g = LOCK.write();
+    unsafe {
+        // Too late to change anything after the at_exit hook of `init`.
+        if DIRECTIVES.is_null() {
+            return;
+        }
+        let _old: Box<Vec<directive::LogDirective>> = mem::transmute(DIRECTIVES);
+        DIRECTIVES = mem::transmute(box directives);
+        if !FILTER.is_null() {
+            let _old: Box<Regex> = mem::transmute(FILTER);
+            FILTER = 0 as *const _;
+        }
+        match filter {
+            Some(f) => FILTER = mem::transmute(box f),
+            None => {}
+        }
+        LOG_LEVEL = max_level(&*DIRECTIVES);
+    }
+}
+
 /// A LogRecord is created by the logging macros, and passed as the only
 /// argument to Loggers.
 #[deriving(Show)]
@@ -336,6 +605,72 @@ pub struct LogRecord<'a> {
 
     /// The line number of where the LogRecord originated.
     pub line: uint,
+
+    /// The key-value pairs given to the logging macro.
+    pub fields: &'a [LogField<'a>],
+
+    /// The key-value pairs set with `push_context` in the logging task, from
+    /// the outermost to the innermost.
+    pub context: &'a [(String, String)],
+
+    /// The wall clock time at which the LogRecord was created.
+    pub time: SystemTime,
+}
+
+/// A structured key-value pair attached to a single log line, as in
+/// `info!({ user = name }, "logged in")`.
+#[deriving(Show)]
+pub struct LogField<'a> {
+    /// The name of the field.
+    pub key: &'a str,
+
+    /// The value of the field.
+    pub value: &'a (fmt::Show + 'a),
+}
+
+/// Adds a key-value pair to the context of the current task. Every record
+/// logged by this task carries the pair until the returned guard is dropped.
+///
+/// # Example
+///
+/// ```
+/// let _guard = log::push_context("request_id", 1234u);
+/// // all log lines from here on carry request_id=1234
+/// ```
+pub fn push_context<T: fmt::Show>(key: &str, value: T) -> ContextGuard {
+    CONTEXT.with(|context| {
+        let mut context = context.borrow_mut();
+        let id = context.next_id;
+        context.next_id += 1;
+        context.pairs.push((key.to_string(), value.to_string()));
+        context.ids.push(id);
+        ContextGuard { id: id, marker: marker::NoSend }
+    })
+}
+
+/// Removes a key-value pair added by `push_context` from the context of the
+/// current task when dropped. Guards may be dropped in any order: each only
+/// removes its own pair.
+pub struct ContextGuard {
+    id: uint,
+    marker: marker::NoSend,
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        let id = self.id;
+        CONTEXT.with(|context| {
+            let mut context = context.borrow_mut();
+            let pos = context.ids.iter().position(|&i| i == id);
+            match pos {
+                Some(pos) => {
+                    context.ids.remove(pos);
+                    context.pairs.remove(pos);
+                }
+                None => {}
+            }
+        });
+    }
 }
 
 #[doc(hidden)]
@@ -351,7 +686,6 @@ pub struct LogLocation {
 /// module's log statement should be emitted or not.
 #[doc(hidden)]
 pub fn mod_enabled(level: u32, module: &str) -> bool {
-    static INIT: Once = ONCE_INIT;
     INIT.doit(init);
 
     // It's possible for many threads are in this function, only one of them
@@ -361,6 +695,8 @@ pub fn mod_enabled(level: u32, module: &str) -> bool {
     // the log level again.
     if level > unsafe { LOG_LEVEL } { return false }
 
+    let _g = LOCK.read();
+
     // This assertion should never get tripped unless we're in an at_exit
     // handler after logging has been torn down and a logging attempt was made.
     assert!(unsafe { !DIRECTIVES.is_null() });
@@ -389,24 +725,13 @@ fn enabled(level: u32,
 /// This is not threadsafe at all, so initialization is performed through a
 /// `Once` primitive (and this function is called from that primitive).
 fn init() {
-    let (mut directives, filter) = match os::getenv("RUST_LOG") {
-        Some(spec) => directive::parse_logging_spec(spec.as_slice()),
+    let (directives, filter) = match os::getenv("RUST_LOG") {
+        Some(spec) => parse_spec(spec.as_slice()),
         None => (Vec::new(), None),
     };
+    let max_level = max_level(directives.as_slice());
 
-    // Sort the provided directives by length of their name, this allows a
-    // little more efficient lookup at runtime.
-    directives.sort_by(|a, b| {
-        let alen = a.name.as_ref().map(|a| a.len()).unwrap_or(0);
-        let blen = b.name.as_ref().map(|b| b.len()).unwrap_or(0);
-        alen.cmp(&blen)
-    });
-
-    let max_level = {
-        let max = directives.iter().max_by(|d| d.level);
-        max.map(|d| d.level).unwrap_or(DEFAULT_LOG_LEVEL)
-    };
-
+    let _g = LOCK.write();
     unsafe {
         LOG_LEVEL = max_level;
 
@@ -419,8 +744,12 @@ fn init() {
         assert!(DIRECTIVES.is_null());
         DIRECTIVES = mem::transmute(box directives);
 
+        assert!(SINKS.is_null());
+        SINKS = mem::transmute(box Vec::<Arc<Sink>>::new());
+
         // Schedule the cleanup for the globals for when the runtime exits.
         rt::at_exit(move |:| {
+            let _g = LOCK.write();
             assert!(!DIRECTIVES.is_null());
             let _directives: Box<Vec<directive::LogDirective>> =
                 mem::transmute(DIRECTIVES);
@@ -430,14 +759,157 @@ fn init() {
                 let _filter: Box<Regex> = mem::transmute(FILTER);
                 FILTER = 0 as *const _;
             }
+
+            let _sinks: Box<Vec<Arc<Sink>>> = mem::transmute(SINKS);
+            SINKS = 0 as *const Vec<Arc<Sink>>;
         });
     }
 }
 
+/// Parses a logging specification, sorting the directives for `enabled`.
+fn parse_spec(spec: &str) -> (Vec<directive::LogDirective>, Option<Regex>) {
+    let (mut directives, filter) = directive::parse_logging_spec(spec);
+
+    // Sort the provided directives by length of their name, this allows a
+    // little more efficient lookup at runtime.
+    directives.sort_by(|a, b| {
+        let alen = a.name.as_ref().map(|a| a.len()).unwrap_or(0);
+        let blen = b.name.as_ref().map(|b| b.len()).unwrap_or(0);
+        alen.cmp(&blen)
+    });
+    (directives, filter)
+}
+
+fn max_level(directives: &[directive::LogDirective]) -> u32 {
+    let max = directives.iter().max_by(|d| d.level);
+    max.map(|d| d.level).unwrap_or(DEFAULT_LOG_LEVEL)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::enabled;
+    use super::{enabled, log_level, mod_enabled, push_context, set_directives, CONTEXT};
+    use super::{Logger, LogLevel, LogRecord, Sink, TextLogger, DEBUG, INFO, WARN};
     use directive::LogDirective;
+    use regex::Regex;
+    use std::io::{mod, IoResult};
+    use std::sync::Arc;
+    use std::thread::Thread;
+    use std::time::SystemTime;
+
+    struct ChannelLogger(Sender<String>);
+
+    impl Logger for ChannelLogger {
+        fn log(&mut self, record: &LogRecord) {
+            let ChannelLogger(ref tx) = *self;
+            tx.send(record.args.to_string());
+        }
+    }
+
+    struct FailingWriter;
+
+    impl Writer for FailingWriter {
+        fn write(&mut self, _: &[u8]) -> IoResult<()> {
+            Err(io::standard_error(io::BrokenPipe))
+        }
+    }
+
+    fn log_to(sink: &Sink, level: u32, msg: &str) {
+        format_args!(|args| {
+            sink.log(&LogRecord {
+                module_path: "app",
+                level: LogLevel(level),
+                args: args,
+                file: "app.rs",
+                line: 1,
+                fields: &[],
+                context: &[],
+                time: SystemTime::now(),
+            })
+        }, "{}", msg)
+    }
+
+    #[test]
+    fn sink_level_and_filter() {
+        let (tx, rx) = channel();
+        let sink = Sink::new(box ChannelLogger(tx)).level(INFO).filter(Regex::new("^a").unwrap());
+        for &(level, msg) in [(INFO, "a1"), (DEBUG, "a2"), (WARN, "b3"), (WARN, "a4")].iter() {
+            log_to(&sink, level, msg);
+        }
+        drop(sink);
+        assert_eq!(rx.iter().collect::<Vec<String>>(),
+                   vec!["a1".to_string(), "a4".to_string()]);
+    }
+
+    #[test]
+    fn failing_sink() {
+        // A logger that fails to write doesn't panic, so later records are
+        // still logged.
+        let sink = Sink::new(box TextLogger::new(FailingWriter));
+        log_to(&sink, WARN, "a1");
+        log_to(&sink, WARN, "a2");
+    }
+
+    #[test]
+    fn panicking_sink() {
+        struct PanickingLogger(Sender<String>);
+
+        impl Logger for PanickingLogger {
+            fn log(&mut self, record: &LogRecord) {
+                let msg = record.args.to_string();
+                if msg.as_slice() == "panic" {
+                    panic!("logger panicked");
+                }
+                let PanickingLogger(ref tx) = *self;
+                tx.send(msg);
+            }
+        }
+
+        // The sink is still usable by other tasks after its logger panics.
+        let (tx, rx) = channel();
+        let sink = Arc::new(Sink::new(box PanickingLogger(tx)));
+        let sink2 = sink.clone();
+        let result = Thread::spawn(move || log_to(&*sink2, WARN, "panic")).join();
+        assert!(result.is_err());
+        log_to(&*sink, WARN, "a1");
+        drop(sink);
+        assert_eq!(rx.iter().collect::<Vec<String>>(), vec!["a1".to_string()]);
+    }
+
+    #[test]
+    fn reconfigure() {
+        set_directives("crate1=warn,crate1::mod1=debug");
+        assert_eq!(log_level(), DEBUG);
+        assert!(mod_enabled(DEBUG, "crate1::mod1"));
+        assert!(mod_enabled(WARN, "crate1::mod2"));
+        assert!(!mod_enabled(INFO, "crate1::mod2"));
+
+        set_directives("crate1=info");
+        assert_eq!(log_level(), INFO);
+        assert!(!mod_enabled(DEBUG, "crate1::mod1"));
+        assert!(mod_enabled(INFO, "crate1::mod2"));
+    }
+
+    #[test]
+    fn context() {
+        fn context() -> Vec<(String, String)> {
+            CONTEXT.with(|context| context.borrow().pairs.clone())
+        }
+
+        let outer = push_context("request_id", 7u);
+        {
+            let _inner = push_context("user", "alice");
+            assert_eq!(context(), vec![("request_id".to_string(), "7".to_string()),
+                                       ("user".to_string(), "alice".to_string())]);
+        }
+        assert_eq!(context(), vec![("request_id".to_string(), "7".to_string())]);
+
+        // Dropping a guard before one pushed after it keeps the later pair.
+        let inner = push_context("user", "bob");
+        drop(outer);
+        assert_eq!(context(), vec![("user".to_string(), "bob".to_string())]);
+        drop(inner);
+        assert!(context().is_empty());
+    }
 
     #[test]
     fn match_full_path() {
@@ -0,0 +1,607 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Loggers which write records to an arbitrary `Writer`.
+
+use std::io::fs::{mod, PathExtensions};
+use std::io::{stdio, File, IoError, IoResult, Append, Truncate, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH, civil_from_days};
+
+#[cfg(unix)] use libc;
+#[cfg(unix)] use std::io;
+#[cfg(unix)] use std::mem;
+
+use {Logger, LogRecord};
+#[cfg(unix)] use {LogLevel, ERROR, INFO, WARN};
+
+/// A logger which writes one line of plain text per record.
+///
+/// Records are written as `LEVEL:module: message`, optionally preceded by an
+/// RFC 3339 timestamp, and followed by the task context and the fields of
+/// the record as `key=value` pairs. Values containing spaces, quotes, `=` or
+/// control characters are quoted, with quotes, backslashes and control
+/// characters escaped as in JSON. Each record is flushed once written.
+pub struct TextLogger<W> {
+    handle: W,
+    timestamps: bool,
+}
+
+impl<W: Writer> TextLogger<W> {
+    /// Creates a logger writing to `handle`, without timestamps.
+    pub fn new(handle: W) -> TextLogger<W> {
+        TextLogger { handle: handle, timestamps: false }
+    }
+
+    /// Sets whether each line is prefixed with the time of the record.
+    pub fn timestamps(mut self, timestamps: bool) -> TextLogger<W> {
+        self.timestamps = timestamps;
+        self
+    }
+
+    /// Unwraps this logger, returning the underlying writer.
+    pub fn into_inner(self) -> W { self.handle }
+}
+
+impl<W: Writer> Logger for TextLogger<W> {
+    fn log(&mut self, record: &LogRecord) {
+        let timestamps = self.timestamps;
+        match write_text(&mut self.handle, record, timestamps).and_then(|()| self.handle.flush()) {
+            Err(e) => report_error(e),
+            Ok(()) => {}
+        }
+    }
+}
+
+/// A logger which writes one JSON object per record, one record per line.
+///
+/// Each object has the members `time` (an RFC 3339 timestamp in UTC),
+/// `level`, `module`, `file`, `line` and `message`, followed by one string
+/// member per context entry and field, in that order. Parsers which keep the
+/// last of duplicate members will thus prefer a field to a context entry of
+/// the same name.
+pub struct JsonLogger<W> {
+    handle: W,
+}
+
+impl<W: Writer> JsonLogger<W> {
+    /// Creates a logger writing to `handle`.
+    pub fn new(handle: W) -> JsonLogger<W> {
+        JsonLogger { handle: handle }
+    }
+
+    /// Unwraps this logger, returning the underlying writer.
+    pub fn into_inner(self) -> W { self.handle }
+}
+
+impl<W: Writer> Logger for JsonLogger<W> {
+    fn log(&mut self, record: &LogRecord) {
+        match write_json(&mut self.handle, record) {
+            Err(e) => report_error(e),
+            Ok(()) => {}
+        }
+    }
+}
+
+// Reports that a record couldn't be written. The loggers don't panic instead,
+// so that a task doesn't die because a shared log file or socket is broken.
+fn report_error(e: IoError) {
+    let _ = writeln!(&mut stdio::stderr_raw(), "failed to log: {}", e);
+}
+
+/// A logger which writes lines of text to a file, as `TextLogger` does with
+/// timestamps, and starts a new file before the current one grows too large.
+///
+/// When a line would take the file past its maximum size, the file is renamed
+/// by appending `.1` to its name, any `.1` file is renamed to `.2`, and so on,
+/// keeping at most the given number of old files.
+pub struct RotatingFileLogger {
+    path: Path,
+    max_size: u64,
+    keep: uint,
+    // `None` only if reopening the file after a rotation failed.
+    file: Option<File>,
+    size: u64,
+}
+
+impl RotatingFileLogger {
+    /// Opens the file at `path` for appending, creating it if needed. It is
+    /// rotated when it would exceed `max_size` bytes, keeping `keep` old files.
+    pub fn new(path: &Path, max_size: u64, keep: uint) -> IoResult<RotatingFileLogger> {
+        let file = try!(File::open_mode(path, Append, Write));
+        let size = try!(file.stat()).size;
+        Ok(RotatingFileLogger {
+            path: path.clone(),
+            max_size: max_size,
+            keep: keep,
+            file: Some(file),
+            size: size,
+        })
+    }
+
+    fn rotated(&self, n: uint) -> Path {
+        let mut name = self.path.filename().unwrap_or(&[]).to_vec();
+        name.push_all(format!(".{}", n).as_bytes());
+        self.path.with_filename(name)
+    }
+
+    fn rotate(&mut self) -> IoResult<()> {
+        // Close the file first, as open files cannot be renamed on Windows.
+        self.file = None;
+        self.size = 0;
+        if self.keep == 0 {
+            self.file = Some(try!(File::open_mode(&self.path, Truncate, Write)));
+            return Ok(());
+        }
+
+        let oldest = self.rotated(self.keep);
+        if oldest.exists() {
+            try!(fs::unlink(&oldest));
+        }
+        for n in range(1, self.keep).rev() {
+            let from = self.rotated(n);
+            if from.exists() {
+                try!(fs::rename(&from, &self.rotated(n + 1)));
+            }
+        }
+        try!(fs::rename(&self.path, &self.rotated(1)));
+        self.file = Some(try!(File::open_mode(&self.path, Append, Write)));
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &LogRecord) -> IoResult<()> {
+        let mut line = Vec::new();
+        try!(write_text(&mut line, record, true));
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            try!(self.rotate());
+        }
+        if self.file.is_none() {
+            self.file = Some(try!(File::open_mode(&self.path, Append, Write)));
+        }
+        try!(self.file.as_mut().unwrap().write(line.as_slice()));
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+impl Logger for RotatingFileLogger {
+    fn log(&mut self, record: &LogRecord) {
+        match self.write_record(record) {
+            Err(e) => report_error(e),
+            Ok(()) => {}
+        }
+    }
+}
+
+/// The syslog facility of a `SyslogLogger`, identifying the kind of program
+/// logging.
+#[cfg(unix)]
+#[deriving(Copy, Clone, PartialEq, Show)]
+pub enum Facility {
+    /// A user-level program.
+    User = 1,
+    /// A system daemon.
+    Daemon = 3,
+    /// Reserved for local use.
+    Local0 = 16,
+    /// Reserved for local use.
+    Local1 = 17,
+    /// Reserved for local use.
+    Local2 = 18,
+    /// Reserved for local use.
+    Local3 = 19,
+    /// Reserved for local use.
+    Local4 = 20,
+    /// Reserved for local use.
+    Local5 = 21,
+    /// Reserved for local use.
+    Local6 = 22,
+    /// Reserved for local use.
+    Local7 = 23,
+}
+
+/// A logger which sends each record to the local syslog daemon as a datagram
+/// over a Unix socket.
+///
+/// Messages have the form `<PRI>tag[pid]: LEVEL:module: message key=value`,
+/// leaving the timestamp and host name to the daemon. Levels map to the
+/// severities err, warning, info and debug.
+///
+/// If a message can't be sent, as when the daemon has been restarted, the
+/// logger connects to the socket again and retries it once.
+#[cfg(unix)]
+pub struct SyslogLogger {
+    path: Path,
+    fd: libc::c_int,
+    tag: String,
+    facility: Facility,
+}
+
+#[cfg(unix)]
+impl SyslogLogger {
+    /// Connects to the syslog daemon at its usual socket, `/var/run/syslog`
+    /// on OS X and `/dev/log` elsewhere.
+    pub fn new(tag: &str, facility: Facility) -> IoResult<SyslogLogger> {
+        let path = if cfg!(target_os = "macos") { "/var/run/syslog" } else { "/dev/log" };
+        SyslogLogger::connect(&Path::new(path), tag, facility)
+    }
+
+    /// Connects to a syslog daemon receiving datagrams on the socket at `path`.
+    pub fn connect(path: &Path, tag: &str, facility: Facility) -> IoResult<SyslogLogger> {
+        let fd = try!(connect_unix_datagram(path));
+        Ok(SyslogLogger { path: path.clone(), fd: fd, tag: tag.to_string(), facility: facility })
+    }
+
+    // Replaces the socket with a new one connected to the same path.
+    fn reconnect(&mut self) -> IoResult<()> {
+        let fd = try!(connect_unix_datagram(&self.path));
+        unsafe { libc::close(self.fd); }
+        self.fd = fd;
+        Ok(())
+    }
+
+    fn send(&mut self, record: &LogRecord) -> IoResult<()> {
+        let LogLevel(level) = record.level;
+        let severity = match level {
+            0 | ERROR => 3,
+            WARN => 4,
+            INFO => 6,
+            _ => 7,
+        };
+        let priority = self.facility as u32 * 8 + severity;
+
+        let mut msg = Vec::new();
+        try!(write!(&mut msg, "<{}>{}[{}]: ", priority, self.tag, unsafe { libc::getpid() }));
+        try!(write_text(&mut msg, record, false));
+        // Drop the newline, which the daemon would keep.
+        msg.pop();
+
+        match send_datagram(self.fd, msg.as_slice()) {
+            Ok(()) => Ok(()),
+            Err(..) => {
+                try!(self.reconnect());
+                send_datagram(self.fd, msg.as_slice())
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Logger for SyslogLogger {
+    fn log(&mut self, record: &LogRecord) {
+        match self.send(record) {
+            Err(e) => report_error(e),
+            Ok(()) => {}
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for SyslogLogger {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd); }
+    }
+}
+
+#[cfg(unix)]
+fn connect_unix_datagram(path: &Path) -> IoResult<libc::c_int> {
+    let addr = try!(unix_addr(path));
+    let fd = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_DGRAM, 0) };
+    if fd == -1 {
+        return Err(IoError::last_error());
+    }
+    let ret = unsafe {
+        libc::connect(fd, &addr as *const _ as *const libc::sockaddr,
+                      mem::size_of::<libc::sockaddr_un>() as libc::socklen_t)
+    };
+    if ret == -1 {
+        let err = IoError::last_error();
+        unsafe { libc::close(fd); }
+        return Err(err);
+    }
+    Ok(fd)
+}
+
+#[cfg(unix)]
+fn send_datagram(fd: libc::c_int, msg: &[u8]) -> IoResult<()> {
+    let ret = unsafe {
+        libc::send(fd, msg.as_ptr() as *const libc::c_void, msg.len() as libc::size_t, 0)
+    };
+    if ret == -1 { Err(IoError::last_error()) } else { Ok(()) }
+}
+
+#[cfg(unix)]
+fn unix_addr(path: &Path) -> IoResult<libc::sockaddr_un> {
+    let path = path.as_vec();
+    let mut addr: libc::sockaddr_un = unsafe { mem::zeroed() };
+    // Leave room for the terminating null.
+    if path.len() >= addr.sun_path.len() {
+        return Err(IoError {
+            kind: io::InvalidInput,
+            desc: "invalid argument: path must be smaller than SUN_LEN",
+            detail: None,
+        });
+    }
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    for (slot, &b) in addr.sun_path.iter_mut().zip(path.iter()) {
+        *slot = b as libc::c_char;
+    }
+    Ok(addr)
+}
+
+/// Writes `record` as a line of text, as done by `TextLogger` and the
+/// default logger.
+pub fn write_text(w: &mut Writer, record: &LogRecord, timestamp: bool) -> IoResult<()> {
+    if timestamp {
+        try!(write_timestamp(w, record.time));
+        try!(w.write_str(" "));
+    }
+    try!(write!(w, "{}:{}: {}", record.level, record.module_path, record.args));
+    for &(ref key, ref value) in record.context.iter() {
+        try!(write_text_pair(w, key.as_slice(), value.as_slice()));
+    }
+    for field in record.fields.iter() {
+        try!(write_text_pair(w, field.key, field.value.to_string().as_slice()));
+    }
+    w.write_str("\n")
+}
+
+fn write_text_pair(w: &mut Writer, key: &str, value: &str) -> IoResult<()> {
+    let quote = value.is_empty() || value.chars().any(|c| {
+        c.is_whitespace() || c.is_control() || c == '"' || c == '='
+    });
+    if quote {
+        try!(write!(w, " {}=\"", key));
+        for c in value.chars() {
+            match c {
+                '"' => try!(w.write_str("\\\"")),
+                '\\' => try!(w.write_str("\\\\")),
+                '\n' => try!(w.write_str("\\n")),
+                '\r' => try!(w.write_str("\\r")),
+                '\t' => try!(w.write_str("\\t")),
+                c if c.is_control() => try!(write!(w, "\\u{:04x}", c as u32)),
+                c => try!(w.write_char(c)),
+            }
+        }
+        w.write_str("\"")
+    } else {
+        write!(w, " {}={}", key, value)
+    }
+}
+
+fn write_json(w: &mut Writer, record: &LogRecord) -> IoResult<()> {
+    try!(w.write_str("{\"time\":\""));
+    try!(write_timestamp(w, record.time));
+    try!(w.write_str("\",\"level\":"));
+    try!(write_json_str(w, record.level.to_string().as_slice()));
+    try!(w.write_str(",\"module\":"));
+    try!(write_json_str(w, record.module_path));
+    try!(w.write_str(",\"file\":"));
+    try!(write_json_str(w, record.file));
+    try!(write!(w, ",\"line\":{},\"message\":", record.line));
+    try!(write_json_str(w, record.args.to_string().as_slice()));
+    for &(ref key, ref value) in record.context.iter() {
+        try!(write_json_pair(w, key.as_slice(), value.as_slice()));
+    }
+    for field in record.fields.iter() {
+        try!(write_json_pair(w, field.key, field.value.to_string().as_slice()));
+    }
+    try!(w.write_str("}\n"));
+    w.flush()
+}
+
+fn write_json_pair(w: &mut Writer, key: &str, value: &str) -> IoResult<()> {
+    try!(w.write_str(","));
+    try!(write_json_str(w, key));
+    try!(w.write_str(":"));
+    write_json_str(w, value)
+}
+
+fn write_json_str(w: &mut Writer, s: &str) -> IoResult<()> {
+    try!(w.write_str("\""));
+    for c in s.chars() {
+        match c {
+            '"' => try!(w.write_str("\\\"")),
+            '\\' => try!(w.write_str("\\\\")),
+            '\n' => try!(w.write_str("\\n")),
+            '\r' => try!(w.write_str("\\r")),
+            '\t' => try!(w.write_str("\\t")),
+            c if c < ' ' => try!(write!(w, "\\u{:04x}", c as u32)),
+            c => try!(w.write_char(c)),
+        }
+    }
+    w.write_str("\"")
+}
+
+/// Writes `time` in UTC as `YYYY-MM-DDTHH:MM:SS.ssssssZ`.
+pub fn write_timestamp(w: &mut Writer, time: SystemTime) -> IoResult<()> {
+    let since_epoch = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d,
+        Err(e) => -e.duration(),
+    };
+    let mut secs = since_epoch.num_seconds();
+    let mut nanos = (since_epoch - Duration::seconds(secs)).num_nanoseconds().unwrap();
+    if nanos < 0 {
+        secs -= 1;
+        nanos += 1_000_000_000;
+    }
+
+    let days = div_floor(secs, 86400);
+    let secs_of_day = secs - days * 86400;
+    let (year, month, mday) = civil_from_days(days);
+    write!(w, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+           year, month, mday,
+           secs_of_day / 3600, secs_of_day / 60 % 60, secs_of_day % 60,
+           nanos / 1000)
+}
+
+fn div_floor(a: i64, b: i64) -> i64 {
+    let q = a / b;
+    if (a % b != 0) && ((a < 0) != (b < 0)) { q - 1 } else { q }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JsonLogger, RotatingFileLogger, TextLogger, write_timestamp};
+    use std::io::fs::PathExtensions;
+    use std::io::{BufferedWriter, File, MemWriter, TempDir};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use {Logger, LogField, LogLevel, LogRecord, INFO};
+
+    fn timestamp(secs: i64, nanos: i64) -> String {
+        let mut w = MemWriter::new();
+        let time = UNIX_EPOCH + Duration::seconds(secs) + Duration::nanoseconds(nanos);
+        write_timestamp(&mut w, time).unwrap();
+        String::from_utf8(w.into_inner()).unwrap()
+    }
+
+    fn log_to<L: Logger>(logger: &mut L, context: &[(String, String)]) {
+        let id = 42u;
+        let name = "a \"quoted\" name";
+        let fields = [LogField { key: "id", value: &id },
+                      LogField { key: "name", value: &name }];
+        format_args!(|args| {
+            logger.log(&LogRecord {
+                module_path: "app::server",
+                level: LogLevel(INFO),
+                args: args,
+                file: "server.rs",
+                line: 12,
+                fields: &fields,
+                context: context,
+                time: UNIX_EPOCH + Duration::seconds(1418601600),
+            })
+        }, "served {}", "/index")
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(timestamp(0, 0).as_slice(), "1970-01-01T00:00:00.000000Z");
+        assert_eq!(timestamp(1418601600, 1_500).as_slice(), "2014-12-15T00:00:00.000001Z");
+        assert_eq!(timestamp(951825599, 0).as_slice(), "2000-02-29T11:59:59.000000Z");
+        assert_eq!(timestamp(-1, 500_000_000).as_slice(), "1969-12-31T23:59:59.500000Z");
+    }
+
+    #[test]
+    fn text() {
+        let mut logger = TextLogger::new(MemWriter::new());
+        log_to(&mut logger, &[("request".to_string(), "r1".to_string())]);
+        let out = String::from_utf8(logger.into_inner().into_inner()).unwrap();
+        assert_eq!(out.as_slice(),
+                   "INFO:app::server: served /index request=r1 id=42 \
+                    name=\"a \\\"quoted\\\" name\"\n");
+
+        let mut logger = TextLogger::new(MemWriter::new()).timestamps(true);
+        log_to(&mut logger, &[]);
+        let out = String::from_utf8(logger.into_inner().into_inner()).unwrap();
+        assert!(out.as_slice().starts_with("2014-12-15T00:00:00.000000Z INFO:app::server: "));
+
+        let mut logger = TextLogger::new(BufferedWriter::new(MemWriter::new()));
+        log_to(&mut logger, &[("request".to_string(), "a\r\tb\x01\x7f".to_string())]);
+        let out = String::from_utf8(logger.into_inner().get_ref().get_ref().to_vec()).unwrap();
+        assert_eq!(out.as_slice(),
+                   "INFO:app::server: served /index request=\"a\\r\\tb\\u0001\\u007f\" \
+                    id=42 name=\"a \\\"quoted\\\" name\"\n");
+    }
+
+    #[test]
+    fn json() {
+        let mut logger = JsonLogger::new(MemWriter::new());
+        log_to(&mut logger, &[("request".to_string(), "r\n1".to_string())]);
+        let out = String::from_utf8(logger.into_inner().into_inner()).unwrap();
+        assert_eq!(out.as_slice(),
+                   "{\"time\":\"2014-12-15T00:00:00.000000Z\",\"level\":\"INFO\",\
+                    \"module\":\"app::server\",\"file\":\"server.rs\",\"line\":12,\
+                    \"message\":\"served /index\",\"request\":\"r\\n1\",\"id\":\"42\",\
+                    \"name\":\"a \\\"quoted\\\" name\"}\n");
+    }
+
+    #[test]
+    fn rotating_file() {
+        let dir = TempDir::new("liblog").unwrap();
+        let path = dir.path().join("app.log");
+        let rotated = |n: uint| dir.path().join(format!("app.log.{}", n));
+
+        // Each line is a little shorter than half the maximum size, so two
+        // lines fit in a file.
+        let mut logger = RotatingFileLogger::new(&path, 200, 2).unwrap();
+        for _ in range(0u, 7) {
+            log_to(&mut logger, &[]);
+        }
+        drop(logger);
+
+        let contents = File::open(&path).read_to_string().unwrap();
+        assert_eq!(contents.as_slice().lines().count(), 1);
+        for n in range(1u, 3) {
+            let contents = File::open(&rotated(n)).read_to_string().unwrap();
+            assert_eq!(contents.as_slice().lines().count(), 2);
+        }
+        assert!(!rotated(3).exists());
+
+        // Without old files the log is truncated instead, and an existing
+        // file counts towards the maximum size.
+        let mut logger = RotatingFileLogger::new(&path, 100, 0).unwrap();
+        log_to(&mut logger, &[]);
+        drop(logger);
+        let contents = File::open(&path).read_to_string().unwrap();
+        assert_eq!(contents.as_slice().lines().count(), 1);
+        assert!(contents.as_slice().contains(" INFO:app::server: served /index "));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn syslog() {
+        use super::{Facility, SyslogLogger, unix_addr};
+        use libc;
+        use std::io::fs;
+        use std::mem;
+
+        // Binds a datagram socket at `path`, as the daemon does.
+        fn bind(path: &Path) -> libc::c_int {
+            let addr = unix_addr(path).unwrap();
+            let fd = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_DGRAM, 0) };
+            assert!(fd != -1);
+            let ret = unsafe {
+                libc::bind(fd, &addr as *const _ as *const libc::sockaddr,
+                           mem::size_of::<libc::sockaddr_un>() as libc::socklen_t)
+            };
+            assert!(ret != -1);
+            fd
+        }
+
+        fn recv(fd: libc::c_int) -> String {
+            let mut buf = [0u8, ..512];
+            let n = unsafe {
+                libc::recv(fd, buf.as_mut_ptr() as *mut libc::c_void,
+                           buf.len() as libc::size_t, 0)
+            };
+            assert!(n > 0);
+            String::from_utf8(buf.slice_to(n as uint).to_vec()).unwrap()
+        }
+
+        let dir = TempDir::new("liblog").unwrap();
+        let path = dir.path().join("log");
+        let fd = bind(&path);
+
+        let mut logger = SyslogLogger::connect(&path, "app", Facility::Local0).unwrap();
+        log_to(&mut logger, &[]);
+        let expected = format!("<134>app[{}]: INFO:app::server: served /index id=42 \
+                                name=\"a \\\"quoted\\\" name\"", unsafe { libc::getpid() });
+        assert_eq!(recv(fd), expected);
+
+        // The daemon restarts, and the logger connects to its new socket.
+        unsafe { libc::close(fd); }
+        fs::unlink(&path).unwrap();
+        let fd = bind(&path);
+        log_to(&mut logger, &[]);
+        assert_eq!(recv(fd), expected);
+        unsafe { libc::close(fd); }
+    }
+}
@@ -50,8 +50,43 @@
 /// WARN:main: this is a warning message
 /// 6:main: this is a custom logging level: 6
 /// ```
+///
+/// Structured fields can be attached to the log line by listing them in braces
+/// before the format string. Each value is formatted with `Show` only if the
+/// line is logged, and is then available to the logger by its key:
+///
+/// ```
+/// #![feature(phase)]
+/// #[phase(plugin, link)] extern crate log;
+///
+/// fn main() {
+///     let (user, elapsed) = ("alice", 12u);
+///     log!(log::INFO, { user = user, elapsed_ms = elapsed }, "request served");
+/// }
+/// ```
+///
+/// ```{.bash}
+/// $ RUST_LOG=info ./main
+/// INFO:main: request served user=alice elapsed_ms=12
+/// ```
 #[macro_export]
 macro_rules! log {
+    ($lvl:expr, { $($key:ident = $value:expr),+ }, $($arg:tt)+) => ({
+        static LOC: ::log::LogLocation = ::log::LogLocation {
+            line: line!(),
+            file: file!(),
+            module_path: module_path!(),
+        };
+        let lvl = $lvl;
+        if log_enabled!(lvl) {
+            format_args!(|args| {
+                ::log::log_with_fields(lvl, &LOC, &[$(::log::LogField {
+                    key: stringify!($key),
+                    value: &$value as &::std::fmt::Show,
+                }),+], args)
+            }, $($arg)+)
+        }
+    });
     ($lvl:expr, $($arg:tt)+) => ({
         static LOC: ::log::LogLocation = ::log::LogLocation {
             line: line!(),
@@ -136,6 +171,9 @@ macro_rules! warn {
 /// $ RUST_LOG=info ./main
 /// INFO:main: this function is about to return: 3
 /// ```
+///
+/// Like `log!`, this macro accepts structured fields in braces before the
+/// format string, as in `info!({ user = name }, "logged in")`.
 #[macro_export]
 macro_rules! info {
     ($($arg:tt)*) => (log!(::log::INFO, $($arg)*))
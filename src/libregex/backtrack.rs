@@ -0,0 +1,376 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// A backtracking engine for programs with look-around assertions or
+// backreferences, which neither the NFA nor the DFA can run.
+//
+// At each starting position, it follows one path through the program at a
+// time, and when a path fails, it resumes the most recent alternative it
+// skipped. Alternatives are tried in the same order in which the NFA ranks
+// its threads, so the first match found is the leftmost-first match.
+//
+// Unlike the NFA, this can take time exponential in the length of the text,
+// so every search is limited to a number of steps (one per instruction
+// executed), after which it gives up with an error instead.
+
+use std::collections::HashSet;
+use std::fmt;
+use compile::{
+    Program,
+    Match, SetMatch, OneChar, CharClass, Any, EmptyBegin, EmptyEnd, EmptyWordBoundary,
+    Save, Jump, Split, EmptyLook, LookMatch, Backreference,
+};
+use parse::{FLAG_NOCASE, FLAG_MULTI, FLAG_DOTNL, FLAG_NEGATED};
+use vm;
+use vm::{CaptureLocs, CharReader, MatchKind, Exists, Location, Submatches};
+
+use self::Dir::*;
+use self::Job::*;
+
+/// The number of steps a search may take by default before it gives up.
+pub const DEFAULT_LIMIT: uint = 1_000_000;
+
+/// The error returned when a search with the backtracking engine takes more
+/// steps than its limit allows.
+///
+/// This usually means that the expression backtracks catastrophically on the
+/// text searched, as `(a+)+b` does on a long run of `a`s.
+#[deriving(Clone, PartialEq)]
+pub struct LimitError {
+    /// The number of steps allowed.
+    pub limit: uint,
+    /// The byte index at which the match being tried would have started.
+    pub pos: uint,
+}
+
+impl fmt::Show for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Regex search gave up after {} backtracking steps while \
+                   trying a match at position {}. The expression may \
+                   backtrack catastrophically on this text.",
+               self.limit, self.pos)
+    }
+}
+
+/// Runs the backtracking engine on the search text `input`, exactly like
+/// `vm::run_bytes`, except that it gives up with an error once it has
+/// executed `limit` instructions.
+pub fn run(which: MatchKind, prog: &Program, input: &[u8], unicode: bool,
+           start: uint, end: uint, limit: uint)
+          -> Result<CaptureLocs, LimitError> {
+    if !vm::has_suffix(prog, input, start) {
+        return Ok(vec![None, None])
+    }
+    let mut bt = Backtrack {
+        prog: prog,
+        input: input,
+        unicode: unicode,
+        chars: CharReader::from_bytes(input, unicode),
+        limit: limit,
+        steps: 0,
+        start: start,
+        caps: Vec::from_elem(prog.num_captures() * 2, None),
+        jobs: vec![],
+        visiting: HashSet::new(),
+    };
+    // Just like the NFA, don't bother trying other starting positions if
+    // the expression starts with a '^'.
+    let anchored = match prog.insts[1] {
+        EmptyBegin(flags) if flags & FLAG_MULTI == 0 => true,
+        _ => false,
+    };
+    let mut s = start;
+    while s <= end {
+        if !prog.prefixes.is_empty() {
+            match prog.prefixes.find(input[s..]) {
+                None => break,
+                Some(i) => s += i,
+            }
+        }
+        bt.start = s;
+        for cap in bt.caps.iter_mut() {
+            *cap = None;
+        }
+        if try!(bt.try_at(0, s, Forward)) {
+            let caps = bt.caps;
+            return Ok(match which {
+                Exists => vec![Some(0), Some(0)],
+                Location => vec![caps[0], caps[1]],
+                Submatches => caps,
+            })
+        }
+        if anchored {
+            break
+        }
+        s = match bt.read(s, Forward) {
+            None => s + 1,
+            Some((_, next)) => next,
+        };
+    }
+    Ok(vec![None, None])
+}
+
+/// The direction in which text is read. The body of a look-behind assertion
+/// reads backwards from the current position.
+#[deriving(Copy, PartialEq)]
+enum Dir {
+    Forward,
+    Backward,
+}
+
+/// Something to do when backtracking.
+enum Job {
+    /// Tries the path from an instruction at a position.
+    Step(uint, uint),
+    /// Restores the value of a capture slot.
+    RestoreCap(uint, Option<uint>),
+    /// Marks a `Split` instruction at a position as no longer being on the
+    /// current path.
+    Unvisit(uint, uint),
+}
+
+struct Backtrack<'r, 't> {
+    prog: &'r Program,
+    input: &'t [u8],
+    unicode: bool,
+    chars: CharReader<'t>,
+    limit: uint,
+    steps: uint,
+    // Where the match being tried begins.
+    start: uint,
+    caps: CaptureLocs,
+    jobs: Vec<Job>,
+    // The `Split` instructions (and the positions at which they were
+    // reached) on the current path. Reaching one again without having read
+    // anything means an empty loop, which can't lead anywhere new.
+    visiting: HashSet<(uint, uint)>,
+}
+
+impl<'r, 't> Backtrack<'r, 't> {
+    // Returns true if the instructions starting at `pc` match the text at
+    // `pos`, in which case the capture slots hold the locations of the first
+    // match found. Otherwise, they are left unchanged.
+    fn try_at(&mut self, pc: uint, pos: uint, dir: Dir)
+             -> Result<bool, LimitError> {
+        let base = self.jobs.len();
+        self.jobs.push(Step(pc, pos));
+        while self.jobs.len() > base {
+            match self.jobs.pop().unwrap() {
+                Step(pc, pos) => {
+                    if try!(self.step(pc, pos, dir)) {
+                        // Drop the alternatives that weren't needed, but keep
+                        // the capture locations of this match.
+                        while self.jobs.len() > base {
+                            match self.jobs.pop().unwrap() {
+                                Unvisit(pc, pos) => {
+                                    self.visiting.remove(&(pc, pos));
+                                }
+                                Step(_, _) | RestoreCap(_, _) => {}
+                            }
+                        }
+                        return Ok(true)
+                    }
+                }
+                RestoreCap(slot, old) => self.caps[slot] = old,
+                Unvisit(pc, pos) => {
+                    self.visiting.remove(&(pc, pos));
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    // Follows a single path from the instruction at `pc`, pushing the
+    // alternatives it skips, until it matches or fails.
+    fn step(&mut self, mut pc: uint, mut pos: uint, dir: Dir)
+           -> Result<bool, LimitError> {
+        let prog = self.prog;
+        loop {
+            self.steps += 1;
+            if self.steps > self.limit {
+                return Err(LimitError { limit: self.limit, pos: self.start })
+            }
+            match prog.insts[pc] {
+                Match | LookMatch => return Ok(true),
+                SetMatch(_) => {
+                    panic!("BUG: Instruction can't be run by the \
+                            backtracking engine.")
+                }
+                OneChar(c, flags) => {
+                    match self.read(pos, dir) {
+                        Some((textc, next))
+                                if char_eq(flags & FLAG_NOCASE > 0, textc, c) => {
+                            pc += 1;
+                            pos = next;
+                        }
+                        _ => return Ok(false),
+                    }
+                }
+                CharClass(ref ranges, flags) => {
+                    let (c, next) = match self.read(pos, dir) {
+                        None => return Ok(false),
+                        Some(x) => x,
+                    };
+                    let negate = flags & FLAG_NEGATED > 0;
+                    let casei = flags & FLAG_NOCASE > 0;
+                    let found = ranges.as_slice();
+                    let found = found.binary_search(|&rc| vm::class_cmp(casei, c, rc))
+                        .found().is_some();
+                    if found == negate {
+                        return Ok(false)
+                    }
+                    pc += 1;
+                    pos = next;
+                }
+                Any(flags) => {
+                    match self.read(pos, dir) {
+                        Some((c, next)) if flags & FLAG_DOTNL > 0 || c != '\n' => {
+                            pc += 1;
+                            pos = next;
+                        }
+                        _ => return Ok(false),
+                    }
+                }
+                EmptyBegin(flags) => {
+                    self.chars.set(pos);
+                    let multi = flags & FLAG_MULTI > 0;
+                    if !self.chars.is_begin()
+                       && !(multi && self.chars.prev == Some('\n')) {
+                        return Ok(false)
+                    }
+                    pc += 1;
+                }
+                EmptyEnd(flags) => {
+                    self.chars.set(pos);
+                    let multi = flags & FLAG_MULTI > 0;
+                    if !self.chars.is_end()
+                       && !(multi && self.chars.cur == Some('\n')) {
+                        return Ok(false)
+                    }
+                    pc += 1;
+                }
+                EmptyWordBoundary(flags) => {
+                    self.chars.set(pos);
+                    let negate = flags & FLAG_NEGATED > 0;
+                    if self.chars.is_word_boundary() == negate {
+                        return Ok(false)
+                    }
+                    pc += 1;
+                }
+                Save(slot) => {
+                    self.jobs.push(RestoreCap(slot, self.caps[slot]));
+                    self.caps[slot] = Some(pos);
+                    pc += 1;
+                }
+                Jump(to) => pc = to,
+                Split(x, y) => {
+                    if !self.visiting.insert((pc, pos)) {
+                        return Ok(false)
+                    }
+                    self.jobs.push(Unvisit(pc, pos));
+                    self.jobs.push(Step(y, pos));
+                    pc = x;
+                }
+                EmptyLook(kind, next) => {
+                    // Look-around assertions are atomic: once one has
+                    // succeeded, other ways of matching it aren't tried.
+                    let saved = self.caps.clone();
+                    let look_dir = if kind.is_behind() { Backward } else { Forward };
+                    let matched = try!(self.try_at(pc + 1, pos, look_dir));
+                    if matched == kind.is_negated() {
+                        self.caps = saved;
+                        return Ok(false)
+                    }
+                    // Groups matched inside a positive look-around keep their
+                    // locations, until this path is backtracked over.
+                    for (slot, old) in saved.into_iter().enumerate() {
+                        if self.caps[slot] != old {
+                            self.jobs.push(RestoreCap(slot, old));
+                        }
+                    }
+                    pc = next;
+                }
+                Backreference(cap, flags) => {
+                    let casei = flags & FLAG_NOCASE > 0;
+                    match (self.caps[2 * cap], self.caps[2 * cap + 1]) {
+                        (Some(s), Some(e)) => {
+                            match self.backref(s, e, pos, dir, casei) {
+                                None => return Ok(false),
+                                Some(next) => {
+                                    pc += 1;
+                                    pos = next;
+                                }
+                            }
+                        }
+                        // A group that hasn't matched anything doesn't match
+                        // anything when referred to either.
+                        _ => return Ok(false),
+                    }
+                }
+            }
+        }
+    }
+
+    // Reads the character after `pos` (or before it, when reading
+    // backwards), and returns it along with the position on its other side.
+    fn read(&self, pos: uint, dir: Dir) -> Option<(char, uint)> {
+        match dir {
+            Forward if pos < self.input.len() => {
+                if self.unicode {
+                    Some(vm::decode_utf8(self.input, pos))
+                } else {
+                    Some((self.input[pos] as char, pos + 1))
+                }
+            }
+            Backward if pos > 0 => {
+                if self.unicode {
+                    Some(vm::decode_last_utf8(self.input, pos))
+                } else {
+                    Some((self.input[pos - 1] as char, pos - 1))
+                }
+            }
+            Forward | Backward => None,
+        }
+    }
+
+    // Returns the position on the other side of the text at `pos` that is the
+    // same as the text between `s` and `e`, if there is such text.
+    fn backref(&self, s: uint, e: uint, pos: uint, dir: Dir,
+               casei: bool) -> Option<uint> {
+        let len = e - s;
+        if !casei {
+            return match dir {
+                Forward if self.input[pos..].starts_with(self.input[s..e]) => {
+                    Some(pos + len)
+                }
+                Backward if self.input[..pos].ends_with(self.input[s..e]) => {
+                    Some(pos - len)
+                }
+                Forward | Backward => None,
+            }
+        }
+        let (mut i, mut pos) = (if dir == Forward { s } else { e }, pos);
+        while (dir == Forward && i < e) || (dir == Backward && i > s) {
+            let (c, next_i) = self.read(i, dir).unwrap();
+            match self.read(pos, dir) {
+                Some((textc, next)) if char_eq(true, textc, c) => pos = next,
+                _ => return None,
+            }
+            i = next_i;
+        }
+        Some(pos)
+    }
+}
+
+// See the FIXME for `char_eq` in vm.rs about case folding.
+#[inline]
+fn char_eq(casei: bool, textc: char, regc: char) -> bool {
+    regc == textc || (casei && regc.to_uppercase() == textc.to_uppercase())
+}
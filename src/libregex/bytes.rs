@@ -0,0 +1,934 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Regular expressions for searching arbitrary bytes.
+//!
+//! The `Regex` type in this module has the same interface as `regex::Regex`,
+//! except that it searches `&[u8]` instead of `&str`. This makes it possible
+//! to search binary data or text in an unknown encoding without converting
+//! it first.
+//!
+//! By default, Unicode mode is enabled: the bytes are decoded as UTF-8 and
+//! the expression is matched against the characters they encode. Bytes that
+//! are not part of a valid UTF-8 sequence are each read as a single `U+FFFD`
+//! (REPLACEMENT CHARACTER), so for example `.` still matches them, but they
+//! never match the text of the expression literally.
+//!
+//! Unicode mode can be disabled for an entire expression by starting it with
+//! `(?-u)`. Then every byte is matched on its own: `.` matches any byte
+//! except `\n`, `\xFF` matches the byte `0xFF` and negated classes like
+//! `[^a]` match any byte except `a`. In this mode, `\d`, `\s`, `\w` and `\b`
+//! only consider ASCII characters, Unicode classes like `\pN` are not allowed
+//! and every character in the expression must be in the range `\x00-\xFF`.
+//!
+//! # Example
+//!
+//! Find every run of non-ASCII bytes in some binary data:
+//!
+//! ```rust
+//! use regex::bytes::Regex;
+//! let re = Regex::new(r"(?-u)[\x80-\xFF]+").unwrap();
+//! let data = b"GIF89a\x01\x00\x01\x00\x80\xff\x00";
+//! assert_eq!(re.find(data), Some((10, 12)));
+//! ```
+//!
+//! # Searching a stream
+//!
+//! `find_iter_reader` and `captures_iter_reader` search the bytes read from
+//! any `Reader` (including a `Buffer`) incrementally, without reading all of
+//! them first. Matches may span the blocks in which the bytes are read, and
+//! all assertions, like `^` and `$` in multi-line mode, behave exactly as if
+//! the whole stream were searched at once. Offsets are counted in bytes from
+//! the start of the stream.
+//!
+//! Only the bytes from where the next match could begin are kept, so memory
+//! use doesn't grow with the size of the stream, only with the length of the
+//! longest match (or of the longest text that could still turn out to be
+//! part of a match).
+//!
+//! ```rust
+//! use std::io::MemReader;
+//! use regex::bytes::Regex;
+//!
+//! let re = Regex::new(r"(?m)^ERROR: (.+)$").unwrap();
+//! let log = MemReader::new(b"INFO: started\nERROR: disk full\n".to_vec());
+//! for caps in re.captures_iter_reader(log) {
+//!     let caps = caps.unwrap();
+//!     assert_eq!(caps.pos(0), Some((14, 30)));
+//!     assert_eq!(caps.at(1), Some(b"disk full"));
+//! }
+//! ```
+
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{IoResult, EndOfFile};
+use std::str;
+use std::vec::CowVec;
+
+use compile::Program;
+use dfa::DfaExec;
+use parse;
+use vm;
+use vm::{CaptureLocs, MatchKind, Exists, Location, Submatches};
+use vm::{Final, NeedMore};
+
+static EMPTY: &'static [u8] = &[];
+
+/// The most bytes requested from a `Reader` at once when searching a stream.
+const READ_SIZE: uint = 64 * 1024;
+
+/// A compiled regular expression for searching arbitrary bytes.
+///
+/// It can be used to search, split or replace sequences of bytes. All
+/// searching is done with an implicit `.*?` at the beginning and end of an
+/// expression, exactly as with `regex::Regex`. All positions returned are
+/// byte indices into the bytes searched.
+///
+/// See the module documentation for how Unicode mode affects matching.
+#[deriving(Clone)]
+pub struct Regex {
+    original: String,
+    names: Vec<Option<String>>,
+    prog: Program,
+    dfa: DfaExec,
+    unicode: bool,
+}
+
+impl fmt::Show for Regex {
+    /// Shows the original regular expression.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Regex {
+    /// Compiles a regular expression for searching bytes. Once compiled, it
+    /// can be used repeatedly to search, split or replace bytes.
+    ///
+    /// If an invalid expression is given, then an error is returned.
+    pub fn new(re: &str) -> Result<Regex, parse::Error> {
+        let (ast, unicode) = try!(parse::parse_bytes(re));
+        let rprog = Program::new_reverse(ast.clone());
+        let (prog, names) = Program::with_encoding(ast, unicode);
+        Ok(Regex {
+            original: re.to_string(),
+            names: names,
+            dfa: DfaExec::new(&prog, rprog),
+            prog: prog,
+            unicode: unicode,
+        })
+    }
+
+    /// Returns true if and only if the regex matches the bytes given.
+    pub fn is_match(&self, text: &[u8]) -> bool {
+        has_match(&self.exec(Exists, text, 0, text.len()))
+    }
+
+    /// Returns the start and end byte range of the leftmost-first match in
+    /// `text`. If no match exists, then `None` is returned.
+    pub fn find(&self, text: &[u8]) -> Option<(uint, uint)> {
+        let caps = self.exec(Location, text, 0, text.len());
+        if has_match(&caps) {
+            Some((caps[0].unwrap(), caps[1].unwrap()))
+        } else {
+            None
+        }
+    }
+
+    /// Returns an iterator for each successive non-overlapping match in
+    /// `text`, returning the start and end byte indices with respect to
+    /// `text`.
+    pub fn find_iter<'r, 't>(&'r self, text: &'t [u8]) -> FindMatches<'r, 't> {
+        FindMatches {
+            re: self,
+            search: text,
+            last_end: 0,
+            last_match: None,
+        }
+    }
+
+    /// Returns the capture groups corresponding to the leftmost-first
+    /// match in `text`. Capture group `0` always corresponds to the entire
+    /// match. If no match is found, then `None` is returned.
+    pub fn captures<'t>(&self, text: &'t [u8]) -> Option<Captures<'t>> {
+        let caps = self.exec(Submatches, text, 0, text.len());
+        Captures::new(self, text, caps)
+    }
+
+    /// Returns an iterator over all the non-overlapping capture groups matched
+    /// in `text`. This is operationally the same as `find_iter` (except it
+    /// yields information about submatches).
+    pub fn captures_iter<'r, 't>(&'r self, text: &'t [u8])
+                                -> FindCaptures<'r, 't> {
+        FindCaptures {
+            re: self,
+            search: text,
+            last_match: None,
+            last_end: 0,
+        }
+    }
+
+    /// Returns an iterator for each successive non-overlapping match in the
+    /// bytes read from `rdr`, returning the start and end byte offsets with
+    /// respect to the start of the stream.
+    ///
+    /// The matches are exactly those that `find_iter` would find if it were
+    /// given all of the bytes at once. If reading fails, then the error is
+    /// yielded and the iteration stops.
+    pub fn find_iter_reader<'r, R: Reader>(&'r self, rdr: R)
+                                          -> FindReaderMatches<'r, R> {
+        FindReaderMatches { search: ReaderSearch::new(self, rdr) }
+    }
+
+    /// Returns an iterator over all the non-overlapping capture groups
+    /// matched in the bytes read from `rdr`. This is operationally the same
+    /// as `find_iter_reader` (except it yields copies of the bytes matched
+    /// by each capture group).
+    pub fn captures_iter_reader<'r, R: Reader>(&'r self, rdr: R)
+                                              -> FindReaderCaptures<'r, R> {
+        FindReaderCaptures { search: ReaderSearch::new(self, rdr) }
+    }
+
+    /// Returns an iterator of subslices of `text` delimited by a match
+    /// of the regular expression.
+    ///
+    /// This method will *not* copy the bytes given.
+    pub fn split<'r, 't>(&'r self, text: &'t [u8]) -> RegexSplits<'r, 't> {
+        RegexSplits {
+            finder: self.find_iter(text),
+            last: 0,
+        }
+    }
+
+    /// Returns an iterator of at most `limit` subslices of `text` delimited
+    /// by a match of the regular expression. (A `limit` of `0` will return no
+    /// subslices.) The remainder of the bytes that are not split will be the
+    /// last element in the iterator.
+    ///
+    /// This method will *not* copy the bytes given.
+    pub fn splitn<'r, 't>(&'r self, text: &'t [u8], limit: uint)
+                         -> RegexSplitsN<'r, 't> {
+        RegexSplitsN {
+            splits: self.split(text),
+            cur: 0,
+            limit: limit,
+        }
+    }
+
+    /// Replaces the leftmost-first match with the replacement provided.
+    /// The replacement can be a byte string (where `$N` and `$name` are
+    /// expanded to match capture groups) or a function that takes the matches'
+    /// `Captures` and returns the replaced bytes.
+    ///
+    /// If no match is found, then a copy of the bytes is returned unchanged.
+    pub fn replace<R: Replacer>(&self, text: &[u8], rep: R) -> Vec<u8> {
+        self.replacen(text, 1, rep)
+    }
+
+    /// Replaces all non-overlapping matches in `text` with the
+    /// replacement provided. This is the same as calling `replacen` with
+    /// `limit` set to `0`.
+    pub fn replace_all<R: Replacer>(&self, text: &[u8], rep: R) -> Vec<u8> {
+        self.replacen(text, 0, rep)
+    }
+
+    /// Replaces at most `limit` non-overlapping matches in `text` with the
+    /// replacement provided. If `limit` is 0, then all non-overlapping matches
+    /// are replaced.
+    pub fn replacen<R: Replacer>
+                   (&self, text: &[u8], limit: uint, mut rep: R) -> Vec<u8> {
+        let mut new = Vec::with_capacity(text.len());
+        let mut last_match = 0u;
+
+        for (i, cap) in self.captures_iter(text).enumerate() {
+            if limit > 0 && i >= limit {
+                break
+            }
+
+            let (s, e) = cap.pos(0).unwrap(); // captures only reports matches
+            new.push_all(text[last_match..s]);
+            new.push_all(&*rep.reg_replace(&cap));
+            last_match = e;
+        }
+        new.push_all(text[last_match..]);
+        new
+    }
+
+    /// Returns the original string of this regex.
+    pub fn as_str<'a>(&'a self) -> &'a str {
+        self.original.as_slice()
+    }
+
+    fn exec(&self, which: MatchKind, input: &[u8],
+            s: uint, e: uint) -> CaptureLocs {
+        self.dfa.exec(which, &self.prog, input, self.unicode, s, e)
+    }
+
+    // Returns the byte index of the position following `i`, which is always
+    // at a character boundary when Unicode mode is enabled.
+    fn next_position(&self, input: &[u8], i: uint) -> uint {
+        if self.unicode && i < input.len() {
+            vm::decode_utf8(input, i).1
+        } else {
+            i + 1
+        }
+    }
+}
+
+/// NoExpand indicates literal byte string replacement.
+///
+/// It can be used with `replace` and `replace_all` to do a literal
+/// replacement without expanding `$name` to their corresponding
+/// capture groups.
+///
+/// `'t` is the lifetime of the literal bytes.
+pub struct NoExpand<'t>(pub &'t [u8]);
+
+/// Replacer describes types that can be used to replace matches in a
+/// sequence of bytes.
+pub trait Replacer {
+    /// Returns possibly owned bytes that are used to replace the match
+    /// corresponding to the `caps` capture group.
+    ///
+    /// The `'a` lifetime refers to the lifetime of borrowed bytes when
+    /// new owned bytes aren't needed (e.g., for `NoExpand`).
+    fn reg_replace<'a>(&'a mut self, caps: &Captures) -> CowVec<'a, u8>;
+}
+
+impl<'t> Replacer for NoExpand<'t> {
+    fn reg_replace<'a>(&'a mut self, _: &Captures) -> CowVec<'a, u8> {
+        let NoExpand(s) = *self;
+        s.into_cow()
+    }
+}
+
+impl<'t> Replacer for &'t [u8] {
+    fn reg_replace<'a>(&'a mut self, caps: &Captures) -> CowVec<'a, u8> {
+        caps.expand(*self).into_cow()
+    }
+}
+
+impl<F> Replacer for F where F: FnMut(&Captures) -> Vec<u8> {
+    fn reg_replace<'a>(&'a mut self, caps: &Captures) -> CowVec<'a, u8> {
+        (*self)(caps).into_cow()
+    }
+}
+
+/// Yields all subslices delimited by a regular expression match.
+///
+/// `'r` is the lifetime of the compiled expression and `'t` is the lifetime
+/// of the bytes being split.
+pub struct RegexSplits<'r, 't> {
+    finder: FindMatches<'r, 't>,
+    last: uint,
+}
+
+impl<'r, 't> Iterator<&'t [u8]> for RegexSplits<'r, 't> {
+    fn next(&mut self) -> Option<&'t [u8]> {
+        let text = self.finder.search;
+        match self.finder.next() {
+            None => {
+                if self.last >= text.len() {
+                    None
+                } else {
+                    let s = text[self.last..];
+                    self.last = text.len();
+                    Some(s)
+                }
+            }
+            Some((s, e)) => {
+                let matched = text[self.last..s];
+                self.last = e;
+                Some(matched)
+            }
+        }
+    }
+}
+
+/// Yields at most `N` subslices delimited by a regular expression match.
+///
+/// The last subslice will be whatever remains after splitting.
+///
+/// `'r` is the lifetime of the compiled expression and `'t` is the lifetime
+/// of the bytes being split.
+pub struct RegexSplitsN<'r, 't> {
+    splits: RegexSplits<'r, 't>,
+    cur: uint,
+    limit: uint,
+}
+
+impl<'r, 't> Iterator<&'t [u8]> for RegexSplitsN<'r, 't> {
+    fn next(&mut self) -> Option<&'t [u8]> {
+        let text = self.splits.finder.search;
+        if self.cur >= self.limit {
+            None
+        } else {
+            self.cur += 1;
+            if self.cur >= self.limit {
+                Some(text[self.splits.last..])
+            } else {
+                self.splits.next()
+            }
+        }
+    }
+}
+
+/// Captures represents a group of captured byte strings for a single match.
+///
+/// The 0th capture always corresponds to the entire match. Each subsequent
+/// index corresponds to the next capture group in the regex.
+/// If a capture group is named, then the matched bytes are *also* available
+/// via the `name` method.
+///
+/// Positions returned from a capture group are always byte indices.
+///
+/// `'t` is the lifetime of the matched bytes.
+pub struct Captures<'t> {
+    text: &'t [u8],
+    locs: CaptureLocs,
+    named: Option<HashMap<String, uint>>,
+}
+
+impl<'t> Captures<'t> {
+    fn new(re: &Regex, search: &'t [u8], locs: CaptureLocs)
+          -> Option<Captures<'t>> {
+        if !has_match(&locs) {
+            return None
+        }
+
+        let named =
+            if re.names.len() == 0 {
+                None
+            } else {
+                let mut named = HashMap::new();
+                for (i, name) in re.names.iter().enumerate() {
+                    match *name {
+                        None => {},
+                        Some(ref name) => {
+                            named.insert(name.clone(), i);
+                        }
+                    }
+                }
+                Some(named)
+            };
+        Some(Captures {
+            text: search,
+            locs: locs,
+            named: named,
+        })
+    }
+
+    /// Returns the start and end positions of the Nth capture group.
+    /// Returns `None` if `i` is not a valid capture group or if the capture
+    /// group did not match anything.
+    pub fn pos(&self, i: uint) -> Option<(uint, uint)> {
+        let (s, e) = (i * 2, i * 2 + 1);
+        if e >= self.locs.len() || self.locs[s].is_none() {
+            // VM guarantees that each pair of locations are both Some or None.
+            return None
+        }
+        Some((self.locs[s].unwrap(), self.locs[e].unwrap()))
+    }
+
+    /// Returns the matched bytes for the capture group `i`. If `i` isn't
+    /// a valid capture group or didn't match anything, then `None` is
+    /// returned.
+    pub fn at(&self, i: uint) -> Option<&'t [u8]> {
+        match self.pos(i) {
+            None => None,
+            Some((s, e)) => Some(self.text[s..e])
+        }
+    }
+
+    /// Returns the matched bytes for the capture group named `name`. If
+    /// `name` isn't a valid capture group or didn't match anything, then
+    /// `None` is returned.
+    pub fn name(&self, name: &str) -> Option<&'t [u8]> {
+        match self.named {
+            None => None,
+            Some(ref h) => {
+                match h.get(name) {
+                    None => None,
+                    Some(i) => self.at(*i),
+                }
+            }
+        }
+    }
+
+    /// Creates an iterator of all the capture groups in order of appearance
+    /// in the regular expression.
+    pub fn iter(&'t self) -> SubCaptures<'t> {
+        SubCaptures { idx: 0, caps: self, }
+    }
+
+    /// Creates an iterator of all the capture group positions in order of
+    /// appearance in the regular expression. Positions are byte indices
+    /// in terms of the original bytes matched.
+    pub fn iter_pos(&'t self) -> SubCapturesPos<'t> {
+        SubCapturesPos { idx: 0, caps: self, }
+    }
+
+    /// Expands all instances of `$name` in `text` to the corresponding capture
+    /// group `name`.
+    ///
+    /// `name` may be an integer corresponding to the index of the
+    /// capture group (counted by order of opening parenthesis where `0` is the
+    /// entire match) or it can be a name (consisting of letters, digits or
+    /// underscores) corresponding to a named capture group.
+    ///
+    /// If `name` isn't a valid capture group (whether the name doesn't exist or
+    /// isn't a valid index), then it is replaced with nothing.
+    ///
+    /// To write a literal `$` use `$$`.
+    pub fn expand(&self, text: &[u8]) -> Vec<u8> {
+        let mut expanded = Vec::with_capacity(text.len());
+        let mut i = 0;
+        while i < text.len() {
+            if text[i] != b'$' {
+                expanded.push(text[i]);
+                i += 1;
+                continue
+            }
+            if i + 1 < text.len() && text[i + 1] == b'$' {
+                expanded.push(b'$');
+                i += 2;
+                continue
+            }
+            let start = i + 1;
+            let mut end = start;
+            while end < text.len() && is_valid_cap(text[end]) {
+                end += 1;
+            }
+            if end == start {
+                expanded.push(b'$');
+                i += 1;
+                continue
+            }
+            // Names only contain ASCII characters, so this can't fail.
+            let name = ::std::str::from_utf8(text[start..end]).unwrap();
+            let group = match from_str::<uint>(name) {
+                None => self.name(name),
+                Some(i) => self.at(i),
+            };
+            expanded.push_all(group.unwrap_or(EMPTY));
+            i = end;
+        }
+        expanded
+    }
+
+    /// Returns the number of captured groups.
+    #[inline]
+    pub fn len(&self) -> uint { self.locs.len() / 2 }
+
+    /// Returns if there are no captured groups.
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+}
+
+/// An iterator over capture groups for a particular match of a regular
+/// expression.
+///
+/// `'t` is the lifetime of the matched bytes.
+pub struct SubCaptures<'t> {
+    idx: uint,
+    caps: &'t Captures<'t>,
+}
+
+impl<'t> Iterator<&'t [u8]> for SubCaptures<'t> {
+    fn next(&mut self) -> Option<&'t [u8]> {
+        if self.idx < self.caps.len() {
+            self.idx += 1;
+            Some(self.caps.at(self.idx - 1).unwrap_or(EMPTY))
+        } else {
+            None
+        }
+    }
+}
+
+/// An iterator over capture group positions for a particular match of a
+/// regular expression.
+///
+/// Positions are byte indices in terms of the original bytes matched.
+///
+/// `'t` is the lifetime of the matched bytes.
+pub struct SubCapturesPos<'t> {
+    idx: uint,
+    caps: &'t Captures<'t>,
+}
+
+impl<'t> Iterator<Option<(uint, uint)>> for SubCapturesPos<'t> {
+    fn next(&mut self) -> Option<Option<(uint, uint)>> {
+        if self.idx < self.caps.len() {
+            self.idx += 1;
+            Some(self.caps.pos(self.idx - 1))
+        } else {
+            None
+        }
+    }
+}
+
+/// An iterator that yields all non-overlapping capture groups matching a
+/// particular regular expression.
+///
+/// The iterator stops when no more matches can be found.
+///
+/// `'r` is the lifetime of the compiled expression and `'t` is the lifetime
+/// of the matched bytes.
+pub struct FindCaptures<'r, 't> {
+    re: &'r Regex,
+    search: &'t [u8],
+    last_match: Option<uint>,
+    last_end: uint,
+}
+
+impl<'r, 't> Iterator<Captures<'t>> for FindCaptures<'r, 't> {
+    fn next(&mut self) -> Option<Captures<'t>> {
+        if self.last_end > self.search.len() {
+            return None
+        }
+
+        let caps = self.re.exec(Submatches, self.search,
+                                self.last_end, self.search.len());
+        let (s, e) =
+            if !has_match(&caps) {
+                return None
+            } else {
+                (caps[0].unwrap(), caps[1].unwrap())
+            };
+
+        // Don't accept empty matches immediately following a match.
+        // i.e., no infinite loops please.
+        if e == s && Some(self.last_end) == self.last_match {
+            self.last_end = self.re.next_position(self.search, self.last_end);
+            return self.next()
+        }
+        self.last_end = e;
+        self.last_match = Some(self.last_end);
+        Captures::new(self.re, self.search, caps)
+    }
+}
+
+/// An iterator over all non-overlapping matches for a particular sequence of
+/// bytes.
+///
+/// The iterator yields a tuple of integers corresponding to the start and end
+/// of the match. The indices are byte offsets. The iterator stops when no more
+/// matches can be found.
+///
+/// `'r` is the lifetime of the compiled expression and `'t` is the lifetime
+/// of the matched bytes.
+pub struct FindMatches<'r, 't> {
+    re: &'r Regex,
+    search: &'t [u8],
+    last_match: Option<uint>,
+    last_end: uint,
+}
+
+impl<'r, 't> Iterator<(uint, uint)> for FindMatches<'r, 't> {
+    fn next(&mut self) -> Option<(uint, uint)> {
+        if self.last_end > self.search.len() {
+            return None
+        }
+
+        let caps = self.re.exec(Location, self.search,
+                                self.last_end, self.search.len());
+        let (s, e) =
+            if !has_match(&caps) {
+                return None
+            } else {
+                (caps[0].unwrap(), caps[1].unwrap())
+            };
+
+        // Don't accept empty matches immediately following a match.
+        // i.e., no infinite loops please.
+        if e == s && Some(self.last_end) == self.last_match {
+            self.last_end = self.re.next_position(self.search, self.last_end);
+            return self.next()
+        }
+        self.last_end = e;
+        self.last_match = Some(self.last_end);
+        Some((s, e))
+    }
+}
+
+/// An iterator over all non-overlapping matches in the bytes read from a
+/// `Reader`.
+///
+/// The iterator yields a tuple of byte offsets from the start of the stream
+/// corresponding to the start and end of the match. It stops when no more
+/// matches can be found or after yielding an error.
+///
+/// `'r` is the lifetime of the compiled expression.
+pub struct FindReaderMatches<'r, R> {
+    search: ReaderSearch<'r, R>,
+}
+
+impl<'r, R: Reader> Iterator<IoResult<(u64, u64)>>
+    for FindReaderMatches<'r, R> {
+    fn next(&mut self) -> Option<IoResult<(u64, u64)>> {
+        match self.search.next(Location) {
+            Ok(None) => None,
+            Ok(Some(caps)) => {
+                let offset = self.search.offset;
+                Some(Ok((offset + caps[0].unwrap() as u64,
+                         offset + caps[1].unwrap() as u64)))
+            }
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+/// An iterator that yields the capture groups of all non-overlapping matches
+/// in the bytes read from a `Reader`.
+///
+/// The iterator stops when no more matches can be found or after yielding
+/// an error.
+///
+/// `'r` is the lifetime of the compiled expression.
+pub struct FindReaderCaptures<'r, R> {
+    search: ReaderSearch<'r, R>,
+}
+
+impl<'r, R: Reader> Iterator<IoResult<ReaderCaptures<'r>>>
+    for FindReaderCaptures<'r, R> {
+    fn next(&mut self) -> Option<IoResult<ReaderCaptures<'r>>> {
+        match self.search.next(Submatches) {
+            Ok(None) => None,
+            Ok(Some(caps)) => {
+                let re: &'r Regex = self.search.re;
+                let (s, e) = (caps[0].unwrap(), caps[1].unwrap());
+                let locs = caps.iter().map(|loc| loc.map(|i| i - s)).collect();
+                Some(Ok(ReaderCaptures {
+                    names: re.names.as_slice(),
+                    offset: self.search.offset + s as u64,
+                    text: self.search.buf[s..e].to_vec(),
+                    locs: locs,
+                }))
+            }
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+/// The capture groups of a match found in a stream.
+///
+/// Unlike `Captures`, this owns a copy of the bytes matched, since they may
+/// no longer be in memory when the next match is found. Positions are byte
+/// offsets from the start of the stream.
+///
+/// `'r` is the lifetime of the compiled expression.
+pub struct ReaderCaptures<'r> {
+    names: &'r [Option<String>],
+    offset: u64,
+    text: Vec<u8>,
+    locs: CaptureLocs,
+}
+
+impl<'r> ReaderCaptures<'r> {
+    /// Returns the start and end offsets of the Nth capture group.
+    /// Returns `None` if `i` is not a valid capture group or if the capture
+    /// group did not match anything.
+    pub fn pos(&self, i: uint) -> Option<(u64, u64)> {
+        let (s, e) = (i * 2, i * 2 + 1);
+        if e >= self.locs.len() || self.locs[s].is_none() {
+            return None
+        }
+        Some((self.offset + self.locs[s].unwrap() as u64,
+              self.offset + self.locs[e].unwrap() as u64))
+    }
+
+    /// Returns the matched bytes for the capture group `i`. If `i` isn't
+    /// a valid capture group or didn't match anything, then `None` is
+    /// returned.
+    pub fn at<'a>(&'a self, i: uint) -> Option<&'a [u8]> {
+        let (s, e) = (i * 2, i * 2 + 1);
+        if e >= self.locs.len() || self.locs[s].is_none() {
+            return None
+        }
+        Some(self.text[self.locs[s].unwrap()..self.locs[e].unwrap()])
+    }
+
+    /// Returns the matched bytes for the capture group named `name`. If
+    /// `name` isn't a valid capture group or didn't match anything, then
+    /// `None` is returned.
+    pub fn name<'a>(&'a self, name: &str) -> Option<&'a [u8]> {
+        let found = self.names.iter().position(|n| match *n {
+            None => false,
+            Some(ref n) => n.as_slice() == name,
+        });
+        match found {
+            None => None,
+            Some(i) => self.at(i),
+        }
+    }
+
+    /// Returns the number of captured groups.
+    #[inline]
+    pub fn len(&self) -> uint { self.locs.len() / 2 }
+
+    /// Returns if there are no captured groups.
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+}
+
+// Searches the bytes read from a `Reader` for successive matches.
+//
+// Only part of the stream is in `buf` at any time. Until the end of the
+// stream is reached, the positions searched are those whose following
+// character has been read (see `vm::run_partial`), and a match is reported
+// only once more bytes couldn't change it. Otherwise, the bytes before the
+// earliest position at which a match could still begin are dropped, more are
+// read and the search is restarted from that position.
+struct ReaderSearch<'r, R> {
+    re: &'r Regex,
+    rdr: R,
+    buf: Vec<u8>,
+    // The offset in the stream of the first byte in `buf`.
+    offset: u64,
+    // Where the next search begins in `buf`.
+    pos: uint,
+    // The offset in the stream of the end of the last match.
+    last_match: Option<u64>,
+    eof: bool,
+    done: bool,
+}
+
+impl<'r, R: Reader> ReaderSearch<'r, R> {
+    fn new(re: &'r Regex, rdr: R) -> ReaderSearch<'r, R> {
+        ReaderSearch {
+            re: re,
+            rdr: rdr,
+            buf: vec![],
+            offset: 0,
+            pos: 0,
+            last_match: None,
+            eof: false,
+            done: false,
+        }
+    }
+
+    // Finds the next match and returns its capture locations as indices into
+    // `buf`, which are valid until the next call.
+    fn next(&mut self, which: MatchKind) -> IoResult<Option<CaptureLocs>> {
+        if self.done {
+            return Ok(None)
+        }
+        loop {
+            let caps = match self.search(which) {
+                Ok(Some(caps)) => caps,
+                Ok(None) => {
+                    self.done = true;
+                    return Ok(None)
+                }
+                Err(err) => {
+                    self.done = true;
+                    return Err(err)
+                }
+            };
+            let (s, e) = (caps[0].unwrap(), caps[1].unwrap());
+
+            // Don't accept empty matches immediately following a match.
+            // i.e., no infinite loops please.
+            if e == s && Some(self.offset + s as u64) == self.last_match {
+                if e >= self.buf.len() {
+                    self.done = true;
+                    return Ok(None)
+                }
+                self.pos = self.re.next_position(self.buf.as_slice(), e);
+                continue
+            }
+            self.pos = e;
+            self.last_match = Some(self.offset + e as u64);
+            return Ok(Some(caps))
+        }
+    }
+
+    fn search(&mut self, which: MatchKind) -> IoResult<Option<CaptureLocs>> {
+        loop {
+            if self.eof {
+                let caps = self.re.exec(which, self.buf.as_slice(),
+                                        self.pos, self.buf.len());
+                return Ok(if has_match(&caps) { Some(caps) } else { None })
+            }
+            let avail = self.available();
+            if avail > self.pos {
+                let result = self.re.dfa.exec_partial(
+                    which, &self.re.prog, self.buf[..avail], self.re.unicode,
+                    self.pos, avail - 1);
+                match result {
+                    Final(caps) => {
+                        return Ok(if has_match(&caps) { Some(caps) } else { None })
+                    }
+                    NeedMore(keep) => self.discard(keep),
+                }
+            }
+            try!(self.fill());
+        }
+    }
+
+    // Returns the number of bytes at the start of `buf` that can be searched.
+    // When decoding UTF-8, a sequence that is cut off at the end of `buf` is
+    // left until the rest of it has been read.
+    fn available(&self) -> uint {
+        let len = self.buf.len();
+        if !self.re.unicode {
+            return len
+        }
+        let mut i = len;
+        while i > 0 && len - i < 3 && self.buf[i - 1] & 0xC0 == 0x80 {
+            i -= 1;
+        }
+        if i == 0 {
+            return len
+        }
+        let lead = i - 1;
+        if lead + str::utf8_char_width(self.buf[lead]) > len { lead } else { len }
+    }
+
+    // Drops the bytes before `keep`, except for the few needed to decode the
+    // character that precedes it, and restarts the search at `keep`.
+    fn discard(&mut self, keep: uint) {
+        let cut = if keep > 4 { keep - 4 } else { 0 };
+        if cut > 0 {
+            self.buf = self.buf[cut..].to_vec();
+            self.offset += cut as u64;
+        }
+        self.pos = keep - cut;
+    }
+
+    // Reads at least as many bytes as will have to be searched again, so that
+    // the total time spent searching stays linear in the size of the stream.
+    fn fill(&mut self) -> IoResult<()> {
+        let want = cmp::max(1, self.buf.len() - self.pos);
+        let start = self.buf.len();
+        while self.buf.len() - start < want {
+            match self.rdr.push_at_least(1, READ_SIZE, &mut self.buf) {
+                Ok(_) => {}
+                Err(ref err) if err.kind == EndOfFile => {
+                    self.eof = true;
+                    break
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[inline]
+fn has_match(caps: &CaptureLocs) -> bool {
+    caps.len() >= 2 && caps[0].is_some() && caps[1].is_some()
+}
+
+fn is_valid_cap(b: u8) -> bool {
+    b == b'_' || (b >= b'0' && b <= b'9')
+    || (b >= b'a' && b <= b'z') || (b >= b'A' && b <= b'Z')
+}
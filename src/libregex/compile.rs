@@ -13,22 +13,38 @@
 
 pub use self::Inst::*;
 
+use std::char;
 use std::cmp;
+use std::mem;
+use literals::Literals;
 use parse;
 use parse::{
-    Flags, FLAG_EMPTY,
+    Flags, FLAG_NOCASE, FLAG_MULTI, FLAG_NEGATED,
     Nothing, Literal, Dot, AstClass, Begin, End, WordBoundary, Capture, Cat, Alt,
-    Rep,
+    Rep, Look, Backref,
     ZeroOne, ZeroMore, OneMore,
+    LookKind,
 };
 
 type InstIdx = uint;
 
+/// The maximum number of literals extracted from an expression.
+const MAX_LITERALS: uint = 32;
+
+/// The maximum number of characters in a class for it to be turned into a set
+/// of literals.
+const MAX_CLASS_LITERALS: uint = 8;
+
 #[deriving(Show, Clone)]
 pub enum Inst {
     // When a Match instruction is executed, the current thread is successful.
     Match,
 
+    // When a SetMatch instruction is executed, the current thread has matched
+    // the Nth pattern of a set of regular expressions. Unlike Match, this
+    // does not stop the other threads from running.
+    SetMatch(uint),
+
     // The OneChar instruction matches a literal character.
     // The flags indicate whether to do a case insensitive match.
     OneChar(char, Flags),
@@ -69,6 +85,21 @@ pub enum Inst {
     // a panic state, then the instruction at the second index given is
     // tried.
     Split(InstIdx, InstIdx),
+
+    // Asserts that the instructions that follow, up to a LookMatch
+    // instruction, match (or don't match) at the current position, and
+    // consumes no characters. Execution then continues at the index given.
+    // For a look-behind, the instructions read the text backwards.
+    // Only the backtracking engine can run this instruction.
+    EmptyLook(LookKind, InstIdx),
+
+    // Ends the instructions of a look-around assertion successfully.
+    LookMatch,
+
+    // Matches the text last matched by the capture group given. The flags
+    // indicate whether to do a case insensitive match.
+    // Only the backtracking engine can run this instruction.
+    Backreference(uint, Flags),
 }
 
 /// Program represents a compiled regular expression. Once an expression is
@@ -81,18 +112,36 @@ pub enum Inst {
 pub struct Program {
     /// A sequence of instructions.
     pub insts: Vec<Inst>,
-    /// If the regular expression requires a literal prefix in order to have a
-    /// match, that prefix is stored here. (It's used in the VM to implement
-    /// an optimization.)
-    pub prefix: String,
+    /// If every match of the regular expression has to start with one of a
+    /// small set of literals, they are stored here. (They're used in the VMs
+    /// to skip ahead to the next place a match could start.)
+    pub prefixes: Literals,
+    /// Likewise, the literals that every match has to end with, if any.
+    /// (They're used to give up early when none of them occur in the text.)
+    pub suffixes: Literals,
 }
 
 impl Program {
     /// Compiles a Regex given its AST.
     pub fn new(ast: parse::Ast) -> (Program, Vec<Option<String>>) {
+        Program::with_encoding(ast, true)
+    }
+
+    /// Compiles a Regex given its AST, exactly like `new`.
+    ///
+    /// The literal prefixes and suffixes of the program are encoded as UTF-8
+    /// if `unicode` is true, and as a single byte per character otherwise, to
+    /// agree with how a `CharReader` reads the search text.
+    pub fn with_encoding(ast: parse::Ast, unicode: bool)
+                        -> (Program, Vec<Option<String>>) {
+        let prefixes = literals(&ast, false, unicode);
+        let suffixes = literals(&ast, true, unicode);
+
         let mut c = Compiler {
             insts: Vec::with_capacity(100),
             names: Vec::with_capacity(10),
+            reverse: false,
+            backward: false,
         };
 
         c.insts.push(Save(0));
@@ -100,25 +149,95 @@ impl Program {
         c.insts.push(Save(1));
         c.insts.push(Match);
 
-        // Try to discover a literal string prefix.
-        // This is a bit hacky since we have to skip over the initial
-        // 'Save' instruction.
-        let mut pre = String::with_capacity(5);
-        for inst in c.insts[1..].iter() {
-            match *inst {
-                OneChar(c, FLAG_EMPTY) => pre.push(c),
-                _ => break
-            }
-        }
+        // There's no point in searching for a prefix when a match can only
+        // start at the beginning of the text.
+        let prefixes = match c.insts[1] {
+            EmptyBegin(flags) if flags & FLAG_MULTI == 0 => Literals::empty(),
+            _ => prefixes,
+        };
 
-        let Compiler { insts, names } = c;
+        let Compiler { insts, names, .. } = c;
         let prog = Program {
             insts: insts,
-            prefix: pre,
+            prefixes: prefixes,
+            suffixes: suffixes,
         };
         (prog, names)
     }
 
+    /// Compiles a set of regular expressions given their ASTs into a single
+    /// program.
+    ///
+    /// Each expression is tried in order by a chain of `Split` instructions,
+    /// and each one ends in a `SetMatch` instruction that records the index of
+    /// the expression that matched. Capture groups are not tracked, so no
+    /// names are returned.
+    pub fn new_set(asts: Vec<parse::Ast>) -> Program {
+        let mut c = Compiler {
+            insts: Vec::with_capacity(100),
+            names: Vec::with_capacity(10),
+            reverse: false,
+            backward: false,
+        };
+
+        let n = asts.len();
+        for (i, ast) in asts.into_iter().enumerate() {
+            if i + 1 < n {
+                let split = c.empty_split(); // push: split 0, 0
+                let j1 = c.insts.len();
+                c.compile(ast);              // push: insts for pattern i
+                c.push(SetMatch(i));
+                let j2 = c.insts.len();
+                c.set_split(split, j1, j2); // split 0, 0 -> split j1, j2
+            } else {
+                c.compile(ast);
+                c.push(SetMatch(i));
+            }
+        }
+
+        Program {
+            insts: c.insts,
+            prefixes: Literals::empty(),
+            suffixes: Literals::empty(),
+        }
+    }
+
+    /// Compiles a program that matches the reverse of the text matched by the
+    /// regular expression given by its AST.
+    ///
+    /// Concatenations are compiled back to front and the beginning and end
+    /// assertions trade places. Capture groups are not tracked, and unlike
+    /// `new`, the program starts with the expression itself rather than a
+    /// `Save` instruction. It is used to find where a match begins by
+    /// scanning backwards from where it ends.
+    pub fn new_reverse(ast: parse::Ast) -> Program {
+        let mut c = Compiler {
+            insts: Vec::with_capacity(100),
+            names: vec![],
+            reverse: true,
+            backward: false,
+        };
+        c.compile(ast);
+        c.push(Match);
+
+        Program {
+            insts: c.insts,
+            prefixes: Literals::empty(),
+            suffixes: Literals::empty(),
+        }
+    }
+
+    /// Returns true if the program uses look-around assertions or
+    /// backreferences, so that only the backtracking engine can run it.
+    pub fn needs_backtrack(&self) -> bool {
+        self.insts.iter().any(|inst| {
+            match *inst {
+                EmptyLook(_, _) | LookMatch | Backreference(_, _) => true,
+                _ => false,
+            }
+        })
+    }
+
     /// Returns the total number of capture groups in the regular expression.
     /// This includes the zeroth capture.
     pub fn num_captures(&self) -> uint {
@@ -134,9 +253,169 @@ impl Program {
     }
 }
 
+/// Returns the approximate number of bytes used by the instructions that the
+/// expression given by its AST compiles to.
+///
+/// This lets the parser enforce a size limit before anything big is built, so
+/// it has to be kept in sync with `Compiler::compile`.
+pub fn approx_size(ast: &parse::Ast) -> uint {
+    let inst = mem::size_of::<Inst>();
+    match *ast {
+        Nothing => 0,
+        Literal(_, _) | Dot(_) | Begin(_) | End(_) | WordBoundary(_)
+        | Backref(_, _) => inst,
+        AstClass(ref ranges, _) => {
+            inst + ranges.len() * mem::size_of::<(char, char)>()
+        }
+        Capture(_, _, ref x) => 2 * inst + approx_size(&**x),
+        Cat(ref xs) => xs.iter().fold(0, |size, x| size + approx_size(x)),
+        Alt(ref x, ref y) => 2 * inst + approx_size(&**x) + approx_size(&**y),
+        Rep(ref x, ZeroMore, _) => 2 * inst + approx_size(&**x),
+        Rep(ref x, ZeroOne, _) | Rep(ref x, OneMore, _) => {
+            inst + approx_size(&**x)
+        }
+        Look(_, ref x) => 2 * inst + approx_size(&**x),
+    }
+}
+
+/// Returns the literals that every match of the expression given by its AST
+/// has to start with, or if `suffixes` is true, end with. If there aren't
+/// any, then the set returned is empty.
+///
+/// Each character is encoded as UTF-8 if `unicode` is true, and as a single
+/// byte otherwise.
+fn literals(ast: &parse::Ast, suffixes: bool, unicode: bool) -> Literals {
+    let seqs = literal_seqs(ast, suffixes).seqs;
+    // An empty sequence means that a match can start (or end) with anything.
+    if seqs.iter().any(|seq| seq.len() == 0) {
+        return Literals::empty()
+    }
+    Literals::new(seqs.into_iter().map(|mut seq| {
+        if suffixes {
+            seq.reverse();
+        }
+        if unicode {
+            String::from_chars(seq.as_slice()).into_bytes()
+        } else {
+            seq.iter().map(|&c| c as u8).collect()
+        }
+    }).collect())
+}
+
+/// Returns the sequences of characters that the text matched by the
+/// expression given by its AST can start with, or if `reverse` is true, end
+/// with. When `reverse` is true, each sequence is returned backwards.
+fn literal_seqs(ast: &parse::Ast, reverse: bool) -> LiteralSeqs {
+    match *ast {
+        Nothing | Begin(_) | End(_) | WordBoundary(_) | Look(_, _) => {
+            LiteralSeqs::empty()
+        }
+        // A literal U+FFFD can match bytes that aren't valid UTF-8 when
+        // searching bytes, so it can't be searched for as a literal.
+        Literal(c, flags) if flags & FLAG_NOCASE == 0 && c != '\u{FFFD}' => {
+            LiteralSeqs { seqs: vec![vec![c]], complete: true }
+        }
+        AstClass(ref ranges, flags)
+                if flags & (FLAG_NOCASE | FLAG_NEGATED) == 0 => {
+            let size = ranges.iter().fold(0, |size, &(start, end)| {
+                size + (end as uint - start as uint + 1)
+            });
+            if size > MAX_CLASS_LITERALS {
+                return LiteralSeqs::unknown()
+            }
+            let mut seqs = vec![];
+            for &(start, end) in ranges.iter() {
+                for c in range(start as u32, end as u32 + 1) {
+                    match char::from_u32(c) {
+                        Some(c) if c != '\u{FFFD}' => seqs.push(vec![c]),
+                        Some(_) => return LiteralSeqs::unknown(),
+                        None => {}
+                    }
+                }
+            }
+            LiteralSeqs { seqs: seqs, complete: true }
+        }
+        Capture(_, _, ref x) => literal_seqs(&**x, reverse),
+        Cat(ref xs) => {
+            let mut seqs = LiteralSeqs::empty();
+            for i in range(0, xs.len()) {
+                if !seqs.complete {
+                    break
+                }
+                let x = if reverse { &xs[xs.len() - 1 - i] } else { &xs[i] };
+                seqs.concat(literal_seqs(x, reverse));
+            }
+            seqs
+        }
+        Alt(ref x, ref y) => {
+            literal_seqs(&**x, reverse).union(literal_seqs(&**y, reverse))
+        }
+        Rep(ref x, OneMore, _) => {
+            let mut seqs = literal_seqs(&**x, reverse);
+            seqs.complete = false;
+            seqs
+        }
+        _ => LiteralSeqs::unknown(),
+    }
+}
+
+/// The sequences of characters that some text has to start (or end) with.
+struct LiteralSeqs {
+    seqs: Vec<Vec<char>>,
+    /// Whether each sequence is all of the text, rather than only its start
+    /// (or end). Only then can sequences that follow be appended.
+    complete: bool,
+}
+
+impl LiteralSeqs {
+    /// The sequences for text that is always empty.
+    fn empty() -> LiteralSeqs {
+        LiteralSeqs { seqs: vec![vec![]], complete: true }
+    }
+
+    /// The sequences for text that can start with anything.
+    fn unknown() -> LiteralSeqs {
+        LiteralSeqs { seqs: vec![vec![]], complete: false }
+    }
+
+    /// Appends each of the sequences in `other` to each of these sequences.
+    fn concat(&mut self, other: LiteralSeqs) {
+        if self.seqs.len() * other.seqs.len() > MAX_LITERALS {
+            self.complete = false;
+            return
+        }
+        let mut seqs = Vec::with_capacity(self.seqs.len() * other.seqs.len());
+        for seq in self.seqs.iter() {
+            for more in other.seqs.iter() {
+                let mut seq = seq.clone();
+                seq.push_all(more.as_slice());
+                seqs.push(seq);
+            }
+        }
+        self.seqs = seqs;
+        self.complete = other.complete;
+    }
+
+    /// Returns the sequences of text that matches either these sequences or
+    /// the sequences in `other`.
+    fn union(mut self, other: LiteralSeqs) -> LiteralSeqs {
+        if self.seqs.len() + other.seqs.len() > MAX_LITERALS {
+            return LiteralSeqs::unknown()
+        }
+        self.seqs.extend(other.seqs.into_iter());
+        self.complete = self.complete && other.complete;
+        self
+    }
+}
+
 struct Compiler<'r> {
     insts: Vec<Inst>,
     names: Vec<Option<String>>,
+    reverse: bool,
+    // Whether the expression being compiled is the body of a look-behind,
+    // which reads the text backwards. Unlike `reverse`, assertions keep their
+    // meaning and capture groups are still tracked.
+    backward: bool,
 }
 
 // The compiler implemented here is extremely simple. Most of the complexity
@@ -151,23 +430,41 @@ impl<'r> Compiler<'r> {
             Dot(nl) => self.push(Any(nl)),
             AstClass(ranges, flags) =>
                 self.push(CharClass(ranges, flags)),
+            Begin(flags) if self.reverse => self.push(EmptyEnd(flags)),
             Begin(flags) => self.push(EmptyBegin(flags)),
+            End(flags) if self.reverse => self.push(EmptyBegin(flags)),
             End(flags) => self.push(EmptyEnd(flags)),
             WordBoundary(flags) => self.push(EmptyWordBoundary(flags)),
             Capture(cap, name, x) => {
+                if self.reverse {
+                    return self.compile(*x)
+                }
                 let len = self.names.len();
                 if cap >= len {
                     self.names.grow(10 + cap - len, None)
                 }
                 self.names[cap] = name;
 
-                self.push(Save(2 * cap));
-                self.compile(*x);
-                self.push(Save(2 * cap + 1));
+                // Reading backwards, the end of the group is reached first.
+                if self.backward {
+                    self.push(Save(2 * cap + 1));
+                    self.compile(*x);
+                    self.push(Save(2 * cap));
+                } else {
+                    self.push(Save(2 * cap));
+                    self.compile(*x);
+                    self.push(Save(2 * cap + 1));
+                }
             }
             Cat(xs) => {
-                for x in xs.into_iter() {
-                    self.compile(x)
+                if self.reverse || self.backward {
+                    for x in xs.into_iter().rev() {
+                        self.compile(x)
+                    }
+                } else {
+                    for x in xs.into_iter() {
+                        self.compile(x)
+                    }
                 }
             }
             Alt(x, y) => {
@@ -221,6 +518,26 @@ impl<'r> Compiler<'r> {
                     self.set_split(split, j2, j1);
                 }
             }
+            Look(kind, x) => {
+                if self.reverse {
+                    panic!("BUG: Look-around can't be compiled in reverse.")
+                }
+                let look = self.insts.len();
+                self.push(EmptyLook(kind, 0));   // push: look 0
+                let backward = self.backward;
+                self.backward = kind.is_behind();
+                self.compile(*x);                // push: insts for x
+                self.backward = backward;
+                self.push(LookMatch);
+                let next = self.insts.len();
+                self.insts[look] = EmptyLook(kind, next); // look 0 -> look next
+            }
+            Backref(cap, flags) => {
+                if self.reverse {
+                    panic!("BUG: Backreferences can't be compiled in reverse.")
+                }
+                self.push(Backreference(cap, flags))
+            }
         }
     }
 
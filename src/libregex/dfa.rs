@@ -0,0 +1,858 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// This is a lazy DFA in the style of RE2. Instead of compiling a program to a
+// DFA ahead of time (which can take exponential time and space), the states
+// of the DFA are built from the NFA in vm.rs as they are visited, and are
+// cached so that text which revisits them runs at the speed of a table
+// lookup.
+//
+// A DFA state is the ordered list of instructions that the NFA simulation
+// would have in its queue of threads at some position in the input (keeping
+// the order is what lets the DFA mimic the NFA's leftmost-first semantics
+// exactly), along with a flag recording whether a match has already been
+// seen. Following Cox's suggestion (see the comment at the top of vm.rs), the
+// DFA answers "does this match" and "where does the match end" questions by
+// scanning forward. The start of a match is found by running a DFA for the
+// reversed program backwards from the end. Submatches are left to the NFA,
+// but it then only has to run over the text of the match itself.
+//
+// The only empty-width assertions that a cached state can account for are
+// `^` and `$` outside of multi-line mode, since they only depend on whether
+// the current position is the beginning or the end of the input. Those two
+// positions are handled without the cache. Programs with word boundaries or
+// multi-line anchors (which depend on the surrounding characters) are always
+// run with the NFA.
+//
+// If the cache grows too large, either in states or in transitions on
+// non-ASCII characters, it is thrown away and rebuilt as the search
+// continues, so memory use is bounded.
+
+pub use self::DfaResult::*;
+
+use std::collections::HashMap;
+use std::slice::SliceExt;
+use std::sync::Mutex;
+use compile::{
+    Inst, Program,
+    Match, SetMatch, OneChar, CharClass, Any, EmptyBegin, EmptyEnd, EmptyWordBoundary,
+    Save, Jump, Split, EmptyLook, LookMatch, Backreference,
+};
+use parse::{FLAG_NOCASE, FLAG_MULTI, FLAG_DOTNL, FLAG_NEGATED};
+use vm;
+use vm::{CaptureLocs, CharReader, MatchKind, Exists, Location, Submatches};
+use vm::{Partial, Final, NeedMore};
+
+type InstIdx = uint;
+type StatePtr = uint;
+
+/// Marks a transition that hasn't been computed yet.
+const UNKNOWN: StatePtr = ::std::uint::MAX;
+
+/// The maximum number of states kept in a cache before it is flushed.
+const MAX_STATES: uint = 2000;
+
+/// The maximum number of transitions on non-ASCII characters kept in a cache
+/// before it is flushed. Unlike ASCII transitions, which are a fixed number
+/// per state, these grow with the number of distinct characters in the text.
+const MAX_OTHER_TRANSITIONS: uint = 10000;
+
+/// The result of running a DFA.
+#[deriving(Copy, Show, PartialEq)]
+pub enum DfaResult {
+    /// There is no match.
+    NoMatch,
+    /// A match ends at the byte index given. (When scanning backwards, the
+    /// match begins at the byte index given.)
+    MatchEnd(uint),
+    /// The DFA could not finish the search. The NFA must be used instead.
+    GaveUp,
+}
+
+/// Executes compiled programs with lazily built DFAs when possible, and with
+/// the NFA in `vm` otherwise.
+///
+/// It owns a copy of the program compiled in reverse (see
+/// `Program::new_reverse`) and the caches of the states built so far. The
+/// caches are shared by every search with the same regular expression. If a
+/// cache is in use by another task, a search builds its own states from
+/// scratch instead of waiting for it.
+pub struct DfaExec {
+    rprog: Program,
+    can_exec: bool,
+    classes: Vec<u8>,
+    nclasses: uint,
+    forward: Mutex<Cache>,
+    reverse: Mutex<Cache>,
+}
+
+impl DfaExec {
+    /// Creates the DFA machinery for `prog`, where `rprog` must be the same
+    /// expression compiled with `Program::new_reverse`.
+    pub fn new(prog: &Program, rprog: Program) -> DfaExec {
+        let (classes, nclasses) = ascii_classes(prog);
+        DfaExec {
+            can_exec: can_exec(prog),
+            rprog: rprog,
+            classes: classes,
+            nclasses: nclasses,
+            forward: Mutex::new(Cache::new()),
+            reverse: Mutex::new(Cache::new()),
+        }
+    }
+
+    /// Runs `prog` on the search text `input` exactly like `vm::run_bytes`.
+    pub fn exec(&self, which: MatchKind, prog: &Program, input: &[u8],
+                unicode: bool, start: uint, end: uint) -> CaptureLocs {
+        if !vm::has_suffix(prog, input, start) {
+            return vec![None, None]
+        }
+        if !self.can_exec {
+            return vm::run_bytes(which, prog, input, unicode, start, end)
+        }
+        let quit_early = match which {
+            Exists => true,
+            Location | Submatches => false,
+        };
+        let (result, _) = self.run_forward(prog, quit_early, input,
+                                           unicode, start, end);
+        self.finish(which, prog, input, unicode, start, end, result)
+    }
+
+    /// Runs `prog` on the part of the search text read so far exactly like
+    /// `vm::run_partial`.
+    pub fn exec_partial(&self, which: MatchKind, prog: &Program,
+                        input: &[u8], unicode: bool,
+                        start: uint, end: uint) -> Partial {
+        if !self.can_exec {
+            return vm::run_partial(which, prog, input, unicode, start, end)
+        }
+        let quit_early = match which {
+            Exists => true,
+            Location | Submatches => false,
+        };
+        match self.run_forward(prog, quit_early, input, unicode, start, end) {
+            (GaveUp, _) => {
+                vm::run_partial(which, prog, input, unicode, start, end)
+            }
+            (_, Some(keep)) => NeedMore(keep),
+            (result, None) => {
+                // The match ends before the end of `input`, so the character
+                // after it is known and the reverse DFA and the NFA find the
+                // same match they would if all of the text were given.
+                Final(self.finish(which, prog, input, unicode,
+                                  start, end, result))
+            }
+        }
+    }
+
+    // Finds the start of the match found by the forward DFA (and its
+    // submatches, if requested).
+    fn finish(&self, which: MatchKind, prog: &Program, input: &[u8],
+              unicode: bool, start: uint, end: uint,
+              result: DfaResult) -> CaptureLocs {
+        let quit_early = match which {
+            Exists => true,
+            Location | Submatches => false,
+        };
+        let match_end = match result {
+            NoMatch => return vec![None, None],
+            MatchEnd(_) if quit_early => return vec![Some(0), Some(0)],
+            MatchEnd(e) => e,
+            GaveUp => {
+                return vm::run_bytes(which, prog, input, unicode, start, end)
+            }
+        };
+        let match_start = match self.run_reverse(input, unicode,
+                                                 start, match_end) {
+            MatchEnd(s) => s,
+            NoMatch | GaveUp => {
+                return vm::run_bytes(which, prog, input, unicode, start, end)
+            }
+        };
+        match which {
+            Location => vec![Some(match_start), Some(match_end)],
+            Exists | Submatches => {
+                vm::run_bytes(Submatches, prog, input, unicode,
+                              match_start, match_end)
+            }
+        }
+    }
+
+    fn run_forward(&self, prog: &Program, quit_early: bool, input: &[u8],
+                   unicode: bool, start: uint, end: uint)
+                  -> (DfaResult, Option<uint>) {
+        match self.forward.try_lock() {
+            Some(mut cache) => {
+                self.dfa(prog, false, &mut *cache)
+                    .exec_forward(quit_early, input, unicode, start, end)
+            }
+            None => {
+                self.dfa(prog, false, &mut Cache::new())
+                    .exec_forward(quit_early, input, unicode, start, end)
+            }
+        }
+    }
+
+    fn run_reverse(&self, input: &[u8], unicode: bool,
+                   start: uint, end: uint) -> DfaResult {
+        match self.reverse.try_lock() {
+            Some(mut cache) => {
+                self.dfa(&self.rprog, true, &mut *cache)
+                    .exec_reverse(input, unicode, start, end)
+            }
+            None => {
+                self.dfa(&self.rprog, true, &mut Cache::new())
+                    .exec_reverse(input, unicode, start, end)
+            }
+        }
+    }
+
+    fn dfa<'a>(&'a self, prog: &'a Program, longest: bool,
+               cache: &'a mut Cache) -> Dfa<'a> {
+        Dfa {
+            prog: prog,
+            classes: self.classes.as_slice(),
+            nclasses: self.nclasses,
+            longest: longest,
+            cache: cache,
+        }
+    }
+}
+
+impl Clone for DfaExec {
+    /// Clones the reversed program. The clone starts with empty caches.
+    fn clone(&self) -> DfaExec {
+        DfaExec {
+            rprog: self.rprog.clone(),
+            can_exec: self.can_exec,
+            classes: self.classes.clone(),
+            nclasses: self.nclasses,
+            forward: Mutex::new(Cache::new()),
+            reverse: Mutex::new(Cache::new()),
+        }
+    }
+}
+
+/// A DFA whose states and transitions on ASCII characters were all built
+/// ahead of time, so that it can be represented as static data. (This is
+/// used by the `regex!` macro.)
+///
+/// State `0` is the start state. Transitions out of a state are indexed by
+/// the equivalence class of an ASCII character.
+pub struct StaticDfa {
+    /// Whether matches can only begin at the beginning of the input.
+    pub anchored: bool,
+    /// The equivalence class of every ASCII character.
+    pub classes: &'static [u8],
+    /// The number of equivalence classes.
+    pub nclasses: uint,
+    /// The state reached from state `s` on a character of class `c` is at
+    /// index `s * nclasses + c`.
+    pub trans: &'static [u16],
+    /// The states reached from the start state on each class of characters
+    /// at the beginning of the input.
+    pub begin_trans: &'static [u16],
+    /// Whether a match ends at a position in state `s` that is neither the
+    /// beginning nor the end of the input.
+    pub matches: &'static [bool],
+    /// Whether a match ends at the end of the input in state `s`.
+    pub matches_at_end: &'static [bool],
+    /// Whether no further match can be found after reaching state `s`.
+    pub done: &'static [bool],
+    /// Whether a match ends at the beginning of non-empty input.
+    pub matches_at_begin: bool,
+    /// Whether the empty string matches.
+    pub matches_empty: bool,
+}
+
+/// The tables of a `StaticDfa`, as built by `build_static`.
+pub struct StaticDfaTables {
+    /// See `StaticDfa::anchored`.
+    pub anchored: bool,
+    /// See `StaticDfa::classes`.
+    pub classes: Vec<u8>,
+    /// See `StaticDfa::nclasses`.
+    pub nclasses: uint,
+    /// See `StaticDfa::trans`.
+    pub trans: Vec<u16>,
+    /// See `StaticDfa::begin_trans`.
+    pub begin_trans: Vec<u16>,
+    /// See `StaticDfa::matches`.
+    pub matches: Vec<bool>,
+    /// See `StaticDfa::matches_at_end`.
+    pub matches_at_end: Vec<bool>,
+    /// See `StaticDfa::done`.
+    pub done: Vec<bool>,
+    /// See `StaticDfa::matches_at_begin`.
+    pub matches_at_begin: bool,
+    /// See `StaticDfa::matches_empty`.
+    pub matches_empty: bool,
+}
+
+/// Builds every state of a DFA for `prog` that can be reached on ASCII
+/// input.
+///
+/// If the program can't be run with a DFA, or if it needs more than
+/// `max_states` states, then `None` is returned.
+pub fn build_static(prog: &Program, max_states: uint)
+                   -> Option<StaticDfaTables> {
+    if !can_exec(prog) || max_states >= MAX_STATES {
+        return None
+    }
+    let (classes, nclasses) = ascii_classes(prog);
+    // The first ASCII character in each class stands in for all of them.
+    let mut reps = Vec::from_elem(nclasses, '\0');
+    for b in range(0u, 128).rev() {
+        reps[classes[b] as uint] = b as u8 as char;
+    }
+
+    let mut cache = Cache::new();
+    let mut dfa = Dfa {
+        prog: prog,
+        classes: classes.as_slice(),
+        nclasses: nclasses,
+        longest: false,
+        cache: &mut cache,
+    };
+    let anchored = dfa.is_anchored();
+    let key = dfa.start_key();
+    let (begin_insts, matches_at_begin) = dfa.closure(&key, true, false);
+    let matches_empty = dfa.closure(&key, true, true).1;
+    dfa.add_state(key);
+    let mut begin_trans = Vec::with_capacity(nclasses);
+    for &c in reps.iter() {
+        let key = StateKey {
+            insts: dfa.step(begin_insts.as_slice(), c),
+            matched: matches_at_begin,
+        };
+        begin_trans.push(dfa.add_state(key) as u16);
+    }
+
+    let (mut trans, mut matches) = (vec![], vec![]);
+    let (mut matches_at_end, mut done) = (vec![], vec![]);
+    let mut si = 0;
+    while si < dfa.cache.states.len() {
+        for &c in reps.iter() {
+            // This also keeps the cache from being flushed.
+            if dfa.cache.states.len() > max_states {
+                return None
+            }
+            trans.push(dfa.next_state(si, c) as u16);
+        }
+        let key = dfa.cache.states[si].key.clone();
+        matches.push(dfa.cache.states[si].is_match);
+        matches_at_end.push(dfa.closure(&key, false, true).1);
+        done.push(dfa.is_done(si) || (anchored && key.insts.len() == 0));
+        si += 1;
+    }
+    Some(StaticDfaTables {
+        anchored: anchored,
+        classes: classes.clone(),
+        nclasses: nclasses,
+        trans: trans,
+        begin_trans: begin_trans,
+        matches: matches,
+        matches_at_end: matches_at_end,
+        done: done,
+        matches_at_begin: matches_at_begin,
+        matches_empty: matches_empty,
+    })
+}
+
+/// Runs a DFA built ahead of time forward over `input` between byte indices
+/// `start` and `end`.
+///
+/// If `quit_early` is true, then the search stops at the first position where
+/// a match is known to exist. The DFA gives up as soon as it reads a
+/// character that isn't ASCII.
+pub fn exec_static(dfa: &StaticDfa, quit_early: bool, input: &str,
+                   start: uint, end: uint) -> DfaResult {
+    let input = input.as_bytes();
+    if dfa.anchored && start > 0 {
+        return NoMatch
+    }
+    let mut si = 0u;
+    let mut last_match = None;
+    let mut ic = start;
+    while ic <= end {
+        let (begin, at_end) = (ic == 0, ic == input.len());
+        let is_match =
+            if begin && at_end {
+                dfa.matches_empty
+            } else if begin {
+                dfa.matches_at_begin
+            } else if at_end {
+                dfa.matches_at_end[si]
+            } else {
+                dfa.matches[si]
+            };
+        if is_match {
+            if quit_early {
+                return MatchEnd(ic)
+            }
+            last_match = Some(ic);
+        }
+        if at_end {
+            break
+        }
+        let b = input[ic];
+        if b >= 0x80 {
+            return GaveUp
+        }
+        let class = dfa.classes[b as uint] as uint;
+        si = if begin {
+            dfa.begin_trans[class] as uint
+        } else {
+            dfa.trans[si * dfa.nclasses + class] as uint
+        };
+        if dfa.done[si] {
+            break
+        }
+        ic += 1;
+    }
+    match last_match {
+        None => NoMatch,
+        Some(e) => MatchEnd(e),
+    }
+}
+
+/// Returns true if and only if `prog` can be run with a DFA.
+fn can_exec(prog: &Program) -> bool {
+    prog.insts.iter().all(|inst| {
+        match *inst {
+            EmptyBegin(flags) | EmptyEnd(flags) => flags & FLAG_MULTI == 0,
+            EmptyWordBoundary(_) | SetMatch(_) => false,
+            EmptyLook(_, _) | LookMatch | Backreference(_, _) => false,
+            _ => true,
+        }
+    })
+}
+
+/// Partitions the ASCII characters into equivalence classes, such that every
+/// instruction in `prog` either matches all of the characters in a class or
+/// none of them. Returns the class of each character and the number of
+/// classes.
+fn ascii_classes(prog: &Program) -> (Vec<u8>, uint) {
+    let consuming: Vec<&Inst> = prog.insts.iter().filter(|inst| {
+        match **inst {
+            OneChar(_, _) | CharClass(_, _) | Any(_) => true,
+            _ => false,
+        }
+    }).collect();
+    let mut signatures: Vec<Vec<bool>> = vec![];
+    let mut classes = Vec::with_capacity(128);
+    for b in range(0u8, 128) {
+        let c = b as char;
+        let sig: Vec<bool> = consuming.iter()
+                                      .map(|inst| matches_char(*inst, c))
+                                      .collect();
+        let class = match signatures.iter().position(|s| *s == sig) {
+            Some(class) => class,
+            None => {
+                signatures.push(sig);
+                signatures.len() - 1
+            }
+        };
+        classes.push(class as u8);
+    }
+    (classes, signatures.len())
+}
+
+/// Returns true if and only if `inst` consumes the character `c`.
+fn matches_char(inst: &Inst, c: char) -> bool {
+    match *inst {
+        OneChar(oc, flags) => {
+            oc == c
+                || (flags & FLAG_NOCASE > 0
+                    && oc.to_uppercase() == c.to_uppercase())
+        }
+        CharClass(ref ranges, flags) => {
+            let negate = flags & FLAG_NEGATED > 0;
+            let casei = flags & FLAG_NOCASE > 0;
+            let found = ranges.as_slice();
+            let found = found.binary_search(|&rc| vm::class_cmp(casei, c, rc))
+                .found().is_some();
+            found ^ negate
+        }
+        Any(flags) => flags & FLAG_DOTNL > 0 || c != '\n',
+        _ => false,
+    }
+}
+
+/// Decodes the character that ends just before byte index `i`, the same way
+/// a `CharReader` would, and returns it along with the index it starts at.
+#[inline]
+fn decode_before(input: &[u8], unicode: bool, i: uint) -> (char, uint) {
+    if unicode {
+        vm::decode_last_utf8(input, i)
+    } else {
+        (input[i - 1] as char, i - 1)
+    }
+}
+
+/// Identifies a DFA state.
+#[deriving(Clone, PartialEq, Eq, Hash)]
+struct StateKey {
+    /// The instructions that threads resume from after consuming a
+    /// character, in priority order.
+    insts: Vec<InstIdx>,
+    /// Whether a match was found at an earlier position. Once it is, new
+    /// threads are no longer started at the beginning of the program.
+    matched: bool,
+}
+
+struct State {
+    key: StateKey,
+    /// The instructions of the state that consume a character, in priority
+    /// order, at a position that is neither the beginning nor the end of the
+    /// input.
+    insts: Vec<InstIdx>,
+    /// Whether a match ends at such a position.
+    is_match: bool,
+    /// Transitions on ASCII characters, indexed by equivalence class.
+    ascii: Vec<StatePtr>,
+    /// Transitions on all other characters.
+    other: HashMap<char, StatePtr>,
+}
+
+/// The states of a DFA that have been built so far.
+struct Cache {
+    states: Vec<State>,
+    map: HashMap<StateKey, StatePtr>,
+    /// The number of transitions in the `other` maps of all states.
+    other_transitions: uint,
+}
+
+impl Cache {
+    fn new() -> Cache {
+        Cache { states: vec![], map: HashMap::new(), other_transitions: 0 }
+    }
+
+    fn clear(&mut self) {
+        self.states.clear();
+        self.map.clear();
+        self.other_transitions = 0;
+    }
+
+    fn is_full(&self) -> bool {
+        self.states.len() >= MAX_STATES || self.other_transitions >= MAX_OTHER_TRANSITIONS
+    }
+}
+
+struct Dfa<'a> {
+    prog: &'a Program,
+    classes: &'a [u8],
+    nclasses: uint,
+    /// When true, the DFA finds the longest match that begins where the
+    /// search starts (this is how a reversed program is run). Otherwise, it
+    /// finds where the leftmost-first match ends.
+    longest: bool,
+    cache: &'a mut Cache,
+}
+
+impl<'a> Dfa<'a> {
+    /// Finds the end of the leftmost-first match.
+    ///
+    /// The second value returned is `None` if the search stopped before
+    /// `end`, in which case text following `end` couldn't change its result.
+    /// Otherwise, it is a position before which no match can begin (see
+    /// `vm::run_partial`).
+    fn exec_forward(&mut self, quit_early: bool, input: &[u8], unicode: bool,
+                    start: uint, end: uint) -> (DfaResult, Option<uint>) {
+        let anchored = self.is_anchored();
+        let mut chars = CharReader::from_bytes(input, unicode);
+        let key = self.start_key();
+        let mut si = self.add_state(key);
+        let mut last_match = None;
+        let mut keep = Some(start);
+
+        let skip = !self.prog.prefixes.is_empty();
+        let mut ic = start;
+        let mut next_ic = chars.set(start);
+        while ic <= end {
+            if self.is_start(si) {
+                keep = Some(ic);
+            }
+            if skip && ic < input.len() && self.is_start(si) {
+                // No match can start before one of the prefixes.
+                match self.prog.prefixes.find(input[ic..]) {
+                    None => {
+                        keep = Some(vm::prefix_keep(self.prog, input,
+                                                    unicode, ic));
+                        break
+                    }
+                    Some(0) => {}
+                    Some(i) => {
+                        ic += i;
+                        next_ic = chars.set(ic);
+                        continue
+                    }
+                }
+            }
+            let (is_match, next) =
+                if chars.is_begin() || chars.is_end() {
+                    // These positions aren't cached since the assertions in
+                    // the state may behave differently here.
+                    let key = self.cache.states[si].key.clone();
+                    let (insts, is_match) =
+                        self.closure(&key, chars.is_begin(), chars.is_end());
+                    let next = chars.cur.map(|c| StateKey {
+                        insts: self.step(insts.as_slice(), c),
+                        matched: key.matched || is_match,
+                    });
+                    (is_match, next.map(|key| self.add_state(key)))
+                } else {
+                    let is_match = self.cache.states[si].is_match;
+                    (is_match, Some(self.next_state(si, chars.cur.unwrap())))
+                };
+            if is_match {
+                if quit_early {
+                    return (MatchEnd(ic), None)
+                }
+                last_match = Some(ic);
+            }
+            si = match next {
+                None => {
+                    keep = None;
+                    break
+                }
+                Some(si) => si,
+            };
+            if self.is_done(si)
+               || (anchored && self.cache.states[si].key.insts.len() == 0) {
+                keep = None;
+                break
+            }
+            ic = next_ic;
+            next_ic = chars.advance();
+        }
+        let result = match last_match {
+            None => NoMatch,
+            Some(e) => MatchEnd(e),
+        };
+        (result, keep)
+    }
+
+    fn exec_reverse(&mut self, input: &[u8], unicode: bool,
+                    start: uint, end: uint) -> DfaResult {
+        let key = self.start_key();
+        let mut si = self.add_state(key);
+        let mut last_match = None;
+
+        let mut ic = end;
+        loop {
+            // The reversed program reads the input back to front, so the end
+            // of the input is its beginning, and vice versa.
+            let (begin, at_end) = (ic == input.len(), ic == 0);
+            let (insts, is_match) =
+                if begin || at_end {
+                    let key = self.cache.states[si].key.clone();
+                    let (insts, is_match) = self.closure(&key, begin, at_end);
+                    (Some(insts), is_match)
+                } else {
+                    (None, self.cache.states[si].is_match)
+                };
+            if is_match {
+                last_match = Some(ic);
+            }
+            if ic <= start {
+                break
+            }
+            let (c, prev_ic) = decode_before(input, unicode, ic);
+            si = match insts {
+                None => self.next_state(si, c),
+                Some(insts) => {
+                    let key = StateKey {
+                        insts: self.step(insts.as_slice(), c),
+                        matched: false,
+                    };
+                    self.add_state(key)
+                }
+            };
+            if self.is_done(si) {
+                break
+            }
+            ic = prev_ic;
+        }
+        match last_match {
+            None => NoMatch,
+            Some(s) => MatchEnd(s),
+        }
+    }
+
+    /// Returns true if matches can only begin at the beginning of the input.
+    /// (See the same check in vm.rs.)
+    fn is_anchored(&self) -> bool {
+        !self.longest && match self.prog.insts[1] {
+            EmptyBegin(flags) if flags & FLAG_MULTI == 0 => true,
+            _ => false,
+        }
+    }
+
+    fn start_key(&self) -> StateKey {
+        StateKey {
+            insts: if self.longest { vec![0] } else { vec![] },
+            matched: false,
+        }
+    }
+
+    /// Returns true if state `si` has no threads and hasn't matched yet,
+    /// which means a match can only start at the current position or later.
+    fn is_start(&self, si: StatePtr) -> bool {
+        let key = &self.cache.states[si].key;
+        key.insts.len() == 0 && !key.matched
+    }
+
+    /// Returns true if no further match can be found from state `si`.
+    fn is_done(&self, si: StatePtr) -> bool {
+        let key = &self.cache.states[si].key;
+        key.insts.len() == 0 && (self.longest || key.matched)
+    }
+
+    /// Follows the empty transitions out of the instructions in `key` (and,
+    /// for a leftmost-first search that hasn't matched yet, out of the
+    /// beginning of the program), in priority order, and returns the
+    /// instructions reached that consume a character. The second value
+    /// returned is whether a match is reached.
+    ///
+    /// `begin` and `end` indicate whether the position is the beginning or
+    /// the end of the input.
+    fn closure(&self, key: &StateKey, begin: bool, end: bool)
+              -> (Vec<InstIdx>, bool) {
+        let mut seen = Vec::from_elem(self.prog.insts.len(), false);
+        let mut reached = vec![];
+        for &pc in key.insts.iter() {
+            self.follow(pc, begin, end, &mut seen, &mut reached);
+        }
+        if !self.longest && !key.matched {
+            self.follow(0, begin, end, &mut seen, &mut reached);
+        }
+
+        let mut insts = Vec::with_capacity(reached.len());
+        let mut is_match = false;
+        for &pc in reached.iter() {
+            match self.prog.insts[pc] {
+                Match => {
+                    is_match = true;
+                    // Just like the NFA, threads with a lower priority than
+                    // a match are dropped for leftmost-first semantics.
+                    if !self.longest {
+                        break
+                    }
+                }
+                _ => insts.push(pc),
+            }
+        }
+        (insts, is_match)
+    }
+
+    fn follow(&self, pc: InstIdx, begin: bool, end: bool,
+              seen: &mut Vec<bool>, reached: &mut Vec<InstIdx>) {
+        if seen[pc] {
+            return
+        }
+        seen[pc] = true;
+        match self.prog.insts[pc] {
+            EmptyBegin(_) => {
+                if begin {
+                    self.follow(pc + 1, begin, end, seen, reached)
+                }
+            }
+            EmptyEnd(_) => {
+                if end {
+                    self.follow(pc + 1, begin, end, seen, reached)
+                }
+            }
+            Save(_) => self.follow(pc + 1, begin, end, seen, reached),
+            Jump(to) => self.follow(to, begin, end, seen, reached),
+            Split(x, y) => {
+                self.follow(x, begin, end, seen, reached);
+                self.follow(y, begin, end, seen, reached);
+            }
+            Match | OneChar(_, _) | CharClass(_, _) | Any(_) => {
+                reached.push(pc)
+            }
+            EmptyWordBoundary(_) | SetMatch(_)
+            | EmptyLook(_, _) | LookMatch | Backreference(_, _) => {
+                panic!("BUG: Instruction can't be run by a DFA.")
+            }
+        }
+    }
+
+    /// Returns the instructions that threads resume from after the
+    /// instructions in `insts` consume `c`.
+    fn step(&self, insts: &[InstIdx], c: char) -> Vec<InstIdx> {
+        insts.iter()
+             .filter(|&&pc| matches_char(&self.prog.insts[pc], c))
+             .map(|&pc| pc + 1)
+             .collect()
+    }
+
+    /// Returns the state identified by `key`, building it if necessary.
+    fn add_state(&mut self, key: StateKey) -> StatePtr {
+        match self.cache.map.get(&key) {
+            Some(&si) => return si,
+            None => {}
+        }
+        let (insts, is_match) = self.closure(&key, false, false);
+        let si = self.cache.states.len();
+        self.cache.states.push(State {
+            key: key.clone(),
+            insts: insts,
+            is_match: is_match,
+            ascii: Vec::from_elem(self.nclasses, UNKNOWN),
+            other: HashMap::new(),
+        });
+        self.cache.map.insert(key, si);
+        si
+    }
+
+    /// Returns the state reached from state `si` by consuming `c` at a
+    /// position that is neither the beginning nor the end of the input.
+    ///
+    /// If the cache is full, then it is flushed, so any other state pointers
+    /// held by the caller are invalidated.
+    fn next_state(&mut self, mut si: StatePtr, c: char) -> StatePtr {
+        let ascii = (c as u32) < 0x80;
+        let class = if ascii { self.classes[c as uint] as uint } else { 0 };
+        let cached =
+            if ascii {
+                self.cache.states[si].ascii[class]
+            } else {
+                match self.cache.states[si].other.get(&c) {
+                    Some(&next) => next,
+                    None => UNKNOWN,
+                }
+            };
+        if cached != UNKNOWN {
+            return cached
+        }
+
+        let key = StateKey {
+            insts: self.step(self.cache.states[si].insts.as_slice(), c),
+            matched: !self.longest && (self.cache.states[si].key.matched
+                                       || self.cache.states[si].is_match),
+        };
+        if self.cache.is_full() {
+            let cur = self.cache.states[si].key.clone();
+            self.cache.clear();
+            si = self.add_state(cur);
+        }
+        let next = self.add_state(key);
+        if ascii {
+            self.cache.states[si].ascii[class] = next;
+        } else {
+            self.cache.states[si].other.insert(c, next);
+            self.cache.other_transitions += 1;
+        }
+        next
+    }
+}
@@ -13,9 +13,10 @@
 //! This crate provides a native implementation of regular expressions that is
 //! heavily based on RE2 both in syntax and in implementation. Notably,
 //! backreferences and arbitrary lookahead/lookbehind assertions are not
-//! provided. In return, regular expression searching provided by this package
-//! has excellent worst case performance. The specific syntax supported is
-//! documented further down.
+//! provided by default. In return, regular expression searching provided by
+//! this package has excellent worst case performance. (They can be enabled
+//! at the cost of that guarantee, see "Look-around and backreferences" below.)
+//! The specific syntax supported is documented further down.
 //!
 //! This crate's documentation provides some simple examples, describes Unicode
 //! support and exhaustively lists the supported syntax. For more specific
@@ -157,6 +158,40 @@
 //! only need to test if an expression matches a string. (Use `is_match`
 //! instead.)
 //!
+//! When an expression doesn't use word boundaries or multi-line anchors, the
+//! first two questions are answered with a lazily built DFA, which is much
+//! faster than simulating an NFA. Finding submatches still requires the NFA,
+//! but the DFA first narrows the search down to the text of the match.
+//!
+//! Searches are also faster when every match has to start or end with one of
+//! a few literal strings, as in `ERROR: .*timeout` or `(foo|bar)\d+`. Those
+//! literals are found with a fast substring search, which skips over text
+//! where a match can't start, and quickly rules out text that can't match at
+//! all. Case insensitive literals don't benefit from this.
+//!
+//! # Searching bytes
+//!
+//! The `Regex` type only searches valid UTF-8 text. To search arbitrary bytes,
+//! such as binary files or text in an unknown encoding, use the `Regex` type
+//! in the `bytes` module instead. It provides the same interface for `&[u8]`
+//! and allows Unicode mode to be disabled with a leading `(?-u)`, in which
+//! case every byte is matched on its own.
+//!
+//! # Matching many expressions at once
+//!
+//! When you need to know which of many expressions match some text, compiling
+//! them into a `RegexSet` lets the text be scanned only once, no matter how
+//! many expressions are in the set:
+//!
+//! ```rust
+//! use regex::RegexSet;
+//! let set = RegexSet::new(&[r"^ERROR", r"timeout", r"\d{3}ms"]).unwrap();
+//! let matched: Vec<uint> = set.matches("ERROR: timeout").iter().collect();
+//! assert_eq!(matched, vec![0, 1]);
+//! ```
+//!
+//! A set can only report which expressions matched, not where they matched.
+//!
 //! # Unicode
 //!
 //! This implementation executes regular expressions **only** on sequences of
@@ -264,6 +299,21 @@
 //! (?flags:exp)   set flags for exp (non-capturing)
 //! </pre>
 //!
+//! With the backtracking engine enabled (see below), the following are also
+//! supported:
+//!
+//! <pre class="rust">
+//! (?=exp)        look-ahead: exp matches the text that follows
+//! (?!exp)        negative look-ahead: exp doesn't match the text that follows
+//! (?&lt;=exp)       look-behind: exp matches the text that precedes
+//! (?&lt;!exp)       negative look-behind: exp doesn't match the text that precedes
+//! \1 ... \99     backreference: the text last matched by that capture group
+//! </pre>
+//!
+//! Note that this means an escape like `\12` is a backreference rather than
+//! an octal character code. Octal escapes starting with `0`, like `\012`, are
+//! unaffected.
+//!
 //! Flags are each a single character. For example, `(?x)` sets the flag `x`
 //! and `(?-x)` clears the flag `x`. Multiple flags can be set or cleared at
 //! the same time: `(?xy)` sets both the `x` and `y` flags and `(?x-y)` sets
@@ -276,8 +326,17 @@
 //! m     multi-line mode: ^ and $ match begin/end of line
 //! s     allow . to match \n
 //! U     swap the meaning of x* and x*?
+//! u     Unicode mode (only disabled with a leading `(?-u)` in `bytes::Regex`)
+//! x     ignore whitespace and allow line comments (starting with `#`)
 //! </pre>
 //!
+//! The `i`, `m`, `s` and `x` flags can also be set for an entire expression
+//! with a `RegexBuilder`.
+//!
+//! With the `x` flag set, whitespace is still matched inside character
+//! classes, and can be matched elsewhere by escaping it (as in `\ `). A `#`
+//! can be matched with `\#`.
+//!
 //! Here's an example that matches case insensitively for only part of the
 //! expression:
 //!
@@ -342,24 +401,55 @@
 //! [:xdigit:]   hex digit ([0-9A-Fa-f])
 //! </pre>
 //!
+//! # Look-around and backreferences
+//!
+//! Look-around assertions and backreferences can't be matched by finite
+//! automata, so they are only available with a backtracking engine, which
+//! must be enabled explicitly with `RegexBuilder::backtrack`:
+//!
+//! ```rust
+//! use regex::RegexBuilder;
+//! let re = RegexBuilder::new(r"\b(\w+) \1\b").backtrack(true).compile().unwrap();
+//! assert_eq!(re.find("it is is it"), Some((3, 8)));
+//! ```
+//!
+//! Only expressions that actually use these features are run by the
+//! backtracking engine. It tries the alternatives of an expression one at a
+//! time, so its search time can be exponential in the length of the text.
+//! To keep that in check, every search is limited to a number of steps, set
+//! with `RegexBuilder::backtrack_limit`. A search that exceeds it makes the
+//! `try_` methods, such as `try_find` and `try_replace_all`, return a
+//! `LimitError`. The other search methods treat it as finding no match from
+//! where the search gave up, so they never panic on untrusted text.
+//!
+//! Look-around assertions are atomic (once one matches, other ways of matching
+//! it aren't tried), may not be repeated and may contain capture groups.
+//! Look-behind assertions may match text of any length. These features aren't
+//! supported by the `regex!` macro, `RegexSet` or `bytes::Regex`.
+//!
 //! # Untrusted input
 //!
 //! There are two factors to consider here: untrusted regular expressions and
 //! untrusted search text.
 //!
-//! Currently, there are no counter-measures in place to prevent a malicious
-//! user from writing an expression that may use a lot of resources. One such
-//! example is to repeat counted repetitions: `((a{100}){100}){100}` will try
-//! to repeat the `a` instruction `100^3` times. Essentially, this means it's
-//! very easy for an attacker to exhaust your system's memory if they are
-//! allowed to execute arbitrary regular expressions. A possible solution to
-//! this is to impose a hard limit on the size of a compiled expression, but it
-//! does not yet exist.
+//! A malicious user could write an expression that uses a lot of resources.
+//! One such example is to repeat counted repetitions: `((a{100}){100}){100}`
+//! will try to repeat the `a` instruction `100^3` times. To prevent this,
+//! compile untrusted expressions with `RegexBuilder::size_limit`, which
+//! rejects expressions whose compiled form would be bigger than the limit
+//! before they are built. (`RegexSet` and `bytes::Regex` don't impose a
+//! limit.)
+//!
+//! When showing a parse error to the user who wrote the expression,
+//! `Error::render` quotes the expression and points out where the error is.
 //!
 //! The story is a bit better with untrusted search text, since this crate's
 //! implementation provides `O(nm)` search where `n` is the number of
 //! characters in the search text and `m` is the number of instructions in a
-//! compiled expression.
+//! compiled expression. (The DFA never makes this worse, since the states it
+//! builds are cached up to a fixed limit, after which the cache is cleared.)
+//! This does not hold for the backtracking engine, which is why it is opt-in
+//! and why its searches are limited.
 
 #![crate_name = "regex"]
 #![crate_type = "rlib"]
@@ -388,15 +478,23 @@ extern crate regex;
 // Unicode tables for character classes are defined in libunicode
 extern crate unicode;
 
+pub use backtrack::LimitError;
 pub use parse::Error;
-pub use re::{Regex, Captures, SubCaptures, SubCapturesPos};
-pub use re::{FindCaptures, FindMatches};
+pub use re::{Regex, RegexBuilder, Captures, SubCaptures, SubCapturesPos};
+pub use re::{FindCaptures, FindMatches, TryFindCaptures, TryFindMatches};
 pub use re::{Replacer, NoExpand, RegexSplits, RegexSplitsN};
 pub use re::{quote, is_match};
+pub use set::{RegexSet, SetMatches, SetMatchesIter};
+
+pub mod bytes;
 
+mod backtrack;
 mod compile;
+mod dfa;
+mod literals;
 mod parse;
 mod re;
+mod set;
 mod vm;
 
 #[cfg(test)]
@@ -429,10 +527,15 @@ pub mod native {
         FLAG_EMPTY, FLAG_NOCASE, FLAG_MULTI, FLAG_DOTNL,
         FLAG_SWAP_GREED, FLAG_NEGATED,
     };
+    pub use dfa::{
+        DfaResult, NoMatch, MatchEnd, GaveUp,
+        StaticDfa, StaticDfaTables, build_static, exec_static,
+    };
+    pub use literals::{Literals, find_any};
     pub use re::{Dynamic, ExDynamic, Native, ExNative};
     pub use vm::{
         MatchKind, Exists, Location, Submatches,
         StepState, StepMatchEarlyReturn, StepMatch, StepContinue,
-        CharReader, find_prefix,
+        CharReader,
     };
 }
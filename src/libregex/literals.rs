@@ -0,0 +1,160 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Substring searching for the literals that every match of an expression has
+// to start (or end) with. The literals themselves are found by the compiler.
+//
+// Finding a literal with a plain substring search is much faster than
+// stepping the VM through text that can't possibly match, so the VMs use
+// these searchers to skip ahead to the next place a match could start, and
+// to give up early when a literal that every match ends with doesn't occur.
+
+use self::Searcher::*;
+
+/// A set of literal byte strings, at least one of which occurs in every match
+/// of a regular expression, along with a searcher for them.
+///
+/// An empty set means that nothing is known about the matches.
+#[deriving(Clone)]
+pub struct Literals {
+    lits: Vec<Vec<u8>>,
+    searcher: Searcher,
+}
+
+#[deriving(Clone)]
+enum Searcher {
+    Nothing,
+    // A single byte, found by scanning for it, like memchr.
+    OneByte(u8),
+    // A single literal, found with Boyer-Moore-Horspool.
+    OneLiteral(BoyerMoore),
+    // Several literals, found by scanning for any of their first bytes and
+    // then checking each literal at that position.
+    Many(Vec<bool>),
+}
+
+impl Literals {
+    /// Returns an empty set of literals.
+    pub fn empty() -> Literals {
+        Literals { lits: vec![], searcher: Nothing }
+    }
+
+    /// Builds a searcher for the literals given. None of them may be empty.
+    pub fn new(mut lits: Vec<Vec<u8>>) -> Literals {
+        lits.sort();
+        lits.dedup();
+        let searcher =
+            if lits.len() == 0 {
+                Nothing
+            } else if lits.len() == 1 && lits[0].len() == 1 {
+                OneByte(lits[0][0])
+            } else if lits.len() == 1 {
+                OneLiteral(BoyerMoore::new(lits[0].clone()))
+            } else {
+                let mut first = Vec::from_elem(256, false);
+                for lit in lits.iter() {
+                    first[lit[0] as uint] = true;
+                }
+                Many(first)
+            };
+        Literals { lits: lits, searcher: searcher }
+    }
+
+    /// Returns true if and only if there are no literals in this set.
+    pub fn is_empty(&self) -> bool {
+        self.lits.len() == 0
+    }
+
+    /// Returns the literals in this set, in sorted order.
+    pub fn literals<'a>(&'a self) -> &'a [Vec<u8>] {
+        self.lits.as_slice()
+    }
+
+    /// Returns the index of the earliest occurrence of any of the literals in
+    /// `haystack`. If the set is empty, then `0` is returned, since a match
+    /// could begin anywhere.
+    #[inline]
+    pub fn find(&self, haystack: &[u8]) -> Option<uint> {
+        match self.searcher {
+            Nothing => Some(0),
+            OneByte(b) => haystack.iter().position(|&x| x == b),
+            OneLiteral(ref bm) => bm.find(haystack),
+            Many(ref first) => {
+                for (i, &b) in haystack.iter().enumerate() {
+                    if first[b as uint] {
+                        let rest = haystack[i..];
+                        if self.lits.iter().any(|lit| rest.starts_with(&**lit)) {
+                            return Some(i)
+                        }
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+/// Returns the index of the earliest occurrence of any of `lits` in
+/// `haystack`. If `lits` is empty, then `0` is returned.
+///
+/// This is used by the code generated by `regex!`, which can't build the
+/// tables of a `Literals` searcher ahead of time.
+pub fn find_any(lits: &[&[u8]], haystack: &[u8]) -> Option<uint> {
+    match lits.len() {
+        0 => Some(0),
+        1 if lits[0].len() == 1 => {
+            let b = lits[0][0];
+            haystack.iter().position(|&x| x == b)
+        }
+        _ => {
+            for i in range(0, haystack.len()) {
+                let rest = haystack[i..];
+                if lits.iter().any(|&lit| rest.starts_with(lit)) {
+                    return Some(i)
+                }
+            }
+            None
+        }
+    }
+}
+
+/// A Boyer-Moore-Horspool searcher for a single literal.
+#[deriving(Clone)]
+struct BoyerMoore {
+    pat: Vec<u8>,
+    // How far the pattern can be shifted when the byte in the haystack that
+    // is aligned with the end of the pattern is the byte given.
+    skip: Vec<uint>,
+}
+
+impl BoyerMoore {
+    fn new(pat: Vec<u8>) -> BoyerMoore {
+        let n = pat.len();
+        let mut skip = Vec::from_elem(256, n);
+        for (i, &b) in pat[..n - 1].iter().enumerate() {
+            skip[b as uint] = n - 1 - i;
+        }
+        BoyerMoore { pat: pat, skip: skip }
+    }
+
+    fn find(&self, haystack: &[u8]) -> Option<uint> {
+        let n = self.pat.len();
+        let last = self.pat[n - 1];
+        let mut i = 0;
+        while i + n <= haystack.len() {
+            let b = haystack[i + n - 1];
+            if b == last && haystack[i..i + n] == self.pat[] {
+                return Some(i)
+            }
+            i += self.skip[b as uint];
+        }
+        None
+    }
+}
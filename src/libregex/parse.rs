@@ -9,6 +9,7 @@
 // except according to those terms.
 
 pub use self::Ast::*;
+pub use self::LookKind::*;
 pub use self::Repeater::*;
 pub use self::Greed::*;
 use self::BuildAst::*;
@@ -19,6 +20,7 @@ use std::fmt;
 use std::iter;
 use std::num;
 use std::slice::BinarySearchResult;
+use compile;
 
 /// Static data containing Unicode ranges for general categories and scripts.
 use unicode::regex::{UNICODE_CLASSES, PERLD, PERLS, PERLW};
@@ -30,12 +32,75 @@ static MAX_REPEAT: uint = 1000;
 /// a regular expression.
 ///
 /// (Once an expression is compiled, it is not possible to produce an error
-/// via searching, splitting or replacing.)
+/// via searching, splitting or replacing, except when the backtracking engine
+/// gives up. See `LimitError`.)
 pub struct Error {
     /// The *approximate* character index of where the error occurred.
     pub pos: uint,
     /// A message describing the error.
     pub msg: String,
+    span: (uint, uint),
+    expr: String,
+}
+
+impl Error {
+    /// Returns the character indices of the start and end of the part of the
+    /// expression that the error is about, e.g., an entire unclosed group.
+    /// The end may be one past the end of the expression when the error is
+    /// that it ends too early.
+    pub fn span(&self) -> (uint, uint) {
+        self.span
+    }
+
+    /// Returns the expression that failed to parse.
+    pub fn expr(&self) -> &str {
+        self.expr.as_slice()
+    }
+
+    /// Returns a description of the error that quotes the expression and
+    /// points out where the error is, suitable for showing to end users.
+    ///
+    /// For example, the error for `a(b|c` is rendered as:
+    ///
+    /// ```text
+    /// regex parse error:
+    ///     a(b|c
+    ///      ^^^^
+    /// error: Unclosed parenthesis.
+    /// ```
+    ///
+    /// Only the line the error starts on is quoted if the expression spans
+    /// several lines.
+    pub fn render(&self) -> String {
+        let chars: Vec<char> = self.expr.chars().collect();
+        let (start, end) = self.span;
+        let start = cmp::min(start, chars.len());
+        let mut line_start = start;
+        while line_start > 0 && chars[line_start - 1] != '\n' {
+            line_start -= 1;
+        }
+        let mut line_end = start;
+        while line_end < chars.len() && chars[line_end] != '\n' {
+            line_end += 1;
+        }
+        // Point at one character past the end of the line at most, but always
+        // at least one character.
+        let end = cmp::max(cmp::min(end, line_end + 1), start + 1);
+
+        let mut out = String::from_str("regex parse error:\n    ");
+        out.push_str(String::from_chars(chars[line_start..line_end]).as_slice());
+        out.push_str("\n    ");
+        for &c in chars[line_start..start].iter() {
+            // Keep the carets lined up with the expression when it has tabs.
+            out.push(if c == '\t' { '\t' } else { ' ' });
+        }
+        for _ in range(start, end) {
+            out.push('^');
+        }
+        out.push_str("\nerror: ");
+        out.push_str(self.msg.as_slice());
+        out
+    }
 }
 
 impl fmt::Show for Error {
@@ -68,6 +133,39 @@ pub enum Ast {
     Cat(Vec<Ast>),
     Alt(Box<Ast>, Box<Ast>),
     Rep(Box<Ast>, Repeater, Greed),
+    // Look-around assertions and backreferences are only parsed when the
+    // backtracking engine is enabled (see `Options`).
+    Look(LookKind, Box<Ast>),
+    Backref(uint, Flags),
+}
+
+/// The kinds of look-around assertions.
+#[deriving(Copy, Show, PartialEq, Clone)]
+pub enum LookKind {
+    LookAhead,     // (?=exp)
+    NegLookAhead,  // (?!exp)
+    LookBehind,    // (?<=exp)
+    NegLookBehind, // (?<!exp)
+}
+
+impl LookKind {
+    /// Returns true if the assertion succeeds when its expression does *not*
+    /// match.
+    pub fn is_negated(&self) -> bool {
+        match *self {
+            NegLookAhead | NegLookBehind => true,
+            LookAhead | LookBehind => false,
+        }
+    }
+
+    /// Returns true if the expression has to match the text that ends at the
+    /// current position, rather than the text that begins there.
+    pub fn is_behind(&self) -> bool {
+        match *self {
+            LookBehind | NegLookBehind => true,
+            LookAhead | NegLookAhead => false,
+        }
+    }
 }
 
 #[deriving(Show, PartialEq, Clone)]
@@ -108,35 +206,43 @@ impl Greed {
 enum BuildAst {
     Expr(Ast),
     Paren(Flags, uint, String), // '('
+    LookParen(Flags, LookKind), // '(?=', '(?!', '(?<=' or '(?<!'
     Bar, // '|'
 }
 
 impl BuildAst {
     fn paren(&self) -> bool {
         match *self {
-            Paren(_, _, _) => true,
+            Paren(_, _, _) | LookParen(_, _) => true,
             _ => false,
         }
     }
 
     fn flags(&self) -> Flags {
         match *self {
-            Paren(flags, _, _) => flags,
+            Paren(flags, _, _) | LookParen(flags, _) => flags,
             _ => panic!("Cannot get flags from {}", self),
         }
     }
 
     fn capture(&self) -> Option<uint> {
         match *self {
-            Paren(_, 0, _) => None,
+            Paren(_, 0, _) | LookParen(_, _) => None,
             Paren(_, c, _) => Some(c),
             _ => panic!("Cannot get capture group from {}", self),
         }
     }
 
+    fn look(&self) -> Option<LookKind> {
+        match *self {
+            LookParen(_, kind) => Some(kind),
+            _ => None,
+        }
+    }
+
     fn capture_name(&self) -> Option<String> {
         match *self {
-            Paren(_, 0, _) => None,
+            Paren(_, 0, _) | LookParen(_, _) => None,
             Paren(_, _, ref name) => {
                 if name.len() == 0 {
                     None
@@ -173,6 +279,7 @@ pub const FLAG_MULTI:      u8 = 1 << 1; // m
 pub const FLAG_DOTNL:      u8 = 1 << 2; // s
 pub const FLAG_SWAP_GREED: u8 = 1 << 3; // U
 pub const FLAG_NEGATED:    u8 = 1 << 4; // char class or not word boundary
+pub const FLAG_IGNORE_SPACE: u8 = 1 << 5; // x
 
 struct Parser<'a> {
     // The input, parsed only as a sequence of UTF8 code points.
@@ -189,20 +296,84 @@ struct Parser<'a> {
     caps: uint,
     // A set of all capture group names used only to detect duplicates.
     names: Vec<String>,
+    // Whether the expression will be used to search arbitrary bytes instead
+    // of text. Unicode mode can only be disabled in that case.
+    bytes: bool,
+    // Whether Unicode mode is enabled. When it is disabled, the expression
+    // matches one byte at a time and may only use the characters `U+0000`
+    // through `U+00FF`, which stand for the bytes with the same value.
+    unicode: bool,
+    // Whether look-around assertions and backreferences are allowed.
+    backtrack: bool,
+    // The maximum approximate size in bytes of the compiled program, if any.
+    size_limit: Option<uint>,
+    // The index of the opening parenthesis of every group that is still open.
+    parens: Vec<uint>,
+}
+
+/// Options that change how an expression is parsed, which can't be set from
+/// within the expression.
+#[deriving(Copy, Clone)]
+pub struct Options {
+    /// Whether look-around assertions and backreferences are allowed. Only
+    /// the backtracking engine can run expressions that use them.
+    pub backtrack: bool,
+    /// The flags set at the start of the expression, as if it began with a
+    /// `(?flags)` group.
+    pub flags: Flags,
+    /// The maximum approximate size in bytes of the program an expression
+    /// compiles to. Expressions that are bigger are rejected (before they
+    /// are fully built).
+    pub size_limit: Option<uint>,
+}
+
+impl Options {
+    /// The options used by `parse`.
+    pub fn new() -> Options {
+        Options { backtrack: false, flags: FLAG_EMPTY, size_limit: None }
+    }
 }
 
 pub fn parse(s: &str) -> Result<Ast, Error> {
-    Parser {
-        chars: s.chars().collect(),
-        chari: 0,
-        stack: vec!(),
-        flags: FLAG_EMPTY,
-        caps: 0,
-        names: vec!(),
-    }.parse()
+    parse_with(s, Options::new())
+}
+
+/// Parses an expression for searching text with the options given.
+pub fn parse_with(s: &str, opts: Options) -> Result<Ast, Error> {
+    let mut p = Parser::new(s, false);
+    p.backtrack = opts.backtrack;
+    p.flags = opts.flags;
+    p.size_limit = opts.size_limit;
+    p.parse()
+}
+
+/// Parses an expression used to search arbitrary bytes instead of text.
+///
+/// Along with the AST, this returns whether Unicode mode is enabled for the
+/// expression. It is enabled unless the expression starts with `(?-u)`.
+pub fn parse_bytes(s: &str) -> Result<(Ast, bool), Error> {
+    let mut p = Parser::new(s, true);
+    let ast = try!(p.parse());
+    Ok((ast, p.unicode))
 }
 
 impl<'a> Parser<'a> {
+    fn new(s: &str, bytes: bool) -> Parser<'a> {
+        Parser {
+            chars: s.chars().collect(),
+            chari: 0,
+            stack: vec!(),
+            flags: FLAG_EMPTY,
+            caps: 0,
+            names: vec!(),
+            bytes: bytes,
+            unicode: true,
+            backtrack: false,
+            size_limit: None,
+            parens: vec!(),
+        }
+    }
+
     fn parse(&mut self) -> Result<Ast, Error> {
         if self.chars.len() == 0 {
             return Ok(Nothing);
@@ -210,6 +381,8 @@ impl<'a> Parser<'a> {
         loop {
             let c = self.cur();
             match c {
+                c if self.flags & FLAG_IGNORE_SPACE > 0 && c.is_whitespace() => {}
+                '#' if self.flags & FLAG_IGNORE_SPACE > 0 => self.skip_comment(),
                 '?' | '*' | '+' => try!(self.push_repeater(c)),
                 '\\' => {
                     let ast = try!(self.parse_escape());
@@ -226,6 +399,7 @@ impl<'a> Parser<'a> {
                         try!(self.parse_group_opts())
                     } else {
                         self.caps += 1;
+                        self.parens.push(self.chari);
                         self.stack.push(Paren(self.flags,
                                               self.caps,
                                               "".to_string()))
@@ -239,13 +413,15 @@ impl<'a> Parser<'a> {
                     let altfrom = try!(self.pos_last(false, |x| x.paren()));
                     // Before we smush the alternates together and pop off the
                     // left paren, let's grab the old flags and see if we
-                    // need a capture.
-                    let (cap, cap_name, oldflags) = {
+                    // need a capture (or a look-around assertion).
+                    let (cap, cap_name, look, oldflags) = {
                         let paren = &self.stack[altfrom-1];
-                        (paren.capture(), paren.capture_name(), paren.flags())
+                        (paren.capture(), paren.capture_name(), paren.look(),
+                         paren.flags())
                     };
                     try!(self.alternate(altfrom));
                     self.flags = oldflags;
+                    self.parens.pop();
 
                     // If this was a capture, pop what we just pushed in
                     // alternate and make it a capture.
@@ -253,6 +429,10 @@ impl<'a> Parser<'a> {
                         let ast = try!(self.pop_ast());
                         self.push(Capture(cap.unwrap(), cap_name, box ast));
                     }
+                    if look.is_some() {
+                        let ast = try!(self.pop_ast());
+                        self.push(Look(look.unwrap(), box ast));
+                    }
                 }
                 '|' => {
                     let catfrom = try!(
@@ -271,14 +451,33 @@ impl<'a> Parser<'a> {
         // Try to improve error handling. At this point, there should be
         // no remaining open parens.
         if self.stack.iter().any(|x| x.paren()) {
-            return self.err("Unclosed parenthesis.")
+            let open = self.parens[self.parens.len() - 1];
+            return self.err_span(open, self.chars.len(), "Unclosed parenthesis.")
         }
         let catfrom = try!(self.pos_last(true, |x| x.bar()));
         try!(self.concat(catfrom));
         try!(self.alternate(0));
 
         assert!(self.stack.len() == 1);
-        self.pop_ast()
+        let ast = try!(self.pop_ast());
+        match self.size_limit {
+            Some(limit) if compile::approx_size(&ast) > limit => {
+                return self.err_span(0, self.chars.len(), format!(
+                    "Compiled regex exceeds size limit of {} bytes.",
+                    limit).as_slice())
+            }
+            _ => {}
+        }
+        Ok(ast)
+    }
+
+    // Skips a comment, which runs from a '#' to the end of the line.
+    // Assumes that '#' is the current character.
+    // When done, parser will be at the last character before the newline.
+    fn skip_comment(&mut self) {
+        while self.peek(1).map_or(false, |c| c != '\n') {
+            self.chari += 1;
+        }
     }
 
     fn noteof(&mut self, expected: &str) -> Result<(), Error> {
@@ -337,7 +536,7 @@ impl<'a> Parser<'a> {
         }
         let ast = try!(self.pop_ast());
         match ast {
-            Begin(_) | End(_) | WordBoundary(_) =>
+            Begin(_) | End(_) | WordBoundary(_) | Look(_, _) =>
                 return self.err(
                     "Repeat arguments cannot be empty width assertions."),
             _ => {}
@@ -360,6 +559,7 @@ impl<'a> Parser<'a> {
                 self.push(End(flags))
             }
             _ => {
+                let c = try!(self.check_unicode(c));
                 self.push(Literal(c, flags))
             }
         }
@@ -385,6 +585,7 @@ impl<'a> Parser<'a> {
         }
         loop {
             try!(self.noteof("a closing ']' or a non-empty character class)"));
+            let cstart = self.chari;
             let mut c = self.cur();
             match c {
                 '[' =>
@@ -406,9 +607,13 @@ impl<'a> Parser<'a> {
                         }
                         Literal(c2, _) => c = c2, // process below
                         Begin(_) | End(_) | WordBoundary(_) =>
-                            return self.err(
+                            return self.err_at(cstart,
                                 "\\A, \\z, \\b and \\B are not valid escape \
                                  sequences inside a character class."),
+                        Backref(_, _) =>
+                            return self.err_at(cstart,
+                                "Backreferences are not valid inside a \
+                                 character class."),
                         ast => panic!("Unexpected AST item '{}'", ast),
                     }
                 }
@@ -447,13 +652,15 @@ impl<'a> Parser<'a> {
                     }
                 }
                 if c2 < c {
-                    return self.err(format!("Invalid character class \
-                                             range '{}-{}'",
-                                            c,
-                                            c2).as_slice())
+                    return self.err_at(cstart, format!(
+                        "Invalid character class range '{}-{}'",
+                        c, c2).as_slice())
                 }
-                ranges.push((c, self.cur()))
+                let start = try!(self.check_unicode(c));
+                let end = try!(self.check_unicode(c2));
+                ranges.push((start, end))
             } else {
+                let c = try!(self.check_unicode(c));
                 ranges.push((c, c))
             }
         }
@@ -510,10 +717,9 @@ impl<'a> Parser<'a> {
             match self.pos('}') {
                 Some(i) => i,
                 None => {
-                    return self.err(format!("No closing brace for counted \
-                                             repetition starting at position \
-                                             {}.",
-                                            start).as_slice())
+                    return self.err_span(start, self.chars.len(), format!(
+                        "No closing brace for counted repetition starting at \
+                         position {}.", start).as_slice())
                 }
             };
         self.chari = closer;
@@ -530,8 +736,8 @@ impl<'a> Parser<'a> {
             let pieces: Vec<&str> = inner.splitn(1, ',').collect();
             let (smin, smax) = (pieces[0], pieces[1]);
             if smin.len() == 0 {
-                return self.err("Max repetitions cannot be specified \
-                                    without min repetitions.")
+                return self.err_at(start, "Max repetitions cannot be specified \
+                                           without min repetitions.")
             }
             min = try!(self.parse_uint(smin));
             max =
@@ -544,28 +750,42 @@ impl<'a> Parser<'a> {
 
         // Do some bounds checking and make sure max >= min.
         if min > MAX_REPEAT {
-            return self.err(format!(
+            return self.err_at(start, format!(
                 "{} exceeds maximum allowed repetitions ({})",
                 min, MAX_REPEAT).as_slice());
         }
         if max.is_some() {
             let m = max.unwrap();
             if m > MAX_REPEAT {
-                return self.err(format!(
+                return self.err_at(start, format!(
                     "{} exceeds maximum allowed repetitions ({})",
                     m, MAX_REPEAT).as_slice());
             }
             if m < min {
-                return self.err(format!(
+                return self.err_at(start, format!(
                     "Max repetitions ({}) cannot be smaller than min \
                      repetitions ({}).", m, min).as_slice());
             }
         }
 
+        // Check the size of the copies before making them, since they could
+        // be enormous when counted repetitions are nested.
+        let ast = try!(self.pop_ast());
+        match self.size_limit {
+            Some(limit) => {
+                let copies = cmp::max(max.unwrap_or(min + 1), 1);
+                if compile::approx_size(&ast) > limit / copies {
+                    return self.err_at(start, format!(
+                        "Compiled regex exceeds size limit of {} bytes.",
+                        limit).as_slice())
+                }
+            }
+            None => {}
+        }
+
         // Now manipulate the AST be repeating elements.
         if max.is_none() {
             // Require N copies of what's on the stack and then repeat it.
-            let ast = try!(self.pop_ast());
             for _ in iter::range(0, min) {
                 self.push(ast.clone())
             }
@@ -573,7 +793,6 @@ impl<'a> Parser<'a> {
         } else {
             // Require N copies of what's on the stack and then repeat it
             // up to M times optionally.
-            let ast = try!(self.pop_ast());
             for _ in iter::range(0, min) {
                 self.push(ast.clone())
             }
@@ -601,6 +820,9 @@ impl<'a> Parser<'a> {
         if is_punct(c) {
             return Ok(Literal(c, FLAG_EMPTY))
         }
+        if self.flags & FLAG_IGNORE_SPACE > 0 && (c == '#' || c.is_whitespace()) {
+            return Ok(Literal(c, FLAG_EMPTY))
+        }
         match c {
             'a' => Ok(Literal('\x07', FLAG_EMPTY)),
             'f' => Ok(Literal('\x0C', FLAG_EMPTY)),
@@ -612,18 +834,25 @@ impl<'a> Parser<'a> {
             'z' => Ok(End(FLAG_EMPTY)),
             'b' => Ok(WordBoundary(FLAG_EMPTY)),
             'B' => Ok(WordBoundary(FLAG_NEGATED)),
+            '1'...'9' if self.backtrack => Ok(try!(self.parse_backref())),
             '0'|'1'|'2'|'3'|'4'|'5'|'6'|'7' => Ok(try!(self.parse_octal())),
             'x' => Ok(try!(self.parse_hex())),
             'p' | 'P' => Ok(try!(self.parse_unicode_name())),
             'd' | 'D' | 's' | 'S' | 'w' | 'W' => {
-                let ranges = perl_unicode_class(c);
+                let ranges =
+                    if self.unicode {
+                        perl_unicode_class(c)
+                    } else {
+                        perl_ascii_class(c)
+                    };
                 let mut flags = self.flags & FLAG_NOCASE;
                 if c.is_uppercase() { flags |= FLAG_NEGATED }
                 Ok(AstClass(ranges, flags))
             }
             _ => {
-                self.err(format!("Invalid escape sequence '\\\\{}'",
-                                 c).as_slice())
+                self.err_at(self.chari - 1,
+                            format!("Invalid escape sequence '\\\\{}'",
+                                    c).as_slice())
             }
         }
     }
@@ -634,6 +863,11 @@ impl<'a> Parser<'a> {
     // Assumes that \p or \P has been read (and 'p' or 'P' is the current
     // character).
     fn parse_unicode_name(&mut self) -> Result<Ast, Error> {
+        let start = self.chari - 1;
+        if !self.unicode {
+            return self.err_at(start, "Unicode character classes are not \
+                                       allowed when Unicode mode is disabled.")
+        }
         let negated = if self.cur() == 'P' { FLAG_NEGATED } else { FLAG_EMPTY };
         let mut name: String;
         if self.peek_is(1, '{') {
@@ -641,7 +875,7 @@ impl<'a> Parser<'a> {
             let closer =
                 match self.pos('}') {
                     Some(i) => i,
-                    None => return self.err(format!(
+                    None => return self.err_span(start, self.chars.len(), format!(
                         "Missing '}}' for unclosed '{{' at position {}",
                         self.chari).as_slice()),
                 };
@@ -659,8 +893,9 @@ impl<'a> Parser<'a> {
         }
         match find_class(UNICODE_CLASSES, name.as_slice()) {
             None => {
-                return self.err(format!("Could not find Unicode class '{}'",
-                                        name).as_slice())
+                return self.err_at(start,
+                                   format!("Could not find Unicode class '{}'",
+                                           name).as_slice())
             }
             Some(ranges) => {
                 Ok(AstClass(ranges, negated | (self.flags & FLAG_NOCASE)))
@@ -668,6 +903,24 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // Parses a backreference, which is a decimal capture group number.
+    // Assumes that \n has been read, where n is the first digit.
+    // When done, parser will be at the last digit.
+    fn parse_backref(&mut self) -> Result<Ast, Error> {
+        let start = self.chari;
+        while self.peek(1).map_or(false, |c| c >= '0' && c <= '9') {
+            self.chari += 1;
+        }
+        let n = try!(self.parse_uint(self.slice(start, self.chari + 1)
+                                         .as_slice()));
+        if n > self.caps {
+            return self.err_at(start - 1, format!(
+                "Backreference \\{} refers to a capture group that hasn't \
+                 been opened yet.", n).as_slice())
+        }
+        Ok(Backref(n, self.flags & FLAG_NOCASE))
+    }
+
     // Parses an octal number, up to 3 digits.
     // Assumes that \n has been read, where n is the first digit.
     fn parse_octal(&mut self) -> Result<Ast, Error> {
@@ -741,27 +994,32 @@ impl<'a> Parser<'a> {
     // is '<'.
     // When done, parser will be at the closing '>' character.
     fn parse_named_capture(&mut self) -> Result<(), Error> {
+        let start = self.chari - 3;
         try!(self.noteof("a capture name"));
         let closer =
             match self.pos('>') {
                 Some(i) => i,
-                None => return self.err("Capture name must end with '>'."),
+                None => return self.err_span(start, self.chars.len(),
+                                             "Capture name must end with '>'."),
             };
         if closer - self.chari == 0 {
-            return self.err("Capture names must have at least 1 character.")
+            return self.err_span(start, closer + 1,
+                                 "Capture names must have at least 1 character.")
         }
         let name = self.slice(self.chari, closer);
         if !name.chars().all(is_valid_cap) {
-            return self.err(
+            return self.err_span(start, closer + 1,
                 "Capture names can only have underscores, letters and digits.")
         }
         if self.names.contains(&name) {
-            return self.err(format!("Duplicate capture group name '{}'.",
-                                    name).as_slice())
+            return self.err_span(start, closer + 1,
+                                 format!("Duplicate capture group name '{}'.",
+                                         name).as_slice())
         }
         self.names.push(name.clone());
         self.chari = closer;
         self.caps += 1;
+        self.parens.push(start);
         self.stack.push(Paren(self.flags, self.caps, name));
         Ok(())
     }
@@ -775,39 +1033,68 @@ impl<'a> Parser<'a> {
             try!(self.expect('<'));
             return self.parse_named_capture()
         }
+        let look = match (self.peek(1), self.peek(2)) {
+            (Some('='), _) => Some((LookAhead, 1)),
+            (Some('!'), _) => Some((NegLookAhead, 1)),
+            (Some('<'), Some('=')) => Some((LookBehind, 2)),
+            (Some('<'), Some('!')) => Some((NegLookBehind, 2)),
+            _ => None,
+        };
+        match look {
+            None => {}
+            Some((kind, len)) => {
+                let open = self.chari - 1;
+                if !self.backtrack {
+                    return self.err_span(open, self.chari + len + 1,
+                                         "Look-around assertions are only \
+                                          supported by the backtracking \
+                                          engine. Enable it with \
+                                          `RegexBuilder::backtrack`.")
+                }
+                self.chari += len;
+                self.parens.push(open);
+                self.stack.push(LookParen(self.flags, kind));
+                return Ok(())
+            }
+        }
         let start = self.chari;
         let mut flags = self.flags;
         let mut sign = 1i;
         let mut saw_flag = false;
+        let mut unicode = None;
         loop {
             try!(self.noteof(
                     "expected non-empty set of flags or closing ')'"));
-            match self.cur() {
-                'i' => { flags = flags | FLAG_NOCASE;     saw_flag = true},
-                'm' => { flags = flags | FLAG_MULTI;      saw_flag = true},
-                's' => { flags = flags | FLAG_DOTNL;      saw_flag = true},
-                'U' => { flags = flags | FLAG_SWAP_GREED; saw_flag = true},
+            let flag = match self.cur() {
+                'i' => FLAG_NOCASE,
+                'm' => FLAG_MULTI,
+                's' => FLAG_DOTNL,
+                'U' => FLAG_SWAP_GREED,
+                'x' => FLAG_IGNORE_SPACE,
+                'u' => { unicode = Some(sign > 0); FLAG_EMPTY }
                 '-' => {
                     if sign < 0 {
-                        return self.err(format!(
+                        return self.err_at(start - 1, format!(
                             "Cannot negate flags twice in '{}'.",
                             self.slice(start, self.chari + 1)).as_slice())
                     }
                     sign = -1;
                     saw_flag = false;
-                    flags = flags ^ flags;
+                    continue
                 }
                 ':' | ')' => {
-                    if sign < 0 {
-                        if !saw_flag {
-                            return self.err(format!(
-                                "A valid flag does not follow negation in '{}'",
-                                self.slice(start, self.chari + 1)).as_slice())
-                        }
-                        flags = flags ^ flags;
+                    if sign < 0 && !saw_flag {
+                        return self.err_at(start - 1, format!(
+                            "A valid flag does not follow negation in '{}'",
+                            self.slice(start, self.chari + 1)).as_slice())
+                    }
+                    match unicode {
+                        None => {},
+                        Some(u) => try!(self.set_unicode(u, start)),
                     }
                     if self.cur() == ':' {
                         // Save the old flags with the opening paren.
+                        self.parens.push(start - 1);
                         self.stack.push(Paren(self.flags, 0, "".to_string()));
                     }
                     self.flags = flags;
@@ -815,7 +1102,48 @@ impl<'a> Parser<'a> {
                 }
                 _ => return self.err(format!(
                     "Unrecognized flag '{}'.", self.cur()).as_slice()),
+            };
+            // Only the flags given are set or cleared. The others keep the
+            // values they had outside of this group.
+            if sign > 0 {
+                flags = flags | flag;
+            } else {
+                flags = flags & !flag;
             }
+            saw_flag = true;
+        }
+    }
+
+    // Sets Unicode mode from a `u` flag in the group starting at `start`.
+    // Unicode mode applies to an entire expression, so it can only be turned
+    // off by a `(?-u)` at the very beginning of an expression for bytes.
+    // Assumes that the closing ')' or ':' of the group is the current
+    // character.
+    fn set_unicode(&mut self, unicode: bool, start: uint) -> Result<(), Error> {
+        if unicode == self.unicode {
+            return Ok(())
+        }
+        if !self.bytes {
+            return self.err("Unicode mode cannot be disabled when matching \
+                             text. Use `regex::bytes::Regex` instead.")
+        }
+        if start != 1 || self.cur() != ')' {
+            return self.err("The 'u' flag can only be set for an entire \
+                             expression, with '(?-u)' at its beginning.")
+        }
+        self.unicode = unicode;
+        Ok(())
+    }
+
+    // Returns the character given if it is allowed by the current Unicode
+    // mode. With Unicode mode disabled, only `U+0000` through `U+00FF` are
+    // allowed since each character matches a single byte.
+    fn check_unicode(&self, c: char) -> Result<char, Error> {
+        if self.unicode || c <= '\xFF' {
+            Ok(c)
+        } else {
+            self.err(format!("Character '{}' cannot be matched when Unicode \
+                              mode is disabled.", c).as_slice())
         }
     }
 
@@ -919,7 +1247,7 @@ impl<'a> Parser<'a> {
 
     fn char_from_u32(&self, n: u32) -> Result<char, Error> {
         match char::from_u32(n) {
-            Some(c) => Ok(c),
+            Some(c) => self.check_unicode(c),
             None => {
                 self.err(format!("Could not decode '{}' to unicode \
                                   character.",
@@ -934,9 +1262,22 @@ impl<'a> Parser<'a> {
     }
 
     fn err<T>(&self, msg: &str) -> Result<T, Error> {
+        self.err_span(self.chari, self.chari + 1, msg)
+    }
+
+    // Returns an error about the part of the expression from `start` up to
+    // and including the current character.
+    fn err_at<T>(&self, start: uint, msg: &str) -> Result<T, Error> {
+        self.err_span(start, self.chari + 1, msg)
+    }
+
+    fn err_span<T>(&self, start: uint, end: uint,
+                   msg: &str) -> Result<T, Error> {
         Err(Error {
             pos: self.chari,
             msg: msg.to_string(),
+            span: (start, end),
+            expr: String::from_chars(self.chars[]),
         })
     }
 
@@ -1005,6 +1346,18 @@ fn perl_unicode_class(which: char) -> Vec<(char, char)> {
     }
 }
 
+// Constructs an ASCII-only Perl character class from \d, \s or \w (or any
+// of their negated forms), for use when Unicode mode is disabled. Note that
+// this does not handle negation.
+fn perl_ascii_class(which: char) -> Vec<(char, char)> {
+    match which.to_lowercase() {
+        'd' => DIGIT.to_vec(),
+        's' => SPACE.to_vec(),
+        'w' => WORD.to_vec(),
+        _ => unreachable!(),
+    }
+}
+
 // Returns a concatenation of two expressions. This also guarantees that a
 // `Cat` expression will never be a direct child of another `Cat` expression.
 fn concat_flatten(x: Ast, y: Ast) -> Ast {
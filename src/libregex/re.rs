@@ -11,13 +11,17 @@
 pub use self::NamesIter::*;
 pub use self::Regex::*;
 
+use self::Engine::*;
+
 use std::collections::HashMap;
 use std::fmt;
 use std::str::CowString;
 
+use backtrack;
+use backtrack::LimitError;
 use compile::Program;
+use dfa::DfaExec;
 use parse;
-use vm;
 use vm::{CaptureLocs, MatchKind, Exists, Location, Submatches};
 
 /// Escapes all regular expression meta characters in `text`.
@@ -103,6 +107,17 @@ pub fn is_match(regex: &str, text: &str) -> Result<bool, parse::Error> {
 /// makes it much faster when searching text.
 /// More details about the `regex!` macro can be found in the `regex` crate
 /// documentation.
+///
+/// # Backtracking limits
+///
+/// Expressions compiled with `RegexBuilder::backtrack` that use look-around
+/// assertions or backreferences give up on a search after a number of steps
+/// (see `RegexBuilder::backtrack_limit`). The methods that can't return an
+/// error then behave as if there were no match from where the search gave
+/// up: `is_match` returns false, `find` and `captures` return `None`, the
+/// iterators stop, and `split` and the `replace` methods leave the rest of the
+/// text as it is. Each of them has a `try_` variant that returns a
+/// `LimitError` instead.
 #[deriving(Clone)]
 pub enum Regex {
     // The representation of `Regex` is exported to support the `regex!`
@@ -122,7 +137,17 @@ pub struct ExDynamic {
     original: String,
     names: Vec<Option<String>>,
     #[doc(hidden)]
-    pub prog: Program
+    pub prog: Program,
+    engine: Engine,
+}
+
+// How a dynamic regex is run. The automata are used whenever they can run the
+// program, i.e., unless it uses look-around assertions or backreferences.
+#[deriving(Clone)]
+enum Engine {
+    Automata(DfaExec),
+    // The backtracking engine, with a limit on the steps a search may take.
+    Backtrack(uint),
 }
 
 #[doc(hidden)]
@@ -157,14 +182,11 @@ impl Regex {
     /// safer and always faster.
     ///
     /// If an invalid expression is given, then an error is returned.
+    ///
+    /// To set options, such as the size limit or enabling look-around
+    /// assertions and backreferences, use a `RegexBuilder` instead.
     pub fn new(re: &str) -> Result<Regex, parse::Error> {
-        let ast = try!(parse::parse(re));
-        let (prog, names) = Program::new(ast);
-        Ok(Dynamic(ExDynamic {
-            original: re.to_string(),
-            names: names,
-            prog: prog,
-        }))
+        RegexBuilder::new(re).compile()
     }
 
     /// Returns true if and only if the regex matches the string given.
@@ -217,6 +239,28 @@ impl Regex {
         }
     }
 
+    /// Like `is_match`, but returns an error instead of `false` if the
+    /// backtracking engine takes more steps than its limit allows.
+    ///
+    /// This only ever fails for expressions compiled with
+    /// `RegexBuilder::backtrack` that use look-around assertions or
+    /// backreferences.
+    pub fn try_is_match(&self, text: &str) -> Result<bool, LimitError> {
+        try_exec(self, Exists, text).map(|caps| has_match(&caps))
+    }
+
+    /// Like `find`, but returns an error instead of `None` if the
+    /// backtracking engine takes more steps than its limit allows.
+    pub fn try_find(&self, text: &str)
+                   -> Result<Option<(uint, uint)>, LimitError> {
+        let caps = try!(try_exec(self, Location, text));
+        Ok(if has_match(&caps) {
+            Some((caps[0].unwrap(), caps[1].unwrap()))
+        } else {
+            None
+        })
+    }
+
     /// Returns an iterator for each successive non-overlapping match in
     /// `text`, returning the start and end byte indices with respect to
     /// `text`.
@@ -250,6 +294,12 @@ impl Regex {
         }
     }
 
+    /// Like `find_iter`, but yields an error, and then stops, if the
+    /// backtracking engine takes more steps than its limit allows.
+    pub fn try_find_iter<'r, 't>(&'r self, text: &'t str) -> TryFindMatches<'r, 't> {
+        TryFindMatches { matches: self.find_iter(text), done: false }
+    }
+
     /// Returns the capture groups corresponding to the leftmost-first
     /// match in `text`. Capture group `0` always corresponds to the entire
     /// match. If no match is found, then `None` is returned.
@@ -307,6 +357,14 @@ impl Regex {
         Captures::new(self, text, caps)
     }
 
+    /// Like `captures`, but returns an error instead of `None` if the
+    /// backtracking engine takes more steps than its limit allows.
+    pub fn try_captures<'t>(&self, text: &'t str)
+                           -> Result<Option<Captures<'t>>, LimitError> {
+        let caps = try!(try_exec(self, Submatches, text));
+        Ok(Captures::new(self, text, caps))
+    }
+
     /// Returns an iterator over all the non-overlapping capture groups matched
     /// in `text`. This is operationally the same as `find_iter` (except it
     /// yields information about submatches).
@@ -341,6 +399,13 @@ impl Regex {
         }
     }
 
+    /// Like `captures_iter`, but yields an error, and then stops, if the
+    /// backtracking engine takes more steps than its limit allows.
+    pub fn try_captures_iter<'r, 't>(&'r self, text: &'t str)
+                                    -> TryFindCaptures<'r, 't> {
+        TryFindCaptures { captures: self.captures_iter(text), done: false }
+    }
+
     /// Returns an iterator of substrings of `text` delimited by a match
     /// of the regular expression.
     /// Namely, each element of the iterator corresponds to text that *isn't*
@@ -368,6 +433,23 @@ impl Regex {
         }
     }
 
+    /// Like `split`, but returns an error if the backtracking engine takes
+    /// more steps than its limit allows. The substrings are returned all at
+    /// once.
+    pub fn try_split<'t>(&self, text: &'t str) -> Result<Vec<&'t str>, LimitError> {
+        let mut fields = Vec::new();
+        let mut last = 0;
+        for pos in self.try_find_iter(text) {
+            let (s, e) = try!(pos);
+            fields.push(text.slice(last, s));
+            last = e;
+        }
+        if last < text.len() {
+            fields.push(text.slice_from(last));
+        }
+        Ok(fields)
+    }
+
     /// Returns an iterator of at most `limit` substrings of `text` delimited
     /// by a match of the regular expression. (A `limit` of `0` will return no
     /// substrings.)
@@ -493,24 +575,30 @@ impl Regex {
     /// See the documentation for `replace` for details on how to access
     /// submatches in the replacement string.
     pub fn replacen<R: Replacer>
-                   (&self, text: &str, limit: uint, mut rep: R) -> String {
-        let mut new = String::with_capacity(text.len());
-        let mut last_match = 0u;
-
-        for (i, cap) in self.captures_iter(text).enumerate() {
-            // It'd be nicer to use the 'take' iterator instead, but it seemed
-            // awkward given that '0' => no limit.
-            if limit > 0 && i >= limit {
-                break
-            }
+                   (&self, text: &str, limit: uint, rep: R) -> String {
+        let caps = self.captures_iter(text).map(|cap| Ok(cap));
+        replace_captures(text, caps, limit, rep).unwrap()
+    }
 
-            let (s, e) = cap.pos(0).unwrap(); // captures only reports matches
-            new.push_str(text.slice(last_match, s));
-            new.push_str(rep.reg_replace(&cap).as_slice());
-            last_match = e;
-        }
-        new.push_str(text.slice(last_match, text.len()));
-        return new;
+    /// Like `replace`, but returns an error if the backtracking engine takes
+    /// more steps than its limit allows.
+    pub fn try_replace<R: Replacer>(&self, text: &str, rep: R)
+                                   -> Result<String, LimitError> {
+        self.try_replacen(text, 1, rep)
+    }
+
+    /// Like `replace_all`, but returns an error if the backtracking engine
+    /// takes more steps than its limit allows.
+    pub fn try_replace_all<R: Replacer>(&self, text: &str, rep: R)
+                                       -> Result<String, LimitError> {
+        self.try_replacen(text, 0, rep)
+    }
+
+    /// Like `replacen`, but returns an error if the backtracking engine takes
+    /// more steps than its limit allows.
+    pub fn try_replacen<R: Replacer>(&self, text: &str, limit: uint, rep: R)
+                                    -> Result<String, LimitError> {
+        replace_captures(text, self.try_captures_iter(text), limit, rep)
     }
 
     /// Returns the original string of this regex.
@@ -539,6 +627,152 @@ impl Regex {
 
 }
 
+/// A builder for a `Regex` with options that `Regex::new` doesn't set.
+///
+/// The flags set with a builder apply as if the expression started with a
+/// `(?flags)` group, so the expression itself can still clear them.
+///
+/// # Examples
+///
+/// Compile an expression written by a user, with a tight size limit and
+/// case insensitive matching:
+///
+/// ```rust
+/// # use regex::RegexBuilder;
+/// let re = RegexBuilder::new(r"hello \w+")
+///                       .case_insensitive(true)
+///                       .size_limit(10000)
+///                       .compile()
+///                       .unwrap();
+/// assert_eq!(re.find("HELLO world"), Some((0, 11)));
+/// assert!(RegexBuilder::new(r"\w{100}").size_limit(10000).compile().is_err());
+/// ```
+///
+/// Find a price that is preceded by a dollar sign, without including the
+/// dollar sign in the match:
+///
+/// ```rust
+/// # use regex::RegexBuilder;
+/// let re = RegexBuilder::new(r"(?<=\$)\d+").backtrack(true).compile().unwrap();
+/// assert_eq!(re.find("costs $42"), Some((7, 9)));
+/// ```
+pub struct RegexBuilder {
+    pattern: String,
+    flags: parse::Flags,
+    size_limit: Option<uint>,
+    backtrack: bool,
+    backtrack_limit: uint,
+}
+
+impl RegexBuilder {
+    /// Returns a builder for the regular expression given, with every option
+    /// set to its default.
+    pub fn new(re: &str) -> RegexBuilder {
+        RegexBuilder {
+            pattern: re.to_string(),
+            flags: parse::FLAG_EMPTY,
+            size_limit: None,
+            backtrack: false,
+            backtrack_limit: backtrack::DEFAULT_LIMIT,
+        }
+    }
+
+    /// Sets the `i` flag: letters match both their uppercase and lowercase
+    /// forms.
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.set_flag(parse::FLAG_NOCASE, yes)
+    }
+
+    /// Sets the `m` flag: `^` and `$` match at the beginning and end of every
+    /// line, instead of only at the beginning and end of the text.
+    pub fn multi_line(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.set_flag(parse::FLAG_MULTI, yes)
+    }
+
+    /// Sets the `s` flag: `.` matches any character, including `\n`.
+    pub fn dot_matches_new_line(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.set_flag(parse::FLAG_DOTNL, yes)
+    }
+
+    /// Sets the `x` flag: whitespace in the expression is ignored (except in
+    /// character classes or when escaped), and a `#` starts a comment that
+    /// runs to the end of the line.
+    pub fn ignore_whitespace(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.set_flag(parse::FLAG_IGNORE_SPACE, yes)
+    }
+
+    /// Sets the approximate size in bytes that the compiled expression may
+    /// take up. Expressions that are bigger fail to compile, which protects
+    /// against untrusted expressions like `(\w{1000}){1000}` that are short
+    /// but compile to huge programs.
+    ///
+    /// By default, there is no limit.
+    pub fn size_limit(&mut self, limit: uint) -> &mut RegexBuilder {
+        self.size_limit = Some(limit);
+        self
+    }
+
+    /// Allows look-around assertions (`(?=...)`, `(?!...)`, `(?<=...)` and
+    /// `(?<!...)`) and backreferences (`\1` through `\9`, and so on) in the
+    /// expression. This is disabled by default.
+    ///
+    /// Expressions that use them are run by a backtracking engine, which can
+    /// take time exponential in the length of the text searched. So every
+    /// search is limited to a number of steps (see `backtrack_limit`).
+    /// Expressions that don't use them are run exactly as they would be
+    /// without this option.
+    pub fn backtrack(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.backtrack = yes;
+        self
+    }
+
+    /// Sets the number of steps a search with the backtracking engine may
+    /// take before it gives up. When it does, the `try_*` methods of `Regex`
+    /// return a `LimitError`, and all other methods behave as if there were
+    /// no match (see the documentation of `Regex`).
+    ///
+    /// The default limit is 1,000,000 steps.
+    pub fn backtrack_limit(&mut self, limit: uint) -> &mut RegexBuilder {
+        self.backtrack_limit = limit;
+        self
+    }
+
+    /// Compiles the expression with the options set.
+    ///
+    /// If an invalid expression is given, then an error is returned.
+    pub fn compile(&self) -> Result<Regex, parse::Error> {
+        let opts = parse::Options {
+            backtrack: self.backtrack,
+            flags: self.flags,
+            size_limit: self.size_limit,
+        };
+        let ast = try!(parse::parse_with(self.pattern.as_slice(), opts));
+        let rast = ast.clone();
+        let (prog, names) = Program::new(ast);
+        let engine =
+            if prog.needs_backtrack() {
+                Backtrack(self.backtrack_limit)
+            } else {
+                Automata(DfaExec::new(&prog, Program::new_reverse(rast)))
+            };
+        Ok(Dynamic(ExDynamic {
+            original: self.pattern.clone(),
+            names: names,
+            prog: prog,
+            engine: engine,
+        }))
+    }
+
+    fn set_flag(&mut self, flag: parse::Flags, yes: bool) -> &mut RegexBuilder {
+        if yes {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+        self
+    }
+}
+
 pub enum NamesIter<'a> {
     NamesIterNative(::std::slice::Items<'a, Option<&'static str>>),
     NamesIterDynamic(::std::slice::Items<'a, Option<String>>)
@@ -843,17 +1077,17 @@ pub struct FindCaptures<'r, 't> {
     last_end: uint,
 }
 
-impl<'r, 't> Iterator<Captures<'t>> for FindCaptures<'r, 't> {
-    fn next(&mut self) -> Option<Captures<'t>> {
+impl<'r, 't> FindCaptures<'r, 't> {
+    fn try_next(&mut self) -> Result<Option<Captures<'t>>, LimitError> {
         if self.last_end > self.search.len() {
-            return None
+            return Ok(None)
         }
 
-        let caps = exec_slice(self.re, Submatches, self.search,
-                              self.last_end, self.search.len());
+        let caps = try!(try_exec_slice(self.re, Submatches, self.search,
+                                       self.last_end, self.search.len()));
         let (s, e) =
             if !has_match(&caps) {
-                return None
+                return Ok(None)
             } else {
                 (caps[0].unwrap(), caps[1].unwrap())
             };
@@ -862,11 +1096,43 @@ impl<'r, 't> Iterator<Captures<'t>> for FindCaptures<'r, 't> {
         // i.e., no infinite loops please.
         if e == s && Some(self.last_end) == self.last_match {
             self.last_end += 1;
-            return self.next()
+            return self.try_next()
         }
         self.last_end = e;
         self.last_match = Some(self.last_end);
-        Captures::new(self.re, self.search, caps)
+        Ok(Captures::new(self.re, self.search, caps))
+    }
+}
+
+impl<'r, 't> Iterator<Captures<'t>> for FindCaptures<'r, 't> {
+    fn next(&mut self) -> Option<Captures<'t>> {
+        // A search that gives up counts as finding no more matches.
+        self.try_next().unwrap_or(None)
+    }
+}
+
+/// An iterator that yields all non-overlapping capture groups matching a
+/// particular regular expression, or the error of a search that took more
+/// steps than the backtracking limit allows. The iterator stops after an
+/// error.
+///
+/// `'r` is the lifetime of the compiled expression and `'t` is the lifetime
+/// of the matched string.
+pub struct TryFindCaptures<'r, 't> {
+    captures: FindCaptures<'r, 't>,
+    done: bool,
+}
+
+impl<'r, 't> Iterator<Result<Captures<'t>, LimitError>> for TryFindCaptures<'r, 't> {
+    fn next(&mut self) -> Option<Result<Captures<'t>, LimitError>> {
+        if self.done {
+            return None
+        }
+        match self.captures.try_next() {
+            Ok(Some(caps)) => Some(Ok(caps)),
+            Ok(None) => { self.done = true; None }
+            Err(err) => { self.done = true; Some(Err(err)) }
+        }
     }
 }
 
@@ -885,17 +1151,17 @@ pub struct FindMatches<'r, 't> {
     last_end: uint,
 }
 
-impl<'r, 't> Iterator<(uint, uint)> for FindMatches<'r, 't> {
-    fn next(&mut self) -> Option<(uint, uint)> {
+impl<'r, 't> FindMatches<'r, 't> {
+    fn try_next(&mut self) -> Result<Option<(uint, uint)>, LimitError> {
         if self.last_end > self.search.len() {
-            return None
+            return Ok(None)
         }
 
-        let caps = exec_slice(self.re, Location, self.search,
-                              self.last_end, self.search.len());
+        let caps = try!(try_exec_slice(self.re, Location, self.search,
+                                       self.last_end, self.search.len()));
         let (s, e) =
             if !has_match(&caps) {
-                return None
+                return Ok(None)
             } else {
                 (caps[0].unwrap(), caps[1].unwrap())
             };
@@ -904,23 +1170,98 @@ impl<'r, 't> Iterator<(uint, uint)> for FindMatches<'r, 't> {
         // i.e., no infinite loops please.
         if e == s && Some(self.last_end) == self.last_match {
             self.last_end += 1;
-            return self.next()
+            return self.try_next()
         }
         self.last_end = e;
         self.last_match = Some(self.last_end);
-        Some((s, e))
+        Ok(Some((s, e)))
+    }
+}
+
+impl<'r, 't> Iterator<(uint, uint)> for FindMatches<'r, 't> {
+    fn next(&mut self) -> Option<(uint, uint)> {
+        // A search that gives up counts as finding no more matches.
+        self.try_next().unwrap_or(None)
+    }
+}
+
+/// An iterator over all non-overlapping matches for a particular string, or
+/// the error of a search that took more steps than the backtracking limit
+/// allows. The iterator stops after an error.
+///
+/// `'r` is the lifetime of the compiled expression and `'t` is the lifetime
+/// of the matched string.
+pub struct TryFindMatches<'r, 't> {
+    matches: FindMatches<'r, 't>,
+    done: bool,
+}
+
+impl<'r, 't> Iterator<Result<(uint, uint), LimitError>> for TryFindMatches<'r, 't> {
+    fn next(&mut self) -> Option<Result<(uint, uint), LimitError>> {
+        if self.done {
+            return None
+        }
+        match self.matches.try_next() {
+            Ok(Some(pos)) => Some(Ok(pos)),
+            Ok(None) => { self.done = true; None }
+            Err(err) => { self.done = true; Some(Err(err)) }
+        }
     }
 }
 
+// Replaces at most `limit` of the matches in `caps`, or all of them if
+// `limit` is 0, stopping at the first error.
+fn replace_captures<'t, R, I>(text: &'t str, caps: I, limit: uint, mut rep: R)
+                             -> Result<String, LimitError>
+    where R: Replacer, I: Iterator<Result<Captures<'t>, LimitError>> {
+    let mut new = String::with_capacity(text.len());
+    let mut last_match = 0u;
+
+    for (i, cap) in caps.enumerate() {
+        // It'd be nicer to use the 'take' iterator instead, but it seemed
+        // awkward given that '0' => no limit.
+        if limit > 0 && i >= limit {
+            break
+        }
+
+        let cap = try!(cap);
+        let (s, e) = cap.pos(0).unwrap(); // captures only reports matches
+        new.push_str(text.slice(last_match, s));
+        new.push_str(rep.reg_replace(&cap).as_slice());
+        last_match = e;
+    }
+    new.push_str(text.slice(last_match, text.len()));
+    Ok(new)
+}
+
 fn exec(re: &Regex, which: MatchKind, input: &str) -> CaptureLocs {
     exec_slice(re, which, input, 0, input.len())
 }
 
+// A search that gives up after too many backtracking steps finds no match.
 fn exec_slice(re: &Regex, which: MatchKind,
               input: &str, s: uint, e: uint) -> CaptureLocs {
+    match try_exec_slice(re, which, input, s, e) {
+        Ok(caps) => caps,
+        Err(..) => vec![None, None],
+    }
+}
+
+fn try_exec(re: &Regex, which: MatchKind,
+            input: &str) -> Result<CaptureLocs, LimitError> {
+    try_exec_slice(re, which, input, 0, input.len())
+}
+
+fn try_exec_slice(re: &Regex, which: MatchKind, input: &str,
+                  s: uint, e: uint) -> Result<CaptureLocs, LimitError> {
     match *re {
-        Dynamic(ExDynamic { ref prog, .. }) => vm::run(which, prog, input, s, e),
-        Native(ExNative { ref prog, .. }) => (*prog)(which, input, s, e),
+        Dynamic(ExDynamic { ref prog, engine: Automata(ref dfa), .. }) => {
+            Ok(dfa.exec(which, prog, input.as_bytes(), true, s, e))
+        }
+        Dynamic(ExDynamic { ref prog, engine: Backtrack(limit), .. }) => {
+            backtrack::run(which, prog, input.as_bytes(), true, s, e, limit)
+        }
+        Native(ExNative { ref prog, .. }) => Ok((*prog)(which, input, s, e)),
     }
 }
 
@@ -0,0 +1,162 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fmt;
+
+use compile::Program;
+use parse;
+use vm;
+
+/// A set of regular expressions that can be matched against a string in a
+/// single scan.
+///
+/// Searching with a `RegexSet` is equivalent to calling `Regex::is_match`
+/// once for every expression in the set, except that the text is only
+/// scanned once. This makes it a good fit for classifying text against many
+/// patterns at once.
+///
+/// A `RegexSet` can only report *which* expressions matched. It cannot
+/// report the location of a match or its submatches. If you need those, use
+/// the indices reported by the set to pick out the corresponding `Regex`.
+///
+/// # Example
+///
+/// ```rust
+/// # use regex::RegexSet;
+/// let set = RegexSet::new(&[r"\w+", r"\d+", r"foo", r"bar"]).unwrap();
+/// let matches = set.matches("foobar");
+/// assert!(matches.matched(0));
+/// assert!(!matches.matched(1));
+/// assert_eq!(matches.iter().collect::<Vec<uint>>(), vec![0, 2, 3]);
+/// ```
+#[deriving(Clone)]
+pub struct RegexSet {
+    originals: Vec<String>,
+    prog: Program,
+}
+
+impl RegexSet {
+    /// Compiles a set of regular expressions into a single program.
+    ///
+    /// If any of the expressions are invalid, then an error is returned.
+    /// The error message indicates which expression in the set failed.
+    pub fn new<S: Str>(exprs: &[S]) -> Result<RegexSet, parse::Error> {
+        let mut asts = Vec::with_capacity(exprs.len());
+        for (i, re) in exprs.iter().enumerate() {
+            match parse::parse(re.as_slice()) {
+                Ok(ast) => asts.push(ast),
+                Err(err) => {
+                    return Err(parse::Error {
+                        msg: format!("In expression {} of set: {}",
+                                     i, err.msg),
+                        ..err
+                    })
+                }
+            }
+        }
+        Ok(RegexSet {
+            originals: exprs.iter().map(|re| re.as_slice().to_string())
+                            .collect(),
+            prog: Program::new_set(asts),
+        })
+    }
+
+    /// Returns true if and only if at least one expression in the set
+    /// matches the string given.
+    ///
+    /// This is faster than `matches` since it stops scanning the text as soon
+    /// as any match is found.
+    pub fn is_match(&self, text: &str) -> bool {
+        let mut matched = Vec::from_elem(self.len(), false);
+        vm::run_set(&self.prog, text, 0, text.len(),
+                    matched.as_mut_slice(), true);
+        matched.iter().any(|&m| m)
+    }
+
+    /// Returns the set of expressions that match somewhere in the string
+    /// given.
+    pub fn matches(&self, text: &str) -> SetMatches {
+        let mut matched = Vec::from_elem(self.len(), false);
+        vm::run_set(&self.prog, text, 0, text.len(),
+                    matched.as_mut_slice(), false);
+        SetMatches { matched: matched }
+    }
+
+    /// Returns the number of expressions in this set.
+    pub fn len(&self) -> uint {
+        self.originals.len()
+    }
+
+    /// Returns the original expressions in this set, in the order they were
+    /// given.
+    pub fn patterns<'a>(&'a self) -> &'a [String] {
+        self.originals.as_slice()
+    }
+}
+
+impl fmt::Show for RegexSet {
+    /// Shows the original regular expressions.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.originals)
+    }
+}
+
+/// The set of expressions in a `RegexSet` that matched some text.
+#[deriving(Clone, PartialEq, Show)]
+pub struct SetMatches {
+    matched: Vec<bool>,
+}
+
+impl SetMatches {
+    /// Returns true if and only if at least one expression matched.
+    pub fn matched_any(&self) -> bool {
+        self.matched.iter().any(|&m| m)
+    }
+
+    /// Returns true if and only if the expression at index `i` matched.
+    ///
+    /// Expressions are indexed in the order they were given to
+    /// `RegexSet::new`. This panics if `i` is not a valid index.
+    pub fn matched(&self, i: uint) -> bool {
+        self.matched[i]
+    }
+
+    /// Returns the number of expressions in the set that produced these
+    /// matches.
+    pub fn len(&self) -> uint {
+        self.matched.len()
+    }
+
+    /// Returns an iterator over the indices of the expressions that matched,
+    /// in ascending order.
+    pub fn iter<'a>(&'a self) -> SetMatchesIter<'a> {
+        SetMatchesIter { matched: self.matched.as_slice(), idx: 0 }
+    }
+}
+
+/// An iterator over the indices of the expressions in a set that matched.
+///
+/// `'a` is the lifetime of the `SetMatches` being iterated over.
+pub struct SetMatchesIter<'a> {
+    matched: &'a [bool],
+    idx: uint,
+}
+
+impl<'a> Iterator<uint> for SetMatchesIter<'a> {
+    fn next(&mut self) -> Option<uint> {
+        while self.idx < self.matched.len() {
+            self.idx += 1;
+            if self.matched[self.idx - 1] {
+                return Some(self.idx - 1)
+            }
+        }
+        None
+    }
+}
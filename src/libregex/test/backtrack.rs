@@ -0,0 +1,163 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use regex::{Regex, RegexBuilder, LimitError};
+
+fn regex(re: &str) -> Regex {
+    match RegexBuilder::new(re).backtrack(true).compile() {
+        Ok(re) => re,
+        Err(err) => panic!("{}", err),
+    }
+}
+
+// Checks the locations of every capture group of the first match.
+macro_rules! bt {
+    ($name:ident, $re:expr, $text:expr, [$($loc:expr),*]) => (
+        #[test]
+        fn $name() {
+            let re = regex($re);
+            let expected: Vec<Option<(uint, uint)>> = vec![$($loc),*];
+            let got: Vec<Option<(uint, uint)>> = match re.captures($text) {
+                None => vec![],
+                Some(caps) => range(0, caps.len()).map(|i| caps.pos(i)).collect(),
+            };
+            assert_eq!(got, expected);
+            assert_eq!(re.is_match($text), expected.len() > 0);
+        }
+    );
+}
+
+bt!{look_ahead, r"\w+(?=!)", "hi there!", [Some((3, 8))]}
+bt!{look_ahead_end, r"a(?=b)", "a", []}
+bt!{neg_look_ahead, r"\b\w+\b(?!,)", "a, bc, def", [Some((7, 10))]}
+bt!{neg_look_ahead_end, r"a(?!b)", "ab a", [Some((3, 4))]}
+bt!{look_behind, r"(?<=ab+)c", "xc abbbc", [Some((7, 8))]}
+bt!{look_behind_begin, r"(?<=a)b", "b", []}
+bt!{look_behind_unicode, r"(?<=δ)x", "δx", [Some((2, 3))]}
+bt!{neg_look_behind, r"(?<!\$)\b\d+", "$10 20", [Some((4, 6))]}
+bt!{look_anchored, r"^(?=a)\w", "ba", []}
+bt!{look_ahead_captures, r"(?=(\d+))\w+", "x 123abc",
+    [Some((2, 8)), Some((2, 5))]}
+bt!{look_behind_captures, r"(?<=(\w+)=)\d+", "key=42",
+    [Some((4, 6)), Some((0, 3))]}
+bt!{neg_look_captures, r"(?!(a)b)(\w)", "ab",
+    [Some((1, 2)), None, Some((1, 2))]}
+bt!{backref, r"(\w)\1", "abccd", [Some((2, 4)), Some((2, 3))]}
+bt!{backref_words, r"\b(\w+) \1\b", "it is is it", [Some((3, 8)), Some((3, 5))]}
+bt!{backref_nocase, r"(?i)(\w+) \1", "Hello HELLO", [Some((0, 11)), Some((0, 5))]}
+bt!{backref_case, r"(\w+) \1", "Hello HELLO", []}
+bt!{backref_unset, r"(a)?b\1", "b", []}
+bt!{backref_in_look_behind, r"(\w)(?<=\1\1)", "abccd", [Some((3, 4)), Some((3, 4))]}
+bt!{backref_in_look_behind_nocase, r"(?i)(\w)(?<=\1\1)", "aBbc",
+    [Some((2, 3)), Some((2, 3))]}
+bt!{empty_loop, r"(?:a*)*(b)\1", "aabb", [Some((0, 4)), Some((2, 3))]}
+bt!{no_look, r"a+b", "xaab", [Some((1, 4))]}
+
+#[test]
+fn look_behind_find_iter() {
+    // Look-behind assertions see the text before where each search starts.
+    let re = regex(r"(?<=a)b");
+    let got: Vec<(uint, uint)> = re.find_iter("abab").collect();
+    assert_eq!(got, vec![(1, 2), (3, 4)]);
+}
+
+#[test]
+fn look_around_needs_backtrack() {
+    for re in [r"(?=a)", r"(?!a)", r"(?<=a)", r"(?<!a)"].iter() {
+        let err = Regex::new(*re).unwrap_err();
+        assert!(err.msg.as_slice().contains("RegexBuilder::backtrack"));
+    }
+    // Without the backtracking engine, this is an octal escape.
+    assert_eq!(Regex::new(r"(a)\1").unwrap().find("a\x01"), Some((0, 2)));
+}
+
+#[test]
+fn backref_undefined() {
+    assert!(RegexBuilder::new(r"(a)\2").backtrack(true).compile().is_err());
+    assert!(RegexBuilder::new(r"\1(a)").backtrack(true).compile().is_err());
+    assert!(RegexBuilder::new(r"[\1]").backtrack(true).compile().is_err());
+}
+
+#[test]
+fn look_around_repeat() {
+    assert!(RegexBuilder::new(r"(?=a)*").backtrack(true).compile().is_err());
+    assert!(RegexBuilder::new(r"(?<!a){2}").backtrack(true).compile().is_err());
+}
+
+fn catastrophic() -> (Regex, String) {
+    let re = RegexBuilder::new(r"(?=a)(a+)+b")
+                          .backtrack(true)
+                          .backtrack_limit(10000)
+                          .compile()
+                          .unwrap();
+    // Every way of splitting the `a`s between the repetitions is tried
+    // before giving up on the missing `b`.
+    let mut text = String::from_char(30, 'a');
+    text.push_str("cb");
+    (re, text)
+}
+
+#[test]
+fn limit_error() {
+    let (re, text) = catastrophic();
+    let err = LimitError { limit: 10000, pos: 0 };
+    assert_eq!(re.try_is_match(text.as_slice()), Err(err.clone()));
+    assert_eq!(re.try_find(text.as_slice()), Err(err.clone()));
+    assert!(re.try_captures(text.as_slice()).is_err());
+    assert_eq!(re.try_find("aab"), Ok(Some((0, 3))));
+}
+
+#[test]
+fn limit_is_no_match() {
+    // The text before the catastrophic part is still searched.
+    let (re, catastrophic) = catastrophic();
+    let text = format!("ab aab {}", catastrophic);
+    let text = text.as_slice();
+    assert!(!re.is_match(catastrophic.as_slice()));
+    assert_eq!(re.find(catastrophic.as_slice()), None);
+    assert!(re.captures(catastrophic.as_slice()).is_none());
+    assert_eq!(re.find_iter(text).collect::<Vec<_>>(), vec![(0, 2), (3, 6)]);
+    assert_eq!(re.captures_iter(text).count(), 2);
+    let rest = format!(" {}", catastrophic);
+    assert_eq!(re.split(text).collect::<Vec<_>>(), vec!["", " ", rest.as_slice()]);
+    assert_eq!(re.replace_all(text, "x"), format!("x x {}", catastrophic));
+}
+
+#[test]
+fn limit_error_iter() {
+    let (re, catastrophic) = catastrophic();
+    let text = format!("ab aab {}", catastrophic);
+    let text = text.as_slice();
+    let err = LimitError { limit: 10000, pos: 7 };
+    assert_eq!(re.try_find_iter(text).collect::<Vec<_>>(),
+               vec![Ok((0, 2)), Ok((3, 6)), Err(err.clone())]);
+    let caps = re.try_captures_iter(text).collect::<Vec<_>>();
+    assert_eq!(caps.len(), 3);
+    assert!(caps[2].is_err());
+    assert_eq!(re.try_split(text), Err(err.clone()));
+    assert_eq!(re.try_replace_all(text, "x"), Err(err.clone()));
+    assert_eq!(re.try_replace(text, "x"), Ok(format!("x aab {}", catastrophic)));
+    assert_eq!(re.try_split("ab aab"), Ok(vec!["", " "]));
+    assert_eq!(re.try_replacen("ab aab", 0, "x"), Ok("x x".to_string()));
+}
+
+#[test]
+fn limit_automata() {
+    // Expressions without look-around or backreferences are never run by
+    // the backtracking engine, so they can't hit its limit.
+    let re = RegexBuilder::new(r"(a+)+b")
+                          .backtrack(true)
+                          .backtrack_limit(1)
+                          .compile()
+                          .unwrap();
+    let (_, text) = catastrophic();
+    assert_eq!(re.try_is_match(text.as_slice()), Ok(false));
+    assert_eq!(re.try_find(text.as_slice()), Ok(None));
+}
@@ -0,0 +1,143 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::mem;
+use compile::Inst;
+use regex::{Regex, RegexBuilder, Error};
+
+fn find(builder: &RegexBuilder, text: &str) -> Option<(uint, uint)> {
+    match builder.compile() {
+        Ok(re) => re.find(text),
+        Err(err) => panic!("{}", err),
+    }
+}
+
+fn error(re: &str) -> Error {
+    match Regex::new(re) {
+        Ok(_) => panic!("Regex '{}' should cause a parse error.", re),
+        Err(err) => err,
+    }
+}
+
+#[test]
+fn builder_flags() {
+    let mut b = RegexBuilder::new(r"^a.b$");
+    assert_eq!(find(&b, "x\nA\nb"), None);
+    b.case_insensitive(true);
+    b.multi_line(true);
+    assert_eq!(find(&b, "x\nA_b"), Some((2, 5)));
+    assert_eq!(find(&b, "x\nA\nb"), None);
+    b.dot_matches_new_line(true);
+    assert_eq!(find(&b, "x\nA\nb"), Some((2, 5)));
+    b.case_insensitive(false);
+    assert_eq!(find(&b, "x\nA\nb"), None);
+}
+
+#[test]
+fn builder_flags_cleared_by_expr() {
+    let mut b = RegexBuilder::new(r"a(?-i)b");
+    b.case_insensitive(true);
+    assert_eq!(find(&b, "AB Ab"), Some((3, 5)));
+}
+
+#[test]
+fn builder_ignore_whitespace() {
+    let mut b = RegexBuilder::new("
+        (?P<year>\\d{4}) - # the year
+        (?P<month>\\d{2})  # the month
+    ");
+    b.ignore_whitespace(true);
+    assert_eq!(find(&b, "on 2014-12"), Some((3, 10)));
+}
+
+fn insts(n: uint) -> uint {
+    n * mem::size_of::<Inst>()
+}
+
+#[test]
+fn size_limit() {
+    let re = r"(a{10}){10}";
+    assert!(RegexBuilder::new(re).size_limit(insts(200)).compile().is_ok());
+    let err = RegexBuilder::new(re).size_limit(insts(100)).compile().unwrap_err();
+    let msg = format!("size limit of {} bytes", insts(100));
+    assert!(err.msg.as_slice().contains(msg.as_slice()));
+    // The outer repetition is the one that exceeds the limit.
+    assert_eq!(err.span(), (7, 11));
+}
+
+#[test]
+fn size_limit_opt_in() {
+    // Without a size limit, big programs are built as they always were.
+    let re = r"(a{100}){100}";
+    assert!(Regex::new(re).is_ok());
+    assert!(RegexBuilder::new(re).compile().is_ok());
+    assert!(RegexBuilder::new(re).size_limit(insts(1000)).compile().is_err());
+}
+
+#[test]
+fn size_limit_total() {
+    // No single repetition is too big, but all of them together are.
+    let re = r"a{100}a{100}a{100}";
+    let err = RegexBuilder::new(re).size_limit(insts(250)).compile().unwrap_err();
+    assert_eq!(err.span(), (0, 18));
+}
+
+macro_rules! span {
+    ($name:ident, $re:expr, $span:expr) => (
+        #[test]
+        fn $name() {
+            assert_eq!(error($re).span(), $span);
+        }
+    );
+}
+
+span!{span_unclosed, "a(b|c", (1, 5)}
+span!{span_unclosed_nested, "(a(?:b)(?P<c>d", (7, 14)}
+span!{span_unopened, "ab)", (2, 3)}
+span!{span_escape, r"ab\q", (2, 4)}
+span!{span_escape_eof, r"ab\", (3, 4)}
+span!{span_unicode_class, r"\p{Foo}", (0, 7)}
+span!{span_counted, "a{3,2}", (1, 6)}
+span!{span_counted_no_close, "a{3", (1, 3)}
+span!{span_class_range, "[a-cz-x]", (4, 7)}
+span!{span_name_dup, "(?P<x>a)(?P<x>b)", (8, 14)}
+span!{span_flag, "(?i-q)", (4, 5)}
+span!{span_look_around, "a(?<=b)", (1, 5)}
+
+#[test]
+fn render() {
+    assert_eq!(error("a(b|c").render().as_slice(), "\
+regex parse error:
+    a(b|c
+     ^^^^
+error: Unclosed parenthesis.");
+}
+
+#[test]
+fn render_eof() {
+    assert_eq!(error(r"ab\").render().as_slice(), "\
+regex parse error:
+    ab\\
+       ^
+error: Expected an escape sequence following a '\\' but got EOF.");
+}
+
+#[test]
+fn render_lines() {
+    let err = RegexBuilder::new("a # ok\n\tb{2,1} # bad\nc")
+                           .ignore_whitespace(true)
+                           .compile()
+                           .unwrap_err();
+    assert_eq!(err.render().as_slice(), "\
+regex parse error:
+    \tb{2,1} # bad
+    \t ^^^^^
+error: Max repetitions (1) cannot be smaller than min repetitions (2).");
+}
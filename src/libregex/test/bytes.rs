@@ -0,0 +1,96 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use regex::bytes::{Regex, NoExpand};
+
+macro_rules! bytes_mat {
+    ($name:ident, $re:expr, $text:expr, $loc:expr) => (
+        #[test]
+        fn $name() {
+            let re = match Regex::new($re) {
+                Ok(re) => re,
+                Err(err) => panic!("{}", err),
+            };
+            assert_eq!(re.find($text), $loc);
+        }
+    );
+}
+
+bytes_mat!{bytes_ascii, r"b+", b"aabbbc", Some((2, 5))}
+bytes_mat!{bytes_unicode_literal, r"Δ", b"a\xce\x94", Some((1, 3))}
+bytes_mat!{bytes_unicode_dot_invalid, r"a.b", b"a\xffb", Some((0, 3))}
+bytes_mat!{bytes_unicode_dot_truncated, r"a..b", b"a\xce\xffb", Some((0, 4))}
+bytes_mat!{bytes_unicode_class_no_invalid, r"\w+", b"\xffab\xff", Some((1, 3))}
+bytes_mat!{bytes_unicode_multibyte_one_char, r"^.$", b"\xe2\x98\x83", Some((0, 3))}
+bytes_mat!{bytes_nounicode_dot, r"(?-u)^...$", b"\xe2\x98\x83", Some((0, 3))}
+bytes_mat!{bytes_nounicode_hex, r"(?-u)\xFF+", b"a\xff\xffb", Some((1, 3))}
+bytes_mat!{bytes_nounicode_range, r"(?-u)[\x80-\xFF]+", b"ab\x80\x90\xffc", Some((2, 5))}
+bytes_mat!{bytes_nounicode_negated, r"(?-u)[^a]", b"a\xff", Some((1, 2))}
+bytes_mat!{bytes_nounicode_ascii_word, r"(?-u)\w+", b"\xe9ab\xe9", Some((1, 3))}
+bytes_mat!{bytes_nounicode_ascii_boundary, r"(?-u)\bab\b", b"\xe9ab\xe9", Some((1, 3))}
+bytes_mat!{bytes_nounicode_casei, r"(?-u)(?i)ABC", b"\x00abc", Some((1, 4))}
+bytes_mat!{bytes_no_match, r"\d", b"\xff\xfe", None}
+
+macro_rules! bytes_noparse {
+    ($name:ident, $re:expr) => (
+        #[test]
+        fn $name() {
+            let re = $re;
+            match Regex::new(re) {
+                Err(_) => {},
+                Ok(_) => panic!("Regex '{}' should cause a parse error.", re),
+            }
+        }
+    );
+}
+
+bytes_noparse!{bytes_fail_nounicode_not_at_start, r"a(?-u)"}
+bytes_noparse!{bytes_fail_nounicode_group, r"(?-u:a)"}
+bytes_noparse!{bytes_fail_nounicode_class, r"(?-u)\pN"}
+bytes_noparse!{bytes_fail_nounicode_literal, r"(?-u)Δ"}
+bytes_noparse!{bytes_fail_nounicode_hex, r"(?-u)\x{100}"}
+
+#[test]
+fn text_fail_nounicode() {
+    assert!(::regex::Regex::new(r"(?-u)a").is_err());
+    assert!(::regex::Regex::new(r"(?u)a").is_ok());
+}
+
+#[test]
+fn bytes_find_iter_empty_invalid() {
+    // Empty matches must not split a UTF-8 encoded character in two.
+    let re = Regex::new(r"").unwrap();
+    let ms: Vec<(uint, uint)> = re.find_iter(b"\xce\x94\xff").collect();
+    assert_eq!(ms, vec![(0, 0), (2, 2), (3, 3)]);
+}
+
+#[test]
+fn bytes_captures() {
+    let re = Regex::new(r"(?P<key>\w+)=(\S+)").unwrap();
+    let caps = re.captures(b"\xff key=\xfe\xfd ").unwrap();
+    assert_eq!(caps.name("key"), Some(b"key"));
+    assert_eq!(caps.at(2), Some(b"\xfe\xfd"));
+    assert_eq!(caps.pos(0), Some((2, 8)));
+}
+
+#[test]
+fn bytes_split() {
+    let re = Regex::new(r"(?-u)\x00+").unwrap();
+    let fields: Vec<&[u8]> = re.split(b"a\x00\x00b\x00\xff").collect();
+    assert_eq!(fields, vec![b"a", b"b", b"\xff"]);
+}
+
+#[test]
+fn bytes_replace() {
+    let re = Regex::new(r"(\w+)-(\w+)").unwrap();
+    assert_eq!(re.replace_all(b"ab-cd \xff", b"$2-$1 $$"),
+               b"cd-ab $ \xff".to_vec());
+    assert_eq!(re.replace(b"ab-cd", NoExpand(b"$2")), b"$2".to_vec());
+}
@@ -0,0 +1,149 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// The DFA is already exercised by every dynamic test in tests.rs. The tests
+// here check directly that it agrees with the NFA from every starting
+// position, and that it still does once its cache has been flushed.
+
+use compile::Program;
+use dfa::{DfaExec, build_static};
+use parse;
+use vm;
+use vm::{Exists, Location, Submatches};
+
+fn compile(re: &str) -> (Program, DfaExec) {
+    let ast = match parse::parse(re) {
+        Ok(ast) => ast,
+        Err(err) => panic!("{}", err),
+    };
+    let rprog = Program::new_reverse(ast.clone());
+    let (prog, _) = Program::new(ast);
+    let dfa = DfaExec::new(&prog, rprog);
+    (prog, dfa)
+}
+
+fn check_agrees(re: &str, text: &str) {
+    let (prog, dfa) = compile(re);
+    let input = text.as_bytes();
+    let mut starts: Vec<uint> = text.char_indices().map(|(i, _)| i).collect();
+    starts.push(text.len());
+    for &s in starts.iter() {
+        for &which in [Exists, Location, Submatches].iter() {
+            let expected = vm::run(which, &prog, text, s, text.len());
+            let got = dfa.exec(which, &prog, input, true, s, text.len());
+            if got != expected {
+                panic!("For /{}/ on {} from {}, expected {} but got {}",
+                       re, text, s, expected, got);
+            }
+        }
+    }
+}
+
+macro_rules! agrees {
+    ($name:ident, $re:expr, $text:expr) => (
+        #[test]
+        fn $name() {
+            check_agrees($re, $text);
+        }
+    );
+}
+
+agrees!{dfa_literal, r"abc", "xxabcxxabc"}
+agrees!{dfa_alternate_first, r"a|ab", "xab"}
+agrees!{dfa_alternate_greedy, r"ab|a", "xab"}
+agrees!{dfa_lazy, r"a+?", "aaa"}
+agrees!{dfa_greedy, r"a+", "baaab"}
+agrees!{dfa_empty, r"", "abc"}
+agrees!{dfa_empty_alternate, r"|b", "abc"}
+agrees!{dfa_anchor_begin, r"^a+", "aab"}
+agrees!{dfa_anchor_end, r"a+$", "aab aa"}
+agrees!{dfa_anchor_both, r"^$", ""}
+agrees!{dfa_anchor_optional, r"(?:^|x)a", "aba xa"}
+agrees!{dfa_classes, r"[a-c]+[0-9]*", "zzcab12x"}
+agrees!{dfa_casei, r"(?i)hello", "say HeLLo"}
+agrees!{dfa_dotnl, r"(?s)a.b", "a\nb"}
+agrees!{dfa_nodotnl, r"a.b", "a\nb axb"}
+agrees!{dfa_unicode, r"\pN+|δ", "αβγδ٣٤"}
+agrees!{dfa_captures, r"(a+)(b+)?c", "aabbc ac"}
+agrees!{dfa_repeat_empty, r"(a*)*b", "aaab"}
+agrees!{dfa_no_match, r"xyz", "abcdef"}
+
+#[test]
+fn dfa_cache_flush() {
+    // The DFA for this expression has a state for every string of 12 `a`s
+    // and `b`s, which is more than fit in the cache. The text is pseudo
+    // random so that most of them are visited.
+    let mut text = String::new();
+    let mut x = 1u32;
+    for _ in range(0u, 20000) {
+        x = x * 1103515245 + 12345;
+        text.push(if (x >> 16) & 1 == 0 { 'a' } else { 'b' });
+    }
+    text.push_str("abbbbbbbbbbbc");
+
+    let (prog, dfa) = compile(r"a[ab]{11}c");
+    let input = text.as_bytes();
+    for &which in [Exists, Location, Submatches].iter() {
+        let expected = vm::run(which, &prog, text.as_slice(), 0, input.len());
+        let got = dfa.exec(which, &prog, input, true, 0, input.len());
+        assert_eq!(got, expected);
+    }
+    assert_eq!(dfa.exec(Location, &prog, input, true, 0, input.len()),
+               vec![Some(input.len() - 13), Some(input.len())]);
+}
+
+#[test]
+fn dfa_cache_flush_non_ascii() {
+    // Every character is a distinct transition of the same few states, so
+    // the cache is flushed because of those transitions alone.
+    let mut text: String = range(0u32, 25000).map(|i| {
+        ::std::char::from_u32(0x4E00 + i % 20000).unwrap()
+    }).collect();
+    text.push('z');
+
+    let (prog, dfa) = compile(r"\pL+z");
+    let input = text.as_bytes();
+    for &which in [Exists, Location, Submatches].iter() {
+        let expected = vm::run(which, &prog, text.as_slice(), 0, input.len());
+        let got = dfa.exec(which, &prog, input, true, 0, input.len());
+        assert_eq!(got, expected);
+    }
+    assert_eq!(dfa.exec(Location, &prog, input, true, 0, input.len()),
+               vec![Some(0), Some(input.len())]);
+}
+
+#[test]
+fn dfa_invalid_utf8() {
+    let (prog, dfa) = compile(r"\xFF?.b");
+    let input = b"a\xE2\x82b\xCE\xBBb";
+    for &which in [Location, Submatches].iter() {
+        let expected = vm::run_bytes(which, &prog, input, true,
+                                     0, input.len());
+        let got = dfa.exec(which, &prog, input, true, 0, input.len());
+        assert_eq!(got, expected);
+    }
+}
+
+#[test]
+fn dfa_static() {
+    let (prog, _) = compile(r"[a-z]+@[a-z]+\.com");
+    let tables = build_static(&prog, 64).unwrap();
+    assert!(!tables.anchored);
+    assert!(tables.nclasses < 128);
+    assert!(tables.matches.len() <= 64 + 1);
+}
+
+#[test]
+fn dfa_not_used_for_word_boundaries() {
+    let (prog, _) = compile(r"\bfoo\b");
+    assert!(build_static(&prog, 64).is_none());
+    check_agrees(r"\bfoo\b", "foobar foo");
+    check_agrees(r"(?m)^foo$", "bar\nfoo\nbaz");
+}
@@ -0,0 +1,101 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use compile::Program;
+use literals::{Literals, find_any};
+use parse;
+
+fn compile(re: &str) -> Program {
+    match parse::parse(re) {
+        Ok(ast) => Program::new(ast).0,
+        Err(err) => panic!("{}", err),
+    }
+}
+
+fn lits(lits: &Literals) -> Vec<String> {
+    lits.literals().iter()
+        .map(|lit| String::from_utf8(lit.clone()).unwrap())
+        .collect()
+}
+
+macro_rules! literals {
+    ($name:ident, $re:expr, [$($prefix:expr),*], [$($suffix:expr),*]) => (
+        #[test]
+        fn $name() {
+            let prog = compile($re);
+            let prefixes: Vec<&str> = vec![$($prefix),*];
+            let suffixes: Vec<&str> = vec![$($suffix),*];
+            assert_eq!(lits(&prog.prefixes), prefixes);
+            assert_eq!(lits(&prog.suffixes), suffixes);
+        }
+    );
+}
+
+literals!{lit_plain, r"abc", ["abc"], ["abc"]}
+literals!{lit_log, r"ERROR: .*timeout", ["ERROR: "], ["timeout"]}
+literals!{lit_alternate, r"(foo|bar)\d+", ["bar", "foo"], []}
+literals!{lit_class, r"a[bc]d", ["abd", "acd"], ["abd", "acd"]}
+literals!{lit_repeat, r"(?:ab)+c", ["ab"], ["abc"]}
+literals!{lit_optional, r"a*b", [], ["b"]}
+literals!{lit_empty_alternate, r"a|", [], []}
+literals!{lit_anchored, r"^abc", [], ["abc"]}
+literals!{lit_anchored_multi, r"(?m)^abc", ["abc"], ["abc"]}
+literals!{lit_boundary, r"\bfoo\b", ["foo"], ["foo"]}
+literals!{lit_case_insensitive, r"(?i)abc", [], []}
+literals!{lit_negated_class, r"[^a]b", [], ["b"]}
+literals!{lit_large_class, r"[a-z]b", [], ["b"]}
+literals!{lit_unicode, r"δx", ["δx"], ["δx"]}
+literals!{lit_too_many, r"[a-h][a-h]x", ["a", "b", "c", "d", "e", "f", "g", "h"],
+          ["ax", "bx", "cx", "dx", "ex", "fx", "gx", "hx"]}
+
+#[test]
+fn lit_latin1() {
+    let (ast, unicode) = parse::parse_bytes(r"(?-u)\xE9a").unwrap();
+    let (prog, _) = Program::with_encoding(ast, unicode);
+    assert_eq!(prog.prefixes.literals(), [vec![0xE9, b'a']].as_slice());
+}
+
+#[test]
+fn lit_find_one_byte() {
+    let lits = Literals::new(vec![b"x".to_vec()]);
+    assert_eq!(lits.find(b"abcx"), Some(3));
+    assert_eq!(lits.find(b"abc"), None);
+}
+
+#[test]
+fn lit_find_one_literal() {
+    let lits = Literals::new(vec![b"timeout".to_vec()]);
+    assert_eq!(lits.find(b"read timeout"), Some(5));
+    assert_eq!(lits.find(b"timeou timeou"), None);
+    assert_eq!(lits.find(b"time"), None);
+}
+
+#[test]
+fn lit_find_many() {
+    let lits = Literals::new(vec![b"foo".to_vec(), b"bar".to_vec()]);
+    assert_eq!(lits.find(b"fo ba foo bar"), Some(6));
+    assert_eq!(lits.find(b"xxbarfoo"), Some(2));
+    assert_eq!(lits.find(b"fobaro"), Some(2));
+    assert_eq!(lits.find(b"fob"), None);
+}
+
+#[test]
+fn lit_find_empty() {
+    assert_eq!(Literals::empty().find(b"abc"), Some(0));
+}
+
+#[test]
+fn lit_find_any() {
+    let lits: &[&[u8]] = &[b"foo", b"bar"];
+    assert_eq!(find_any(lits, b"xxbarfoo"), Some(2));
+    assert_eq!(find_any(lits, b"fob"), None);
+    assert_eq!(find_any(&[b"o"], b"foo"), Some(1));
+    assert_eq!(find_any(&[], b"foo"), Some(0));
+}
@@ -40,3 +40,11 @@ mod dynamic_bench;
 #[path = "tests.rs"]
 mod dynamic_tests;
 
+mod backtrack;
+mod builder;
+mod bytes;
+mod dfa;
+mod literals;
+mod set;
+mod stream;
+
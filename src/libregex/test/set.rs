@@ -0,0 +1,62 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use regex::RegexSet;
+
+macro_rules! set {
+    ($name:ident, [$($re:expr),*], $text:expr, [$($matched:expr),*]) => (
+        #[test]
+        fn $name() {
+            let set = match RegexSet::new(&[$($re),*]) {
+                Ok(set) => set,
+                Err(err) => panic!("{}", err),
+            };
+            let expected: Vec<uint> = vec!($($matched),*);
+            let got: Vec<uint> = set.matches($text).iter().collect();
+            assert_eq!(got, expected);
+            assert_eq!(set.is_match($text), expected.len() > 0);
+        }
+    );
+}
+
+set!{set1, [r"a", r"b"], "b", [1]}
+set!{set2, [r"a", r"b"], "ab", [0, 1]}
+set!{set3, [r"a", r"b"], "xyz", []}
+set!{set4, [r"\w+", r"\d+", r"foo", r"bar"], "foobar", [0, 2, 3]}
+set!{set5, [r"^foo", r"foo$", r"^bar"], "foo", [0, 1]}
+set!{set6, [r"a+", r"a+b"], "aaab", [0, 1]}
+set!{set7, [r"(\w+)\s(\w+)", r"^$"], "", [1]}
+set!{set8, [r"abc", r"abd", r"ab"], "xxabd", [1, 2]}
+set!{set9, [r"(?i)ERROR", r"timeout"], "error: timeout", [0, 1]}
+set!{set_empty_pattern, [r""], "abc", [0]}
+set!{set_overlapping, [r"foo", r"oob", r"obar"], "foobar", [0, 1, 2]}
+
+#[test]
+fn set_no_patterns() {
+    let pats: &[&str] = &[];
+    let set = RegexSet::new(pats).unwrap();
+    assert_eq!(set.len(), 0);
+    assert!(!set.is_match("abc"));
+    assert!(!set.matches("abc").matched_any());
+}
+
+#[test]
+fn set_bad_pattern() {
+    match RegexSet::new(&["a", "(", "b"]) {
+        Ok(_) => panic!("set with unclosed paren should not compile"),
+        Err(err) => assert!(err.msg.as_slice().starts_with("In expression 1")),
+    }
+}
+
+#[test]
+fn set_patterns() {
+    let set = RegexSet::new(&["a", "b"]).unwrap();
+    assert_eq!(set.patterns(), ["a".to_string(), "b".to_string()].as_slice());
+}
@@ -0,0 +1,164 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Searching a stream must find exactly the matches that searching all of its
+// bytes at once finds. The readers here hand out a few bytes at a time so that
+// matches (and characters) are split across reads.
+
+use std::cmp;
+use std::io::{IoResult, IoError, EndOfFile, OtherIoError, standard_error};
+use std::slice::bytes;
+use regex::bytes::Regex;
+
+// Yields at most `step` bytes per read, then fails with `err` (or reports the
+// end of the stream).
+struct Trickle {
+    data: Vec<u8>,
+    pos: uint,
+    step: uint,
+    err: Option<IoError>,
+}
+
+impl Trickle {
+    fn new(data: &[u8], step: uint) -> Trickle {
+        Trickle { data: data.to_vec(), pos: 0, step: step, err: None }
+    }
+}
+
+impl Reader for Trickle {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<uint> {
+        if self.pos >= self.data.len() {
+            return Err(match self.err.take() {
+                None => standard_error(EndOfFile),
+                Some(err) => err,
+            })
+        }
+        let n = cmp::min(cmp::min(self.step, buf.len()),
+                         self.data.len() - self.pos);
+        bytes::copy_memory(buf, self.data[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+fn regex(re: &str) -> Regex {
+    match Regex::new(re) {
+        Ok(re) => re,
+        Err(err) => panic!("{}", err),
+    }
+}
+
+fn check_stream(re: &str, text: &[u8]) {
+    let re = regex(re);
+    let expected: Vec<(u64, u64)> =
+        re.find_iter(text).map(|(s, e)| (s as u64, e as u64)).collect();
+    let expected_caps: Vec<Vec<Option<Vec<u8>>>> =
+        re.captures_iter(text)
+          .map(|caps| {
+              range(0, caps.len())
+                  .map(|i| caps.at(i).map(|b| b.to_vec()))
+                  .collect()
+          })
+          .collect();
+    for &step in [1u, 2, 3, 7, 1000].iter() {
+        let got: Vec<(u64, u64)> =
+            re.find_iter_reader(Trickle::new(text, step))
+              .map(|m| m.unwrap())
+              .collect();
+        if got != expected {
+            panic!("For /{}/ read {} bytes at a time, expected {} but got {}",
+                   re, step, expected, got);
+        }
+        let got_caps: Vec<Vec<Option<Vec<u8>>>> =
+            re.captures_iter_reader(Trickle::new(text, step))
+              .map(|caps| {
+                  let caps = caps.unwrap();
+                  range(0, caps.len())
+                      .map(|i| caps.at(i).map(|b| b.to_vec()))
+                      .collect()
+              })
+              .collect();
+        if got_caps != expected_caps {
+            panic!("For /{}/ read {} bytes at a time, expected {} but got {}",
+                   re, step, expected_caps, got_caps);
+        }
+    }
+}
+
+macro_rules! stream {
+    ($name:ident, $re:expr, $text:expr) => (
+        #[test]
+        fn $name() {
+            check_stream($re, $text);
+        }
+    );
+}
+
+stream!{stream_literal, r"abc", b"xxabcxxabcabc"}
+stream!{stream_greedy, r"a+", b"aaaa baa aaaaaaa"}
+stream!{stream_lazy, r"a+?b", b"aaab aab"}
+stream!{stream_alternate, r"ab|abcd", b"abcd abc"}
+stream!{stream_captures, r"(\w+)=(\d+)?", b"a=1 bc=  def=456"}
+stream!{stream_prefix, r"ERROR: \w+", b"INFO: ok\nERROR: disk\nERROR: net"}
+stream!{stream_long_match, r"a.*z", b"a 0123456789 0123456789 z tail"}
+stream!{stream_no_match, r"xyz", b"xy xy x yz xyy"}
+stream!{stream_empty, r"", b"abc"}
+stream!{stream_empty_alternate, r"a*", b"baaab"}
+stream!{stream_anchor_begin, r"^a+", b"aab aa"}
+stream!{stream_anchor_end, r"a+$", b"aab aa"}
+stream!{stream_multi_begin, r"(?m)^\w+", b"foo bar\nbaz\n\nquux"}
+stream!{stream_multi_end, r"(?m)\w+$", b"foo bar\nbaz\n\nquux"}
+stream!{stream_multi_line, r"(?m)^(\w+): (.*)$", b"a: 1\nbb: 22\nnope\nccc: 333"}
+stream!{stream_word_boundary, r"\bfoo\b", b"foobar foo xfoo foo"}
+stream!{stream_unicode, r"δ+|\pN", b"\xce\xb4\xce\xb4x\xd9\xa3"}
+stream!{stream_invalid_utf8, r"a.b", b"a\xffb a\xe2\x82b a\xe2\x82\xacb"}
+stream!{stream_nounicode, r"(?-u)\xE9+", b"a\xe9\xe9b\xe9"}
+
+#[test]
+fn stream_big() {
+    // Many more bytes than are read at once, with a few matches scattered
+    // through them and one spanning two reads.
+    let mut text = Vec::from_elem(200000, b'.');
+    text[10] = b'x';
+    text[99999] = b'x';
+    text[100000] = b'y';
+    text.push_all(b"xy");
+    let re = regex(r"xy|x");
+    let got: Vec<(u64, u64)> =
+        re.find_iter_reader(Trickle::new(text.as_slice(), 50000))
+          .map(|m| m.unwrap())
+          .collect();
+    assert_eq!(got, vec![(10, 11), (99999, 100001), (200000, 200002)]);
+}
+
+#[test]
+fn stream_captures_names() {
+    let re = regex(r"(?P<key>\w+)=(?P<value>\w+)");
+    let mut it = re.captures_iter_reader(Trickle::new(b"-- abc=xyz", 2));
+    let caps = it.next().unwrap().unwrap();
+    assert_eq!(caps.pos(0), Some((3, 10)));
+    assert_eq!(caps.pos(2), Some((7, 10)));
+    assert_eq!(caps.name("key"), Some(b"abc"));
+    assert_eq!(caps.name("value"), Some(b"xyz"));
+    assert_eq!(caps.name("nope"), None);
+    assert!(it.next().is_none());
+}
+
+#[test]
+fn stream_error() {
+    let mut rdr = Trickle::new(b"ab ab a", 1);
+    rdr.err = Some(standard_error(OtherIoError));
+    let re = regex(r"ab");
+    let mut it = re.find_iter_reader(rdr);
+    assert_eq!(it.next().unwrap().unwrap(), (0, 2));
+    assert_eq!(it.next().unwrap().unwrap(), (3, 5));
+    assert_eq!(it.next().unwrap().unwrap_err().kind, OtherIoError);
+    assert!(it.next().is_none());
+}
@@ -29,6 +29,13 @@ fn split() {
     assert_eq!(subs, vec!("cauchy", "plato", "tyler", "binx"));
 }
 
+#[test]
+fn find_iter_literal_prefix() {
+    let re = regex!(r"ab\d");
+    let ms = re.find_iter("xab1ab ab2abab3").collect::<Vec<(uint, uint)>>();
+    assert_eq!(ms, vec![(1, 4), (7, 10), (12, 15)]);
+}
+
 #[test]
 fn empty_regex_empty_match() {
     let re = regex!("");
@@ -207,6 +214,23 @@ mat!{match_flag_multi, "(?m)(?:^\\d+$\n?)+", "123\n456\n789", Some((0, 11))}
 mat!{match_flag_ungreedy, "(?U)a+", "aa", Some((0, 1))}
 mat!{match_flag_ungreedy_greedy, "(?U)a+?", "aa", Some((0, 2))}
 mat!{match_flag_ungreedy_noop, "(?U)(?-U)a+", "aa", Some((0, 2))}
+mat!{match_flag_negate_keeps_others, "(?is)a(?-i).b", "A\nb", Some((0, 3))}
+mat!{match_flag_negate_keeps_others_not, "(?is)a(?-i).b", "A\nB", None}
+mat!{match_flag_x, "(?x) a b  # a comment\n c", "abc", Some((0, 3))}
+mat!{match_flag_x_repeat, "(?x)a +", "aaa", Some((0, 3))}
+mat!{match_flag_x_escape, r"(?x)a\ b\#", "a b#", Some((0, 4))}
+mat!{match_flag_x_class, "(?x)[ ]a", " a", Some((0, 2))}
+mat!{match_flag_x_off, "(?x)a (?-x:b c)", "ab c", Some((0, 4))}
+
+// Exercise the literal prefix and suffix searches.
+mat!{match_literal_prefix, r"ERROR: .*timeout",
+     "INFO: ok\nERROR: read timeout", Some((9, 28))}
+mat!{match_literal_prefix_not, r"ERROR: .*timeout", "ERROR: ok\ntimeout", None}
+mat!{match_literal_alternate, r"(foo|bar)\d+", "foo bar12",
+     Some((4, 9)), Some((4, 7))}
+mat!{match_literal_class, r"[xy]z+", "axbyzz", Some((3, 6))}
+mat!{match_literal_suffix, r"\w+ing", "nothing here", Some((0, 7))}
+mat!{match_literal_suffix_not, r"\w+ing", "nothing here"[..6], None}
 
 // Some Unicode tests.
 // A couple of these are commented out because something in the guts of macro expansion is creating
@@ -8,8 +8,8 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-// FIXME: Currently, the VM simulates an NFA. It would be nice to have another
-// VM that simulates a DFA.
+// The VM here simulates an NFA. When possible, the lazy DFA in dfa.rs finds
+// matches first, and this VM only runs to find the submatches within them.
 //
 // According to Russ Cox[1], a DFA performs better than an NFA, principally
 // because it reuses states previously computed by the machine *and* doesn't
@@ -30,25 +30,32 @@
 // perf improvement.
 //
 // AFAIK, the DFA/NFA approach is implemented in RE2/C++ but *not* in RE2/Go.
+// dfa.rs follows it.
 //
 // [1] - http://swtch.com/~rsc/regex/regex3.html
 
 pub use self::MatchKind::*;
+pub use self::Partial::*;
 pub use self::StepState::*;
 
 use std::cmp;
 use std::mem;
 use std::slice::SliceExt;
+use std::str;
 use compile::{
     Program,
-    Match, OneChar, CharClass, Any, EmptyBegin, EmptyEnd, EmptyWordBoundary,
-    Save, Jump, Split,
+    Match, SetMatch, OneChar, CharClass, Any, EmptyBegin, EmptyEnd, EmptyWordBoundary,
+    Save, Jump, Split, EmptyLook, LookMatch, Backreference,
 };
 use parse::{FLAG_NOCASE, FLAG_MULTI, FLAG_DOTNL, FLAG_NEGATED};
 use unicode::regex::PERLW;
 
 pub type CaptureLocs = Vec<Option<uint>>;
 
+/// The character read in place of a byte that isn't part of a valid UTF-8
+/// encoding.
+const REPLACEMENT: char = '\u{FFFD}';
+
 /// Indicates the type of match to be performed by the VM.
 #[deriving(Copy)]
 pub enum MatchKind {
@@ -61,6 +68,19 @@ pub enum MatchKind {
     Submatches,
 }
 
+/// The result of searching text that may be followed by more text that hasn't
+/// been read yet.
+#[deriving(Show, PartialEq)]
+pub enum Partial {
+    /// The search is finished: its result can't change no matter what text
+    /// follows. (The capture locations may still say there is no match.)
+    Final(CaptureLocs),
+    /// More text is needed to finish the search. No match can begin before
+    /// the byte index given, so the search can be restarted from there once
+    /// more text has been read.
+    NeedMore(uint),
+}
+
 /// Runs an NFA simulation on the compiled expression given on the search text
 /// `input`. The search begins at byte index `start` and ends at byte index
 /// `end`. (The range is specified here so that zero-width assertions will work
@@ -72,6 +92,19 @@ pub enum MatchKind {
 /// locations of each submatch.
 pub fn run<'r, 't>(which: MatchKind, prog: &'r Program, input: &'t str,
                    start: uint, end: uint) -> CaptureLocs {
+    run_bytes(which, prog, input.as_bytes(), true, start, end)
+}
+
+/// Runs an NFA simulation exactly like `run`, except the search text is an
+/// arbitrary sequence of bytes.
+///
+/// When `unicode` is `true`, the input is decoded as UTF-8 where every byte
+/// that is not part of a valid UTF-8 sequence is read as a single `U+FFFD`
+/// character. Otherwise, every byte is read as the character with the same
+/// value (i.e., the input is read as Latin-1).
+pub fn run_bytes<'r, 't>(which: MatchKind, prog: &'r Program, input: &'t [u8],
+                         unicode: bool, start: uint, end: uint)
+                        -> CaptureLocs {
     Nfa {
         which: which,
         prog: prog,
@@ -79,18 +112,72 @@ pub fn run<'r, 't>(which: MatchKind, prog: &'r Program, input: &'t str,
         start: start,
         end: end,
         ic: 0,
-        chars: CharReader::new(input),
+        chars: CharReader::from_bytes(input, unicode),
+        partial: false,
     }.run()
 }
 
+/// Runs an NFA simulation like `run_bytes`, except that `input` is only the
+/// part of the search text that has been read so far.
+///
+/// Only the positions up to and including `end` are searched, and `end` must
+/// be less than `input.len()`, so that the character following every position
+/// searched is known. Assertions are never evaluated at the end of `input`,
+/// since it isn't the end of the text.
+pub fn run_partial<'r, 't>(which: MatchKind, prog: &'r Program,
+                           input: &'t [u8], unicode: bool,
+                           start: uint, end: uint) -> Partial {
+    let mut nfa = Nfa {
+        which: which,
+        prog: prog,
+        input: input,
+        start: start,
+        end: end,
+        ic: 0,
+        chars: CharReader::from_bytes(input, unicode),
+        partial: true,
+    };
+    let (locs, finished, keep) = nfa.search();
+    if finished { Final(locs) } else { NeedMore(keep) }
+}
+
+/// Runs an NFA simulation of a program compiled from a set of regular
+/// expressions (see `Program::new_set`) on the search text `input`.
+///
+/// The `i`th element of `matched` is set to `true` if and only if the `i`th
+/// expression in the set matches somewhere between byte indices `start` and
+/// `end`. All of the expressions are tried in a single scan over the input.
+///
+/// If `quit_after_first` is `true`, then the search stops as soon as any one
+/// expression is known to match.
+pub fn run_set<'r, 't>(prog: &'r Program, input: &'t str,
+                       start: uint, end: uint,
+                       matched: &mut [bool], quit_after_first: bool) {
+    if prog.insts.len() == 0 {
+        return
+    }
+    Nfa {
+        which: Exists,
+        prog: prog,
+        input: input.as_bytes(),
+        start: start,
+        end: end,
+        ic: 0,
+        chars: CharReader::new(input),
+        partial: false,
+    }.run_set(matched, quit_after_first)
+}
+
 struct Nfa<'r, 't> {
     which: MatchKind,
     prog: &'r Program,
-    input: &'t str,
+    input: &'t [u8],
     start: uint,
     end: uint,
     ic: uint,
     chars: CharReader<'t>,
+    /// True when more text may follow `input` (see `run_partial`).
+    partial: bool,
 }
 
 /// Indicates the next action to take after a single non-empty instruction
@@ -111,12 +198,24 @@ pub enum StepState {
 
 impl<'r, 't> Nfa<'r, 't> {
     fn run(&mut self) -> CaptureLocs {
+        let (locs, _, _) = self.search();
+        locs
+    }
+
+    // Runs the simulation. Besides the capture locations, this returns
+    // whether the search stopped before reaching `end` (so that text after
+    // `end` couldn't change the result), and a position before which no
+    // match can begin.
+    fn search(&mut self) -> (CaptureLocs, bool, uint) {
         let ncaps = match self.which {
             Exists => 0,
             Location => 1,
             Submatches => self.prog.num_captures(),
         };
         let mut matched = false;
+        if !self.partial && !has_suffix(self.prog, self.input, self.start) {
+            return (vec![None, None], true, self.start)
+        }
         let ninsts = self.prog.insts.len();
         let mut clist = &mut Threads::new(self.which, ninsts, ncaps);
         let mut nlist = &mut Threads::new(self.which, ninsts, ncaps);
@@ -133,6 +232,10 @@ impl<'r, 't> Nfa<'r, 't> {
                 _ => false,
             };
 
+        // A match can't begin before the last position at which there were
+        // no threads left.
+        let mut keep = self.start;
+        let mut finished = false;
         self.ic = self.start;
         let mut next_ic = self.chars.set(self.start);
         while self.ic <= self.end {
@@ -140,22 +243,29 @@ impl<'r, 't> Nfa<'r, 't> {
                 // We have a match and we're done exploring alternatives.
                 // Time to quit.
                 if matched {
+                    finished = true;
                     break
                 }
+                keep = self.ic;
 
                 // If there are no threads to try, then we'll have to start
                 // over at the beginning of the regex.
-                // BUT, if there's a literal prefix for the program, try to
-                // jump ahead quickly. If it can't be found, then we can bail
+                // BUT, if there are literal prefixes for the program, try to
+                // jump ahead quickly. If none can be found, then we can bail
                 // out early.
-                if self.prog.prefix.len() > 0 && clist.size == 0 {
-                    let needle = self.prog.prefix.as_bytes();
-                    let haystack = self.input.as_bytes()[self.ic..];
-                    match find_prefix(needle, haystack) {
-                        None => break,
+                if !self.prog.prefixes.is_empty() {
+                    let haystack = self.input[self.ic..];
+                    match self.prog.prefixes.find(haystack) {
+                        None => {
+                            keep = prefix_keep(self.prog, self.input,
+                                               self.chars.unicode, self.ic);
+                            break
+                        }
+                        Some(0) => {}
                         Some(i) => {
                             self.ic += i;
                             next_ic = self.chars.set(self.ic);
+                            keep = self.ic;
                         }
                     }
                 }
@@ -179,7 +289,9 @@ impl<'r, 't> Nfa<'r, 't> {
                 let step_state = self.step(groups.as_mut_slice(), nlist,
                                            clist.groups(i), pc);
                 match step_state {
-                    StepMatchEarlyReturn => return vec![Some(0), Some(0)],
+                    StepMatchEarlyReturn => {
+                        return (vec![Some(0), Some(0)], true, keep)
+                    }
                     StepMatch => { matched = true; break },
                     StepContinue => {},
                 }
@@ -187,10 +299,54 @@ impl<'r, 't> Nfa<'r, 't> {
             mem::swap(&mut clist, &mut nlist);
             nlist.empty();
         }
-        match self.which {
+        let locs = match self.which {
             Exists if matched     => vec![Some(0), Some(0)],
             Exists                => vec![None, None],
             Location | Submatches => groups,
+        };
+        (locs, finished, keep)
+    }
+
+    fn run_set(&mut self, matched: &mut [bool], quit_after_first: bool) {
+        let ninsts = self.prog.insts.len();
+        let mut clist = &mut Threads::new(Exists, ninsts, 0);
+        let mut nlist = &mut Threads::new(Exists, ninsts, 0);
+        let mut groups: Vec<Option<uint>> = vec![];
+
+        let total = matched.len();
+        let mut nmatched = matched.iter().filter(|&&m| m).count();
+
+        self.ic = self.start;
+        let mut next_ic = self.chars.set(self.start);
+        while self.ic <= self.end {
+            // Unlike `run`, a match of one expression doesn't stop us from
+            // looking for matches of the others, so we always simulate a
+            // preceding '.*?' by starting a thread at the current position.
+            self.add(clist, 0, groups.as_mut_slice());
+
+            self.ic = next_ic;
+            next_ic = self.chars.advance();
+
+            for i in range(0, clist.size) {
+                let pc = clist.pc(i);
+                match self.prog.insts[pc] {
+                    SetMatch(n) => {
+                        if !matched[n] {
+                            matched[n] = true;
+                            nmatched += 1;
+                        }
+                    }
+                    _ => {
+                        self.step(groups.as_mut_slice(), nlist,
+                                  clist.groups(i), pc);
+                    }
+                }
+            }
+            if nmatched == total || (quit_after_first && nmatched > 0) {
+                break
+            }
+            mem::swap(&mut clist, &mut nlist);
+            nlist.empty();
         }
     }
 
@@ -241,7 +397,10 @@ impl<'r, 't> Nfa<'r, 't> {
                 }
             }
             EmptyBegin(_) | EmptyEnd(_) | EmptyWordBoundary(_)
-            | Save(_) | Jump(_) | Split(_, _) => {},
+            | Save(_) | Jump(_) | Split(_, _) | SetMatch(_) => {},
+            EmptyLook(_, _) | LookMatch | Backreference(_, _) => {
+                panic!("BUG: Instruction can't be run by the NFA.")
+            }
         }
         StepContinue
     }
@@ -314,9 +473,12 @@ impl<'r, 't> Nfa<'r, 't> {
                 self.add(nlist, x, groups);
                 self.add(nlist, y, groups);
             }
-            Match | OneChar(_, _) | CharClass(_, _) | Any(_) => {
+            Match | SetMatch(_) | OneChar(_, _) | CharClass(_, _) | Any(_) => {
                 nlist.add(pc, groups, false);
             }
+            EmptyLook(_, _) | LookMatch | Backreference(_, _) => {
+                panic!("BUG: Instruction can't be run by the NFA.")
+            }
         }
     }
 
@@ -345,13 +507,20 @@ impl<'r, 't> Nfa<'r, 't> {
 /// CharReader is responsible for maintaining a "previous" and a "current"
 /// character. This one-character lookahead is necessary for assertions that
 /// look one character before or after the current position.
+///
+/// A CharReader reads characters from a sequence of bytes. When reading text,
+/// the bytes are decoded as UTF-8. When reading arbitrary bytes, they are
+/// either decoded as UTF-8 (where each invalid byte is read as a single
+/// `U+FFFD` character) or, when Unicode mode is disabled, read one byte at a
+/// time as the characters `U+0000` through `U+00FF`.
 pub struct CharReader<'t> {
     /// The previous character read. It is None only when processing the first
     /// character of the input.
     pub prev: Option<char>,
     /// The current character.
     pub cur: Option<char>,
-    input: &'t str,
+    input: &'t [u8],
+    unicode: bool,
     next: uint,
 }
 
@@ -360,10 +529,17 @@ impl<'t> CharReader<'t> {
     /// Note that a CharReader has no knowledge of the range in which to search
     /// the input.
     pub fn new(input: &'t str) -> CharReader<'t> {
+        CharReader::from_bytes(input.as_bytes(), true)
+    }
+
+    /// Returns a new CharReader that advances through the bytes given.
+    /// If `unicode` is false, then each byte is read as a single character.
+    pub fn from_bytes(input: &'t [u8], unicode: bool) -> CharReader<'t> {
         CharReader {
             prev: None,
             cur: None,
             input: input,
+            unicode: unicode,
             next: 0,
        }
     }
@@ -381,13 +557,12 @@ impl<'t> CharReader<'t> {
         }
         if ic > 0 {
             let i = cmp::min(ic, self.input.len());
-            let prev = self.input.char_range_at_reverse(i);
-            self.prev = Some(prev.ch);
+            self.prev = Some(self.decode_before(i));
         }
         if ic < self.input.len() {
-            let cur = self.input.char_range_at(ic);
-            self.cur = Some(cur.ch);
-            self.next = cur.next;
+            let (cur, next) = self.decode_at(ic);
+            self.cur = Some(cur);
+            self.next = next;
             self.next
         } else {
             self.input.len() + 1
@@ -400,9 +575,9 @@ impl<'t> CharReader<'t> {
     pub fn advance(&mut self) -> uint {
         self.prev = self.cur;
         if self.next < self.input.len() {
-            let cur = self.input.char_range_at(self.next);
-            self.cur = Some(cur.ch);
-            self.next = cur.next;
+            let (cur, next) = self.decode_at(self.next);
+            self.cur = Some(cur);
+            self.next = next;
         } else {
             self.cur = None;
             self.next = self.input.len() + 1;
@@ -410,6 +585,24 @@ impl<'t> CharReader<'t> {
         self.next
     }
 
+    #[inline]
+    fn decode_at(&self, i: uint) -> (char, uint) {
+        if self.unicode {
+            decode_utf8(self.input, i)
+        } else {
+            (self.input[i] as char, i + 1)
+        }
+    }
+
+    #[inline]
+    fn decode_before(&self, i: uint) -> char {
+        if self.unicode {
+            decode_last_utf8(self.input, i).0
+        } else {
+            self.input[i - 1] as char
+        }
+    }
+
     /// Returns true if and only if this is the beginning of the input
     /// (ignoring the range of the input to search).
     #[inline]
@@ -422,15 +615,29 @@ impl<'t> CharReader<'t> {
 
     /// Returns true if and only if the current position is a word boundary.
     /// (Ignoring the range of the input to search.)
+    /// When Unicode mode is disabled, only ASCII word characters are
+    /// considered.
     pub fn is_word_boundary(&self) -> bool {
+        let (prev, cur) = (self.is_word(self.prev), self.is_word(self.cur));
         if self.is_begin() {
-            return is_word(self.cur)
+            return cur
         }
         if self.is_end() {
-            return is_word(self.prev)
+            return prev
+        }
+        (cur && !prev) || (prev && !cur)
+    }
+
+    #[inline]
+    fn is_word(&self, c: Option<char>) -> bool {
+        if self.unicode {
+            is_word(c)
+        } else {
+            match c {
+                Some(c) if (c as u32) < 0x80 => is_word(Some(c)),
+                _ => false,
+            }
         }
-        (is_word(self.cur) && !is_word(self.prev))
-        || (is_word(self.prev) && !is_word(self.cur))
     }
 }
 
@@ -530,6 +737,54 @@ pub fn is_word(c: Option<char>) -> bool {
     }
 }
 
+/// Decodes the UTF-8 encoded character starting at byte index `i` in `input`
+/// and returns it along with the byte index of the character following it.
+///
+/// If the bytes at `i` are not a valid UTF-8 encoding, then `U+FFFD` is
+/// returned and only a single byte is consumed.
+#[inline]
+pub fn decode_utf8(input: &[u8], i: uint) -> (char, uint) {
+    let b = input[i];
+    if b < 0x80 {
+        return (b as char, i + 1)
+    }
+    let width = str::utf8_char_width(b);
+    if width == 0 || i + width > input.len() {
+        return (REPLACEMENT, i + 1)
+    }
+    match str::from_utf8(input[i..i + width]) {
+        Some(s) => (s.char_at(0), i + width),
+        None => (REPLACEMENT, i + 1),
+    }
+}
+
+/// Decodes the character that ends just before byte index `i` in `input` and
+/// returns it along with the byte index at which it starts.
+///
+/// This is consistent with `decode_utf8`: if the bytes preceding `i` are not
+/// the end of a valid UTF-8 encoding, then `U+FFFD` is returned and only a
+/// single byte is consumed.
+#[inline]
+pub fn decode_last_utf8(input: &[u8], i: uint) -> (char, uint) {
+    let b = input[i - 1];
+    if b < 0x80 {
+        return (b as char, i - 1)
+    }
+    let lower = if i >= 4 { i - 4 } else { 0 };
+    let mut start = i - 1;
+    loop {
+        // Look for the nearest byte that isn't a continuation byte.
+        if input[start] & 0xC0 != 0x80 {
+            let (c, next) = decode_utf8(input, start);
+            return if next == i { (c, start) } else { (REPLACEMENT, i - 1) }
+        }
+        if start == lower {
+            return (REPLACEMENT, i - 1)
+        }
+        start -= 1;
+    }
+}
+
 /// Given a character and a single character class range, return an ordering
 /// indicating whether the character is less than the start of the range,
 /// in the range (inclusive) or greater than the end of the range.
@@ -538,8 +793,8 @@ pub fn is_word(c: Option<char>) -> bool {
 ///
 /// This function is meant to be used with a binary search.
 #[inline]
-fn class_cmp(casei: bool, mut textc: char,
-             (mut start, mut end): (char, char)) -> Ordering {
+pub fn class_cmp(casei: bool, mut textc: char,
+                 (mut start, mut end): (char, char)) -> Ordering {
     if casei {
         // FIXME: This is pretty ridiculous. All of this case conversion
         // can be moved outside this function:
@@ -561,20 +816,28 @@ fn class_cmp(casei: bool, mut textc: char,
     }
 }
 
-/// Returns the starting location of `needle` in `haystack`.
-/// If `needle` is not in `haystack`, then `None` is returned.
-///
-/// Note that this is using a naive substring algorithm.
+/// Returns false if the program has literal suffixes (one of which every
+/// match has to end with) and none of them occur in `input` after byte index
+/// `start`. In that case there can't be a match.
 #[inline]
-pub fn find_prefix(needle: &[u8], haystack: &[u8]) -> Option<uint> {
-    let (hlen, nlen) = (haystack.len(), needle.len());
-    if nlen > hlen || nlen == 0 {
-        return None
-    }
-    for (offset, window) in haystack.windows(nlen).enumerate() {
-        if window == needle {
-            return Some(offset)
-        }
+pub fn has_suffix(prog: &Program, input: &[u8], start: uint) -> bool {
+    prog.suffixes.is_empty() || prog.suffixes.find(input[start..]).is_some()
+}
+
+/// Returns the earliest position at or after `i` at which one of the prefixes
+/// of `prog` could still begin, given that none of them occurs in `input`
+/// from `i` on but more text may follow `input`.
+///
+/// The position returned is always at a character boundary.
+pub fn prefix_keep(prog: &Program, input: &[u8], unicode: bool,
+                   i: uint) -> uint {
+    let longest = prog.prefixes.literals().iter()
+                      .map(|lit| lit.len()).max().unwrap_or(1);
+    let tail = if input.len() + 1 > longest { input.len() + 1 - longest } else { 0 };
+    let mut keep = cmp::max(i, tail);
+    while unicode && keep > i && keep < input.len()
+          && input[keep] & 0xC0 == 0x80 {
+        keep -= 1;
     }
-    None
+    keep
 }
@@ -44,8 +44,14 @@ use regex::native::{
     Match, EmptyBegin, EmptyEnd, EmptyWordBoundary,
     Program, Dynamic, ExDynamic, Native,
     FLAG_NOCASE, FLAG_MULTI, FLAG_DOTNL, FLAG_NEGATED,
+    Literals, build_static,
 };
 
+/// The maximum number of states in the DFA that is built for an expression
+/// at compile time. Expressions that need more states are only run with the
+/// generated NFA.
+const MAX_STATIC_DFA_STATES: uint = 256;
+
 /// For the `regex!` syntax extension. Do not use.
 #[plugin_registrar]
 #[doc(hidden)]
@@ -72,6 +78,13 @@ pub fn plugin_registrar(reg: &mut Registry) {
 /// matching from using a binary search to a simple `match` expression (see
 /// `match_class`).
 ///
+/// When the expression can be run with a DFA (see dfa.rs in the `regex`
+/// crate), all of its states on ASCII input are also built ahead of time and
+/// emitted as static tables (see `static_dfa`). The DFA runs first: it
+/// answers `is_match` on its own and otherwise tells the NFA where the match
+/// ends. It gives up on text that isn't ASCII, in which case the NFA does all
+/// of the work.
+///
 /// It is strongly recommended to read the dynamic implementation in vm.rs
 /// first before trying to understand the code generator. The implementation
 /// strategy is identical and vm.rs has comments and will be easier to follow.
@@ -133,9 +146,10 @@ impl<'a> NfaGen<'a> {
         let init_groups = self.vec_expr(range(0, num_cap_locs),
                                         |cx, _| cx.expr_none(self.sp));
 
-        let prefix_lit = Rc::new(self.prog.prefix.as_bytes().to_vec());
-        let prefix_bytes = self.cx.expr_lit(self.sp, ast::LitBinary(prefix_lit));
+        let prefixes = self.literals_expr(&self.prog.prefixes);
 
+        let check_suffix = self.check_suffix();
+        let static_dfa = self.static_dfa();
         let check_prefix = self.check_prefix();
         let step_insts = self.step_insts();
         let add_insts = self.add_insts();
@@ -159,9 +173,13 @@ fn exec<'t>(which: ::regex::native::MatchKind, input: &'t str,
     use regex::native::{
         MatchKind, Exists, Location, Submatches,
         StepState, StepMatchEarlyReturn, StepMatch, StepContinue,
-        CharReader, find_prefix,
+        CharReader, find_any,
+        StaticDfa, exec_static, NoMatch, MatchEnd, GaveUp,
     };
 
+    $check_suffix
+    let end = $static_dfa;
+
     return Nfa {
         which: which,
         input: input,
@@ -182,7 +200,7 @@ fn exec<'t>(which: ::regex::native::MatchKind, input: &'t str,
         #[allow(unused_variables)]
         fn run(&mut self, start: uint, end: uint) -> Vec<Option<uint>> {
             let mut matched = false;
-            let prefix_bytes: &[u8] = $prefix_bytes;
+            let prefixes: &[&[u8]] = &$prefixes;
             let mut clist = &mut Threads::new(self.which);
             let mut nlist = &mut Threads::new(self.which);
 
@@ -541,17 +559,71 @@ fn exec<'t>(which: ::regex::native::MatchKind, input: &'t str,
         self.cx.expr_match(self.sp, match_on, arms)
     }
 
-    // Generates code for checking a literal prefix of the search string.
-    // The code is only generated if the regex *has* a literal prefix.
+    // Generates code that runs a DFA built at compile time over the search
+    // text and evaluates to the index at which the NFA can stop searching.
+    // It returns early if the DFA finds that there is no match, or if it finds
+    // a match and only its existence was asked for.
+    // If the DFA can't be built, then `end` is returned unchanged.
+    fn static_dfa(&self) -> P<ast::Expr> {
+        let dfa = match build_static(&self.prog, MAX_STATIC_DFA_STATES) {
+            None => return quote_expr!(self.cx, end),
+            Some(dfa) => dfa,
+        };
+        let sp = self.sp;
+        let anchored = self.cx.expr_bool(sp, dfa.anchored);
+        let classes = self.vec_expr(dfa.classes.iter(),
+                                    |cx, &class| cx.expr_u8(sp, class));
+        let nclasses = dfa.nclasses;
+        let trans = self.vec_expr(dfa.trans.iter(),
+                                  |cx, &si| expr_u16(cx, sp, si));
+        let begin_trans = self.vec_expr(dfa.begin_trans.iter(),
+                                        |cx, &si| expr_u16(cx, sp, si));
+        let matches = self.vec_expr(dfa.matches.iter(),
+                                    |cx, &b| cx.expr_bool(sp, b));
+        let matches_at_end = self.vec_expr(dfa.matches_at_end.iter(),
+                                           |cx, &b| cx.expr_bool(sp, b));
+        let done = self.vec_expr(dfa.done.iter(),
+                                 |cx, &b| cx.expr_bool(sp, b));
+        let matches_at_begin = self.cx.expr_bool(sp, dfa.matches_at_begin);
+        let matches_empty = self.cx.expr_bool(sp, dfa.matches_empty);
+
+        quote_expr!(self.cx, {
+            static DFA: StaticDfa = StaticDfa {
+                anchored: $anchored,
+                classes: &$classes,
+                nclasses: $nclasses,
+                trans: &$trans,
+                begin_trans: &$begin_trans,
+                matches: &$matches,
+                matches_at_end: &$matches_at_end,
+                done: &$done,
+                matches_at_begin: $matches_at_begin,
+                matches_empty: $matches_empty,
+            };
+            let quit_early = match which {
+                Exists => true,
+                Location | Submatches => false,
+            };
+            match exec_static(&DFA, quit_early, input, start, end) {
+                NoMatch => return vec![None, None],
+                MatchEnd(_) if quit_early => return vec![Some(0u), Some(0u)],
+                MatchEnd(e) => e,
+                GaveUp => end,
+            }
+        })
+    }
+
+    // Generates code for checking the literal prefixes of the search string.
+    // The code is only generated if the regex *has* literal prefixes.
     // Otherwise, a no-op is returned.
     fn check_prefix(&self) -> P<ast::Expr> {
-        if self.prog.prefix.len() == 0 {
+        if self.prog.prefixes.is_empty() {
             self.empty_block()
         } else {
             quote_expr!(self.cx,
                 if clist.size == 0 {
                     let haystack = self.input.as_bytes()[self.ic..];
-                    match find_prefix(prefix_bytes, haystack) {
+                    match find_any(prefixes, haystack) {
                         None => break,
                         Some(i) => {
                             self.ic += i;
@@ -563,6 +635,31 @@ fn exec<'t>(which: ::regex::native::MatchKind, input: &'t str,
         }
     }
 
+    // Generates code that returns early if none of the literal suffixes of the
+    // regex occur in the search string, since there can't be a match then.
+    // The code is only generated if the regex *has* literal suffixes.
+    // Otherwise, a no-op is returned.
+    fn check_suffix(&self) -> P<ast::Expr> {
+        if self.prog.suffixes.is_empty() {
+            self.empty_block()
+        } else {
+            let suffixes = self.literals_expr(&self.prog.suffixes);
+            quote_expr!(self.cx, {
+                let suffixes: &[&[u8]] = &$suffixes;
+                if find_any(suffixes, input.as_bytes()[start..]).is_none() {
+                    return vec![None, None]
+                }
+            })
+        }
+    }
+
+    // Converts a set of literals to a `[b"...", .., b"..."]` expression.
+    fn literals_expr(&self, lits: &Literals) -> P<ast::Expr> {
+        self.vec_expr(lits.literals().iter(), |cx, lit| {
+            cx.expr_lit(self.sp, ast::LitBinary(Rc::new(lit.clone())))
+        })
+    }
+
     // Builds a `match pc { ... }` expression from a list of arms, specifically
     // for matching the current program counter with an instruction.
     // A wild-card arm is automatically added that executes a no-op. It will
@@ -611,6 +708,10 @@ fn exec<'t>(which: ::regex::native::MatchKind, input: &'t str,
     }
 }
 
+fn expr_u16(cx: &ExtCtxt, sp: codemap::Span, n: u16) -> P<ast::Expr> {
+    cx.expr_lit(sp, ast::LitInt(n as u64, ast::UnsignedIntLit(ast::TyU16)))
+}
+
 /// Looks for a single string literal and returns it.
 /// Otherwise, logs an error with cx.span_err and returns None.
 fn parse(cx: &mut ExtCtxt, tts: &[ast::TokenTree]) -> Option<String> {
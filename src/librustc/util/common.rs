@@ -14,7 +14,7 @@ use std::cell::{RefCell, Cell};
 use std::collections::HashMap;
 use std::fmt::Show;
 use std::hash::{Hash, Hasher};
-use std::time::Duration;
+use std::time::Instant;
 
 use syntax::ast;
 use syntax::visit;
@@ -37,16 +37,9 @@ pub fn time<T, U, F>(do_it: bool, what: &str, u: U, f: F) -> T where
         r
     });
 
-    let mut u = Some(u);
-    let mut rv = None;
-    let dur = {
-        let ref mut rvp = rv;
-
-        Duration::span(move || {
-            *rvp = Some(f(u.take().unwrap()))
-        })
-    };
-    let rv = rv.unwrap();
+    let start = Instant::now();
+    let rv = f(u);
+    let dur = start.elapsed();
 
     println!("{}time: {}.{:03} \t{}", "  ".repeat(old),
              dur.num_seconds(), dur.num_milliseconds() % 1000, what);
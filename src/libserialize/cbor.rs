@@ -0,0 +1,1362 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![forbid(non_camel_case_types)]
+#![allow(missing_docs)]
+
+//! CBOR (Concise Binary Object Representation, RFC 7049) encoding and
+//! decoding.
+//!
+//! Values are mapped to CBOR data items much like the `json` module maps
+//! them to JSON:
+//!
+//! * integers are encoded as unsigned or negative integers in as few bytes as
+//!   possible, and `f32`s and `f64`s as single and double precision floats;
+//! * `char`s and strings are text strings, and `()` and `None` are null;
+//! * sequences and tuples are arrays, and maps are maps with keys of any type;
+//! * structs are maps from field names to values;
+//! * enum variants without fields are encoded as their name, and the other
+//!   ones as an array of their name followed by their fields, so that
+//!   `Kangaroo(34, "William")` becomes `["Kangaroo", 34, "William"]`.
+//!
+//! The decoder also accepts indefinite-length items, ignores tags and reads
+//! byte strings as sequences of bytes, such as a `Vec<u8>`, so that it can
+//! read what other implementations write.
+//!
+//! ```rust
+//! extern crate serialize;
+//! use serialize::cbor;
+//!
+//! #[deriving(Decodable, Encodable, PartialEq, Show)]
+//! pub struct TestStruct  {
+//!     data_int: u8,
+//!     data_str: String,
+//!     data_vector: Vec<u8>,
+//! }
+//!
+//! fn main() {
+//!     let object = TestStruct {
+//!         data_int: 1,
+//!         data_str: "homura".to_string(),
+//!         data_vector: vec![2,3,4,5],
+//!     };
+//!
+//!     let encoded: Vec<u8> = cbor::encode(&object);
+//!     let decoded: TestStruct = cbor::decode(encoded.as_slice()).unwrap();
+//!     assert_eq!(decoded, object);
+//! }
+//! ```
+
+use self::DecoderError::*;
+use self::Item::*;
+
+use std;
+use std::{i64, io, num};
+use std::mem::{replace, transmute};
+use std::num::Float;
+use std::string;
+
+use {Encodable, Decodable};
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum DecoderError {
+    /// The data ends in the middle of an item.
+    EndOfData,
+    /// The data isn't valid CBOR, starting at the given offset.
+    InvalidData(uint),
+    /// The text string at the given offset isn't valid UTF-8.
+    InvalidUtf8(uint),
+    /// There is more data after the decoded item, starting at the given
+    /// offset.
+    TrailingData(uint),
+    /// The array, map or tag at the given offset is nested more than
+    /// `MAX_DEPTH` levels deep in an item being skipped.
+    TooDeeplyNested(uint),
+    ExpectedError(string::String, string::String),
+    MissingFieldError(string::String),
+    UnknownVariantError(string::String),
+    UnknownFieldError(string::String),
+    ApplicationError(string::String)
+}
+
+impl std::error::Error for DecoderError {
+    fn description(&self) -> &str { "decoder error" }
+    fn detail(&self) -> Option<std::string::String> { Some(self.to_string()) }
+}
+
+pub type EncodeResult = io::IoResult<()>;
+pub type DecodeResult<T> = Result<T, DecoderError>;
+
+/// Shortcut function to encode a `T` into CBOR
+pub fn encode<'a, T: Encodable<Encoder<'a>, io::IoError>>(object: &T) -> Vec<u8> {
+    Encoder::buffer_encode(object)
+}
+
+/// Shortcut function to decode a CBOR data item into an object. All of
+/// `data` must be used.
+pub fn decode<'a, T: Decodable<Decoder<'a>, DecoderError>>(data: &'a [u8]) -> DecodeResult<T> {
+    let mut decoder = Decoder::new(data);
+    let value = try!(Decodable::decode(&mut decoder));
+    if decoder.position() != data.len() {
+        return Err(TrailingData(decoder.position()));
+    }
+    Ok(value)
+}
+
+// Major types.
+const UNSIGNED: u8 = 0;
+const NEGATIVE: u8 = 1;
+const TEXT: u8 = 3;
+const ARRAY: u8 = 4;
+const MAP: u8 = 5;
+
+const FALSE: u8 = 0xf4;
+const TRUE: u8 = 0xf5;
+const NULL: u8 = 0xf6;
+const FLOAT32: u8 = 0xfa;
+const FLOAT64: u8 = 0xfb;
+const BREAK: u8 = 0xff;
+
+/// A structure for implementing serialization to CBOR.
+pub struct Encoder<'a> {
+    writer: &'a mut (io::Writer+'a),
+}
+
+impl<'a> Encoder<'a> {
+    /// Creates a new CBOR encoder whose output will be written to the writer
+    /// specified.
+    pub fn new(writer: &'a mut io::Writer) -> Encoder<'a> {
+        Encoder { writer: writer }
+    }
+
+    /// Encode the specified struct into a CBOR [u8]
+    pub fn buffer_encode<T:Encodable<Encoder<'a>, io::IoError>>(object: &T) -> Vec<u8>  {
+        let mut m = Vec::new();
+        // FIXME(14302) remove the transmute and unsafe block.
+        unsafe {
+            let mut encoder = Encoder::new(&mut m as &mut io::Writer);
+            // Vec<u8> never Errs
+            let _ = object.encode(transmute(&mut encoder));
+        }
+        m
+    }
+
+    // Writes the first byte of an item of the `major` type, with the
+    // argument `n` in it or in as few bytes as possible after it.
+    fn write_head(&mut self, major: u8, n: u64) -> EncodeResult {
+        let major = major << 5;
+        if n < 24 {
+            self.writer.write_u8(major | n as u8)
+        } else if n <= 0xff {
+            try!(self.writer.write_u8(major | 24));
+            self.writer.write_u8(n as u8)
+        } else if n <= 0xffff {
+            try!(self.writer.write_u8(major | 25));
+            self.writer.write_be_u16(n as u16)
+        } else if n <= 0xffffffff {
+            try!(self.writer.write_u8(major | 26));
+            self.writer.write_be_u32(n as u32)
+        } else {
+            try!(self.writer.write_u8(major | 27));
+            self.writer.write_be_u64(n)
+        }
+    }
+}
+
+impl<'a> ::Encoder<io::IoError> for Encoder<'a> {
+    fn emit_nil(&mut self) -> EncodeResult { self.writer.write_u8(NULL) }
+
+    fn emit_uint(&mut self, v: uint) -> EncodeResult { self.emit_u64(v as u64) }
+    fn emit_u64(&mut self, v: u64) -> EncodeResult { self.write_head(UNSIGNED, v) }
+    fn emit_u32(&mut self, v: u32) -> EncodeResult { self.emit_u64(v as u64) }
+    fn emit_u16(&mut self, v: u16) -> EncodeResult { self.emit_u64(v as u64) }
+    fn emit_u8(&mut self, v: u8) -> EncodeResult { self.emit_u64(v as u64) }
+
+    fn emit_int(&mut self, v: int) -> EncodeResult { self.emit_i64(v as i64) }
+    fn emit_i64(&mut self, v: i64) -> EncodeResult {
+        if v >= 0 {
+            self.write_head(UNSIGNED, v as u64)
+        } else {
+            // Negative integers are encoded as -1 - v.
+            self.write_head(NEGATIVE, !v as u64)
+        }
+    }
+    fn emit_i32(&mut self, v: i32) -> EncodeResult { self.emit_i64(v as i64) }
+    fn emit_i16(&mut self, v: i16) -> EncodeResult { self.emit_i64(v as i64) }
+    fn emit_i8(&mut self, v: i8) -> EncodeResult { self.emit_i64(v as i64) }
+
+    fn emit_bool(&mut self, v: bool) -> EncodeResult {
+        self.writer.write_u8(if v { TRUE } else { FALSE })
+    }
+
+    fn emit_f64(&mut self, v: f64) -> EncodeResult {
+        try!(self.writer.write_u8(FLOAT64));
+        self.writer.write_be_u64(unsafe { transmute(v) })
+    }
+    fn emit_f32(&mut self, v: f32) -> EncodeResult {
+        try!(self.writer.write_u8(FLOAT32));
+        self.writer.write_be_u32(unsafe { transmute(v) })
+    }
+
+    fn emit_char(&mut self, v: char) -> EncodeResult {
+        self.emit_str(string::String::from_char(1, v).as_slice())
+    }
+    fn emit_str(&mut self, v: &str) -> EncodeResult {
+        try!(self.write_head(TEXT, v.len() as u64));
+        self.writer.write_str(v)
+    }
+
+    fn emit_enum<F>(&mut self, _name: &str, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_enum_variant<F>(&mut self,
+                            name: &str,
+                            _id: uint,
+                            cnt: uint,
+                            f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        // enums are encoded as strings or arrays
+        // Bunny => "Bunny"
+        // Kangaroo(34,"William") => ["Kangaroo", 34, "William"]
+        if cnt == 0 {
+            self.emit_str(name)
+        } else {
+            try!(self.write_head(ARRAY, cnt as u64 + 1));
+            try!(self.emit_str(name));
+            f(self)
+        }
+    }
+
+    fn emit_enum_variant_arg<F>(&mut self, _idx: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_enum_struct_variant<F>(&mut self,
+                                   name: &str,
+                                   id: uint,
+                                   cnt: uint,
+                                   f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.emit_enum_variant(name, id, cnt, f)
+    }
+
+    fn emit_enum_struct_variant_field<F>(&mut self,
+                                         _: &str,
+                                         idx: uint,
+                                         f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.emit_enum_variant_arg(idx, f)
+    }
+
+    fn emit_struct<F>(&mut self, _: &str, len: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        try!(self.write_head(MAP, len as u64));
+        f(self)
+    }
+
+    fn emit_struct_field<F>(&mut self, name: &str, _idx: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        try!(self.emit_str(name));
+        f(self)
+    }
+
+    fn emit_tuple<F>(&mut self, len: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.emit_seq(len, f)
+    }
+    fn emit_tuple_arg<F>(&mut self, idx: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.emit_seq_elt(idx, f)
+    }
+
+    fn emit_tuple_struct<F>(&mut self, _name: &str, len: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.emit_seq(len, f)
+    }
+    fn emit_tuple_struct_arg<F>(&mut self, idx: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.emit_seq_elt(idx, f)
+    }
+
+    fn emit_option<F>(&mut self, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        f(self)
+    }
+    fn emit_option_none(&mut self) -> EncodeResult { self.emit_nil() }
+    fn emit_option_some<F>(&mut self, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_seq<F>(&mut self, len: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        try!(self.write_head(ARRAY, len as u64));
+        f(self)
+    }
+
+    fn emit_seq_elt<F>(&mut self, _idx: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_map<F>(&mut self, len: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        try!(self.write_head(MAP, len as u64));
+        f(self)
+    }
+
+    fn emit_map_elt_key<F>(&mut self, _idx: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_map_elt_val<F>(&mut self, _idx: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        f(self)
+    }
+}
+
+// The head of a data item: its type and argument. Lengths are None for
+// indefinite-length items.
+#[deriving(Clone, Copy, PartialEq, Show)]
+enum Item {
+    Unsigned(u64),
+    // The value is -1 - n.
+    Negative(u64),
+    Bytes(Option<u64>),
+    Text(Option<u64>),
+    Array(Option<u64>),
+    Map(Option<u64>),
+    Tag(u64),
+    Simple(u8),
+    Float(f64),
+    Break,
+}
+
+// Describes an item for error messages.
+fn describe(item: Item) -> string::String {
+    match item {
+        Unsigned(n) => n.to_string(),
+        Negative(n) if n <= i64::MAX as u64 => (-1 - n as i64).to_string(),
+        Negative(n) => format!("-1-{}", n),
+        Bytes(_) => "Bytes".to_string(),
+        Text(_) => "String".to_string(),
+        Array(_) => "Array".to_string(),
+        Map(_) => "Map".to_string(),
+        Tag(n) => format!("Tag({})", n),
+        Simple(20) => "false".to_string(),
+        Simple(21) => "true".to_string(),
+        Simple(22) => "null".to_string(),
+        Simple(23) => "undefined".to_string(),
+        Simple(n) => format!("Simple({})", n),
+        Float(f) => f.to_string(),
+        Break => "Break".to_string(),
+    }
+}
+
+fn half_to_f64(half: u16) -> f64 {
+    let exp = ((half >> 10) & 0x1f) as i32;
+    let mant = (half & 0x3ff) as f64;
+    let value = match exp {
+        0 => mant * 2f64.powi(-24),
+        31 if mant == 0.0 => Float::infinity(),
+        31 => Float::nan(),
+        _ => (mant + 1024.0) * 2f64.powi(exp - 25),
+    };
+    if half & 0x8000 != 0 { -value } else { value }
+}
+
+fn variant_index(names: &[&str], name: string::String) -> DecodeResult<uint> {
+    match names.iter().position(|n| *n == name.as_slice()) {
+        Some(idx) => Ok(idx),
+        None => Err(UnknownVariantError(name)),
+    }
+}
+
+// Decodes missing struct fields, in case they are options.
+static NULL_ITEM: &'static [u8] = b"\xf6";
+
+/// How deeply the items that the decoder skips, such as the values of unknown
+/// struct fields, may be nested. Skipping is recursive, so this bounds the
+/// stack used for untrusted data.
+pub const MAX_DEPTH: uint = 512;
+
+// A struct being read.
+struct StructMap {
+    // Where its members start, and how many there are.
+    start: uint,
+    len: Option<u64>,
+    // The names of the fields read from it so far.
+    fields: Vec<string::String>,
+}
+
+/// A structure to decode CBOR data items to values in rust.
+pub struct Decoder<'a> {
+    data: &'a [u8],
+    pos: uint,
+    // The number of items left in each array and map being read, or None
+    // for indefinite-length ones.
+    remaining: Vec<Option<u64>>,
+    structs: Vec<StructMap>,
+    // The byte string being read as a sequence, and how many of its bytes
+    // have been read.
+    bytes: Option<(Vec<u8>, uint)>,
+    // The byte of that byte string to read next, as an unsigned integer.
+    byte: Option<u8>,
+}
+
+impl<'a> Decoder<'a> {
+    /// Creates a new decoder for the data items in `data`.
+    pub fn new(data: &'a [u8]) -> Decoder<'a> {
+        Decoder {
+            data: data,
+            pos: 0,
+            remaining: Vec::new(),
+            structs: Vec::new(),
+            bytes: None,
+            byte: None,
+        }
+    }
+
+    /// Returns the offset in the data of the next item to decode.
+    pub fn position(&self) -> uint {
+        self.pos
+    }
+
+    fn read_bytes(&mut self, n: u64) -> DecodeResult<&'a [u8]> {
+        if n > (self.data.len() - self.pos) as u64 {
+            return Err(EndOfData);
+        }
+        let data = self.data;
+        let start = self.pos;
+        self.pos += n as uint;
+        Ok(data[start..self.pos])
+    }
+
+    fn read_be(&mut self, n: u64) -> DecodeResult<u64> {
+        let bytes = try!(self.read_bytes(n));
+        Ok(bytes.iter().fold(0, |n, &b| (n << 8) | b as u64))
+    }
+
+    fn read_item(&mut self) -> DecodeResult<Item> {
+        if let Some(b) = self.byte.take() {
+            return Ok(Unsigned(b as u64));
+        }
+        let start = self.pos;
+        let initial = try!(self.read_be(1)) as u8;
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        let arg = match info {
+            0 ... 23 => Some(info as u64),
+            24 => Some(try!(self.read_be(1))),
+            25 => Some(try!(self.read_be(2))),
+            26 => Some(try!(self.read_be(4))),
+            27 => Some(try!(self.read_be(8))),
+            31 => None,
+            _ => return Err(InvalidData(start)),
+        };
+        match (major, arg) {
+            (0, Some(n)) => Ok(Unsigned(n)),
+            (1, Some(n)) => Ok(Negative(n)),
+            (2, len) => Ok(Bytes(len)),
+            (3, len) => Ok(Text(len)),
+            (4, len) => Ok(Array(len)),
+            (5, len) => Ok(Map(len)),
+            (6, Some(n)) => Ok(Tag(n)),
+            (7, None) => Ok(Break),
+            (7, Some(n)) => match info {
+                25 => Ok(Float(half_to_f64(n as u16))),
+                26 => Ok(Float(unsafe { transmute::<u32, f32>(n as u32) } as f64)),
+                27 => Ok(Float(unsafe { transmute::<u64, f64>(n) })),
+                _ => Ok(Simple(n as u8)),
+            },
+            _ => Err(InvalidData(start)),
+        }
+    }
+
+    // Reads the head of the next item, skipping the tags before it.
+    fn next_item(&mut self) -> DecodeResult<Item> {
+        loop {
+            match try!(self.read_item()) {
+                Tag(_) => {}
+                item => return Ok(item),
+            }
+        }
+    }
+
+    fn peek_item(&mut self) -> DecodeResult<Item> {
+        let (pos, byte) = (self.pos, self.byte);
+        let item = self.next_item();
+        self.pos = pos;
+        self.byte = byte;
+        item
+    }
+
+    fn at_break(&self) -> DecodeResult<bool> {
+        match self.data.get(self.pos) {
+            Some(&b) => Ok(b == BREAK),
+            None => Err(EndOfData),
+        }
+    }
+
+    fn skip_item(&mut self) -> DecodeResult<()> {
+        self.skip_nested(0)
+    }
+
+    // Skips an item nested `depth` levels deep in the item being skipped.
+    fn skip_nested(&mut self, depth: uint) -> DecodeResult<()> {
+        let start = self.pos;
+        if depth > MAX_DEPTH {
+            return Err(TooDeeplyNested(start));
+        }
+        match try!(self.read_item()) {
+            Bytes(Some(n)) | Text(Some(n)) => { try!(self.read_bytes(n)); }
+            Array(Some(n)) => {
+                for _ in range(0, n) {
+                    try!(self.skip_nested(depth + 1));
+                }
+            }
+            Map(Some(n)) => {
+                for _ in range(0, n) {
+                    try!(self.skip_nested(depth + 1));
+                    try!(self.skip_nested(depth + 1));
+                }
+            }
+            Bytes(None) | Text(None) | Array(None) | Map(None) => {
+                try!(self.skip_until_break(depth + 1));
+            }
+            Tag(_) => try!(self.skip_nested(depth + 1)),
+            Break => return Err(InvalidData(start)),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn skip_until_break(&mut self, depth: uint) -> DecodeResult<()> {
+        while !try!(self.at_break()) {
+            try!(self.skip_nested(depth));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn expected<T>(&self, name: &str, item: Item) -> DecodeResult<T> {
+        Err(ExpectedError(name.to_string(), describe(item)))
+    }
+
+    // Checks that a definite length isn't more than the rest of the data
+    // could hold, with items of at least `size` bytes.
+    fn check_len(&self, len: Option<u64>, size: u64) -> DecodeResult<()> {
+        match len {
+            Some(n) if n > (self.data.len() - self.pos) as u64 / size => Err(EndOfData),
+            _ => Ok(()),
+        }
+    }
+
+    // Reads the contents of a byte string, or of a text string if `text`.
+    fn read_string(&mut self, len: Option<u64>, text: bool) -> DecodeResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+        match len {
+            Some(n) => bytes.push_all(try!(self.read_bytes(n))),
+            // Indefinite-length strings are made of definite-length chunks
+            // of the same type.
+            None => {
+                while !try!(self.at_break()) {
+                    let chunk = self.pos;
+                    match (try!(self.read_item()), text) {
+                        (Text(Some(n)), true) | (Bytes(Some(n)), false) => {
+                            bytes.push_all(try!(self.read_bytes(n)));
+                        }
+                        _ => return Err(InvalidData(chunk)),
+                    }
+                }
+                self.pos += 1;
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn read_text(&mut self, len: Option<u64>) -> DecodeResult<string::String> {
+        let start = self.pos;
+        let bytes = try!(self.read_string(len, true));
+        string::String::from_utf8(bytes).map_err(|_| InvalidUtf8(start))
+    }
+
+    fn read_array(&mut self) -> DecodeResult<Option<u64>> {
+        match try!(self.next_item()) {
+            Array(len) => {
+                try!(self.check_len(len, 1));
+                Ok(len)
+            }
+            item => self.expected("Array", item),
+        }
+    }
+
+    // Reads the items of an array or a map with `f`, then skips the ones it
+    // didn't read and the break that ends an indefinite-length one.
+    fn read_items<T, F>(&mut self, len: Option<u64>, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        self.remaining.push(len);
+        let value = f(self);
+        let left = self.remaining.pop().unwrap();
+        let value = try!(value);
+        match left {
+            Some(n) => {
+                for _ in range(0, n) {
+                    try!(self.skip_item());
+                }
+            }
+            None => try!(self.skip_until_break(0)),
+        }
+        Ok(value)
+    }
+
+    // Reads the next item of the innermost array or map.
+    fn read_next<T, F>(&mut self, end: &str, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        let top = try!(self.top_items());
+        let left = self.remaining[top];
+        let more = match left {
+            Some(n) => n > 0,
+            None => !try!(self.at_break()),
+        };
+        if !more {
+            return Err(ExpectedError("Value".to_string(), end.to_string()));
+        }
+        if let Some(n) = left {
+            self.remaining[top] = Some(n - 1);
+        }
+        f(self)
+    }
+
+    fn has_next(&self) -> DecodeResult<bool> {
+        match self.remaining[try!(self.top_items())] {
+            Some(n) => Ok(n > 0),
+            None => self.at_break().map(|end| !end),
+        }
+    }
+
+    // Returns the index of the innermost array or map, failing when its
+    // items are read outside of `read_seq`, `read_map` and the like.
+    fn top_items(&self) -> DecodeResult<uint> {
+        match self.remaining.len() {
+            0 => Err(ExpectedError("Array or Map".to_string(), "no Array or Map".to_string())),
+            n => Ok(n - 1),
+        }
+    }
+
+    // Returns the innermost struct, failing when its fields are read outside
+    // of `read_struct`.
+    fn top_struct(&mut self) -> DecodeResult<&mut StructMap> {
+        match self.structs.last_mut() {
+            Some(s) => Ok(s),
+            None => Err(ExpectedError("Map".to_string(), "no Map".to_string())),
+        }
+    }
+
+    // Reads a struct member key, returning whether it is `name`.
+    fn key_is(&mut self, name: &str) -> DecodeResult<bool> {
+        let pos = self.pos;
+        match try!(self.next_item()) {
+            Text(Some(n)) => Ok(try!(self.read_bytes(n)) == name.as_bytes()),
+            Text(None) => {
+                self.pos = pos;
+                Ok(try!(::Decoder::read_str(self)).as_slice() == name)
+            }
+            _ => {
+                self.pos = pos;
+                try!(self.skip_item());
+                Ok(false)
+            }
+        }
+    }
+
+    // Finds the key of a member of the innermost struct that wasn't read as
+    // a field, leaving the position anywhere in the struct.
+    fn unknown_field(&mut self) -> DecodeResult<Option<string::String>> {
+        let (start, len) = {
+            let s = try!(self.top_struct());
+            (s.start, s.len)
+        };
+        self.pos = start;
+        let mut i = 0;
+        loop {
+            let done = match len {
+                Some(n) => i == n,
+                None => try!(self.at_break()),
+            };
+            if done {
+                return Ok(None);
+            }
+            let key = match try!(self.peek_item()) {
+                Text(_) => try!(::Decoder::read_str(self)),
+                item => return Ok(Some(describe(item))),
+            };
+            if !try!(self.top_struct()).fields.contains(&key) {
+                return Ok(Some(key));
+            }
+            try!(self.skip_item());
+            i += 1;
+        }
+    }
+}
+
+macro_rules! read_integer {
+    ($name:ident, $ty:ty) => {
+        fn $name(&mut self) -> DecodeResult<$ty> {
+            let item = try!(self.next_item());
+            let value = match item {
+                Unsigned(n) => num::cast(n),
+                Negative(n) if n <= i64::MAX as u64 => num::cast(-1 - n as i64),
+                Negative(_) => None,
+                _ => return self.expected("Integer", item),
+            };
+            match value {
+                Some(v) => Ok(v),
+                None => self.expected(stringify!($ty), item),
+            }
+        }
+    }
+}
+
+impl<'a> ::Decoder<DecoderError> for Decoder<'a> {
+    fn read_nil(&mut self) -> DecodeResult<()> {
+        match try!(self.next_item()) {
+            Simple(22) | Simple(23) => Ok(()),
+            item => self.expected("Null", item),
+        }
+    }
+
+    read_integer! { read_uint, uint }
+    read_integer! { read_u8, u8 }
+    read_integer! { read_u16, u16 }
+    read_integer! { read_u32, u32 }
+    read_integer! { read_u64, u64 }
+    read_integer! { read_int, int }
+    read_integer! { read_i8, i8 }
+    read_integer! { read_i16, i16 }
+    read_integer! { read_i32, i32 }
+    read_integer! { read_i64, i64 }
+
+    fn read_f32(&mut self) -> DecodeResult<f32> { self.read_f64().map(|x| x as f32) }
+
+    fn read_f64(&mut self) -> DecodeResult<f64> {
+        match try!(self.next_item()) {
+            Unsigned(n) => Ok(n as f64),
+            Negative(n) => Ok(-1.0 - n as f64),
+            Float(f) => Ok(f),
+            item => self.expected("Number", item),
+        }
+    }
+
+    fn read_bool(&mut self) -> DecodeResult<bool> {
+        match try!(self.next_item()) {
+            Simple(20) => Ok(false),
+            Simple(21) => Ok(true),
+            item => self.expected("Boolean", item),
+        }
+    }
+
+    fn read_char(&mut self) -> DecodeResult<char> {
+        let s = try!(self.read_str());
+        {
+            let mut it = s.chars();
+            match (it.next(), it.next()) {
+                // exactly one character
+                (Some(c), None) => return Ok(c),
+                _ => ()
+            }
+        }
+        Err(ExpectedError("single character string".to_string(), s))
+    }
+
+    fn read_str(&mut self) -> DecodeResult<string::String> {
+        match try!(self.next_item()) {
+            Text(len) => self.read_text(len),
+            item => self.expected("String", item),
+        }
+    }
+
+    fn read_enum<T, F>(&mut self, _name: &str, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_enum_variant<T, F>(&mut self, names: &[&str],
+                               mut f: F) -> DecodeResult<T>
+        where F: FnMut(&mut Decoder<'a>, uint) -> DecodeResult<T>,
+    {
+        match try!(self.peek_item()) {
+            Text(_) => {
+                let idx = try!(variant_index(names, try!(self.read_str())));
+                f(self, idx)
+            }
+            Array(_) => {
+                let len = try!(self.read_array());
+                self.read_items(len, |d| {
+                    let name = try!(d.read_next("end of Array", |d| d.read_str()));
+                    let idx = try!(variant_index(names, name));
+                    f(d, idx)
+                })
+            }
+            item => self.expected("String or Array", item),
+        }
+    }
+
+    fn read_enum_variant_arg<T, F>(&mut self, _idx: uint, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        self.read_next("end of Array", f)
+    }
+
+    fn read_enum_struct_variant<T, F>(&mut self, names: &[&str], f: F) -> DecodeResult<T> where
+        F: FnMut(&mut Decoder<'a>, uint) -> DecodeResult<T>,
+    {
+        self.read_enum_variant(names, f)
+    }
+
+    fn read_enum_struct_variant_field<T, F>(&mut self,
+                                         _name: &str,
+                                         idx: uint,
+                                         f: F)
+                                         -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        self.read_enum_variant_arg(idx, f)
+    }
+
+    fn read_struct<T, F>(&mut self, _name: &str, _len: uint, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        let len = match try!(self.next_item()) {
+            Map(len) => len,
+            item => return self.expected("Map", item),
+        };
+        try!(self.check_len(len, 2));
+        // Fields are looked up by name, so find the end of the map first to
+        // be able to move past it when they have all been read.
+        let start = self.pos;
+        match len {
+            Some(n) => {
+                for _ in range(0, n * 2) {
+                    try!(self.skip_item());
+                }
+            }
+            None => try!(self.skip_until_break(0)),
+        }
+        let end = self.pos;
+        self.structs.push(StructMap { start: start, len: len, fields: Vec::new() });
+        let value = f(self);
+        self.structs.pop();
+        let value = try!(value);
+        self.pos = end;
+        Ok(value)
+    }
+
+    fn read_struct_field<T, F>(&mut self,
+                               name: &str,
+                               _idx: uint,
+                               f: F)
+                               -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        let (start, len) = {
+            let s = try!(self.top_struct());
+            s.fields.push(name.to_string());
+            (s.start, s.len)
+        };
+        self.pos = start;
+        let mut i = 0;
+        loop {
+            let done = match len {
+                Some(n) => i == n,
+                None => try!(self.at_break()),
+            };
+            if done {
+                break;
+            }
+            if try!(self.key_is(name)) {
+                return f(self);
+            }
+            try!(self.skip_item());
+            i += 1;
+        }
+        // Decode a null as an Option<_> to get None as a default value.
+        let (data, pos) = (self.data, self.pos);
+        self.data = NULL_ITEM;
+        self.pos = 0;
+        let value = f(self);
+        self.data = data;
+        self.pos = pos;
+        value.map_err(|_| MissingFieldError(name.to_string()))
+    }
+
+    fn deny_unknown_fields(&mut self) -> DecodeResult<()> {
+        let pos = self.pos;
+        let unknown = self.unknown_field();
+        self.pos = pos;
+        match try!(unknown) {
+            Some(key) => Err(UnknownFieldError(key)),
+            None => Ok(()),
+        }
+    }
+
+    fn read_tuple<T, F>(&mut self, tuple_len: uint, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        let len = try!(self.read_array());
+        match len {
+            Some(n) if n != tuple_len as u64 => {
+                Err(ExpectedError(format!("Tuple{}", tuple_len), format!("Tuple{}", n)))
+            }
+            _ => self.read_items(len, f),
+        }
+    }
+
+    fn read_tuple_arg<T, F>(&mut self, idx: uint, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        self.read_seq_elt(idx, f)
+    }
+
+    fn read_tuple_struct<T, F>(&mut self,
+                               _name: &str,
+                               len: uint,
+                               f: F)
+                               -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        self.read_tuple(len, f)
+    }
+
+    fn read_tuple_struct_arg<T, F>(&mut self,
+                                   idx: uint,
+                                   f: F)
+                                   -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        self.read_tuple_arg(idx, f)
+    }
+
+    fn read_option<T, F>(&mut self, mut f: F) -> DecodeResult<T> where
+        F: FnMut(&mut Decoder<'a>, bool) -> DecodeResult<T>,
+    {
+        match try!(self.peek_item()) {
+            Simple(22) | Simple(23) => {
+                try!(self.next_item());
+                f(self, false)
+            }
+            _ => f(self, true),
+        }
+    }
+
+    fn read_seq<T, F>(&mut self, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>, uint) -> DecodeResult<T>,
+    {
+        // A byte string is a sequence of bytes, so that a `Vec<u8>` can be
+        // read from one.
+        if let Bytes(len) = try!(self.peek_item()) {
+            try!(self.next_item());
+            let bytes = try!(self.read_string(len, false));
+            let hint = bytes.len();
+            let outer = replace(&mut self.bytes, Some((bytes, 0)));
+            let value = f(self, hint);
+            self.bytes = outer;
+            return value;
+        }
+        let len = try!(self.read_array());
+        let hint = len.unwrap_or(0) as uint;
+        self.read_items(len, move |d| f(d, hint))
+    }
+
+    fn read_seq_elt<T, F>(&mut self, _idx: uint, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        let byte = match self.bytes {
+            Some((ref bytes, ref mut read)) => {
+                if *read == bytes.len() {
+                    return Err(ExpectedError("Value".to_string(), "end of Bytes".to_string()));
+                }
+                *read += 1;
+                Some(bytes[*read - 1])
+            }
+            None => None,
+        };
+        match byte {
+            Some(b) => {
+                self.byte = Some(b);
+                let value = f(self);
+                self.byte = None;
+                value
+            }
+            None => self.read_next("end of Array", f),
+        }
+    }
+
+    fn read_seq_has_elt(&mut self, _idx: uint, _len: uint) -> DecodeResult<bool> {
+        match self.bytes {
+            Some((ref bytes, read)) => Ok(read < bytes.len()),
+            None => self.has_next(),
+        }
+    }
+
+    fn read_map<T, F>(&mut self, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>, uint) -> DecodeResult<T>,
+    {
+        let len = match try!(self.next_item()) {
+            Map(len) => len,
+            item => return self.expected("Map", item),
+        };
+        try!(self.check_len(len, 2));
+        let hint = len.unwrap_or(0) as uint;
+        self.read_items(len.map(|n| n * 2), move |d| f(d, hint))
+    }
+
+    fn read_map_elt_key<T, F>(&mut self, _idx: uint, f: F) -> DecodeResult<T> where
+       F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        self.read_next("end of Map", f)
+    }
+
+    fn read_map_elt_val<T, F>(&mut self, _idx: uint, f: F) -> DecodeResult<T> where
+       F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        self.read_next("end of Map", f)
+    }
+
+    fn read_map_has_elt(&mut self, _idx: uint, _len: uint) -> DecodeResult<bool> {
+        self.has_next()
+    }
+
+    fn error(&mut self, err: &str) -> DecoderError {
+        ApplicationError(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use self::Animal::*;
+    use super::{encode, decode, Decoder, DecodeResult};
+    use super::DecoderError::*;
+    use {Encodable, Decodable};
+    use hex::{FromHex, ToHex};
+    use std::collections::{BTreeMap, HashMap};
+    use std::fmt::Show;
+    use std::io::IoError;
+    use std::num::Float;
+    use std::{f32, f64, i64, u64};
+
+    fn hex(s: &str) -> Vec<u8> {
+        s.replace(" ", "").as_slice().from_hex().unwrap()
+    }
+
+    fn check_encode<'a, T: Encodable<super::Encoder<'a>, IoError>>(value: T, expected: &str) {
+        assert_eq!(encode(&value).to_hex(), expected.replace(" ", ""));
+    }
+
+    fn check<T: PartialEq + Show>(value: DecodeResult<T>, expected: T) {
+        assert_eq!(value, Ok(expected));
+    }
+
+    macro_rules! check_decode {
+        ($data:expr, $expected:expr) => (check(decode(hex($data).as_slice()), $expected))
+    }
+
+    #[deriving(PartialEq, Encodable, Decodable, Show)]
+    enum Animal {
+        Dog,
+        Frog(String, int)
+    }
+
+    #[deriving(PartialEq, Encodable, Decodable, Show)]
+    struct Inner {
+        a: (),
+        b: uint,
+        c: Vec<String>,
+    }
+
+    #[deriving(PartialEq, Encodable, Decodable, Show)]
+    struct Outer {
+        inner: Vec<Inner>,
+        name: Option<String>,
+        animals: HashMap<String, Animal>,
+        pairs: Vec<(i8, char, f32)>,
+        floats: (f64, f64),
+    }
+
+    #[test]
+    fn test_encode_vectors() {
+        // The examples from RFC 7049, appendix A.
+        check_encode(0u, "00");
+        check_encode(1u8, "01");
+        check_encode(10u16, "0a");
+        check_encode(23u32, "17");
+        check_encode(24u64, "1818");
+        check_encode(25i, "1819");
+        check_encode(100i8, "1864");
+        check_encode(1000i16, "1903e8");
+        check_encode(1000000i32, "1a000f4240");
+        check_encode(1000000000000i64, "1b000000e8d4a51000");
+        check_encode(u64::MAX, "1bffffffffffffffff");
+        check_encode(-1i, "20");
+        check_encode(-10i8, "29");
+        check_encode(-100i16, "3863");
+        check_encode(-1000i32, "3903e7");
+        check_encode(i64::MIN, "3b7fffffffffffffff");
+        check_encode(1.1f64, "fb3ff199999999999a");
+        check_encode(100000.0f32, "fa47c35000");
+        check_encode(1.0e+300f64, "fb7e37e43c8800759c");
+        check_encode(-4.1f64, "fbc010666666666666");
+        check_encode(false, "f4");
+        check_encode(true, "f5");
+        check_encode((), "f6");
+        check_encode(None::<uint>, "f6");
+        check_encode(Some(1u), "01");
+        check_encode("".to_string(), "60");
+        check_encode("a".to_string(), "6161");
+        check_encode("IETF".to_string(), "6449455446");
+        check_encode('ü', "62c3bc");
+        check_encode("水".to_string(), "63e6b0b4");
+        check_encode(Vec::<uint>::new(), "80");
+        check_encode(vec![1u, 2, 3], "83010203");
+        check_encode((1u, (2u, 3u), vec![4u, 5]), "8301820203820405");
+        check_encode(range(1u, 26).collect::<Vec<uint>>(),
+                     "98190102030405060708090a0b0c0d0e0f101112131415161718181819");
+        check_encode(BTreeMap::<uint, uint>::new(), "a0");
+        let mut map = BTreeMap::new();
+        map.insert(1u, 2u);
+        map.insert(3, 4);
+        check_encode(map, "a201020304");
+    }
+
+    #[test]
+    fn test_encode_compound() {
+        check_encode(Dog, "63 446f67");
+        check_encode(Frog("Henry".to_string(), 349), "83 6446726f67 6548656e7279 19015d");
+        check_encode(Inner { a: (), b: 2, c: vec!["x".to_string()] },
+                     "a3 6161f6 616202 6163816178");
+    }
+
+    #[test]
+    fn test_decode_vectors() {
+        check_decode!("00", 0u);
+        check_decode!("1bffffffffffffffff", u64::MAX);
+        check_decode!("3b7fffffffffffffff", i64::MIN);
+        check_decode!("3903e7", -1000i);
+        check_decode!("1903e8", 1000.0f64);
+        check_decode!("fb3ff199999999999a", 1.1f64);
+        check_decode!("fa47c35000", 100000.0f32);
+        check_decode!("f93c00", 1.0f64);
+        check_decode!("f93e00", 1.5f64);
+        check_decode!("f97bff", 65504.0f64);
+        check_decode!("f90001", 5.9604644775390625e-8f64);
+        check_decode!("f90400", 0.00006103515625f64);
+        check_decode!("f9c400", -4.0f64);
+        check_decode!("f97c00", f64::INFINITY);
+        check_decode!("f9fc00", f64::NEG_INFINITY);
+        check_decode!("fa7f800000", f32::INFINITY);
+        check_decode!("f5", true);
+        check_decode!("f6", ());
+        check_decode!("f7", None::<uint>);
+        check_decode!("62c3bc", 'ü');
+        check_decode!("64f0908591", "\U00010151".to_string());
+        check_decode!("8301820203820405", (1u, vec![2u, 3], (4u, 5u)));
+        check_decode!("a201020304", {
+            let mut map = BTreeMap::new();
+            map.insert(1u, 2u);
+            map.insert(3, 4);
+            map
+        });
+        check_decode!("826161a161626163", ("a".to_string(), {
+            let mut map = HashMap::new();
+            map.insert("b".to_string(), "c".to_string());
+            map
+        }));
+        // Tags are ignored.
+        check_decode!("c11a514b67b0", 1363896240u);
+        check_decode!("c074323031332d30332d32315432303a30343a30305a",
+                      "2013-03-21T20:04:00Z".to_string());
+    }
+
+    #[test]
+    fn test_decode_indefinite() {
+        check_decode!("7f657374726561646d696e67ff", "streaming".to_string());
+        check_decode!("9fff", Vec::<uint>::new());
+        check_decode!("9f018202039f0405ffff", (1u, vec![2u, 3], vec![4u, 5]));
+        check_decode!("83018202039f0405ff", (1u, vec![2u, 3], vec![4u, 5]));
+        check_decode!("9f0102030405060708090a0b0c0d0e0f101112131415161718181819ff",
+                      range(1u, 26).collect::<Vec<uint>>());
+        check_decode!("bf616101616202ff", {
+            let mut map = BTreeMap::new();
+            map.insert("a".to_string(), 1u);
+            map.insert("b".to_string(), 2);
+            map
+        });
+    }
+
+    #[test]
+    fn test_decode_struct() {
+        #[deriving(Decodable, PartialEq, Show)]
+        #[allow(non_snake_case)]
+        struct Fun {
+            Amt: int,
+            Fun: bool,
+            Missing: Option<uint>,
+        }
+        // An indefinite-length map, with the fields in another order.
+        check_decode!("bf 6346756ef5 63416d7421 ff", Fun { Amt: -2, Fun: true, Missing: None });
+        // Unknown fields are skipped.
+        check_decode!("a3 617801 6346756ef4 63416d7403",
+                      Fun { Amt: 3, Fun: false, Missing: None });
+    }
+
+    #[test]
+    fn test_deny_unknown_fields() {
+        #[deriving(Decodable, PartialEq, Show)]
+        #[serialize(deny_unknown_fields)]
+        struct Strict {
+            a: uint,
+            b: Option<uint>,
+        }
+        check_decode!("a2 616101 616202", Strict { a: 1, b: Some(2) });
+        check_decode!("bf 616101 ff", Strict { a: 1, b: None });
+        let value: DecodeResult<Strict> = decode(hex("a3 616101 616202 617803").as_slice());
+        assert_eq!(value, Err(UnknownFieldError("x".to_string())));
+        let value: DecodeResult<Strict> = decode(hex("bf 616101 0102 ff").as_slice());
+        assert_eq!(value, Err(UnknownFieldError("1".to_string())));
+    }
+
+    #[test]
+    fn test_skip_depth() {
+        // An unknown field holding arrays nested far too deeply to skip them
+        // recursively.
+        let mut data = hex("a4 6161f6 616200 616380 6178");
+        let start = data.len();
+        data.grow(100000, 0x81);
+        data.push(0x00);
+        let value: DecodeResult<Inner> = decode(data.as_slice());
+        assert_eq!(value, Err(TooDeeplyNested(start + super::MAX_DEPTH + 1)));
+
+        // Up to the limit, nesting is fine.
+        let mut data = hex("a4 6161f6 616200 616380 6178");
+        data.grow(super::MAX_DEPTH, 0x81);
+        data.push(0x00);
+        let value: DecodeResult<Inner> = decode(data.as_slice());
+        assert_eq!(value, Ok(Inner { a: (), b: 0, c: vec![] }));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut animals = HashMap::new();
+        animals.insert("dog".to_string(), Dog);
+        animals.insert("frog".to_string(), Frog("Henry".to_string(), -349));
+        let value = Outer {
+            inner: vec![
+                Inner { a: (), b: 0, c: vec![] },
+                Inner { a: (), b: 1 << 40, c: vec!["abc".to_string(), "δ".to_string()] },
+            ],
+            name: Some("outer".to_string()),
+            animals: animals,
+            pairs: vec![(-128, 'x', 1.5), (127, 'ü', f32::MAX)],
+            floats: (f64::NEG_INFINITY, -0.0),
+        };
+        let data = encode(&value);
+        check(decode(data.as_slice()), value);
+
+        let nan: f64 = decode(encode(&f64::NAN).as_slice()).unwrap();
+        assert!(nan.is_nan());
+    }
+
+    #[test]
+    fn test_decode_bytes() {
+        // Byte strings can be read as sequences of bytes.
+        check_decode!("4401020304", vec![1u8, 2, 3, 4]);
+        check_decode!("40", Vec::<u8>::new());
+        check_decode!("5f 420102 43030405 ff", vec![1u8, 2, 3, 4, 5]);
+        check_decode!("82 4101 820203", (vec![1u8], vec![2u8, 3]));
+        let value: DecodeResult<Vec<u8>> = decode(hex("5f 6161 ff").as_slice());
+        assert_eq!(value, Err(InvalidData(1)));
+        let value: DecodeResult<Vec<String>> = decode(hex("4101").as_slice());
+        assert_eq!(value, Err(ExpectedError("String".to_string(), "1".to_string())));
+        let value: DecodeResult<(u8, u8)> = decode(hex("420102").as_slice());
+        assert_eq!(value, Err(ExpectedError("Array".to_string(), "Bytes".to_string())));
+    }
+
+    #[test]
+    fn test_decode_outside_container() {
+        let data = hex("a1616101");
+        let mut d = Decoder::new(data.as_slice());
+        let value: DecodeResult<uint> = ::Decoder::read_struct_field(&mut d, "a", 0, |d| {
+            Decodable::decode(d)
+        });
+        assert_eq!(value, Err(ExpectedError("Map".to_string(), "no Map".to_string())));
+        let value: DecodeResult<uint> = ::Decoder::read_seq_elt(&mut d, 0, |d| {
+            Decodable::decode(d)
+        });
+        assert_eq!(value, Err(ExpectedError("Array or Map".to_string(),
+                                            "no Array or Map".to_string())));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        fn check_err(data: &str, expected: super::DecoderError) {
+            let data = hex(data);
+            let value: DecodeResult<Inner> = decode(data.as_slice());
+            assert_eq!(value, Err(expected));
+        }
+        check_err("", EndOfData);
+        check_err("a3", EndOfData);
+        check_err("1c", InvalidData(0));
+        check_err("ff", ExpectedError("Map".to_string(), "Break".to_string()));
+        check_err("80", ExpectedError("Map".to_string(), "Array".to_string()));
+        check_err("a1 6161f6", MissingFieldError("b".to_string()));
+        check_err("a1 616101", ExpectedError("Null".to_string(), "1".to_string()));
+        check_err("a3 6161f6 616220 616380", ExpectedError("uint".to_string(), "-1".to_string()));
+        check_err("a3 6161f6 616200 616381 62c328", InvalidUtf8(11));
+        check_err("a3 6161f6 616200 616380 00", TrailingData(10));
+        check_err("a3 6161f6 616200 616381 01",
+                  ExpectedError("String".to_string(), "1".to_string()));
+
+        let value: DecodeResult<Animal> = decode(hex("63436174").as_slice());
+        assert_eq!(value, Err(UnknownVariantError("Cat".to_string())));
+        let value: DecodeResult<Animal> = decode(hex("81 6446726f67").as_slice());
+        assert_eq!(value, Err(ExpectedError("Value".to_string(), "end of Array".to_string())));
+        let value: DecodeResult<(uint, uint)> = decode(hex("83010203").as_slice());
+        assert_eq!(value, Err(ExpectedError("Tuple2".to_string(), "Tuple3".to_string())));
+        let value: DecodeResult<(uint, uint)> = decode(hex("9f01ff").as_slice());
+        assert_eq!(value, Err(ExpectedError("Value".to_string(), "end of Array".to_string())));
+        // A length that can't be right isn't used to allocate memory.
+        let value: DecodeResult<Vec<uint>> = decode(hex("9b00ffffffffffffff00").as_slice());
+        assert_eq!(value, Err(EndOfData));
+    }
+}
@@ -37,8 +37,10 @@ impl<E, D:Decoder<E>,T:Decodable<D, E>> Decodable<D, E> for DList<T> {
     fn decode(d: &mut D) -> Result<DList<T>, E> {
         d.read_seq(|d, len| {
             let mut list = DList::new();
-            for i in range(0u, len) {
+            let mut i = 0;
+            while try!(d.read_seq_has_elt(i, len)) {
                 list.push_back(try!(d.read_seq_elt(i, |d| Decodable::decode(d))));
+                i += 1;
             }
             Ok(list)
         })
@@ -64,8 +66,10 @@ impl<E, D:Decoder<E>,T:Decodable<D, E>> Decodable<D, E> for RingBuf<T> {
     fn decode(d: &mut D) -> Result<RingBuf<T>, E> {
         d.read_seq(|d, len| {
             let mut deque: RingBuf<T> = RingBuf::new();
-            for i in range(0u, len) {
+            let mut i = 0;
+            while try!(d.read_seq_has_elt(i, len)) {
                 deque.push_back(try!(d.read_seq_elt(i, |d| Decodable::decode(d))));
+                i += 1;
             }
             Ok(deque)
         })
@@ -100,10 +104,12 @@ impl<
     fn decode(d: &mut D) -> Result<BTreeMap<K, V>, E> {
         d.read_map(|d, len| {
             let mut map = BTreeMap::new();
-            for i in range(0u, len) {
+            let mut i = 0;
+            while try!(d.read_map_has_elt(i, len)) {
                 let key = try!(d.read_map_elt_key(i, |d| Decodable::decode(d)));
                 let val = try!(d.read_map_elt_val(i, |d| Decodable::decode(d)));
                 map.insert(key, val);
+                i += 1;
             }
             Ok(map)
         })
@@ -135,8 +141,10 @@ impl<
     fn decode(d: &mut D) -> Result<BTreeSet<T>, E> {
         d.read_seq(|d, len| {
             let mut set = BTreeSet::new();
-            for i in range(0u, len) {
+            let mut i = 0;
+            while try!(d.read_seq_has_elt(i, len)) {
                 set.insert(try!(d.read_seq_elt(i, |d| Decodable::decode(d))));
+                i += 1;
             }
             Ok(set)
         })
@@ -207,10 +215,12 @@ impl<
         d.read_map(|d, len| {
             let hasher = Default::default();
             let mut map = HashMap::with_capacity_and_hasher(len, hasher);
-            for i in range(0u, len) {
+            let mut i = 0;
+            while try!(d.read_map_has_elt(i, len)) {
                 let key = try!(d.read_map_elt_key(i, |d| Decodable::decode(d)));
                 let val = try!(d.read_map_elt_val(i, |d| Decodable::decode(d)));
                 map.insert(key, val);
+                i += 1;
             }
             Ok(map)
         })
@@ -246,8 +256,10 @@ impl<
     fn decode(d: &mut D) -> Result<HashSet<T, H>, E> {
         d.read_seq(|d, len| {
             let mut set = HashSet::with_capacity_and_hasher(len, Default::default());
-            for i in range(0u, len) {
+            let mut i = 0;
+            while try!(d.read_seq_has_elt(i, len)) {
                 set.insert(try!(d.read_seq_elt(i, |d| Decodable::decode(d))));
+                i += 1;
             }
             Ok(set)
         })
@@ -278,10 +290,12 @@ impl<
     fn decode(d: &mut D) -> Result<VecMap<V>, E> {
         d.read_map(|d, len| {
             let mut map = VecMap::new();
-            for i in range(0u, len) {
+            let mut i = 0;
+            while try!(d.read_map_has_elt(i, len)) {
                 let key = try!(d.read_map_elt_key(i, |d| Decodable::decode(d)));
                 let val = try!(d.read_map_elt_val(i, |d| Decodable::decode(d)));
                 map.insert(key, val);
+                i += 1;
             }
             Ok(map)
         })
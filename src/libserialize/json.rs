@@ -66,6 +66,9 @@
 //! The `ToJson` trait provides a `to_json` method to convert an object into a `json::Json` value.
 //! A `json::Json` value can be encoded as a string or buffer using the functions described above.
 //! You can also use the `json::Encoder` object, which implements the `Encoder` trait.
+//! `json::decode_stream` and `json::decode_reader` use a `json::StreamDecoder`, which decodes
+//! values straight from the events of the streaming parser instead of building a `Json` value
+//! first.
 //!
 //! When using `ToJson` the `Encodable` trait implementation is not mandatory.
 //!
@@ -196,6 +199,7 @@ use self::ParserError::*;
 use self::DecoderError::*;
 use self::ParserState::*;
 use self::InternalStackElement::*;
+use self::PatchErrorCode::*;
 
 use std;
 use std::collections::{HashMap, BTreeMap};
@@ -234,6 +238,7 @@ pub enum ErrorCode {
     EOFWhileParsingArray,
     EOFWhileParsingValue,
     EOFWhileParsingString,
+    EOFWhileParsingComment,
     KeyMustBeAString,
     ExpectedColon,
     TrailingCharacters,
@@ -263,7 +268,47 @@ pub enum DecoderError {
     ExpectedError(string::String, string::String),
     MissingFieldError(string::String),
     UnknownVariantError(string::String),
-    ApplicationError(string::String)
+    UnknownFieldError(string::String),
+    ApplicationError(string::String),
+}
+
+/// A `DecoderError` with the path and the position of the value that
+/// couldn't be decoded, as reported by `StreamDecoder::locate`.
+#[deriving(Clone, PartialEq, Show)]
+pub struct LocatedError {
+    pub error: DecoderError,
+    /// The path of the value, like `.servers[3].port`.
+    pub path: string::String,
+    pub line: uint,
+    pub col: uint,
+}
+
+/// The ways applying a JSON Patch (RFC 6902) can fail.
+#[deriving(Clone, Copy, PartialEq)]
+pub enum PatchErrorCode {
+    NotAnArray,
+    InvalidOperation,
+    MissingOp,
+    UnknownOp,
+    MissingPath,
+    MissingFrom,
+    MissingValue,
+    InvalidPointer,
+    PathNotFound,
+    MoveIntoChild,
+    TestFailed,
+}
+
+/// The error returned when a JSON Patch can't be applied.
+#[deriving(Clone, PartialEq, Show)]
+pub struct PatchError {
+    /// What went wrong.
+    pub code: PatchErrorCode,
+    /// The index of the operation that failed in the patch.
+    pub index: uint,
+    /// The JSON Pointer the failed operation couldn't be applied to, which
+    /// is its `from` pointer if that is the one that is at fault.
+    pub path: string::String,
 }
 
 /// Returns a readable error string for a given error code.
@@ -275,6 +320,7 @@ pub fn error_str(error: ErrorCode) -> &'static str {
         EOFWhileParsingArray => "EOF While parsing array",
         EOFWhileParsingValue => "EOF While parsing value",
         EOFWhileParsingString => "EOF While parsing string",
+        EOFWhileParsingComment => "EOF While parsing comment",
         KeyMustBeAString => "key must be a string",
         ExpectedColon => "expected `:`",
         TrailingCharacters => "trailing characters",
@@ -289,6 +335,23 @@ pub fn error_str(error: ErrorCode) -> &'static str {
     }
 }
 
+/// Returns a readable error string for a given patch error code.
+pub fn patch_error_str(error: PatchErrorCode) -> &'static str {
+    match error {
+        NotAnArray => "patch is not an array",
+        InvalidOperation => "operation is not an object",
+        MissingOp => "missing or invalid `op` member",
+        UnknownOp => "unknown operation",
+        MissingPath => "missing or invalid `path` member",
+        MissingFrom => "missing or invalid `from` member",
+        MissingValue => "missing `value` member",
+        InvalidPointer => "invalid JSON pointer",
+        PathNotFound => "path does not exist",
+        MoveIntoChild => "cannot move a value into one of its children",
+        TestFailed => "test failed",
+    }
+}
+
 /// Shortcut function to decode a JSON `&str` into an object
 pub fn decode<T: ::Decodable<Decoder, DecoderError>>(s: &str) -> DecodeResult<T> {
     let json = match from_str(s) {
@@ -300,6 +363,62 @@ pub fn decode<T: ::Decodable<Decoder, DecoderError>>(s: &str) -> DecodeResult<T>
     ::Decodable::decode(&mut decoder)
 }
 
+/// Like `decode`, but decodes the JSON `&str` straight from the parser
+/// with a `StreamDecoder`, without building a `Json` value first.
+pub fn decode_stream<'a, T>(s: &'a str) -> DecodeResult<T> where
+    T: ::Decodable<StreamDecoder<str::Chars<'a>>, DecoderError>,
+{
+    let mut decoder = StreamDecoder::new(s.chars());
+    let value = try!(::Decodable::decode(&mut decoder));
+    try!(decoder.finish());
+    Ok(value)
+}
+
+/// Like `decode_stream`, but errors tell the path and the position of the
+/// value that couldn't be decoded.
+pub fn decode_located<'a, T>(s: &'a str) -> Result<T, LocatedError> where
+    T: ::Decodable<StreamDecoder<str::Chars<'a>>, DecoderError>,
+{
+    let mut decoder = StreamDecoder::new(s.chars());
+    let res = match ::Decodable::decode(&mut decoder) {
+        Ok(value) => decoder.finish().map(|()| value),
+        Err(e) => Err(e),
+    };
+    res.map_err(|e| decoder.locate(e))
+}
+
+/// Like `decode_located`, but the JSON `&str` may contain comments and
+/// trailing commas, like a hand-edited configuration file.
+pub fn decode_lenient<'a, T>(s: &'a str) -> Result<T, LocatedError> where
+    T: ::Decodable<StreamDecoder<str::Chars<'a>>, DecoderError>,
+{
+    let mut decoder = StreamDecoder::new(s.chars());
+    decoder.set_lenient(true);
+    let res = match ::Decodable::decode(&mut decoder) {
+        Ok(value) => decoder.finish().map(|()| value),
+        Err(e) => Err(e),
+    };
+    res.map_err(|e| decoder.locate(e))
+}
+
+/// Shortcut function to decode a JSON document read from an `io::Buffer` into
+/// an object, without reading the whole document into memory.
+pub fn decode_reader<B, T>(rdr: B) -> DecodeResult<T> where
+    B: io::Buffer,
+    T: ::Decodable<StreamDecoder<BufferChars<B>>, DecoderError>,
+{
+    let mut decoder = StreamDecoder::new(BufferChars::new(rdr));
+    let res = match ::Decodable::decode(&mut decoder) {
+        Ok(value) => decoder.finish().map(|()| value),
+        Err(e) => Err(e),
+    };
+    // A read error looks like the end of the document to the parser.
+    match decoder.parser.rdr.err.take() {
+        Some(e) => Err(ParseError(io_error_to_error(e))),
+        None => res,
+    }
+}
+
 /// Shortcut function to encode a `T` into a JSON `String`
 pub fn encode<'a, T: Encodable<Encoder<'a>, io::IoError>>(object: &T) -> string::String {
     let buff = Encoder::buffer_encode(object);
@@ -312,6 +431,12 @@ impl fmt::Show for ErrorCode {
     }
 }
 
+impl fmt::Show for PatchErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        patch_error_str(*self).fmt(f)
+    }
+}
+
 fn io_error_to_error(io: io::IoError) -> ParserError {
     IoError(io.kind, io.desc)
 }
@@ -321,6 +446,11 @@ impl std::error::Error for DecoderError {
     fn detail(&self) -> Option<std::string::String> { Some(self.to_string()) }
 }
 
+impl std::error::Error for LocatedError {
+    fn description(&self) -> &str { "decoder error" }
+    fn detail(&self) -> Option<std::string::String> { Some(self.to_string()) }
+}
+
 pub type EncodeResult = io::IoResult<()>;
 pub type DecodeResult<T> = Result<T, DecoderError>;
 
@@ -964,6 +1094,90 @@ impl Json {
         }
     }
 
+    /// Looks up a value by a JSON Pointer (RFC 6901), like `/servers/3/port`.
+    /// The empty pointer refers to the value itself.
+    /// Returns None if the pointer is invalid or the value doesn't exist.
+    pub fn pointer<'a>(&'a self, pointer: &str) -> Option<&'a Json> {
+        let tokens = match pointer_tokens(pointer) {
+            Some(tokens) => tokens,
+            None => return None,
+        };
+        let mut target = self;
+        for token in tokens.iter() {
+            target = match *target {
+                Json::Object(ref map) => match map.get(token.as_slice()) {
+                    Some(t) => t,
+                    None => return None,
+                },
+                Json::Array(ref array) => match array_index(token.as_slice()) {
+                    Some(i) if i < array.len() => &array[i],
+                    _ => return None,
+                },
+                _ => return None,
+            };
+        }
+        Some(target)
+    }
+
+    /// Like `pointer`, but returns a mutable reference to the value.
+    pub fn pointer_mut<'a>(&'a mut self, pointer: &str) -> Option<&'a mut Json> {
+        match pointer_tokens(pointer) {
+            Some(tokens) => lookup_mut(self, tokens.as_slice()),
+            None => None,
+        }
+    }
+
+    /// Applies a JSON Patch (RFC 6902), which is an array of operations.
+    ///
+    /// Either all of the operations are applied or, if one of them fails,
+    /// none is and the error says which one failed.
+    pub fn apply_patch(&mut self, patch: &Json) -> Result<(), PatchError> {
+        let ops = match *patch {
+            Json::Array(ref ops) => ops,
+            _ => return patch_error(NotAnArray, ""),
+        };
+        let mut doc = self.clone();
+        for (i, op) in ops.iter().enumerate() {
+            match apply_operation(&mut doc, op) {
+                Ok(()) => {}
+                Err(e) => return Err(PatchError { index: i, ..e }),
+            }
+        }
+        *self = doc;
+        Ok(())
+    }
+
+    /// Applies a JSON merge patch (RFC 7386): the members of an object patch
+    /// are merged into this value recursively, with null members removing
+    /// them, and any other patch replaces this value.
+    pub fn merge_patch(&mut self, patch: &Json) {
+        let patch = match *patch {
+            Json::Object(ref patch) => patch,
+            _ => {
+                *self = patch.clone();
+                return;
+            }
+        };
+        if !self.is_object() {
+            *self = Json::Object(BTreeMap::new());
+        }
+        let target = match *self {
+            Json::Object(ref mut target) => target,
+            _ => unreachable!(),
+        };
+        for (key, value) in patch.iter() {
+            if value.is_null() {
+                target.remove(key);
+            } else if target.contains_key(key) {
+                target.get_mut(key).unwrap().merge_patch(value);
+            } else {
+                let mut member = Json::Null;
+                member.merge_patch(value);
+                target.insert(key.clone(), member);
+            }
+        }
+    }
+
     /// Returns true if the Json value is an Object. Returns false otherwise.
     pub fn is_object<'a>(&'a self) -> bool {
         self.as_object().is_some()
@@ -1098,6 +1312,221 @@ impl Json {
     }
 }
 
+// Splits a JSON Pointer into its reference tokens, with `~1` and `~0`
+// unescaped. Returns None if the pointer is invalid.
+fn pointer_tokens(pointer: &str) -> Option<Vec<string::String>> {
+    if pointer.is_empty() {
+        return Some(Vec::new());
+    }
+    if !pointer.starts_with("/") {
+        return None;
+    }
+    let mut tokens = Vec::new();
+    for raw in pointer[1..].split('/') {
+        let mut token = string::String::new();
+        let mut chars = raw.chars();
+        loop {
+            match chars.next() {
+                Some('~') => match chars.next() {
+                    Some('0') => token.push('~'),
+                    Some('1') => token.push('/'),
+                    _ => return None,
+                },
+                Some(c) => token.push(c),
+                None => break,
+            }
+        }
+        tokens.push(token);
+    }
+    Some(tokens)
+}
+
+// Array indices in pointers are plain decimal numbers without leading zeros.
+fn array_index(token: &str) -> Option<uint> {
+    if token.is_empty() || (token.len() > 1 && token.starts_with("0")) {
+        return None;
+    }
+    if !token.chars().all(|c| c >= '0' && c <= '9') {
+        return None;
+    }
+    std::str::from_str(token)
+}
+
+fn lookup_mut<'a>(json: &'a mut Json, tokens: &[string::String]) -> Option<&'a mut Json> {
+    if tokens.is_empty() {
+        return Some(json);
+    }
+    let next = match *json {
+        Json::Object(ref mut map) => map.get_mut(tokens[0].as_slice()),
+        Json::Array(ref mut array) => match array_index(tokens[0].as_slice()) {
+            Some(i) => array.get_mut(i),
+            None => None,
+        },
+        _ => None,
+    };
+    match next {
+        Some(next) => lookup_mut(next, tokens[1..]),
+        None => None,
+    }
+}
+
+fn patch_error<T>(code: PatchErrorCode, path: &str) -> Result<T, PatchError> {
+    Err(PatchError { code: code, index: 0, path: path.to_string() })
+}
+
+fn apply_operation(doc: &mut Json, op: &Json) -> Result<(), PatchError> {
+    let op = match *op {
+        Json::Object(ref op) => op,
+        _ => return patch_error(InvalidOperation, ""),
+    };
+    let path = match op.get("path") {
+        Some(&Json::String(ref path)) => path.as_slice(),
+        _ => return patch_error(MissingPath, ""),
+    };
+    let tokens = match pointer_tokens(path) {
+        Some(tokens) => tokens,
+        None => return patch_error(InvalidPointer, path),
+    };
+    let name = match op.get("op") {
+        Some(&Json::String(ref name)) => name.as_slice(),
+        _ => return patch_error(MissingOp, path),
+    };
+    let value = match name {
+        "add" | "replace" | "test" => match op.get("value") {
+            Some(value) => Some(value),
+            None => return patch_error(MissingValue, path),
+        },
+        _ => None,
+    };
+    let from = match name {
+        "move" | "copy" => match op.get("from") {
+            Some(&Json::String(ref from)) => match pointer_tokens(from.as_slice()) {
+                Some(from_tokens) => Some((from.as_slice(), from_tokens)),
+                None => return patch_error(InvalidPointer, from.as_slice()),
+            },
+            _ => return patch_error(MissingFrom, path),
+        },
+        _ => None,
+    };
+
+    let result = match (name, value, from) {
+        ("add", Some(value), _) => add_value(doc, tokens.as_slice(), value.clone()),
+        ("remove", _, _) => remove_value(doc, tokens.as_slice()).map(|_| ()),
+        ("replace", Some(value), _) => match lookup_mut(doc, tokens.as_slice()) {
+            Some(target) => { *target = value.clone(); Ok(()) }
+            None => Err(PathNotFound),
+        },
+        ("test", Some(value), _) => match doc.pointer(path) {
+            Some(target) => if same_value(target, value) { Ok(()) } else { Err(TestFailed) },
+            None => Err(PathNotFound),
+        },
+        ("move", _, Some((from, from_tokens))) => {
+            if tokens == from_tokens {
+                return Ok(());
+            }
+            if tokens.len() > from_tokens.len() &&
+               tokens[..from_tokens.len()] == from_tokens.as_slice() {
+                return patch_error(MoveIntoChild, path);
+            }
+            match remove_value(doc, from_tokens.as_slice()) {
+                Ok(value) => add_value(doc, tokens.as_slice(), value),
+                Err(code) => return patch_error(code, from),
+            }
+        }
+        ("copy", _, Some((from, from_tokens))) => {
+            let value = match doc.pointer(from) {
+                Some(value) => value.clone(),
+                None => return patch_error(PathNotFound, from),
+            };
+            add_value(doc, tokens.as_slice(), value)
+        }
+        _ => Err(UnknownOp),
+    };
+    match result {
+        Ok(()) => Ok(()),
+        Err(code) => patch_error(code, path),
+    }
+}
+
+// Looks up the parent of the value a pointer refers to, and the last token.
+fn lookup_parent<'a, 'b>(doc: &'a mut Json, tokens: &'b [string::String])
+                         -> Result<(&'a mut Json, &'b str), PatchErrorCode> {
+    let last = tokens.len() - 1;
+    match lookup_mut(doc, tokens[..last]) {
+        Some(parent) => Ok((parent, tokens[last].as_slice())),
+        None => Err(PathNotFound),
+    }
+}
+
+fn add_value(doc: &mut Json, tokens: &[string::String], value: Json)
+             -> Result<(), PatchErrorCode> {
+    if tokens.is_empty() {
+        *doc = value;
+        return Ok(());
+    }
+    let (parent, last) = try!(lookup_parent(doc, tokens));
+    match *parent {
+        Json::Object(ref mut map) => {
+            map.insert(last.to_string(), value);
+            Ok(())
+        }
+        Json::Array(ref mut array) => {
+            if last == "-" {
+                array.push(value);
+                return Ok(());
+            }
+            match array_index(last) {
+                Some(i) if i <= array.len() => {
+                    array.insert(i, value);
+                    Ok(())
+                }
+                _ => Err(PathNotFound),
+            }
+        }
+        _ => Err(PathNotFound),
+    }
+}
+
+fn remove_value(doc: &mut Json, tokens: &[string::String]) -> Result<Json, PatchErrorCode> {
+    if tokens.is_empty() {
+        return Ok(std::mem::replace(doc, Json::Null));
+    }
+    let (parent, last) = try!(lookup_parent(doc, tokens));
+    match *parent {
+        Json::Object(ref mut map) => match map.remove(last) {
+            Some(value) => Ok(value),
+            None => Err(PathNotFound),
+        },
+        Json::Array(ref mut array) => match array_index(last) {
+            Some(i) if i < array.len() => Ok(array.remove(i).unwrap()),
+            _ => Err(PathNotFound),
+        },
+        _ => Err(PathNotFound),
+    }
+}
+
+// Compares values the way the `test` operation does, where numbers are equal
+// if their values are, whatever their representation.
+fn same_value(a: &Json, b: &Json) -> bool {
+    match (a, b) {
+        (&Json::Array(ref a), &Json::Array(ref b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| same_value(a, b))
+        }
+        (&Json::Object(ref a), &Json::Object(ref b)) => {
+            a.len() == b.len() &&
+                a.iter().zip(b.iter()).all(|((ka, va), (kb, vb))| ka == kb && same_value(va, vb))
+        }
+        _ if a.is_number() && b.is_number() => {
+            match (a.as_i64(), b.as_i64(), a.as_u64(), b.as_u64()) {
+                (Some(x), Some(y), _, _) => x == y,
+                (_, _, Some(x), Some(y)) => x == y,
+                _ => a.as_f64() == b.as_f64(),
+            }
+        }
+        _ => a == b,
+    }
+}
+
 impl<'a> ops::Index<&'a str, Json>  for Json {
     fn index(&self, idx: & &str) -> &Json {
         self.find(*idx).unwrap()
@@ -1239,7 +1668,8 @@ impl Stack {
         }
     }
 
-    // Used by Parser to insert Key elements at the top of the stack.
+    // Used by Parser and StreamDecoder to insert Key elements at the top of
+    // the stack.
     fn push_key(&mut self, key: string::String) {
         self.stack.push(InternalKey(self.str_buffer.len() as u16, key.len() as u16));
         for c in key.as_bytes().iter() {
@@ -1247,12 +1677,14 @@ impl Stack {
         }
     }
 
-    // Used by Parser to insert Index elements at the top of the stack.
+    // Used by Parser and StreamDecoder to insert Index elements at the top of
+    // the stack.
     fn push_index(&mut self, index: u32) {
         self.stack.push(InternalIndex(index));
     }
 
-    // Used by Parser to remove the top-most element of the stack.
+    // Used by Parser and StreamDecoder to remove the top-most element of the
+    // stack.
     fn pop(&mut self) {
         assert!(!self.is_empty());
         match *self.stack.last().unwrap() {
@@ -1285,6 +1717,26 @@ impl Stack {
     }
 }
 
+/// Formats the stack as a path like `.foo.bar[3].x`, with keys that aren't
+/// made of letters, digits and underscores quoted, like `["a b"]`.
+impl fmt::Show for Stack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for i in range(0, self.len()) {
+            match self.get(i) {
+                Index(idx) => try!(write!(f, "[{}]", idx)),
+                Key(key) => {
+                    if !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                        try!(write!(f, ".{}", key));
+                    } else {
+                        try!(write!(f, "[{}]", Json::String(key.to_string())));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 /// A streaming JSON parser implemented as an iterator of JsonEvent, consuming
 /// an iterator of char.
 pub struct Parser<T> {
@@ -1297,6 +1749,11 @@ pub struct Parser<T> {
     stack: Stack,
     // A state machine is kept to make it possible to interrupt and resume parsing.
     state: ParserState,
+    // Whether comments and trailing commas are allowed.
+    lenient: bool,
+    // Where the last value that was parsed starts.
+    value_line: uint,
+    value_col: uint,
 }
 
 impl<T: Iterator<char>> Iterator<JsonEvent> for Parser<T> {
@@ -1306,7 +1763,10 @@ impl<T: Iterator<char>> Iterator<JsonEvent> for Parser<T> {
         }
 
         if self.state == ParseBeforeFinish {
-            self.parse_whitespace();
+            match self.parse_whitespace() {
+                Some(evt) => return Some(evt),
+                None => {}
+            }
             // Make sure there is no trailing characters.
             if self.eof() {
                 self.state = ParseFinished;
@@ -1330,11 +1790,21 @@ impl<T: Iterator<char>> Parser<T> {
             col: 0,
             stack: Stack::new(),
             state: ParseStart,
+            lenient: false,
+            value_line: 1,
+            value_col: 1,
         };
         p.bump();
         return p;
     }
 
+    /// Sets whether the parser accepts `//` and `/* */` comments wherever
+    /// whitespace is allowed, and a trailing comma after the last element of
+    /// an array or object, as is common in hand-edited files.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
     /// Provides access to the current position in the logical structure of the
     /// JSON stream.
     pub fn stack<'l>(&'l self) -> &'l Stack {
@@ -1366,11 +1836,37 @@ impl<T: Iterator<char>> Parser<T> {
         Err(SyntaxError(reason, self.line, self.col))
     }
 
-    fn parse_whitespace(&mut self) {
-        while self.ch_is(' ') ||
-              self.ch_is('\n') ||
-              self.ch_is('\t') ||
-              self.ch_is('\r') { self.bump(); }
+    // Skips whitespace, and comments in lenient mode. Returns an error event
+    // if a comment is malformed.
+    fn parse_whitespace(&mut self) -> Option<JsonEvent> {
+        loop {
+            while self.ch_is(' ') ||
+                  self.ch_is('\n') ||
+                  self.ch_is('\t') ||
+                  self.ch_is('\r') { self.bump(); }
+            if !self.lenient || !self.ch_is('/') {
+                return None;
+            }
+            self.bump();
+            if self.ch_is('/') {
+                while !self.eof() && !self.ch_is('\n') { self.bump(); }
+            } else if self.ch_is('*') {
+                self.bump();
+                loop {
+                    if self.eof() {
+                        return Some(self.error_event(EOFWhileParsingComment));
+                    }
+                    let star = self.ch_is('*');
+                    self.bump();
+                    if star && self.ch_is('/') {
+                        self.bump();
+                        break;
+                    }
+                }
+            } else {
+                return Some(self.error_event(InvalidSyntax));
+            }
+        }
     }
 
     fn parse_number(&mut self) -> JsonEvent {
@@ -1628,7 +2124,10 @@ impl<T: Iterator<char>> Parser<T> {
             // ParseArray(false) and ParseObject(false), which always return,
             // so there is no risk of getting stuck in an infinite loop.
             // All other paths return before the end of the loop's iteration.
-            self.parse_whitespace();
+            match self.parse_whitespace() {
+                Some(evt) => return evt,
+                None => {}
+            }
 
             match self.state {
                 ParseStart => {
@@ -1676,18 +2175,21 @@ impl<T: Iterator<char>> Parser<T> {
     fn parse_array(&mut self, first: bool) -> JsonEvent {
         if self.ch_is(']') {
             if !first {
-                self.error_event(InvalidSyntax)
-            } else {
-                self.state = if self.stack.is_empty() {
-                    ParseBeforeFinish
-                } else if self.stack.last_is_index() {
-                    ParseArrayComma
-                } else {
-                    ParseObjectComma
-                };
-                self.bump();
-                ArrayEnd
+                if !self.lenient {
+                    return self.error_event(InvalidSyntax);
+                }
+                // A trailing comma: drop the index of the missing element.
+                self.stack.pop();
             }
+            self.state = if self.stack.is_empty() {
+                ParseBeforeFinish
+            } else if self.stack.last_is_index() {
+                ParseArrayComma
+            } else {
+                ParseObjectComma
+            };
+            self.bump();
+            ArrayEnd
         } else {
             if first {
                 self.stack.push_index(0);
@@ -1729,12 +2231,9 @@ impl<T: Iterator<char>> Parser<T> {
 
     fn parse_object(&mut self, first: bool) -> JsonEvent {
         if self.ch_is('}') {
-            if !first {
-                if self.stack.is_empty() {
-                    return self.error_event(TrailingComma);
-                } else {
-                    self.stack.pop();
-                }
+            // The key of the last member has already been popped.
+            if !first && !self.lenient {
+                return self.error_event(TrailingComma);
             }
             self.state = if self.stack.is_empty() {
                 ParseBeforeFinish
@@ -1759,7 +2258,10 @@ impl<T: Iterator<char>> Parser<T> {
                 return Error(e);
             }
         };
-        self.parse_whitespace();
+        match self.parse_whitespace() {
+            Some(evt) => return evt,
+            None => {}
+        }
         if self.eof() {
             return self.error_event(EOFWhileParsingObject);
         } else if self.ch_or_null() != ':' {
@@ -1767,7 +2269,10 @@ impl<T: Iterator<char>> Parser<T> {
         }
         self.stack.push_key(s);
         self.bump();
-        self.parse_whitespace();
+        match self.parse_whitespace() {
+            Some(evt) => return evt,
+            None => {}
+        }
 
         let val = self.parse_value();
 
@@ -1800,6 +2305,8 @@ impl<T: Iterator<char>> Parser<T> {
 
     fn parse_value(&mut self) -> JsonEvent {
         if self.eof() { return self.error_event(EOFWhileParsingValue); }
+        self.value_line = self.line;
+        self.value_col = self.col;
         match self.ch_or_null() {
             'n' => { self.parse_ident("ull", NullValue) }
             't' => { self.parse_ident("rue", BooleanValue(true)) }
@@ -1848,6 +2355,12 @@ impl<T: Iterator<char>> Builder<T> {
         Builder { parser: Parser::new(src), token: None, }
     }
 
+    /// Sets whether the document may contain comments and trailing commas.
+    /// See `Parser::set_lenient`.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.parser.set_lenient(lenient);
+    }
+
     // Decode a Json value from a Parser.
     pub fn build(&mut self) -> Result<Json, BuilderError> {
         self.bump();
@@ -1948,6 +2461,14 @@ pub fn from_str(s: &str) -> Result<Json, BuilderError> {
     builder.build()
 }
 
+/// Decodes a json value from a string that may contain comments and trailing
+/// commas, like a hand-edited configuration file.
+pub fn from_str_lenient(s: &str) -> Result<Json, BuilderError> {
+    let mut builder = Builder::new(s.chars());
+    builder.set_lenient(true);
+    builder.build()
+}
+
 /// A structure to decode JSON to values in rust.
 pub struct Decoder {
     stack: Vec<Json>,
@@ -2175,6 +2696,17 @@ impl ::Decoder<DecoderError> for Decoder {
         Ok(value)
     }
 
+    fn deny_unknown_fields(&mut self) -> DecodeResult<()> {
+        // The fields that were read have been removed from the object.
+        match self.stack.last() {
+            Some(&Json::Object(ref obj)) => match obj.keys().next() {
+                Some(key) => Err(UnknownFieldError(key.clone())),
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
     fn read_tuple<T, F>(&mut self, tuple_len: uint, f: F) -> DecodeResult<T> where
         F: FnOnce(&mut Decoder) -> DecodeResult<T>,
     {
@@ -2267,6 +2799,677 @@ impl ::Decoder<DecoderError> for Decoder {
     }
 }
 
+/// A structure to decode JSON to values in rust directly from a `Parser`,
+/// without building a `Json` value for the whole document first.
+///
+/// Members of an object are decoded as they are parsed, so only the parts of
+/// the document that are read out of order (struct fields that appear before
+/// the field being looked for) are kept in memory.
+///
+/// `locate` tells the path and the position of the value that an error
+/// returned by the decoder was found at.
+pub struct StreamDecoder<T> {
+    parser: Parser<T>,
+    // Events to hand out before reading more from the parser, in reverse
+    // order, with the line and column they start at. The members of objects
+    // are preceded by a `StringValue` event holding their key.
+    replay: Vec<(JsonEvent, uint, uint)>,
+    // The objects whose fields are being read.
+    objects: Vec<ObjectFrame>,
+    // The path of the value being decoded.
+    path: Stack,
+    // The key of the map member being decoded.
+    map_key: string::String,
+    // Where the last event that was read starts.
+    line: uint,
+    col: uint,
+    // The path and the position of the value the last error was found at.
+    error_at: Option<(string::String, uint, uint)>,
+}
+
+struct ObjectFrame {
+    // Members that were read while looking for another field.
+    skipped: Vec<(string::String, Vec<(JsonEvent, uint, uint)>)>,
+    // Whether the end of the object has been read.
+    done: bool,
+    // Where the object starts.
+    line: uint,
+    col: uint,
+}
+
+impl<T: Iterator<char>> StreamDecoder<T> {
+    /// Creates a new decoder reading the JSON document from `rdr`.
+    pub fn new(rdr: T) -> StreamDecoder<T> {
+        StreamDecoder {
+            parser: Parser::new(rdr),
+            replay: Vec::new(),
+            objects: Vec::new(),
+            path: Stack::new(),
+            map_key: string::String::new(),
+            line: 1,
+            col: 1,
+            error_at: None,
+        }
+    }
+
+    /// Sets whether the document may contain comments and trailing commas.
+    /// See `Parser::set_lenient`.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.parser.set_lenient(lenient);
+    }
+
+    /// Checks that nothing but whitespace follows the decoded value.
+    pub fn finish(&mut self) -> DecodeResult<()> {
+        if !self.replay.is_empty() {
+            return self.syntax_error(TrailingCharacters);
+        }
+        match self.parser.next() {
+            None => Ok(()),
+            Some(Error(e)) => Err(ParseError(e)),
+            Some(_) => self.syntax_error(TrailingCharacters),
+        }
+    }
+
+    fn syntax_error<U>(&self, reason: ErrorCode) -> DecodeResult<U> {
+        Err(ParseError(SyntaxError(reason, self.parser.line, self.parser.col)))
+    }
+
+    /// Adds the path and the position of the value that couldn't be decoded
+    /// to an error returned while decoding from this decoder.
+    pub fn locate(&self, err: DecoderError) -> LocatedError {
+        let (path, line, col) = match (&err, &self.error_at) {
+            (&ParseError(SyntaxError(_, line, col)), _) => (self.path.to_string(), line, col),
+            (_, &Some((ref path, line, col))) => (path.clone(), line, col),
+            (_, &None) => (self.path.to_string(), self.line, self.col),
+        };
+        LocatedError { error: err, path: path, line: line, col: col }
+    }
+
+    // Remembers where the error was found for `locate`.
+    fn located_at(&mut self, err: DecoderError, line: uint, col: uint) -> DecoderError {
+        self.error_at = Some((self.path.to_string(), line, col));
+        err
+    }
+
+    // Remembers the path and the position of the last event that was read.
+    fn located(&mut self, err: DecoderError) -> DecoderError {
+        let (line, col) = (self.line, self.col);
+        self.located_at(err, line, col)
+    }
+
+    fn bump(&mut self) -> DecodeResult<JsonEvent> {
+        match self.replay.pop() {
+            Some((evt, line, col)) => {
+                self.line = line;
+                self.col = col;
+                return Ok(evt);
+            }
+            None => {}
+        }
+        let evt = match self.parser.next() {
+            None => return self.syntax_error(EOFWhileParsingValue),
+            Some(Error(e)) => return Err(ParseError(e)),
+            Some(evt) => evt,
+        };
+        match evt {
+            ObjectEnd | ArrayEnd => {
+                self.line = self.parser.line;
+                self.col = self.parser.col;
+                Ok(evt)
+            }
+            evt => {
+                self.line = self.parser.value_line;
+                self.col = self.parser.value_col;
+                let key = match self.parser.stack().top() {
+                    Some(Key(k)) => k.to_string(),
+                    _ => return Ok(evt),
+                };
+                self.replay.push((evt, self.line, self.col));
+                Ok(StringValue(key))
+            }
+        }
+    }
+
+    // Puts back the last event that was read.
+    fn unbump(&mut self, evt: JsonEvent) {
+        self.replay.push((evt, self.line, self.col));
+    }
+
+    // Returns whether the next event is `evt`, without consuming it.
+    fn peek_is(&mut self, evt: JsonEvent) -> DecodeResult<bool> {
+        let next = try!(self.bump());
+        let is = next == evt;
+        self.unbump(next);
+        Ok(is)
+    }
+
+    fn expect(&mut self, evt: JsonEvent, name: &str) -> DecodeResult<()> {
+        let next = try!(self.bump());
+        if next == evt {
+            Ok(())
+        } else {
+            self.expected(name, next)
+        }
+    }
+
+    // Reads the rest of the value starting with `evt` to report what was
+    // found instead of what was expected.
+    fn expected<U>(&mut self, name: &str, evt: JsonEvent) -> DecodeResult<U> {
+        let (line, col) = (self.line, self.col);
+        let found = match evt {
+            ObjectEnd => "}".to_string(),
+            ArrayEnd => "]".to_string(),
+            evt => format!("{}", try!(self.build(evt))),
+        };
+        Err(self.located_at(ExpectedError(name.to_string(), found), line, col))
+    }
+
+    fn build(&mut self, evt: JsonEvent) -> DecodeResult<Json> {
+        match evt {
+            NullValue => Ok(Json::Null),
+            I64Value(n) => Ok(Json::I64(n)),
+            U64Value(n) => Ok(Json::U64(n)),
+            F64Value(n) => Ok(Json::F64(n)),
+            BooleanValue(b) => Ok(Json::Boolean(b)),
+            StringValue(s) => Ok(Json::String(s)),
+            ArrayStart => {
+                let mut values = Vec::new();
+                loop {
+                    match try!(self.bump()) {
+                        ArrayEnd => return Ok(Json::Array(values)),
+                        evt => values.push(try!(self.build(evt))),
+                    }
+                }
+            }
+            ObjectStart => {
+                let mut values = BTreeMap::new();
+                loop {
+                    match try!(self.bump()) {
+                        ObjectEnd => return Ok(Json::Object(values)),
+                        StringValue(key) => {
+                            let evt = try!(self.bump());
+                            values.insert(key, try!(self.build(evt)));
+                        }
+                        _ => return self.syntax_error(InvalidSyntax),
+                    }
+                }
+            }
+            _ => self.syntax_error(InvalidSyntax),
+        }
+    }
+
+    // Reads the events of the next value, keeping them if `keep` is set.
+    fn read_events(&mut self, keep: bool) -> DecodeResult<Vec<(JsonEvent, uint, uint)>> {
+        let mut events = Vec::new();
+        let mut depth = 0u;
+        loop {
+            let evt = try!(self.bump());
+            match evt {
+                ObjectStart | ArrayStart => depth += 1,
+                ObjectEnd | ArrayEnd if depth == 0 => return self.syntax_error(InvalidSyntax),
+                ObjectEnd | ArrayEnd => depth -= 1,
+                _ => {}
+            }
+            if keep {
+                events.push((evt, self.line, self.col));
+            }
+            if depth == 0 {
+                return Ok(events);
+            }
+        }
+    }
+
+    fn skip_value(&mut self) -> DecodeResult<()> {
+        self.read_events(false).map(|_| ())
+    }
+
+    fn begin_object(&mut self) {
+        let frame = ObjectFrame {
+            skipped: Vec::new(),
+            done: false,
+            line: self.line,
+            col: self.col,
+        };
+        self.objects.push(frame);
+    }
+
+    // Skips the members of the innermost object that weren't read.
+    fn end_object(&mut self) -> DecodeResult<()> {
+        let frame = self.objects.pop().unwrap();
+        if !frame.done {
+            loop {
+                match try!(self.bump()) {
+                    ObjectEnd => break,
+                    StringValue(_) => try!(self.skip_value()),
+                    _ => return self.syntax_error(InvalidSyntax),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the index of the innermost object, failing when the fields of
+    // a struct are read outside of `read_struct`.
+    fn top_object(&mut self) -> DecodeResult<uint> {
+        match self.objects.len() {
+            0 => Err(self.located(ExpectedError("Object".into_string(),
+                                                "no object".into_string()))),
+            n => Ok(n - 1),
+        }
+    }
+
+    // Moves to the value of the member `name` of the innermost object,
+    // returning false if there is none.
+    fn find_field(&mut self, name: &str) -> DecodeResult<bool> {
+        let top = try!(self.top_object());
+        let found = self.objects[top].skipped.iter().position(|&(ref k, _)| {
+            k.as_slice() == name
+        });
+        match found {
+            Some(i) => {
+                let (_, events) = self.objects[top].skipped.remove(i).unwrap();
+                self.replay.extend(events.into_iter().rev());
+                return Ok(true);
+            }
+            None => {}
+        }
+        while !self.objects[top].done {
+            match try!(self.bump()) {
+                ObjectEnd => self.objects[top].done = true,
+                StringValue(key) => {
+                    if key.as_slice() == name {
+                        return Ok(true);
+                    }
+                    let events = try!(self.read_events(true));
+                    self.objects[top].skipped.push((key, events));
+                }
+                _ => return self.syntax_error(InvalidSyntax),
+            }
+        }
+        Ok(false)
+    }
+
+    // Decodes a member of an object or a map.
+    fn read_member<U, F>(&mut self, key: string::String, f: F) -> DecodeResult<U> where
+        F: FnOnce(&mut StreamDecoder<T>) -> DecodeResult<U>,
+    {
+        self.path.push_key(key);
+        let value = f(self);
+        self.path.pop();
+        value
+    }
+
+    // Decodes an element of an array.
+    fn read_element<U, F>(&mut self, idx: uint, f: F) -> DecodeResult<U> where
+        F: FnOnce(&mut StreamDecoder<T>) -> DecodeResult<U>,
+    {
+        self.path.push_index(idx as u32);
+        let value = f(self);
+        self.path.pop();
+        value
+    }
+}
+
+macro_rules! read_stream_primitive {
+    ($name:ident, $ty:ty) => {
+        fn $name(&mut self) -> DecodeResult<$ty> {
+            let err = match try!(self.bump()) {
+                I64Value(f) => match num::cast(f) {
+                    Some(f) => return Ok(f),
+                    None => ExpectedError("Number".into_string(), format!("{}", f)),
+                },
+                U64Value(f) => match num::cast(f) {
+                    Some(f) => return Ok(f),
+                    None => ExpectedError("Number".into_string(), format!("{}", f)),
+                },
+                F64Value(f) => ExpectedError("Integer".into_string(), format!("{}", f)),
+                // Numeric map keys are strings, see `read_primitive!`.
+                StringValue(s) => match std::str::from_str(s.as_slice()) {
+                    Some(f) => return Ok(f),
+                    None => ExpectedError("Number".into_string(), s),
+                },
+                evt => return self.expected("Number", evt),
+            };
+            Err(self.located(err))
+        }
+    }
+}
+
+impl<T: Iterator<char>> ::Decoder<DecoderError> for StreamDecoder<T> {
+    fn read_nil(&mut self) -> DecodeResult<()> {
+        self.expect(NullValue, "Null")
+    }
+
+    read_stream_primitive! { read_uint, uint }
+    read_stream_primitive! { read_u8, u8 }
+    read_stream_primitive! { read_u16, u16 }
+    read_stream_primitive! { read_u32, u32 }
+    read_stream_primitive! { read_u64, u64 }
+    read_stream_primitive! { read_int, int }
+    read_stream_primitive! { read_i8, i8 }
+    read_stream_primitive! { read_i16, i16 }
+    read_stream_primitive! { read_i32, i32 }
+    read_stream_primitive! { read_i64, i64 }
+
+    fn read_f32(&mut self) -> DecodeResult<f32> { self.read_f64().map(|x| x as f32) }
+
+    fn read_f64(&mut self) -> DecodeResult<f64> {
+        match try!(self.bump()) {
+            I64Value(f) => Ok(f as f64),
+            U64Value(f) => Ok(f as f64),
+            F64Value(f) => Ok(f),
+            StringValue(s) => match std::str::from_str(s.as_slice()) {
+                Some(f) => Ok(f),
+                None => Err(self.located(ExpectedError("Number".into_string(), s))),
+            },
+            NullValue => Ok(f64::NAN),
+            evt => self.expected("Number", evt),
+        }
+    }
+
+    fn read_bool(&mut self) -> DecodeResult<bool> {
+        match try!(self.bump()) {
+            BooleanValue(b) => Ok(b),
+            evt => self.expected("Boolean", evt),
+        }
+    }
+
+    fn read_char(&mut self) -> DecodeResult<char> {
+        let s = try!(self.read_str());
+        {
+            let mut it = s.chars();
+            match (it.next(), it.next()) {
+                // exactly one character
+                (Some(c), None) => return Ok(c),
+                _ => ()
+            }
+        }
+        Err(self.located(ExpectedError("single character string".into_string(),
+                                       format!("{}", s))))
+    }
+
+    fn read_str(&mut self) -> DecodeResult<string::String> {
+        match try!(self.bump()) {
+            StringValue(s) => Ok(s),
+            evt => self.expected("String", evt),
+        }
+    }
+
+    fn read_enum<U, F>(&mut self, _name: &str, f: F) -> DecodeResult<U> where
+        F: FnOnce(&mut StreamDecoder<T>) -> DecodeResult<U>,
+    {
+        f(self)
+    }
+
+    fn read_enum_variant<U, F>(&mut self, names: &[&str],
+                               mut f: F) -> DecodeResult<U>
+        where F: FnMut(&mut StreamDecoder<T>, uint) -> DecodeResult<U>,
+    {
+        let (name, fields) = match try!(self.bump()) {
+            StringValue(s) => (s, false),
+            ObjectStart => {
+                self.begin_object();
+                if !try!(self.find_field("variant")) {
+                    return Err(self.located(MissingFieldError("variant".into_string())));
+                }
+                let n = try!(self.read_member("variant".into_string(), |d| d.read_str()));
+                if !try!(self.find_field("fields")) {
+                    return Err(self.located(MissingFieldError("fields".into_string())));
+                }
+                try!(self.read_member("fields".into_string(), |d| d.expect(ArrayStart, "Array")));
+                (n, true)
+            }
+            evt => return self.expected("String or Object", evt),
+        };
+        let idx = match names.iter()
+                             .position(|n| str::eq_slice(*n, name.as_slice())) {
+            Some(idx) => idx,
+            None => return Err(self.located(UnknownVariantError(name)))
+        };
+        if !fields {
+            return f(self, idx);
+        }
+        let value = try!(self.read_member("fields".into_string(), |d| {
+            let value = try!(f(d, idx));
+            while !try!(d.peek_is(ArrayEnd)) {
+                try!(d.skip_value());
+            }
+            try!(d.bump());
+            Ok(value)
+        }));
+        try!(self.end_object());
+        Ok(value)
+    }
+
+    fn read_enum_variant_arg<U, F>(&mut self, idx: uint, f: F) -> DecodeResult<U> where
+        F: FnOnce(&mut StreamDecoder<T>) -> DecodeResult<U>,
+    {
+        self.read_element(idx, f)
+    }
+
+    fn read_enum_struct_variant<U, F>(&mut self, names: &[&str], f: F) -> DecodeResult<U> where
+        F: FnMut(&mut StreamDecoder<T>, uint) -> DecodeResult<U>,
+    {
+        self.read_enum_variant(names, f)
+    }
+
+    fn read_enum_struct_variant_field<U, F>(&mut self,
+                                         _name: &str,
+                                         idx: uint,
+                                         f: F)
+                                         -> DecodeResult<U> where
+        F: FnOnce(&mut StreamDecoder<T>) -> DecodeResult<U>,
+    {
+        self.read_enum_variant_arg(idx, f)
+    }
+
+    fn read_struct<U, F>(&mut self, _name: &str, _len: uint, f: F) -> DecodeResult<U> where
+        F: FnOnce(&mut StreamDecoder<T>) -> DecodeResult<U>,
+    {
+        try!(self.expect(ObjectStart, "Object"));
+        self.begin_object();
+        let value = try!(f(self));
+        try!(self.end_object());
+        Ok(value)
+    }
+
+    fn read_struct_field<U, F>(&mut self,
+                               name: &str,
+                               _idx: uint,
+                               f: F)
+                               -> DecodeResult<U> where
+        F: FnOnce(&mut StreamDecoder<T>) -> DecodeResult<U>,
+    {
+        if try!(self.find_field(name)) {
+            return self.read_member(name.to_string(), f);
+        }
+        // Try to decode a Null as an Option<_> to get None as a default
+        // value.
+        let (line, col) = {
+            let frame = self.objects.last().unwrap();
+            (frame.line, frame.col)
+        };
+        self.replay.push((NullValue, line, col));
+        self.read_member(name.to_string(), move |d| {
+            match f(d) {
+                Ok(x) => Ok(x),
+                Err(_) => Err(d.located_at(MissingFieldError(name.to_string()), line, col)),
+            }
+        })
+    }
+
+    fn deny_unknown_fields(&mut self) -> DecodeResult<()> {
+        let top = try!(self.top_object());
+        let skipped = match self.objects[top].skipped.first() {
+            Some(&(ref key, ref events)) => {
+                let (_, line, col) = events[0];
+                Some((key.clone(), line, col))
+            }
+            None => None,
+        };
+        match skipped {
+            Some((key, line, col)) => {
+                return Err(self.located_at(UnknownFieldError(key), line, col));
+            }
+            None => {}
+        }
+        if self.objects[top].done {
+            return Ok(());
+        }
+        match try!(self.bump()) {
+            ObjectEnd => {
+                self.objects[top].done = true;
+                Ok(())
+            }
+            StringValue(key) => Err(self.located(UnknownFieldError(key))),
+            _ => self.syntax_error(InvalidSyntax),
+        }
+    }
+
+    fn read_tuple<U, F>(&mut self, tuple_len: uint, f: F) -> DecodeResult<U> where
+        F: FnOnce(&mut StreamDecoder<T>) -> DecodeResult<U>,
+    {
+        try!(self.expect(ArrayStart, "Array"));
+        let (line, col) = (self.line, self.col);
+        let value = try!(f(self));
+        let mut len = tuple_len;
+        while !try!(self.peek_is(ArrayEnd)) {
+            try!(self.skip_value());
+            len += 1;
+        }
+        try!(self.bump());
+        if len == tuple_len {
+            Ok(value)
+        } else {
+            let err = ExpectedError(format!("Tuple{}", tuple_len), format!("Tuple{}", len));
+            Err(self.located_at(err, line, col))
+        }
+    }
+
+    fn read_tuple_arg<U, F>(&mut self, idx: uint, f: F) -> DecodeResult<U> where
+        F: FnOnce(&mut StreamDecoder<T>) -> DecodeResult<U>,
+    {
+        self.read_seq_elt(idx, f)
+    }
+
+    fn read_tuple_struct<U, F>(&mut self,
+                               _name: &str,
+                               len: uint,
+                               f: F)
+                               -> DecodeResult<U> where
+        F: FnOnce(&mut StreamDecoder<T>) -> DecodeResult<U>,
+    {
+        self.read_tuple(len, f)
+    }
+
+    fn read_tuple_struct_arg<U, F>(&mut self,
+                                   idx: uint,
+                                   f: F)
+                                   -> DecodeResult<U> where
+        F: FnOnce(&mut StreamDecoder<T>) -> DecodeResult<U>,
+    {
+        self.read_tuple_arg(idx, f)
+    }
+
+    fn read_option<U, F>(&mut self, mut f: F) -> DecodeResult<U> where
+        F: FnMut(&mut StreamDecoder<T>, bool) -> DecodeResult<U>,
+    {
+        match try!(self.bump()) {
+            NullValue => f(self, false),
+            evt => { self.unbump(evt); f(self, true) }
+        }
+    }
+
+    fn read_seq<U, F>(&mut self, f: F) -> DecodeResult<U> where
+        F: FnOnce(&mut StreamDecoder<T>, uint) -> DecodeResult<U>,
+    {
+        // The length isn't known until the whole array has been read, so
+        // `read_seq_has_elt` is what tells where the array ends.
+        try!(self.expect(ArrayStart, "Array"));
+        let value = try!(f(self, 0));
+        try!(self.expect(ArrayEnd, "end of Array"));
+        Ok(value)
+    }
+
+    fn read_seq_elt<U, F>(&mut self, idx: uint, f: F) -> DecodeResult<U> where
+        F: FnOnce(&mut StreamDecoder<T>) -> DecodeResult<U>,
+    {
+        self.read_element(idx, f)
+    }
+
+    fn read_seq_has_elt(&mut self, _idx: uint, _len: uint) -> DecodeResult<bool> {
+        self.peek_is(ArrayEnd).map(|end| !end)
+    }
+
+    fn read_map<U, F>(&mut self, f: F) -> DecodeResult<U> where
+        F: FnOnce(&mut StreamDecoder<T>, uint) -> DecodeResult<U>,
+    {
+        try!(self.expect(ObjectStart, "Object"));
+        let value = try!(f(self, 0));
+        try!(self.expect(ObjectEnd, "end of Object"));
+        Ok(value)
+    }
+
+    fn read_map_elt_key<U, F>(&mut self, _idx: uint, f: F) -> DecodeResult<U> where
+       F: FnOnce(&mut StreamDecoder<T>) -> DecodeResult<U>,
+    {
+        match try!(self.bump()) {
+            StringValue(key) => {
+                self.map_key = key.clone();
+                self.unbump(StringValue(key));
+            }
+            evt => self.unbump(evt),
+        }
+        f(self)
+    }
+
+    fn read_map_elt_val<U, F>(&mut self, _idx: uint, f: F) -> DecodeResult<U> where
+       F: FnOnce(&mut StreamDecoder<T>) -> DecodeResult<U>,
+    {
+        let key = std::mem::replace(&mut self.map_key, string::String::new());
+        self.read_member(key, f)
+    }
+
+    fn read_map_has_elt(&mut self, _idx: uint, _len: uint) -> DecodeResult<bool> {
+        self.peek_is(ObjectEnd).map(|end| !end)
+    }
+
+    fn error(&mut self, err: &str) -> DecoderError {
+        self.located(ApplicationError(err.to_string()))
+    }
+}
+
+/// An iterator over the characters of an `io::Buffer`, for feeding a `Parser`
+/// or a `StreamDecoder` from a reader.
+///
+/// Iteration stops at the end of the stream or at the first error, which is
+/// kept in `err`.
+pub struct BufferChars<B> {
+    rdr: B,
+    /// The error that stopped the iteration, if any.
+    pub err: Option<io::IoError>,
+}
+
+impl<B: io::Buffer> BufferChars<B> {
+    /// Creates an iterator over the characters read from `rdr`.
+    pub fn new(rdr: B) -> BufferChars<B> {
+        BufferChars { rdr: rdr, err: None }
+    }
+}
+
+impl<B: io::Buffer> Iterator<char> for BufferChars<B> {
+    fn next(&mut self) -> Option<char> {
+        if self.err.is_some() {
+            return None;
+        }
+        match self.rdr.read_char() {
+            Ok(c) => Some(c),
+            Err(ref e) if e.kind == io::EndOfFile => None,
+            Err(e) => { self.err = Some(e); None }
+        }
+    }
+}
+
 /// A trait for converting values to JSON
 pub trait ToJson for Sized? {
     /// Converts the value of `self` to an instance of JSON
@@ -2421,9 +3624,11 @@ mod tests {
     use super::DecoderError::*;
     use super::JsonEvent::*;
     use super::StackElement::*;
+    use super::PatchErrorCode::*;
     use super::{PrettyEncoder, Json, from_str, DecodeResult, DecoderError, JsonEvent, Parser,
-                StackElement, Stack, Encoder, Decoder};
-    use std::{i64, u64, f32, f64, io};
+                StackElement, Stack, Encoder, Decoder, StreamDecoder, PatchError,
+                PatchErrorCode, LocatedError};
+    use std::{i64, u64, f32, f64, io, str};
     use std::collections::BTreeMap;
     use std::num::Float;
     use std::string;
@@ -3071,19 +4276,25 @@ mod tests {
         A(f64),
         B(string::String)
     }
-    fn check_err<T: Decodable<Decoder, DecoderError>>(to_parse: &'static str,
-                                                      expected: DecoderError) {
+    // Both decoders must report the same error.
+    fn check_err<T>(to_parse: &'static str, expected: DecoderError) where
+        T: Decodable<Decoder, DecoderError>,
+        T: Decodable<StreamDecoder<str::Chars<'static>>, DecoderError>,
+    {
         let res: DecodeResult<T> = match from_str(to_parse) {
             Err(e) => Err(ParseError(e)),
             Ok(json) => Decodable::decode(&mut Decoder::new(json))
         };
-        match res {
-            Ok(_) => panic!("`{}` parsed & decoded ok, expecting error `{}`",
-                              to_parse, expected),
-            Err(ParseError(e)) => panic!("`{}` is not valid json: {}",
-                                           to_parse, e),
-            Err(e) => {
-                assert_eq!(e, expected);
+        let stream_res: DecodeResult<T> = super::decode_stream(to_parse);
+        for res in vec![res, stream_res].into_iter() {
+            match res {
+                Ok(_) => panic!("`{}` parsed & decoded ok, expecting error `{}`",
+                                  to_parse, expected),
+                Err(ParseError(e)) => panic!("`{}` is not valid json: {}",
+                                               to_parse, e),
+                Err(e) => {
+                    assert_eq!(e, expected);
+                }
             }
         }
     }
@@ -3115,6 +4326,150 @@ mod tests {
                                 UnknownVariantError("C".into_string()));
     }
 
+    #[test]
+    fn test_stream_decode_field_order() {
+        // Fields may come in any order, and unknown ones are skipped.
+        let s = "{\"c\": [\"x\"], \"z\": {\"deep\": [1, {}]}, \"b\": 1, \"a\": null}";
+        let v: Inner = super::decode_stream(s).unwrap();
+        assert_eq!(v, Inner { a: (), b: 1, c: vec!["x".into_string()] });
+
+        let s = "{\"fields\": [\"Henry\", 349], \"variant\": \"Frog\", \"x\": 1}";
+        let v: Animal = super::decode_stream(s).unwrap();
+        assert_eq!(v, Frog("Henry".into_string(), 349));
+    }
+
+    #[test]
+    fn test_stream_decode_nested_out_of_order() {
+        let s = "{\"inner\": [{\"c\": [], \"b\": 2, \"a\": null},
+                               {\"b\": 3, \"c\": [\"abc\"], \"a\": null}]}";
+        let v: Outer = super::decode_stream(s).unwrap();
+        assert_eq!(v, Outer { inner: vec![
+            Inner { a: (), b: 2, c: vec![] },
+            Inner { a: (), b: 3, c: vec!["abc".into_string()] },
+        ]});
+    }
+
+    #[test]
+    fn test_stream_decode_trailing() {
+        assert_eq!(super::decode_stream::<uint>("1 2"),
+                   Err(ParseError(SyntaxError(TrailingCharacters, 1, 3))));
+        assert_eq!(super::decode_stream::<Vec<uint>>("[1, 2"),
+                   Err(ParseError(SyntaxError(EOFWhileParsingArray, 1, 6))));
+        assert_eq!(super::decode_stream::<(uint, uint)>("[1, 2, 3]"),
+                   Err(ExpectedError("Tuple2".into_string(), "Tuple3".into_string())));
+    }
+
+    #[test]
+    fn test_stream_decode_field_outside_struct() {
+        let mut d = StreamDecoder::new("{\"a\": 1}".chars());
+        let res: DecodeResult<uint> = ::Decoder::read_struct_field(&mut d, "a", 0, |d| {
+            Decodable::decode(d)
+        });
+        assert_eq!(res.unwrap_err(),
+                   ExpectedError("Object".into_string(), "no object".into_string()));
+    }
+
+    #[deriving(Decodable, PartialEq, Show)]
+    struct Server {
+        host: string::String,
+        port: u16,
+    }
+
+    #[deriving(Decodable, PartialEq, Show)]
+    struct Config {
+        servers: Vec<Server>,
+    }
+
+    #[test]
+    fn test_stream_decode_error_location() {
+        let s = "{\n  \"servers\": [\n    {\"host\": \"a\", \"port\": 80},\n    \
+                 {\"port\": 70000, \"host\": \"b\"}\n  ]\n}";
+        let err = super::decode_located::<Config>(s).unwrap_err();
+        assert_eq!(err, LocatedError {
+            error: ExpectedError("Number".into_string(), "70000".into_string()),
+            path: ".servers[1].port".into_string(),
+            line: 4,
+            col: 15,
+        });
+
+        let err = super::decode_located::<Config>("{\"servers\": [{\"host\": \"a\"}]}");
+        let err = err.unwrap_err();
+        assert_eq!(err.error, MissingFieldError("port".into_string()));
+        assert_eq!(err.path.as_slice(), ".servers[0].port");
+        assert_eq!((err.line, err.col), (1, 14));
+
+        let err = super::decode_located::<BTreeMap<string::String, u8>>("{\"a b\": 300}");
+        let err = err.unwrap_err();
+        assert_eq!(err.path.as_slice(), "[\"a b\"]");
+        assert_eq!((err.line, err.col), (1, 9));
+
+        let frog = "{\"variant\": \"Frog\", \"fields\": [\"x\", []]}";
+        let err = super::decode_located::<Animal>(frog);
+        assert_eq!(err.unwrap_err().path.as_slice(), ".fields[1]");
+
+        // Syntax errors already know where they are.
+        let err = super::decode_located::<Config>("{\"servers\": [}").unwrap_err();
+        match err.error {
+            ParseError(SyntaxError(_, 1, 14)) => {}
+            e => panic!("unexpected error {}", e),
+        }
+        assert_eq!((err.line, err.col), (1, 14));
+
+        // The other decoders leave the error alone.
+        assert_eq!(super::decode_stream::<Config>(s),
+                   Err(ExpectedError("Number".into_string(), "70000".into_string())));
+    }
+
+    #[test]
+    fn test_stack_show() {
+        let mut stack = Stack::new();
+        assert_eq!(stack.to_string(), "");
+        stack.push_key("servers".into_string());
+        stack.push_index(3);
+        stack.push_key("a.b".into_string());
+        stack.push_key("_port2".into_string());
+        assert_eq!(stack.to_string(), ".servers[3][\"a.b\"]._port2");
+    }
+
+    #[test]
+    fn test_lenient() {
+        let s = "// servers\n{\"a\": [1, /* two */ 2,],\n \"b\": {\"c\": null,},}  // end";
+        let expected = from_str("{\"a\": [1, 2], \"b\": {\"c\": null}}").unwrap();
+        assert_eq!(super::from_str_lenient(s), Ok(expected));
+        assert_eq!(from_str(s), Err(SyntaxError(InvalidSyntax, 1, 1)));
+
+        assert_eq!(super::from_str_lenient("[1 / 2]"), Err(SyntaxError(InvalidSyntax, 1, 5)));
+        assert_eq!(super::from_str_lenient("[1 /* x"),
+                   Err(SyntaxError(EOFWhileParsingComment, 1, 8)));
+        assert_eq!(super::from_str_lenient("[1 /**/]"), Ok(Array(vec![U64(1)])));
+        assert_eq!(super::from_str_lenient("[1 /* * **/]"), Ok(Array(vec![U64(1)])));
+        assert_eq!(super::from_str_lenient("[,]"), Err(SyntaxError(InvalidSyntax, 1, 2)));
+
+        // Nested objects can't have trailing commas either in strict mode.
+        assert_eq!(from_str("{\"a\": {\"b\": 1,}}"), Err(SyntaxError(TrailingComma, 1, 15)));
+
+        let config: Config = super::decode_lenient("{
+            // The only server.
+            \"servers\": [{\"host\": \"a\", \"port\": 80,},],
+        }").unwrap();
+        assert_eq!(config, Config { servers: vec![Server { host: "a".into_string(), port: 80 }] });
+    }
+
+    #[test]
+    fn test_decode_reader() {
+        let s = "{\"a\": \"Dog\", \"b\": {\"variant\": \"Frog\", \"fields\": [\"Henry\", 349]}}";
+        let rdr = io::BufReader::new(s.as_bytes());
+        let mut map: BTreeMap<string::String, Animal> = super::decode_reader(rdr).unwrap();
+        assert_eq!(map.remove(&"a".into_string()), Some(Dog));
+        assert_eq!(map.remove(&"b".into_string()), Some(Frog("Henry".into_string(), 349)));
+
+        let rdr = io::BufReader::new(b"[\"\xff\"]");
+        match super::decode_reader::<_, Vec<string::String>>(rdr) {
+            Err(ParseError(IoError(io::InvalidInput, _))) => {}
+            res => panic!("unexpected result {}", res),
+        }
+    }
+
     #[test]
     fn test_find(){
         let json_value = from_str("{\"dog\" : \"cat\"}").unwrap();
@@ -3136,6 +4491,159 @@ mod tests {
         assert!(found_str.unwrap() == "cheese");
     }
 
+    #[test]
+    fn test_pointer() {
+        // The examples from RFC 6901.
+        let json_value = from_str(r#"{
+            "foo": ["bar", "baz"], "": 0, "a/b": 1, "c%d": 2, "e^f": 3,
+            "g|h": 4, "i\\j": 5, "k\"l": 6, " ": 7, "m~n": 8
+        }"#).unwrap();
+        assert_eq!(json_value.pointer(""), Some(&json_value));
+        assert_eq!(json_value.pointer("/foo"), json_value.find("foo"));
+        assert_eq!(json_value.pointer("/foo/0"), Some(&String("bar".into_string())));
+        assert_eq!(json_value.pointer("/"), Some(&U64(0)));
+        assert_eq!(json_value.pointer("/a~1b"), Some(&U64(1)));
+        assert_eq!(json_value.pointer("/c%d"), Some(&U64(2)));
+        assert_eq!(json_value.pointer("/i\\j"), Some(&U64(5)));
+        assert_eq!(json_value.pointer("/k\"l"), Some(&U64(6)));
+        assert_eq!(json_value.pointer("/ "), Some(&U64(7)));
+        assert_eq!(json_value.pointer("/m~0n"), Some(&U64(8)));
+
+        assert_eq!(json_value.pointer("foo"), None);
+        assert_eq!(json_value.pointer("/m~2n"), None);
+        assert_eq!(json_value.pointer("/foo/2"), None);
+        assert_eq!(json_value.pointer("/foo/01"), None);
+        assert_eq!(json_value.pointer("/foo/-"), None);
+        assert_eq!(json_value.pointer("/foo/0/x"), None);
+    }
+
+    #[test]
+    fn test_pointer_mut() {
+        let mut json_value = from_str(r#"{"servers": [{"port": 80}]}"#).unwrap();
+        *json_value.pointer_mut("/servers/0/port").unwrap() = U64(8080);
+        assert_eq!(json_value, from_str(r#"{"servers": [{"port": 8080}]}"#).unwrap());
+        assert!(json_value.pointer_mut("/servers/1").is_none());
+    }
+
+    fn check_patch(doc: &str, patch: &str, expected: &str) {
+        let mut doc = from_str(doc).unwrap();
+        match doc.apply_patch(&from_str(patch).unwrap()) {
+            Ok(()) => assert_eq!(doc, from_str(expected).unwrap()),
+            Err(e) => panic!("applying {} failed: {}", patch, e),
+        }
+    }
+
+    fn check_patch_err(doc: &str, patch: &str, code: PatchErrorCode, index: uint, path: &str) {
+        let mut doc = from_str(doc).unwrap();
+        let original = doc.clone();
+        let err = doc.apply_patch(&from_str(patch).unwrap()).unwrap_err();
+        assert_eq!(err, PatchError { code: code, index: index, path: path.into_string() });
+        // Nothing is applied if an operation fails.
+        assert_eq!(doc, original);
+    }
+
+    #[test]
+    fn test_apply_patch() {
+        // Mostly the examples from RFC 6902.
+        check_patch(r#"{"foo": "bar"}"#,
+                    r#"[{"op": "add", "path": "/baz", "value": "qux"}]"#,
+                    r#"{"baz": "qux", "foo": "bar"}"#);
+        check_patch(r#"{"foo": ["bar", "baz"]}"#,
+                    r#"[{"op": "add", "path": "/foo/1", "value": "qux"}]"#,
+                    r#"{"foo": ["bar", "qux", "baz"]}"#);
+        check_patch(r#"{"foo": ["bar"]}"#,
+                    r#"[{"op": "add", "path": "/foo/-", "value": ["abc"]}]"#,
+                    r#"{"foo": ["bar", ["abc"]]}"#);
+        check_patch(r#"{"baz": "qux", "foo": "bar"}"#,
+                    r#"[{"op": "remove", "path": "/baz"}]"#,
+                    r#"{"foo": "bar"}"#);
+        check_patch(r#"{"foo": ["bar", "qux", "baz"]}"#,
+                    r#"[{"op": "remove", "path": "/foo/1"}]"#,
+                    r#"{"foo": ["bar", "baz"]}"#);
+        check_patch(r#"{"baz": "qux", "foo": "bar"}"#,
+                    r#"[{"op": "replace", "path": "/baz", "value": "boo"}]"#,
+                    r#"{"baz": "boo", "foo": "bar"}"#);
+        check_patch(r#"{"foo": {"bar": "baz", "waldo": "fred"}, "qux": {"corge": "grault"}}"#,
+                    r#"[{"op": "move", "from": "/foo/waldo", "path": "/qux/thud"}]"#,
+                    r#"{"foo": {"bar": "baz"}, "qux": {"corge": "grault", "thud": "fred"}}"#);
+        check_patch(r#"{"foo": ["all", "grass", "cows", "eat"]}"#,
+                    r#"[{"op": "move", "from": "/foo/1", "path": "/foo/3"}]"#,
+                    r#"{"foo": ["all", "cows", "eat", "grass"]}"#);
+        check_patch(r#"{"foo": {"bar": 1}}"#,
+                    r#"[{"op": "copy", "from": "/foo", "path": "/baz"},
+                        {"op": "replace", "path": "/baz/bar", "value": 2}]"#,
+                    r#"{"foo": {"bar": 1}, "baz": {"bar": 2}}"#);
+        check_patch(r#"{"baz": "qux", "foo": ["a", 2, "c"]}"#,
+                    r#"[{"op": "test", "path": "/baz", "value": "qux"},
+                        {"op": "test", "path": "/foo/1", "value": 2.0}]"#,
+                    r#"{"baz": "qux", "foo": ["a", 2, "c"]}"#);
+        check_patch(r#"{"foo": "bar"}"#,
+                    r#"[{"op": "replace", "path": "", "value": [1]}]"#,
+                    r#"[1]"#);
+    }
+
+    #[test]
+    fn test_apply_patch_errors() {
+        check_patch_err("{}", "{}", NotAnArray, 0, "");
+        check_patch_err("{}", "[1]", InvalidOperation, 0, "");
+        check_patch_err("{}", r#"[{"op": "add", "value": 1}]"#, MissingPath, 0, "");
+        check_patch_err("{}", r#"[{"path": "/a", "value": 1}]"#, MissingOp, 0, "/a");
+        check_patch_err("{}", r#"[{"op": "frob", "path": "/a"}]"#, UnknownOp, 0, "/a");
+        check_patch_err("{}", r#"[{"op": "add", "path": "/a"}]"#, MissingValue, 0, "/a");
+        check_patch_err("{}", r#"[{"op": "copy", "path": "/a"}]"#, MissingFrom, 0, "/a");
+        check_patch_err("{}", r#"[{"op": "add", "path": "a", "value": 1}]"#,
+                        InvalidPointer, 0, "a");
+        check_patch_err(r#"{"foo": "bar"}"#,
+                        r#"[{"op": "add", "path": "/baz", "value": 1},
+                            {"op": "add", "path": "/baz/bat", "value": "qux"}]"#,
+                        PathNotFound, 1, "/baz/bat");
+        check_patch_err(r#"{"a": [1, 2]}"#, r#"[{"op": "add", "path": "/a/3", "value": 1}]"#,
+                        PathNotFound, 0, "/a/3");
+        check_patch_err(r#"{"a": [1, 2]}"#, r#"[{"op": "remove", "path": "/a/2"}]"#,
+                        PathNotFound, 0, "/a/2");
+        check_patch_err(r#"{"a": 1}"#, r#"[{"op": "replace", "path": "/b", "value": 2}]"#,
+                        PathNotFound, 0, "/b");
+        check_patch_err(r#"{"a": 1}"#, r#"[{"op": "move", "from": "/b", "path": "/c"}]"#,
+                        PathNotFound, 0, "/b");
+        check_patch_err(r#"{"a": {"b": 1}}"#,
+                        r#"[{"op": "move", "from": "/a", "path": "/a/b/c"}]"#,
+                        MoveIntoChild, 0, "/a/b/c");
+        check_patch_err(r#"{"baz": "qux"}"#,
+                        r#"[{"op": "remove", "path": "/baz"},
+                            {"op": "test", "path": "/baz", "value": "qux"}]"#,
+                        PathNotFound, 1, "/baz");
+        check_patch_err(r#"{"baz": "qux"}"#,
+                        r#"[{"op": "test", "path": "/baz", "value": "bar"}]"#,
+                        TestFailed, 0, "/baz");
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        // The examples from RFC 7386.
+        let cases = [
+            (r#"{"a":"b"}"#, r#"{"a":"c"}"#, r#"{"a":"c"}"#),
+            (r#"{"a":"b"}"#, r#"{"b":"c"}"#, r#"{"a":"b","b":"c"}"#),
+            (r#"{"a":"b"}"#, r#"{"a":null}"#, r#"{}"#),
+            (r#"{"a":"b","b":"c"}"#, r#"{"a":null}"#, r#"{"b":"c"}"#),
+            (r#"{"a":["b"]}"#, r#"{"a":"c"}"#, r#"{"a":"c"}"#),
+            (r#"{"a":"c"}"#, r#"{"a":["b"]}"#, r#"{"a":["b"]}"#),
+            (r#"{"a":{"b":"c"}}"#, r#"{"a":{"b":"d","c":null}}"#, r#"{"a":{"b":"d"}}"#),
+            (r#"{"a":[{"b":"c"}]}"#, r#"{"a":[1]}"#, r#"{"a":[1]}"#),
+            (r#"["a","b"]"#, r#"["c","d"]"#, r#"["c","d"]"#),
+            (r#"{"a":"b"}"#, r#"["c"]"#, r#"["c"]"#),
+            (r#"{"a":"foo"}"#, r#"null"#, r#"null"#),
+            (r#"{"a":"foo"}"#, r#""bar""#, r#""bar""#),
+            (r#"{"e":null}"#, r#"{"a":1}"#, r#"{"e":null,"a":1}"#),
+            (r#"[1,2]"#, r#"{"a":"b","c":null}"#, r#"{"a":"b"}"#),
+            (r#"{}"#, r#"{"a":{"bb":{"ccc":null}}}"#, r#"{"a":{"bb":{}}}"#),
+        ];
+        for &(target, patch, expected) in cases.iter() {
+            let mut target = from_str(target).unwrap();
+            target.merge_patch(&from_str(patch).unwrap());
+            assert_eq!(target, from_str(expected).unwrap());
+        }
+    }
+
     #[test]
     fn test_index(){
         let json_value = from_str("{\"animals\":[\"dog\",\"cat\",\"mouse\"]}").unwrap();
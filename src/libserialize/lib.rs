@@ -42,5 +42,8 @@ mod serialize;
 mod collection_impls;
 
 pub mod base64;
+pub mod cbor;
 pub mod hex;
 pub mod json;
+pub mod msgpack;
+pub mod toml;
@@ -0,0 +1,1294 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![forbid(non_camel_case_types)]
+#![allow(missing_docs)]
+
+//! MessagePack encoding and decoding.
+//!
+//! Values are mapped to MessagePack the same way the `cbor` module maps them
+//! to CBOR: integers use the smallest format that holds them, `char`s and
+//! strings are strings, `()` and `None` are nil, sequences and tuples are
+//! arrays, structs are maps from field names to values, and enum variants
+//! are their name, or an array of their name and fields if they have any.
+//!
+//! Binary values can be read as sequences of bytes, such as a `Vec<u8>`.
+//! Extension values are skipped when they are struct fields that aren't
+//! read, and are errors anywhere else.
+//!
+//! ```rust
+//! extern crate serialize;
+//! use serialize::msgpack;
+//!
+//! #[deriving(Decodable, Encodable, PartialEq, Show)]
+//! pub struct TestStruct  {
+//!     data_int: u8,
+//!     data_str: String,
+//!     data_vector: Vec<u8>,
+//! }
+//!
+//! fn main() {
+//!     let object = TestStruct {
+//!         data_int: 1,
+//!         data_str: "homura".to_string(),
+//!         data_vector: vec![2,3,4,5],
+//!     };
+//!
+//!     let encoded: Vec<u8> = msgpack::encode(&object);
+//!     let decoded: TestStruct = msgpack::decode(encoded.as_slice()).unwrap();
+//!     assert_eq!(decoded, object);
+//! }
+//! ```
+
+use self::DecoderError::*;
+use self::Item::*;
+
+use std;
+use std::{io, num};
+use std::mem::{replace, transmute};
+use std::string;
+
+use {Encodable, Decodable};
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum DecoderError {
+    /// The data ends in the middle of a value.
+    EndOfData,
+    /// The data isn't valid MessagePack, starting at the given offset.
+    InvalidData(uint),
+    /// The string at the given offset isn't valid UTF-8.
+    InvalidUtf8(uint),
+    /// There is more data after the decoded value, starting at the given
+    /// offset.
+    TrailingData(uint),
+    /// The array or map at the given offset is nested more than `MAX_DEPTH`
+    /// levels deep in a value being skipped.
+    TooDeeplyNested(uint),
+    ExpectedError(string::String, string::String),
+    MissingFieldError(string::String),
+    UnknownVariantError(string::String),
+    UnknownFieldError(string::String),
+    ApplicationError(string::String)
+}
+
+impl std::error::Error for DecoderError {
+    fn description(&self) -> &str { "decoder error" }
+    fn detail(&self) -> Option<std::string::String> { Some(self.to_string()) }
+}
+
+pub type EncodeResult = io::IoResult<()>;
+pub type DecodeResult<T> = Result<T, DecoderError>;
+
+/// Shortcut function to encode a `T` into MessagePack
+pub fn encode<'a, T: Encodable<Encoder<'a>, io::IoError>>(object: &T) -> Vec<u8> {
+    Encoder::buffer_encode(object)
+}
+
+/// Shortcut function to decode a MessagePack value into an object. All of
+/// `data` must be used.
+pub fn decode<'a, T: Decodable<Decoder<'a>, DecoderError>>(data: &'a [u8]) -> DecodeResult<T> {
+    let mut decoder = Decoder::new(data);
+    let value = try!(Decodable::decode(&mut decoder));
+    if decoder.position() != data.len() {
+        return Err(TrailingData(decoder.position()));
+    }
+    Ok(value)
+}
+
+const NIL: u8 = 0xc0;
+const FALSE: u8 = 0xc2;
+const TRUE: u8 = 0xc3;
+const FLOAT32: u8 = 0xca;
+const FLOAT64: u8 = 0xcb;
+const UINT8: u8 = 0xcc;
+const UINT16: u8 = 0xcd;
+const UINT32: u8 = 0xce;
+const UINT64: u8 = 0xcf;
+const INT8: u8 = 0xd0;
+const INT16: u8 = 0xd1;
+const INT32: u8 = 0xd2;
+const INT64: u8 = 0xd3;
+const STR8: u8 = 0xd9;
+const STR16: u8 = 0xda;
+const STR32: u8 = 0xdb;
+const ARRAY16: u8 = 0xdc;
+const ARRAY32: u8 = 0xdd;
+const MAP16: u8 = 0xde;
+const MAP32: u8 = 0xdf;
+
+/// A structure for implementing serialization to MessagePack.
+pub struct Encoder<'a> {
+    writer: &'a mut (io::Writer+'a),
+}
+
+impl<'a> Encoder<'a> {
+    /// Creates a new MessagePack encoder whose output will be written to the
+    /// writer specified.
+    pub fn new(writer: &'a mut io::Writer) -> Encoder<'a> {
+        Encoder { writer: writer }
+    }
+
+    /// Encode the specified struct into a MessagePack [u8]
+    pub fn buffer_encode<T:Encodable<Encoder<'a>, io::IoError>>(object: &T) -> Vec<u8>  {
+        let mut m = Vec::new();
+        // FIXME(14302) remove the transmute and unsafe block.
+        unsafe {
+            let mut encoder = Encoder::new(&mut m as &mut io::Writer);
+            // Vec<u8> never Errs
+            let _ = object.encode(transmute(&mut encoder));
+        }
+        m
+    }
+
+    fn write_uint(&mut self, v: u64) -> EncodeResult {
+        if v < 0x80 {
+            self.writer.write_u8(v as u8)
+        } else if v <= 0xff {
+            try!(self.writer.write_u8(UINT8));
+            self.writer.write_u8(v as u8)
+        } else if v <= 0xffff {
+            try!(self.writer.write_u8(UINT16));
+            self.writer.write_be_u16(v as u16)
+        } else if v <= 0xffffffff {
+            try!(self.writer.write_u8(UINT32));
+            self.writer.write_be_u32(v as u32)
+        } else {
+            try!(self.writer.write_u8(UINT64));
+            self.writer.write_be_u64(v)
+        }
+    }
+
+    fn write_int(&mut self, v: i64) -> EncodeResult {
+        if v >= 0 {
+            self.write_uint(v as u64)
+        } else if v >= -32 {
+            self.writer.write_u8(v as u8)
+        } else if v >= -0x80 {
+            try!(self.writer.write_u8(INT8));
+            self.writer.write_u8(v as u8)
+        } else if v >= -0x8000 {
+            try!(self.writer.write_u8(INT16));
+            self.writer.write_be_u16(v as u16)
+        } else if v >= -0x80000000 {
+            try!(self.writer.write_u8(INT32));
+            self.writer.write_be_u32(v as u32)
+        } else {
+            try!(self.writer.write_u8(INT64));
+            self.writer.write_be_u64(v as u64)
+        }
+    }
+
+    // Writes the marker of a string, array or map of `len` items: `fix` with
+    // the length in it if it is less than `fix_len`, or one of `markers`
+    // followed by the length in 1, 2 or 4 bytes.
+    fn write_len(&mut self, len: uint, fix: u8, fix_len: uint,
+                 markers: [Option<u8>, ..3]) -> EncodeResult {
+        if len < fix_len {
+            return self.writer.write_u8(fix | len as u8);
+        }
+        match markers {
+            [Some(marker), _, _] if len <= 0xff => {
+                try!(self.writer.write_u8(marker));
+                self.writer.write_u8(len as u8)
+            }
+            [_, Some(marker), _] if len <= 0xffff => {
+                try!(self.writer.write_u8(marker));
+                self.writer.write_be_u16(len as u16)
+            }
+            [_, _, Some(marker)] if len as u64 <= 0xffffffff => {
+                try!(self.writer.write_u8(marker));
+                self.writer.write_be_u32(len as u32)
+            }
+            _ => Err(io::IoError {
+                kind: io::InvalidInput,
+                desc: "too many items for MessagePack",
+                detail: Some(len.to_string()),
+            }),
+        }
+    }
+}
+
+impl<'a> ::Encoder<io::IoError> for Encoder<'a> {
+    fn emit_nil(&mut self) -> EncodeResult { self.writer.write_u8(NIL) }
+
+    fn emit_uint(&mut self, v: uint) -> EncodeResult { self.write_uint(v as u64) }
+    fn emit_u64(&mut self, v: u64) -> EncodeResult { self.write_uint(v) }
+    fn emit_u32(&mut self, v: u32) -> EncodeResult { self.write_uint(v as u64) }
+    fn emit_u16(&mut self, v: u16) -> EncodeResult { self.write_uint(v as u64) }
+    fn emit_u8(&mut self, v: u8) -> EncodeResult { self.write_uint(v as u64) }
+
+    fn emit_int(&mut self, v: int) -> EncodeResult { self.write_int(v as i64) }
+    fn emit_i64(&mut self, v: i64) -> EncodeResult { self.write_int(v) }
+    fn emit_i32(&mut self, v: i32) -> EncodeResult { self.write_int(v as i64) }
+    fn emit_i16(&mut self, v: i16) -> EncodeResult { self.write_int(v as i64) }
+    fn emit_i8(&mut self, v: i8) -> EncodeResult { self.write_int(v as i64) }
+
+    fn emit_bool(&mut self, v: bool) -> EncodeResult {
+        self.writer.write_u8(if v { TRUE } else { FALSE })
+    }
+
+    fn emit_f64(&mut self, v: f64) -> EncodeResult {
+        try!(self.writer.write_u8(FLOAT64));
+        self.writer.write_be_u64(unsafe { transmute(v) })
+    }
+    fn emit_f32(&mut self, v: f32) -> EncodeResult {
+        try!(self.writer.write_u8(FLOAT32));
+        self.writer.write_be_u32(unsafe { transmute(v) })
+    }
+
+    fn emit_char(&mut self, v: char) -> EncodeResult {
+        self.emit_str(string::String::from_char(1, v).as_slice())
+    }
+    fn emit_str(&mut self, v: &str) -> EncodeResult {
+        try!(self.write_len(v.len(), 0xa0, 32, [Some(STR8), Some(STR16), Some(STR32)]));
+        self.writer.write_str(v)
+    }
+
+    fn emit_enum<F>(&mut self, _name: &str, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_enum_variant<F>(&mut self,
+                            name: &str,
+                            _id: uint,
+                            cnt: uint,
+                            f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        // enums are encoded as strings or arrays
+        // Bunny => "Bunny"
+        // Kangaroo(34,"William") => ["Kangaroo", 34, "William"]
+        if cnt == 0 {
+            self.emit_str(name)
+        } else {
+            try!(self.write_len(cnt + 1, 0x90, 16, [None, Some(ARRAY16), Some(ARRAY32)]));
+            try!(self.emit_str(name));
+            f(self)
+        }
+    }
+
+    fn emit_enum_variant_arg<F>(&mut self, _idx: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_enum_struct_variant<F>(&mut self,
+                                   name: &str,
+                                   id: uint,
+                                   cnt: uint,
+                                   f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.emit_enum_variant(name, id, cnt, f)
+    }
+
+    fn emit_enum_struct_variant_field<F>(&mut self,
+                                         _: &str,
+                                         idx: uint,
+                                         f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.emit_enum_variant_arg(idx, f)
+    }
+
+    fn emit_struct<F>(&mut self, _: &str, len: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.emit_map(len, f)
+    }
+
+    fn emit_struct_field<F>(&mut self, name: &str, _idx: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        try!(self.emit_str(name));
+        f(self)
+    }
+
+    fn emit_tuple<F>(&mut self, len: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.emit_seq(len, f)
+    }
+    fn emit_tuple_arg<F>(&mut self, idx: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.emit_seq_elt(idx, f)
+    }
+
+    fn emit_tuple_struct<F>(&mut self, _name: &str, len: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.emit_seq(len, f)
+    }
+    fn emit_tuple_struct_arg<F>(&mut self, idx: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        self.emit_seq_elt(idx, f)
+    }
+
+    fn emit_option<F>(&mut self, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        f(self)
+    }
+    fn emit_option_none(&mut self) -> EncodeResult { self.emit_nil() }
+    fn emit_option_some<F>(&mut self, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_seq<F>(&mut self, len: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        try!(self.write_len(len, 0x90, 16, [None, Some(ARRAY16), Some(ARRAY32)]));
+        f(self)
+    }
+
+    fn emit_seq_elt<F>(&mut self, _idx: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_map<F>(&mut self, len: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        try!(self.write_len(len, 0x80, 16, [None, Some(MAP16), Some(MAP32)]));
+        f(self)
+    }
+
+    fn emit_map_elt_key<F>(&mut self, _idx: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_map_elt_val<F>(&mut self, _idx: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult,
+    {
+        f(self)
+    }
+}
+
+// The type of a value, with its length for strings, binaries, extensions,
+// arrays and maps.
+#[deriving(Clone, Copy, PartialEq, Show)]
+enum Item {
+    Nil,
+    Boolean(bool),
+    Unsigned(u64),
+    Signed(i64),
+    Float(f64),
+    Str(u64),
+    Bin(u64),
+    Ext(u64),
+    Array(u64),
+    Map(u64),
+}
+
+// Describes an item for error messages.
+fn describe(item: Item) -> string::String {
+    match item {
+        Nil => "nil".to_string(),
+        Boolean(b) => b.to_string(),
+        Unsigned(n) => n.to_string(),
+        Signed(n) => n.to_string(),
+        Float(f) => f.to_string(),
+        Str(_) => "String".to_string(),
+        Bin(_) => "Bin".to_string(),
+        Ext(_) => "Ext".to_string(),
+        Array(_) => "Array".to_string(),
+        Map(_) => "Map".to_string(),
+    }
+}
+
+fn variant_index(names: &[&str], name: string::String) -> DecodeResult<uint> {
+    match names.iter().position(|n| *n == name.as_slice()) {
+        Some(idx) => Ok(idx),
+        None => Err(UnknownVariantError(name)),
+    }
+}
+
+// Decodes missing struct fields, in case they are options.
+static NIL_VALUE: &'static [u8] = b"\xc0";
+
+/// How deeply the values that the decoder skips, such as the values of
+/// unknown struct fields, may be nested. Skipping is recursive, so this bounds
+/// the stack used for untrusted data.
+pub const MAX_DEPTH: uint = 512;
+
+// A struct being read.
+struct StructMap {
+    // Where its members start, and how many there are.
+    start: uint,
+    len: u64,
+    // The names of the fields read from it so far.
+    fields: Vec<string::String>,
+}
+
+/// A structure to decode MessagePack values to values in rust.
+pub struct Decoder<'a> {
+    data: &'a [u8],
+    pos: uint,
+    // The number of values left in each array and map being read.
+    remaining: Vec<u64>,
+    structs: Vec<StructMap>,
+    // The binary being read as a sequence, and how many of its bytes have
+    // been read.
+    bytes: Option<(&'a [u8], uint)>,
+    // The byte of that binary to read next, as an unsigned integer.
+    byte: Option<u8>,
+}
+
+impl<'a> Decoder<'a> {
+    /// Creates a new decoder for the values in `data`.
+    pub fn new(data: &'a [u8]) -> Decoder<'a> {
+        Decoder {
+            data: data,
+            pos: 0,
+            remaining: Vec::new(),
+            structs: Vec::new(),
+            bytes: None,
+            byte: None,
+        }
+    }
+
+    /// Returns the offset in the data of the next value to decode.
+    pub fn position(&self) -> uint {
+        self.pos
+    }
+
+    fn read_bytes(&mut self, n: u64) -> DecodeResult<&'a [u8]> {
+        if n > (self.data.len() - self.pos) as u64 {
+            return Err(EndOfData);
+        }
+        let data = self.data;
+        let start = self.pos;
+        self.pos += n as uint;
+        Ok(data[start..self.pos])
+    }
+
+    fn read_be(&mut self, n: u64) -> DecodeResult<u64> {
+        let bytes = try!(self.read_bytes(n));
+        Ok(bytes.iter().fold(0, |n, &b| (n << 8) | b as u64))
+    }
+
+    // Reads the marker of the next value and the length or value after it,
+    // leaving the contents of strings, binaries and extensions.
+    fn read_item(&mut self) -> DecodeResult<Item> {
+        if let Some(b) = self.byte.take() {
+            return Ok(Unsigned(b as u64));
+        }
+        let start = self.pos;
+        let marker = try!(self.read_be(1)) as u8;
+        Ok(match marker {
+            0x00 ... 0x7f => Unsigned(marker as u64),
+            0x80 ... 0x8f => Map((marker & 0x0f) as u64),
+            0x90 ... 0x9f => Array((marker & 0x0f) as u64),
+            0xa0 ... 0xbf => Str((marker & 0x1f) as u64),
+            NIL => Nil,
+            FALSE => Boolean(false),
+            TRUE => Boolean(true),
+            0xc4 => Bin(try!(self.read_be(1))),
+            0xc5 => Bin(try!(self.read_be(2))),
+            0xc6 => Bin(try!(self.read_be(4))),
+            // Extensions have a length and a type, that is skipped with them.
+            0xc7 => Ext(try!(self.read_be(1)) + 1),
+            0xc8 => Ext(try!(self.read_be(2)) + 1),
+            0xc9 => Ext(try!(self.read_be(4)) + 1),
+            FLOAT32 => {
+                let bits = try!(self.read_be(4)) as u32;
+                Float(unsafe { transmute::<u32, f32>(bits) } as f64)
+            }
+            FLOAT64 => Float(unsafe { transmute::<u64, f64>(try!(self.read_be(8))) }),
+            UINT8 => Unsigned(try!(self.read_be(1))),
+            UINT16 => Unsigned(try!(self.read_be(2))),
+            UINT32 => Unsigned(try!(self.read_be(4))),
+            UINT64 => Unsigned(try!(self.read_be(8))),
+            INT8 => Signed(try!(self.read_be(1)) as i8 as i64),
+            INT16 => Signed(try!(self.read_be(2)) as i16 as i64),
+            INT32 => Signed(try!(self.read_be(4)) as i32 as i64),
+            INT64 => Signed(try!(self.read_be(8)) as i64),
+            0xd4 => Ext(2),
+            0xd5 => Ext(3),
+            0xd6 => Ext(5),
+            0xd7 => Ext(9),
+            0xd8 => Ext(17),
+            STR8 => Str(try!(self.read_be(1))),
+            STR16 => Str(try!(self.read_be(2))),
+            STR32 => Str(try!(self.read_be(4))),
+            ARRAY16 => Array(try!(self.read_be(2))),
+            ARRAY32 => Array(try!(self.read_be(4))),
+            MAP16 => Map(try!(self.read_be(2))),
+            MAP32 => Map(try!(self.read_be(4))),
+            0xe0 ... 0xff => Signed(marker as i8 as i64),
+            _ => return Err(InvalidData(start)),
+        })
+    }
+
+    fn peek_item(&mut self) -> DecodeResult<Item> {
+        let (pos, byte) = (self.pos, self.byte);
+        let item = self.read_item();
+        self.pos = pos;
+        self.byte = byte;
+        item
+    }
+
+    fn skip_item(&mut self) -> DecodeResult<()> {
+        self.skip_nested(0)
+    }
+
+    // Skips a value nested `depth` levels deep in the value being skipped.
+    fn skip_nested(&mut self, depth: uint) -> DecodeResult<()> {
+        if depth > MAX_DEPTH {
+            return Err(TooDeeplyNested(self.pos));
+        }
+        match try!(self.read_item()) {
+            Str(n) | Bin(n) | Ext(n) => { try!(self.read_bytes(n)); }
+            Array(n) => {
+                for _ in range(0, n) {
+                    try!(self.skip_nested(depth + 1));
+                }
+            }
+            Map(n) => {
+                for _ in range(0, n) {
+                    try!(self.skip_nested(depth + 1));
+                    try!(self.skip_nested(depth + 1));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn expected<T>(&self, name: &str, item: Item) -> DecodeResult<T> {
+        Err(ExpectedError(name.to_string(), describe(item)))
+    }
+
+    // Checks that a length isn't more than the rest of the data could hold,
+    // with values of at least `size` bytes.
+    fn check_len(&self, len: u64, size: u64) -> DecodeResult<()> {
+        if len > (self.data.len() - self.pos) as u64 / size {
+            Err(EndOfData)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn read_array(&mut self) -> DecodeResult<u64> {
+        match try!(self.read_item()) {
+            Array(len) => {
+                try!(self.check_len(len, 1));
+                Ok(len)
+            }
+            item => self.expected("Array", item),
+        }
+    }
+
+    fn read_map_len(&mut self) -> DecodeResult<u64> {
+        match try!(self.read_item()) {
+            Map(len) => {
+                try!(self.check_len(len, 2));
+                Ok(len)
+            }
+            item => self.expected("Map", item),
+        }
+    }
+
+    // Reads the values of an array or a map with `f`, then skips the ones it
+    // didn't read.
+    fn read_items<T, F>(&mut self, len: u64, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        self.remaining.push(len);
+        let value = f(self);
+        let left = self.remaining.pop().unwrap();
+        let value = try!(value);
+        for _ in range(0, left) {
+            try!(self.skip_item());
+        }
+        Ok(value)
+    }
+
+    // Reads the next value of the innermost array or map.
+    fn read_next<T, F>(&mut self, end: &str, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        let top = try!(self.top_items());
+        let left = self.remaining[top];
+        if left == 0 {
+            return Err(ExpectedError("Value".to_string(), end.to_string()));
+        }
+        self.remaining[top] = left - 1;
+        f(self)
+    }
+
+    fn has_next(&self) -> DecodeResult<bool> {
+        Ok(self.remaining[try!(self.top_items())] > 0)
+    }
+
+    // Returns the index of the innermost array or map, failing when its
+    // values are read outside of `read_seq`, `read_map` and the like.
+    fn top_items(&self) -> DecodeResult<uint> {
+        match self.remaining.len() {
+            0 => Err(ExpectedError("Array or Map".to_string(), "no Array or Map".to_string())),
+            n => Ok(n - 1),
+        }
+    }
+
+    // Returns the innermost struct, failing when its fields are read outside
+    // of `read_struct`.
+    fn top_struct(&mut self) -> DecodeResult<&mut StructMap> {
+        match self.structs.last_mut() {
+            Some(s) => Ok(s),
+            None => Err(ExpectedError("Map".to_string(), "no Map".to_string())),
+        }
+    }
+
+    // Reads a struct member key, returning whether it is `name`.
+    fn key_is(&mut self, name: &str) -> DecodeResult<bool> {
+        let pos = self.pos;
+        match try!(self.read_item()) {
+            Str(n) => Ok(try!(self.read_bytes(n)) == name.as_bytes()),
+            _ => {
+                self.pos = pos;
+                try!(self.skip_item());
+                Ok(false)
+            }
+        }
+    }
+
+    // Finds the key of a member of the innermost struct that wasn't read as
+    // a field, leaving the position anywhere in the struct.
+    fn unknown_field(&mut self) -> DecodeResult<Option<string::String>> {
+        let (start, len) = {
+            let s = try!(self.top_struct());
+            (s.start, s.len)
+        };
+        self.pos = start;
+        for _ in range(0, len) {
+            let key = match try!(self.peek_item()) {
+                Str(_) => try!(::Decoder::read_str(self)),
+                item => return Ok(Some(describe(item))),
+            };
+            if !try!(self.top_struct()).fields.contains(&key) {
+                return Ok(Some(key));
+            }
+            try!(self.skip_item());
+        }
+        Ok(None)
+    }
+}
+
+macro_rules! read_integer {
+    ($name:ident, $ty:ty) => {
+        fn $name(&mut self) -> DecodeResult<$ty> {
+            let item = try!(self.read_item());
+            let value = match item {
+                Unsigned(n) => num::cast(n),
+                Signed(n) => num::cast(n),
+                _ => return self.expected("Integer", item),
+            };
+            match value {
+                Some(v) => Ok(v),
+                None => self.expected(stringify!($ty), item),
+            }
+        }
+    }
+}
+
+impl<'a> ::Decoder<DecoderError> for Decoder<'a> {
+    fn read_nil(&mut self) -> DecodeResult<()> {
+        match try!(self.read_item()) {
+            Nil => Ok(()),
+            item => self.expected("Nil", item),
+        }
+    }
+
+    read_integer! { read_uint, uint }
+    read_integer! { read_u8, u8 }
+    read_integer! { read_u16, u16 }
+    read_integer! { read_u32, u32 }
+    read_integer! { read_u64, u64 }
+    read_integer! { read_int, int }
+    read_integer! { read_i8, i8 }
+    read_integer! { read_i16, i16 }
+    read_integer! { read_i32, i32 }
+    read_integer! { read_i64, i64 }
+
+    fn read_f32(&mut self) -> DecodeResult<f32> { self.read_f64().map(|x| x as f32) }
+
+    fn read_f64(&mut self) -> DecodeResult<f64> {
+        match try!(self.read_item()) {
+            Unsigned(n) => Ok(n as f64),
+            Signed(n) => Ok(n as f64),
+            Float(f) => Ok(f),
+            item => self.expected("Number", item),
+        }
+    }
+
+    fn read_bool(&mut self) -> DecodeResult<bool> {
+        match try!(self.read_item()) {
+            Boolean(b) => Ok(b),
+            item => self.expected("Boolean", item),
+        }
+    }
+
+    fn read_char(&mut self) -> DecodeResult<char> {
+        let s = try!(self.read_str());
+        {
+            let mut it = s.chars();
+            match (it.next(), it.next()) {
+                // exactly one character
+                (Some(c), None) => return Ok(c),
+                _ => ()
+            }
+        }
+        Err(ExpectedError("single character string".to_string(), s))
+    }
+
+    fn read_str(&mut self) -> DecodeResult<string::String> {
+        match try!(self.read_item()) {
+            Str(n) => {
+                let start = self.pos;
+                let bytes = try!(self.read_bytes(n));
+                match std::str::from_utf8(bytes) {
+                    Some(s) => Ok(s.to_string()),
+                    None => Err(InvalidUtf8(start)),
+                }
+            }
+            item => self.expected("String", item),
+        }
+    }
+
+    fn read_enum<T, F>(&mut self, _name: &str, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_enum_variant<T, F>(&mut self, names: &[&str],
+                               mut f: F) -> DecodeResult<T>
+        where F: FnMut(&mut Decoder<'a>, uint) -> DecodeResult<T>,
+    {
+        match try!(self.peek_item()) {
+            Str(_) => {
+                let idx = try!(variant_index(names, try!(self.read_str())));
+                f(self, idx)
+            }
+            Array(_) => {
+                let len = try!(self.read_array());
+                self.read_items(len, |d| {
+                    let name = try!(d.read_next("end of Array", |d| d.read_str()));
+                    let idx = try!(variant_index(names, name));
+                    f(d, idx)
+                })
+            }
+            item => self.expected("String or Array", item),
+        }
+    }
+
+    fn read_enum_variant_arg<T, F>(&mut self, _idx: uint, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        self.read_next("end of Array", f)
+    }
+
+    fn read_enum_struct_variant<T, F>(&mut self, names: &[&str], f: F) -> DecodeResult<T> where
+        F: FnMut(&mut Decoder<'a>, uint) -> DecodeResult<T>,
+    {
+        self.read_enum_variant(names, f)
+    }
+
+    fn read_enum_struct_variant_field<T, F>(&mut self,
+                                         _name: &str,
+                                         idx: uint,
+                                         f: F)
+                                         -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        self.read_enum_variant_arg(idx, f)
+    }
+
+    fn read_struct<T, F>(&mut self, _name: &str, _len: uint, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        let len = try!(self.read_map_len());
+        // Fields are looked up by name, so find the end of the map first to
+        // be able to move past it when they have all been read.
+        let start = self.pos;
+        for _ in range(0, len * 2) {
+            try!(self.skip_item());
+        }
+        let end = self.pos;
+        self.structs.push(StructMap { start: start, len: len, fields: Vec::new() });
+        let value = f(self);
+        self.structs.pop();
+        let value = try!(value);
+        self.pos = end;
+        Ok(value)
+    }
+
+    fn read_struct_field<T, F>(&mut self,
+                               name: &str,
+                               _idx: uint,
+                               f: F)
+                               -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        let (start, len) = {
+            let s = try!(self.top_struct());
+            s.fields.push(name.to_string());
+            (s.start, s.len)
+        };
+        self.pos = start;
+        for _ in range(0, len) {
+            if try!(self.key_is(name)) {
+                return f(self);
+            }
+            try!(self.skip_item());
+        }
+        // Decode a nil as an Option<_> to get None as a default value.
+        let (data, pos) = (self.data, self.pos);
+        self.data = NIL_VALUE;
+        self.pos = 0;
+        let value = f(self);
+        self.data = data;
+        self.pos = pos;
+        value.map_err(|_| MissingFieldError(name.to_string()))
+    }
+
+    fn deny_unknown_fields(&mut self) -> DecodeResult<()> {
+        let pos = self.pos;
+        let unknown = self.unknown_field();
+        self.pos = pos;
+        match try!(unknown) {
+            Some(key) => Err(UnknownFieldError(key)),
+            None => Ok(()),
+        }
+    }
+
+    fn read_tuple<T, F>(&mut self, tuple_len: uint, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        let len = try!(self.read_array());
+        if len != tuple_len as u64 {
+            return Err(ExpectedError(format!("Tuple{}", tuple_len), format!("Tuple{}", len)));
+        }
+        self.read_items(len, f)
+    }
+
+    fn read_tuple_arg<T, F>(&mut self, idx: uint, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        self.read_seq_elt(idx, f)
+    }
+
+    fn read_tuple_struct<T, F>(&mut self,
+                               _name: &str,
+                               len: uint,
+                               f: F)
+                               -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        self.read_tuple(len, f)
+    }
+
+    fn read_tuple_struct_arg<T, F>(&mut self,
+                                   idx: uint,
+                                   f: F)
+                                   -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        self.read_tuple_arg(idx, f)
+    }
+
+    fn read_option<T, F>(&mut self, mut f: F) -> DecodeResult<T> where
+        F: FnMut(&mut Decoder<'a>, bool) -> DecodeResult<T>,
+    {
+        match try!(self.peek_item()) {
+            Nil => {
+                self.pos += 1;
+                f(self, false)
+            }
+            _ => f(self, true),
+        }
+    }
+
+    fn read_seq<T, F>(&mut self, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>, uint) -> DecodeResult<T>,
+    {
+        // A binary is a sequence of bytes, so that a `Vec<u8>` can be read
+        // from one.
+        if let Bin(len) = try!(self.peek_item()) {
+            try!(self.read_item());
+            let bytes = try!(self.read_bytes(len));
+            let outer = replace(&mut self.bytes, Some((bytes, 0)));
+            let value = f(self, bytes.len());
+            self.bytes = outer;
+            return value;
+        }
+        let len = try!(self.read_array());
+        self.read_items(len, move |d| f(d, len as uint))
+    }
+
+    fn read_seq_elt<T, F>(&mut self, _idx: uint, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        let byte = match self.bytes {
+            Some((bytes, ref mut read)) => {
+                if *read == bytes.len() {
+                    return Err(ExpectedError("Value".to_string(), "end of Bin".to_string()));
+                }
+                *read += 1;
+                Some(bytes[*read - 1])
+            }
+            None => None,
+        };
+        match byte {
+            Some(b) => {
+                self.byte = Some(b);
+                let value = f(self);
+                self.byte = None;
+                value
+            }
+            None => self.read_next("end of Array", f),
+        }
+    }
+
+    fn read_seq_has_elt(&mut self, _idx: uint, _len: uint) -> DecodeResult<bool> {
+        match self.bytes {
+            Some((bytes, read)) => Ok(read < bytes.len()),
+            None => self.has_next(),
+        }
+    }
+
+    fn read_map<T, F>(&mut self, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder<'a>, uint) -> DecodeResult<T>,
+    {
+        let len = try!(self.read_map_len());
+        self.read_items(len * 2, move |d| f(d, len as uint))
+    }
+
+    fn read_map_elt_key<T, F>(&mut self, _idx: uint, f: F) -> DecodeResult<T> where
+       F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        self.read_next("end of Map", f)
+    }
+
+    fn read_map_elt_val<T, F>(&mut self, _idx: uint, f: F) -> DecodeResult<T> where
+       F: FnOnce(&mut Decoder<'a>) -> DecodeResult<T>,
+    {
+        self.read_next("end of Map", f)
+    }
+
+    fn read_map_has_elt(&mut self, _idx: uint, _len: uint) -> DecodeResult<bool> {
+        self.has_next()
+    }
+
+    fn error(&mut self, err: &str) -> DecoderError {
+        ApplicationError(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use self::Animal::*;
+    use super::{encode, decode, Decoder, DecodeResult};
+    use super::DecoderError::*;
+    use {cbor, Encodable, Decodable};
+    use hex::{FromHex, ToHex};
+    use std::collections::{BTreeMap, HashMap};
+    use std::fmt::Show;
+    use std::io::IoError;
+    use std::{f32, f64, i8, i16, i32, i64, u64};
+
+    fn hex(s: &str) -> Vec<u8> {
+        s.replace(" ", "").as_slice().from_hex().unwrap()
+    }
+
+    fn check_encode<'a, T: Encodable<super::Encoder<'a>, IoError>>(value: T, expected: &str) {
+        assert_eq!(encode(&value).to_hex(), expected.replace(" ", ""));
+    }
+
+    fn check<T: PartialEq + Show>(value: DecodeResult<T>, expected: T) {
+        assert_eq!(value, Ok(expected));
+    }
+
+    macro_rules! check_decode {
+        ($data:expr, $expected:expr) => (check(decode(hex($data).as_slice()), $expected))
+    }
+
+    #[deriving(PartialEq, Encodable, Decodable, Show)]
+    enum Animal {
+        Dog,
+        Frog(String, int)
+    }
+
+    #[deriving(PartialEq, Encodable, Decodable, Show)]
+    struct Inner {
+        a: (),
+        b: uint,
+        c: Vec<String>,
+    }
+
+    #[deriving(PartialEq, Encodable, Decodable, Show)]
+    struct Outer {
+        inner: Vec<Inner>,
+        name: Option<String>,
+        animals: HashMap<String, Animal>,
+        pairs: Vec<(i8, char, f32)>,
+        floats: (f64, f64),
+    }
+
+    fn outer() -> Outer {
+        let mut animals = HashMap::new();
+        animals.insert("dog".to_string(), Dog);
+        animals.insert("frog".to_string(), Frog("Henry".to_string(), -349));
+        Outer {
+            inner: vec![
+                Inner { a: (), b: 0, c: vec![] },
+                Inner { a: (), b: 1 << 40, c: vec!["abc".to_string(), "δ".to_string()] },
+            ],
+            name: Some("outer".to_string()),
+            animals: animals,
+            pairs: vec![(-128, 'x', 1.5), (127, 'ü', f32::MAX)],
+            floats: (f64::NEG_INFINITY, -0.0),
+        }
+    }
+
+    #[test]
+    fn test_encode_vectors() {
+        check_encode(0u, "00");
+        check_encode(127u8, "7f");
+        check_encode(128u16, "cc80");
+        check_encode(255u32, "ccff");
+        check_encode(256u64, "cd0100");
+        check_encode(65535u, "cdffff");
+        check_encode(65536u, "ce00010000");
+        check_encode(4294967295u32, "ceffffffff");
+        check_encode(4294967296u64, "cf0000000100000000");
+        check_encode(u64::MAX, "cfffffffffffffffff");
+        check_encode(1i, "01");
+        check_encode(-1i, "ff");
+        check_encode(-32i8, "e0");
+        check_encode(-33i8, "d0df");
+        check_encode(i8::MIN, "d080");
+        check_encode(-129i16, "d1ff7f");
+        check_encode(i16::MIN, "d18000");
+        check_encode(-32769i32, "d2ffff7fff");
+        check_encode(i32::MIN, "d280000000");
+        check_encode(-2147483649i64, "d3ffffffff7fffffff");
+        check_encode(i64::MIN, "d38000000000000000");
+        check_encode(1.5f32, "ca3fc00000");
+        check_encode(1.1f64, "cb3ff199999999999a");
+        check_encode(false, "c2");
+        check_encode(true, "c3");
+        check_encode((), "c0");
+        check_encode(None::<uint>, "c0");
+        check_encode("".to_string(), "a0");
+        check_encode("a".to_string(), "a161");
+        check_encode("a".repeat(31), format!("bf{}", "61".repeat(31)).as_slice());
+        check_encode("a".repeat(32), format!("d920{}", "61".repeat(32)).as_slice());
+        check_encode("a".repeat(256), format!("da0100{}", "61".repeat(256)).as_slice());
+        check_encode('ü', "a2c3bc");
+        check_encode(Vec::<uint>::new(), "90");
+        check_encode(vec![1u, 2, 3], "93010203");
+        check_encode(range(0u, 15).collect::<Vec<uint>>(),
+                     "9f000102030405060708090a0b0c0d0e");
+        check_encode(range(0u, 16).collect::<Vec<uint>>(),
+                     "dc0010000102030405060708090a0b0c0d0e0f");
+        check_encode(BTreeMap::<uint, uint>::new(), "80");
+        let mut map = BTreeMap::new();
+        map.insert(1u, 2u);
+        map.insert(3, 4);
+        check_encode(map, "8201020304");
+        let map: BTreeMap<uint, ()> = range(0u, 16).map(|i| (i, ())).collect();
+        check_encode(map, format!("de0010{}", range(0u, 16).map(|i| {
+            format!("{:02x}c0", i)
+        }).collect::<Vec<String>>().concat()).as_slice());
+    }
+
+    #[test]
+    fn test_encode_compound() {
+        check_encode(Dog, "a3 446f67");
+        check_encode(Frog("Henry".to_string(), 349), "93 a446726f67 a548656e7279 cd015d");
+        check_encode(Inner { a: (), b: 2, c: vec!["x".to_string()] },
+                     "83 a161c0 a16202 a16391a178");
+    }
+
+    #[test]
+    fn test_decode_vectors() {
+        check_decode!("7f", 127u8);
+        check_decode!("cc80", 128u8);
+        check_decode!("cfffffffffffffffff", u64::MAX);
+        check_decode!("d38000000000000000", i64::MIN);
+        check_decode!("e0", -32i);
+        // Signed formats can hold positive values, and unsigned ones small
+        // values.
+        check_decode!("d07f", 127u8);
+        check_decode!("cf0000000000000001", 1i8);
+        check_decode!("d1ff7f", -129.0f64);
+        check_decode!("ca3fc00000", 1.5f64);
+        check_decode!("cb3ff199999999999a", 1.1f64);
+        check_decode!("c3", true);
+        check_decode!("c0", ());
+        check_decode!("c0", None::<uint>);
+        check_decode!("a2c3bc", 'ü');
+        check_decode!("d903616263", "abc".to_string());
+        check_decode!("da0003616263", "abc".to_string());
+        check_decode!("db00000003616263", "abc".to_string());
+        check_decode!("dc0002c2c3", vec![false, true]);
+        check_decode!("dd00000002c2c3", vec![false, true]);
+        check_decode!("93 01 920203 920405", (1u, vec![2u, 3], (4u, 5u)));
+        check_decode!("de0001a161a162", {
+            let mut map = HashMap::new();
+            map.insert("a".to_string(), "b".to_string());
+            map
+        });
+        check_decode!("df0000000101c0", {
+            let mut map = BTreeMap::new();
+            map.insert(1u, None::<uint>);
+            map
+        });
+    }
+
+    #[test]
+    fn test_decode_struct() {
+        #[deriving(Decodable, PartialEq, Show)]
+        struct Point {
+            x: int,
+            y: int,
+            z: Option<int>,
+        }
+        check_decode!("82 a179ff a17801", Point { x: 1, y: -1, z: None });
+        // Unknown fields are skipped, whatever their type.
+        check_decode!("86 a161c4020102 a162d501ffff a163c702030102 a17801 a16493c0c0c0 a17902",
+                      Point { x: 1, y: 2, z: None });
+    }
+
+    #[test]
+    fn test_deny_unknown_fields() {
+        #[deriving(Decodable, PartialEq, Show)]
+        #[serialize(deny_unknown_fields)]
+        struct Strict {
+            a: uint,
+            b: Option<uint>,
+        }
+        check_decode!("82 a16101 a16202", Strict { a: 1, b: Some(2) });
+        check_decode!("81 a16101", Strict { a: 1, b: None });
+        let value: DecodeResult<Strict> = decode(hex("83 a16101 a16202 a17803").as_slice());
+        assert_eq!(value, Err(UnknownFieldError("x".to_string())));
+        let value: DecodeResult<Strict> = decode(hex("82 a16101 0102").as_slice());
+        assert_eq!(value, Err(UnknownFieldError("1".to_string())));
+    }
+
+    #[test]
+    fn test_skip_depth() {
+        // An unknown field holding arrays nested far too deeply to skip them
+        // recursively.
+        let mut data = hex("84 a161c0 a16200 a16390 a178");
+        let start = data.len();
+        data.grow(100000, 0x91);
+        data.push(0x00);
+        let value: DecodeResult<Inner> = decode(data.as_slice());
+        assert_eq!(value, Err(TooDeeplyNested(start + super::MAX_DEPTH + 1)));
+
+        // Up to the limit, nesting is fine.
+        let mut data = hex("84 a161c0 a16200 a16390 a178");
+        data.grow(super::MAX_DEPTH, 0x91);
+        data.push(0x00);
+        let value: DecodeResult<Inner> = decode(data.as_slice());
+        assert_eq!(value, Ok(Inner { a: (), b: 0, c: vec![] }));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let value = outer();
+        check(decode(encode(&value).as_slice()), value);
+
+        let long = range(0u, 70000).map(|i| i.to_string()).collect::<Vec<String>>();
+        check(decode(encode(&long).as_slice()), long);
+    }
+
+    #[test]
+    fn test_cbor_interop() {
+        // Both formats agree on how values are laid out, so a value read from
+        // one can be written to the other and back.
+        let value = outer();
+        let from_cbor: Outer = cbor::decode(cbor::encode(&value).as_slice()).unwrap();
+        let from_msgpack: Outer = decode(encode(&from_cbor).as_slice()).unwrap();
+        assert_eq!(from_msgpack, value);
+    }
+
+    #[test]
+    fn test_decode_bytes() {
+        // Binaries can be read as sequences of bytes.
+        check_decode!("c40401020304", vec![1u8, 2, 3, 4]);
+        check_decode!("c400", Vec::<u8>::new());
+        check_decode!("c5000101", vec![1u8]);
+        check_decode!("92 c40101 920203", (vec![1u8], vec![2u8, 3]));
+        let value: DecodeResult<Vec<String>> = decode(hex("c40101").as_slice());
+        assert_eq!(value, Err(ExpectedError("String".to_string(), "1".to_string())));
+        let value: DecodeResult<Vec<u8>> = decode(hex("c40501").as_slice());
+        assert_eq!(value, Err(EndOfData));
+    }
+
+    #[test]
+    fn test_decode_outside_container() {
+        let data = hex("81a16101");
+        let mut d = Decoder::new(data.as_slice());
+        let value: DecodeResult<uint> = ::Decoder::read_struct_field(&mut d, "a", 0, |d| {
+            Decodable::decode(d)
+        });
+        assert_eq!(value, Err(ExpectedError("Map".to_string(), "no Map".to_string())));
+        let value: DecodeResult<uint> = ::Decoder::read_seq_elt(&mut d, 0, |d| {
+            Decodable::decode(d)
+        });
+        assert_eq!(value, Err(ExpectedError("Array or Map".to_string(),
+                                            "no Array or Map".to_string())));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        fn check_err(data: &str, expected: super::DecoderError) {
+            let data = hex(data);
+            let value: DecodeResult<Inner> = decode(data.as_slice());
+            assert_eq!(value, Err(expected));
+        }
+        check_err("", EndOfData);
+        check_err("83", EndOfData);
+        check_err("c1", InvalidData(0));
+        check_err("90", ExpectedError("Map".to_string(), "Array".to_string()));
+        check_err("81 a161c0", MissingFieldError("b".to_string()));
+        check_err("81 a16101", ExpectedError("Nil".to_string(), "1".to_string()));
+        check_err("83 a161c0 a162ff a16390", ExpectedError("uint".to_string(), "-1".to_string()));
+        check_err("83 a161c0 a16200 a16391 a2c328", InvalidUtf8(11));
+        check_err("83 a161c0 a16200 a16390 00", TrailingData(10));
+        check_err("83 a161c0 a16200 a16391 c40101",
+                  ExpectedError("String".to_string(), "Bin".to_string()));
+
+        let value: DecodeResult<Animal> = decode(hex("a3436174").as_slice());
+        assert_eq!(value, Err(UnknownVariantError("Cat".to_string())));
+        let value: DecodeResult<Animal> = decode(hex("91 a446726f67").as_slice());
+        assert_eq!(value, Err(ExpectedError("Value".to_string(), "end of Array".to_string())));
+        let value: DecodeResult<(uint, uint)> = decode(hex("93010203").as_slice());
+        assert_eq!(value, Err(ExpectedError("Tuple2".to_string(), "Tuple3".to_string())));
+        let value: DecodeResult<u8> = decode(hex("cd0100").as_slice());
+        assert_eq!(value, Err(ExpectedError("u8".to_string(), "256".to_string())));
+        // A length that can't be right isn't used to allocate memory.
+        let value: DecodeResult<Vec<uint>> = decode(hex("ddffffffff00").as_slice());
+        assert_eq!(value, Err(EndOfData));
+    }
+}
@@ -141,6 +141,14 @@ pub trait Decoder<E> {
                                f: F)
                                -> Result<T, E> where
         F: FnOnce(&mut Self) -> Result<T, E>;
+    /// Checks that the struct being read has no fields besides the ones that
+    /// were read from it. `#[deriving(Decodable)]` calls this after reading the
+    /// fields of a struct with the `#[serialize(deny_unknown_fields)]` option.
+    ///
+    /// Decoders that can't tell which fields a struct has accept any.
+    fn deny_unknown_fields(&mut self) -> Result<(), E> {
+        Ok(())
+    }
 
     fn read_tuple<T, F>(&mut self, len: uint, f: F) -> Result<T, E> where
         F: FnOnce(&mut Self) -> Result<T, E>;
@@ -160,6 +168,15 @@ pub trait Decoder<E> {
         F: FnOnce(&mut Self, uint) -> Result<T, E>;
     fn read_seq_elt<T, F>(&mut self, idx: uint, f: F) -> Result<T, E> where
         F: FnOnce(&mut Self) -> Result<T, E>;
+    /// Returns whether the sequence being read has an element at `idx`, where
+    /// `len` is the length that was passed to the closure given to `read_seq`.
+    ///
+    /// Decoders that only learn the length of a sequence by reading all of it
+    /// pass a length of zero to `read_seq`'s closure and override this method
+    /// instead, so the length is just a hint for preallocation.
+    fn read_seq_has_elt(&mut self, idx: uint, len: uint) -> Result<bool, E> {
+        Ok(idx < len)
+    }
 
     fn read_map<T, F>(&mut self, f: F) -> Result<T, E> where
         F: FnOnce(&mut Self, uint) -> Result<T, E>;
@@ -167,6 +184,10 @@ pub trait Decoder<E> {
         F: FnOnce(&mut Self) -> Result<T, E>;
     fn read_map_elt_val<T, F>(&mut self, idx: uint, f: F) -> Result<T, E> where
         F: FnOnce(&mut Self) -> Result<T, E>;
+    /// Like `read_seq_has_elt`, but for maps.
+    fn read_map_has_elt(&mut self, idx: uint, len: uint) -> Result<bool, E> {
+        Ok(idx < len)
+    }
 
     // Failure
     fn error(&mut self, err: &str) -> E;
@@ -443,8 +464,10 @@ impl<E, D:Decoder<E>,T:Decodable<D, E>> Decodable<D, E> for Vec<T> {
     fn decode(d: &mut D) -> Result<Vec<T>, E> {
         d.read_seq(|d, len| {
             let mut v = Vec::with_capacity(len);
-            for i in range(0, len) {
+            let mut i = 0;
+            while try!(d.read_seq_has_elt(i, len)) {
                 v.push(try!(d.read_seq_elt(i, |d| Decodable::decode(d))));
+                i += 1;
             }
             Ok(v)
         })
@@ -620,8 +643,10 @@ impl<E, D:Decoder<E>> DecoderHelpers<E> for D {
     {
         self.read_seq(|this, len| {
             let mut v = Vec::with_capacity(len);
-            for i in range(0, len) {
+            let mut i = 0;
+            while try!(this.read_seq_has_elt(i, len)) {
                 v.push(try!(this.read_seq_elt(i, |this| f(this))));
+                i += 1;
             }
             Ok(v)
         })
@@ -0,0 +1,1994 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![forbid(non_camel_case_types)]
+#![allow(missing_docs)]
+
+//! TOML parsing and serialization
+//!
+//! # What is TOML?
+//!
+//! TOML (Tom's Obvious, Minimal Language) is a format for configuration
+//! files. A document is a table of key/value pairs, split into sections
+//! that name sub-tables:
+//!
+//! ```ignore
+//! # The port the server listens on.
+//! port = 8080
+//! hosts = ["alpha", "omega"]
+//!
+//! [database]
+//! path = '/var/lib/db'
+//! timeout = 2.5
+//!
+//! [[users]]
+//! name = "Amy"
+//!
+//! [[users]]
+//! name = "Bob"
+//! ```
+//!
+//! Values are strings, 64-bit integers, floats, booleans, RFC 3339 dates and
+//! times, arrays whose elements all have the same type, and tables, which
+//! can also be written inline as `{ x = 1, y = 2 }`.
+//!
+//! # Rust Type-based Encoding and Decoding
+//!
+//! Like the `json` module, this module provides an `Encoder` and a `Decoder`
+//! so that any type deriving `Encodable` and `Decodable` can be written to
+//! and read from TOML, which makes it easy to load configuration structs:
+//!
+//! ```rust
+//! extern crate serialize;
+//! use serialize::toml;
+//!
+//! #[deriving(Decodable, Encodable, PartialEq, Show)]
+//! pub struct Database {
+//!     path: String,
+//!     timeout: Option<f64>,
+//! }
+//!
+//! #[deriving(Decodable, Encodable, PartialEq, Show)]
+//! pub struct Config {
+//!     port: u16,
+//!     hosts: Vec<String>,
+//!     database: Database,
+//! }
+//!
+//! fn main() {
+//!     let config: Config = toml::decode(r#"
+//!         port = 8080
+//!         hosts = ["alpha", "omega"]
+//!
+//!         [database]
+//!         path = "/var/lib/db"
+//!     "#).unwrap();
+//!     assert_eq!(config.database.timeout, None);
+//!
+//!     let text = toml::encode(&config).unwrap();
+//!     assert_eq!(toml::decode::<Config>(text.as_slice()).unwrap(), config);
+//! }
+//! ```
+//!
+//! Structs and maps are tables, and sequences and tuples are arrays. `()` and
+//! `None` are left out of tables, since TOML has no null value. Enum variants
+//! are encoded as their name if they have no fields, and as a table like
+//! `{ variant = "Kangaroo", fields = [34, 27] }` otherwise.
+
+use self::ErrorCode::*;
+use self::ParserError::*;
+use self::DecoderError::*;
+
+use std;
+use std::char;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::io;
+use std::mem;
+use std::num;
+use std::num::Float;
+use std::str::{mod, FromStr};
+use std::string;
+
+use Encodable;
+
+/// Represents a TOML value
+#[deriving(Clone, PartialEq, PartialOrd)]
+pub enum Toml {
+    String(string::String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    /// An RFC 3339 date and time, like `1979-05-27T07:32:00Z`.
+    Datetime(string::String),
+    Array(self::Array),
+    Table(self::Table),
+}
+
+pub type Array = Vec<Toml>;
+pub type Table = BTreeMap<string::String, Toml>;
+
+/// The errors that can arise while parsing a TOML document.
+#[deriving(Clone, Copy, PartialEq)]
+pub enum ErrorCode {
+    ExpectedKey,
+    ExpectedEquals,
+    ExpectedValue,
+    ExpectedNewline,
+    ExpectedComma,
+    ExpectedBracket,
+    InvalidNumber,
+    InvalidDatetime,
+    InvalidEscape,
+    InvalidUnicodeCodePoint,
+    NewlineInString,
+    EOFWhileParsingString,
+    EOFWhileParsingArray,
+    EOFWhileParsingTable,
+    EOFWhileParsingValue,
+    MixedArray,
+    DuplicateKey,
+    DuplicateTable,
+    NotATable,
+    NotAnArrayOfTables,
+    NotUtf8,
+}
+
+#[deriving(Clone, Copy, PartialEq, Show)]
+pub enum ParserError {
+    /// msg, line, col
+    SyntaxError(ErrorCode, uint, uint),
+    IoError(io::IoErrorKind, &'static str),
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum DecoderError {
+    ParseError(ParserError),
+    ExpectedError(string::String, string::String),
+    MissingFieldError(string::String),
+    UnknownVariantError(string::String),
+    UnknownFieldError(string::String),
+    ApplicationError(string::String),
+}
+
+/// The ways a value can fail to be encoded as TOML.
+#[deriving(Clone, Copy, PartialEq, Show)]
+pub enum EncoderError {
+    /// A `()` or a `None` is in an array, where it can't be left out.
+    MissingValue,
+    /// The elements of an array don't all have the same type.
+    MixedArray,
+    /// A map key isn't a string, a number or a boolean.
+    KeyMustBeAString,
+    /// An unsigned integer doesn't fit in the 64-bit signed integers of TOML.
+    IntegerOutOfRange,
+    /// The value to encode as a document isn't a table.
+    NotATable,
+}
+
+/// Returns a readable error string for a given error code.
+pub fn error_str(error: ErrorCode) -> &'static str {
+    match error {
+        ExpectedKey => "expected a key",
+        ExpectedEquals => "expected `=`",
+        ExpectedValue => "expected a value",
+        ExpectedNewline => "expected a new line",
+        ExpectedComma => "expected `,`",
+        ExpectedBracket => "expected `]`",
+        InvalidNumber => "invalid number",
+        InvalidDatetime => "invalid date and time",
+        InvalidEscape => "invalid escape",
+        InvalidUnicodeCodePoint => "invalid Unicode code point",
+        NewlineInString => "new line in a single-line string",
+        EOFWhileParsingString => "EOF While parsing string",
+        EOFWhileParsingArray => "EOF While parsing array",
+        EOFWhileParsingTable => "EOF While parsing inline table",
+        EOFWhileParsingValue => "EOF While parsing value",
+        MixedArray => "array elements of different types",
+        DuplicateKey => "duplicate key",
+        DuplicateTable => "table defined twice",
+        NotATable => "key is not a table",
+        NotAnArrayOfTables => "key is not an array of tables",
+        NotUtf8 => "contents not utf-8",
+    }
+}
+
+impl fmt::Show for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        error_str(*self).fmt(f)
+    }
+}
+
+impl std::error::Error for DecoderError {
+    fn description(&self) -> &str { "decoder error" }
+    fn detail(&self) -> Option<std::string::String> { Some(self.to_string()) }
+}
+
+impl std::error::Error for EncoderError {
+    fn description(&self) -> &str { "encoder error" }
+    fn detail(&self) -> Option<std::string::String> { Some(self.to_string()) }
+}
+
+pub type EncodeResult = Result<(), EncoderError>;
+pub type DecodeResult<T> = Result<T, DecoderError>;
+
+/// Shortcut function to decode a TOML document into an object
+pub fn decode<T: ::Decodable<Decoder, DecoderError>>(s: &str) -> DecodeResult<T> {
+    let toml = match from_str(s) {
+        Ok(toml) => toml,
+        Err(e) => return Err(ParseError(e))
+    };
+    let mut decoder = Decoder::new(toml);
+    ::Decodable::decode(&mut decoder)
+}
+
+/// Shortcut function to encode a `T` into a TOML document. `T` must be
+/// encoded as a table, like structs and maps are.
+pub fn encode<T: Encodable<Encoder, EncoderError>>(object: &T) -> Result<string::String,
+                                                                         EncoderError> {
+    let mut encoder = Encoder::new();
+    try!(object.encode(&mut encoder));
+    match encoder.unwrap() {
+        Some(toml @ Toml::Table(_)) => Ok(toml.to_string()),
+        _ => Err(EncoderError::NotATable),
+    }
+}
+
+/// Parses a TOML document from an `&mut io::Reader` into a `Toml::Table`
+pub fn from_reader(rdr: &mut io::Reader) -> Result<Toml, ParserError> {
+    let contents = match rdr.read_to_end() {
+        Ok(c)  => c,
+        Err(e) => return Err(IoError(e.kind, e.desc))
+    };
+    let s = match str::from_utf8(contents.as_slice()) {
+        Some(s) => s,
+        _       => return Err(SyntaxError(NotUtf8, 0, 0))
+    };
+    from_str(s)
+}
+
+/// Parses a TOML document from a string into a `Toml::Table`
+pub fn from_str(s: &str) -> Result<Toml, ParserError> {
+    let mut parser = Parser::new(s.chars());
+    parser.parse().map(Toml::Table)
+}
+
+impl Toml {
+    /// If the value is a table, returns the value associated with the
+    /// provided key. Otherwise, returns None.
+    pub fn find<'a>(&'a self, key: &str) -> Option<&'a Toml> {
+        match *self {
+            Toml::Table(ref table) => table.get(key),
+            _ => None
+        }
+    }
+
+    /// Looks up a value by a dotted path of keys, like `servers.alpha.ip`.
+    /// Parts of the path that are numbers index into arrays.
+    pub fn lookup<'a>(&'a self, path: &str) -> Option<&'a Toml> {
+        let mut target = self;
+        for key in path.split('.') {
+            let next = match *target {
+                Toml::Table(ref table) => table.get(key),
+                Toml::Array(ref array) => {
+                    std::str::from_str::<uint>(key).and_then(|idx| array.get(idx))
+                }
+                _ => None,
+            };
+            match next {
+                Some(value) => target = value,
+                None => return None,
+            }
+        }
+        Some(target)
+    }
+
+    /// Returns the name of the type of the value, as used in the TOML
+    /// specification.
+    pub fn type_str(&self) -> &'static str {
+        match *self {
+            Toml::String(..) => "string",
+            Toml::Integer(..) => "integer",
+            Toml::Float(..) => "float",
+            Toml::Boolean(..) => "boolean",
+            Toml::Datetime(..) => "datetime",
+            Toml::Array(..) => "array",
+            Toml::Table(..) => "table",
+        }
+    }
+
+    /// If the value is a String, returns the associated str.
+    /// Returns None otherwise.
+    pub fn as_string<'a>(&'a self) -> Option<&'a str> {
+        match *self {
+            Toml::String(ref s) => Some(s.as_slice()),
+            _ => None
+        }
+    }
+
+    /// If the value is an Integer, returns it. Returns None otherwise.
+    pub fn as_integer(&self) -> Option<i64> {
+        match *self {
+            Toml::Integer(n) => Some(n),
+            _ => None
+        }
+    }
+
+    /// If the value is a Float, returns it. Returns None otherwise.
+    pub fn as_float(&self) -> Option<f64> {
+        match *self {
+            Toml::Float(n) => Some(n),
+            _ => None
+        }
+    }
+
+    /// If the value is a Boolean, returns it. Returns None otherwise.
+    pub fn as_boolean(&self) -> Option<bool> {
+        match *self {
+            Toml::Boolean(b) => Some(b),
+            _ => None
+        }
+    }
+
+    /// If the value is a Datetime, returns its RFC 3339 representation.
+    /// Returns None otherwise.
+    pub fn as_datetime<'a>(&'a self) -> Option<&'a str> {
+        match *self {
+            Toml::Datetime(ref s) => Some(s.as_slice()),
+            _ => None
+        }
+    }
+
+    /// If the value is an Array, returns the associated vector.
+    /// Returns None otherwise.
+    pub fn as_array<'a>(&'a self) -> Option<&'a Array> {
+        match *self {
+            Toml::Array(ref array) => Some(array),
+            _ => None
+        }
+    }
+
+    /// If the value is a Table, returns the associated BTreeMap.
+    /// Returns None otherwise.
+    pub fn as_table<'a>(&'a self) -> Option<&'a Table> {
+        match *self {
+            Toml::Table(ref table) => Some(table),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Show for Toml {
+    /// Tables are written as TOML documents, with their sub-tables and
+    /// arrays of tables in sections, and other values as they would be
+    /// written after `key = `.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut out = string::String::new();
+        match *self {
+            Toml::Table(ref table) => write_table(&mut out, &mut Vec::new(), table),
+            ref value => write_inline(&mut out, value),
+        }
+        write!(f, "{}", out)
+    }
+}
+
+impl FromStr for Toml {
+    fn from_str(s: &str) -> Option<Toml> {
+        from_str(s).ok()
+    }
+}
+
+// Whether a value is written as a section, `[key]` or `[[key]]`, rather than
+// after `key = `.
+fn is_section(value: &Toml) -> bool {
+    match *value {
+        Toml::Table(_) => true,
+        Toml::Array(ref values) => {
+            !values.is_empty() && values.iter().all(|v| v.as_table().is_some())
+        }
+        _ => false,
+    }
+}
+
+fn write_table(out: &mut string::String, path: &mut Vec<string::String>, table: &Table) {
+    for (key, value) in table.iter() {
+        if !is_section(value) {
+            write_key(out, key.as_slice());
+            out.push_str(" = ");
+            write_inline(out, value);
+            out.push('\n');
+        }
+    }
+    for (key, value) in table.iter() {
+        path.push(key.clone());
+        match *value {
+            Toml::Table(ref inner) => {
+                // A table that only holds other sections doesn't need a
+                // header of its own.
+                if inner.is_empty() || !inner.values().all(is_section) {
+                    write_header(out, path.as_slice(), false);
+                }
+                write_table(out, path, inner);
+            }
+            Toml::Array(ref tables) if is_section(value) => {
+                for inner in tables.iter() {
+                    write_header(out, path.as_slice(), true);
+                    write_table(out, path, inner.as_table().unwrap());
+                }
+            }
+            _ => {}
+        }
+        path.pop();
+    }
+}
+
+fn write_header(out: &mut string::String, path: &[string::String], array: bool) {
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out.push_str(if array { "[[" } else { "[" });
+    for (i, key) in path.iter().enumerate() {
+        if i > 0 {
+            out.push('.');
+        }
+        write_key(out, key.as_slice());
+    }
+    out.push_str(if array { "]]\n" } else { "]\n" });
+}
+
+fn is_bare_key_char(c: char) -> bool {
+    match c {
+        'a' ... 'z' | 'A' ... 'Z' | '0' ... '9' | '_' | '-' => true,
+        _ => false,
+    }
+}
+
+fn write_key(out: &mut string::String, key: &str) {
+    if !key.is_empty() && key.chars().all(is_bare_key_char) {
+        out.push_str(key);
+    } else {
+        write_string(out, key);
+    }
+}
+
+fn write_string(out: &mut string::String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\x08' => out.push_str("\\b"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\x0c' => out.push_str("\\f"),
+            '\r' => out.push_str("\\r"),
+            c if c < ' ' || c == '\x7f' => {
+                out.push_str(format!("\\u{:04X}", c as u32).as_slice())
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn write_inline(out: &mut string::String, value: &Toml) {
+    match *value {
+        Toml::String(ref s) => write_string(out, s.as_slice()),
+        Toml::Integer(n) => out.push_str(n.to_string().as_slice()),
+        Toml::Float(n) => {
+            if n.is_nan() {
+                out.push_str("nan");
+            } else if n.is_infinite() {
+                out.push_str(if n > 0.0 { "inf" } else { "-inf" });
+            } else {
+                let s = n.to_string();
+                out.push_str(s.as_slice());
+                // Floats need a fractional part or an exponent.
+                if !s.as_slice().contains_char('.') && !s.as_slice().contains_char('e') {
+                    out.push_str(".0");
+                }
+            }
+        }
+        Toml::Boolean(b) => out.push_str(if b { "true" } else { "false" }),
+        Toml::Datetime(ref s) => out.push_str(s.as_slice()),
+        Toml::Array(ref values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_inline(out, value);
+            }
+            out.push(']');
+        }
+        Toml::Table(ref table) => {
+            if table.is_empty() {
+                return out.push_str("{}");
+            }
+            out.push_str("{ ");
+            for (i, (key, value)) in table.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_key(out, key.as_slice());
+                out.push_str(" = ");
+                write_inline(out, value);
+            }
+            out.push_str(" }");
+        }
+    }
+}
+
+// The identity of a table defined by a header: the keys leading to it, with
+// the index of the element for arrays of tables.
+type TablePath = Vec<(string::String, Option<uint>)>;
+
+// Finds the table named by `keys` from `table`, creating the missing ones.
+// Arrays of tables lead to their last element.
+fn descend<'a>(mut table: &'a mut Table, keys: &[string::String],
+               path: &mut TablePath) -> Result<&'a mut Table, ErrorCode> {
+    for key in keys.iter() {
+        let parent = table;
+        if !parent.contains_key(key) {
+            parent.insert(key.clone(), Toml::Table(BTreeMap::new()));
+        }
+        table = match *parent.get_mut(key).unwrap() {
+            Toml::Table(ref mut inner) => {
+                path.push((key.clone(), None));
+                inner
+            }
+            Toml::Array(ref mut array) => {
+                let idx = array.len();
+                match array.last_mut() {
+                    Some(last) => match *last {
+                        Toml::Table(ref mut inner) => {
+                            path.push((key.clone(), Some(idx - 1)));
+                            inner
+                        }
+                        _ => return Err(NotATable),
+                    },
+                    None => return Err(NotATable),
+                }
+            }
+            _ => return Err(NotATable),
+        };
+    }
+    Ok(table)
+}
+
+// Creates the table, or the new element of the array of tables, named by a
+// header.
+fn define_table(root: &mut Table, keys: &[string::String], array: bool,
+                defined: &mut HashSet<TablePath>) -> Result<(), ErrorCode> {
+    let (parents, key) = (keys[..keys.len() - 1], &keys[keys.len() - 1]);
+    let mut path = Vec::new();
+    let table = try!(descend(root, parents, &mut path));
+    let fresh = !table.contains_key(key);
+    if array {
+        if fresh {
+            table.insert(key.clone(), Toml::Array(Vec::new()));
+        }
+        match *table.get_mut(key).unwrap() {
+            Toml::Array(ref mut tables) => {
+                let is_tables = match tables.last() {
+                    Some(&Toml::Table(_)) => true,
+                    _ => false,
+                };
+                if !fresh && !is_tables {
+                    return Err(NotAnArrayOfTables);
+                }
+                tables.push(Toml::Table(BTreeMap::new()));
+            }
+            _ => return Err(NotAnArrayOfTables),
+        }
+    } else {
+        if fresh {
+            table.insert(key.clone(), Toml::Table(BTreeMap::new()));
+        }
+        match *table.get(key).unwrap() {
+            Toml::Table(_) => {}
+            _ => return Err(NotATable),
+        }
+        path.push((key.clone(), None));
+        if !defined.insert(path) {
+            return Err(DuplicateTable);
+        }
+    }
+    Ok(())
+}
+
+// Marks an inline table and the ones nested in it as defined, so that
+// headers can't define them again.
+fn define_inline(defined: &mut HashSet<TablePath>, path: &mut TablePath, value: &Toml) {
+    if let Toml::Table(ref table) = *value {
+        defined.insert(path.clone());
+        for (key, value) in table.iter() {
+            path.push((key.clone(), None));
+            define_inline(defined, path, value);
+            path.pop();
+        }
+    }
+}
+
+// Digits with single underscores between them, as in `1_000`. Returns the
+// index after them.
+fn scan_digits(s: &[u8], start: uint) -> Option<uint> {
+    let mut i = start;
+    while i < s.len() {
+        match s[i] {
+            b'0' ... b'9' => i += 1,
+            b'_' if i > start && i + 1 < s.len() && s[i - 1] != b'_' => i += 1,
+            _ => break,
+        }
+    }
+    if i == start || s[i - 1] == b'_' { None } else { Some(i) }
+}
+
+fn parse_number(s: &str) -> Option<Toml> {
+    let b = s.as_bytes();
+    let mut i = 0;
+    if i < b.len() && (b[i] == b'+' || b[i] == b'-') {
+        i += 1;
+    }
+    let start = i;
+    i = match scan_digits(b, i) { Some(i) => i, None => return None };
+    // Leading zeros aren't allowed.
+    if b[start] == b'0' && i > start + 1 {
+        return None;
+    }
+    let mut float = false;
+    if i < b.len() && b[i] == b'.' {
+        float = true;
+        i = match scan_digits(b, i + 1) { Some(i) => i, None => return None };
+    }
+    if i < b.len() && (b[i] == b'e' || b[i] == b'E') {
+        float = true;
+        i += 1;
+        if i < b.len() && (b[i] == b'+' || b[i] == b'-') {
+            i += 1;
+        }
+        i = match scan_digits(b, i) { Some(i) => i, None => return None };
+    }
+    if i != b.len() {
+        return None;
+    }
+    let digits: string::String = s.trim_left_chars('+').chars().filter(|&c| c != '_').collect();
+    if float {
+        std::str::from_str(digits.as_slice()).map(Toml::Float)
+    } else {
+        std::str::from_str(digits.as_slice()).map(Toml::Integer)
+    }
+}
+
+// Whether `s` is a date and time like `1979-05-27T07:32:00.999-07:00`.
+fn is_datetime(s: &str) -> bool {
+    let b = s.as_bytes();
+    let digit = |i: uint| i < b.len() && b[i] >= b'0' && b[i] <= b'9';
+    let is = |i: uint, c: u8| i < b.len() && b[i] == c;
+    let prefix = digit(0) && digit(1) && digit(2) && digit(3) && is(4, b'-') &&
+                 digit(5) && digit(6) && is(7, b'-') && digit(8) && digit(9) &&
+                 is(10, b'T') && digit(11) && digit(12) && is(13, b':') &&
+                 digit(14) && digit(15) && is(16, b':') && digit(17) && digit(18);
+    if !prefix {
+        return false;
+    }
+    let mut i = 19;
+    if is(i, b'.') {
+        i += 1;
+        let start = i;
+        while digit(i) {
+            i += 1;
+        }
+        if i == start {
+            return false;
+        }
+    }
+    if is(i, b'Z') {
+        i += 1;
+    } else if (is(i, b'+') || is(i, b'-')) && digit(i + 1) && digit(i + 2) &&
+              is(i + 3, b':') && digit(i + 4) && digit(i + 5) {
+        i += 6;
+    } else {
+        return false;
+    }
+    i == b.len()
+}
+
+// A TOML document parser.
+struct Parser<T> {
+    rdr: T,
+    ch: Option<char>,
+    line: uint,
+    col: uint,
+    root: Table,
+    // The keys of the table that key/value pairs are added to.
+    current: Vec<string::String>,
+    // The tables that have been defined by a header.
+    defined: HashSet<TablePath>,
+}
+
+impl<T: Iterator<char>> Parser<T> {
+    fn new(rdr: T) -> Parser<T> {
+        let mut p = Parser {
+            rdr: rdr,
+            ch: Some('\x00'),
+            line: 1,
+            col: 0,
+            root: BTreeMap::new(),
+            current: Vec::new(),
+            defined: HashSet::new(),
+        };
+        p.bump();
+        return p;
+    }
+
+    fn eof(&self) -> bool { self.ch.is_none() }
+    fn bump(&mut self) {
+        // A new line is the last character of its line, so the position
+        // moves to the next line on the character after it.
+        if self.ch_is('\n') {
+            self.line += 1u;
+            self.col = 1u;
+        } else {
+            self.col += 1u;
+        }
+
+        self.ch = self.rdr.next();
+    }
+    fn ch_is(&self, c: char) -> bool {
+        self.ch == Some(c)
+    }
+    fn eat(&mut self, c: char) -> bool {
+        if self.ch_is(c) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error<U>(&self, reason: ErrorCode) -> Result<U, ParserError> {
+        Err(SyntaxError(reason, self.line, self.col))
+    }
+    fn error_at<U>(&self, reason: ErrorCode, line: uint, col: uint) -> Result<U, ParserError> {
+        Err(SyntaxError(reason, line, col))
+    }
+
+    fn parse_whitespace(&mut self) {
+        while self.ch_is(' ') || self.ch_is('\t') {
+            self.bump();
+        }
+    }
+
+    fn parse_comment(&mut self) {
+        if self.ch_is('#') {
+            while !self.eof() && !self.ch_is('\n') {
+                self.bump();
+            }
+        }
+    }
+
+    // Reads a new line, which is either `\n` or `\r\n`, returning whether
+    // there was one.
+    fn parse_newline(&mut self) -> Result<bool, ParserError> {
+        let (line, col) = (self.line, self.col);
+        if self.eat('\r') && !self.ch_is('\n') {
+            return self.error_at(ExpectedNewline, line, col);
+        }
+        Ok(self.eat('\n'))
+    }
+
+    // Skips whitespace, comments and new lines.
+    fn parse_blank(&mut self) -> Result<(), ParserError> {
+        loop {
+            self.parse_whitespace();
+            self.parse_comment();
+            if !try!(self.parse_newline()) {
+                return Ok(());
+            }
+        }
+    }
+
+    // Makes sure nothing but a comment follows on the line.
+    fn parse_line_end(&mut self) -> Result<(), ParserError> {
+        self.parse_whitespace();
+        self.parse_comment();
+        if self.eof() || try!(self.parse_newline()) {
+            Ok(())
+        } else {
+            self.error(ExpectedNewline)
+        }
+    }
+
+    fn parse(&mut self) -> Result<Table, ParserError> {
+        loop {
+            try!(self.parse_blank());
+            if self.eof() {
+                break;
+            }
+            if self.ch_is('[') {
+                try!(self.parse_header());
+            } else {
+                try!(self.parse_key_value());
+            }
+            try!(self.parse_line_end());
+        }
+        Ok(mem::replace(&mut self.root, BTreeMap::new()))
+    }
+
+    fn parse_header(&mut self) -> Result<(), ParserError> {
+        let (line, col) = (self.line, self.col);
+        self.bump();
+        let array = self.eat('[');
+        let mut keys = Vec::new();
+        loop {
+            self.parse_whitespace();
+            keys.push(try!(self.parse_key()));
+            self.parse_whitespace();
+            if !self.eat('.') {
+                break;
+            }
+        }
+        if !self.eat(']') || (array && !self.eat(']')) {
+            return self.error(ExpectedBracket);
+        }
+        match define_table(&mut self.root, keys.as_slice(), array, &mut self.defined) {
+            Ok(()) => {
+                self.current = keys;
+                Ok(())
+            }
+            Err(code) => self.error_at(code, line, col),
+        }
+    }
+
+    fn parse_key(&mut self) -> Result<string::String, ParserError> {
+        match self.ch {
+            Some('"') | Some('\'') => {
+                let (line, col) = (self.line, self.col);
+                let key = try!(self.parse_string());
+                // Multi-line strings can't be keys.
+                if self.line != line {
+                    return self.error_at(ExpectedKey, line, col);
+                }
+                Ok(key)
+            }
+            Some(c) if is_bare_key_char(c) => {
+                let mut key = string::String::new();
+                while let Some(c) = self.ch {
+                    if !is_bare_key_char(c) {
+                        break;
+                    }
+                    key.push(c);
+                    self.bump();
+                }
+                Ok(key)
+            }
+            _ => self.error(ExpectedKey),
+        }
+    }
+
+    fn parse_key_value(&mut self) -> Result<(), ParserError> {
+        let (line, col) = (self.line, self.col);
+        let key = try!(self.parse_key());
+        self.parse_whitespace();
+        if !self.eat('=') {
+            return self.error(ExpectedEquals);
+        }
+        self.parse_whitespace();
+        let value = try!(self.parse_value());
+        let mut path = Vec::new();
+        let duplicate = {
+            let table = descend(&mut self.root, self.current.as_slice(), &mut path).unwrap();
+            if table.contains_key(&key) {
+                true
+            } else {
+                path.push((key.clone(), None));
+                define_inline(&mut self.defined, &mut path, &value);
+                table.insert(key, value);
+                false
+            }
+        };
+        if duplicate {
+            return self.error_at(DuplicateKey, line, col);
+        }
+        Ok(())
+    }
+
+    fn parse_value(&mut self) -> Result<Toml, ParserError> {
+        match self.ch {
+            Some('"') | Some('\'') => self.parse_string().map(Toml::String),
+            Some('[') => self.parse_array(),
+            Some('{') => self.parse_inline_table(),
+            None => self.error(EOFWhileParsingValue),
+            _ => self.parse_scalar(),
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<string::String, ParserError> {
+        let quote = self.ch.unwrap();
+        self.bump();
+        let mut multiline = false;
+        if self.eat(quote) {
+            if !self.eat(quote) {
+                return Ok(string::String::new());
+            }
+            multiline = true;
+            // A new line right after the opening quotes isn't part of the
+            // string.
+            try!(self.parse_newline());
+        }
+
+        let mut res = string::String::new();
+        loop {
+            match self.ch {
+                None => return self.error(EOFWhileParsingString),
+                Some(c) if c == quote => {
+                    self.bump();
+                    if !multiline {
+                        return Ok(res);
+                    }
+                    let mut n = 1u;
+                    while n < 3 && self.eat(quote) {
+                        n += 1;
+                    }
+                    if n == 3 {
+                        return Ok(res);
+                    }
+                    for _ in range(0, n) {
+                        res.push(quote);
+                    }
+                }
+                Some('\n') | Some('\r') if !multiline => return self.error(NewlineInString),
+                // Only `\r\n` is a new line; a bare `\r` can't be in a string.
+                Some('\r') => {
+                    try!(self.parse_newline());
+                    res.push_str("\r\n");
+                }
+                Some('\\') if quote == '"' => {
+                    self.bump();
+                    match self.ch {
+                        Some('b') => res.push('\x08'),
+                        Some('t') => res.push('\t'),
+                        Some('n') => res.push('\n'),
+                        Some('f') => res.push('\x0c'),
+                        Some('r') => res.push('\r'),
+                        Some('"') => res.push('"'),
+                        Some('\\') => res.push('\\'),
+                        Some('/') => res.push('/'),
+                        Some('u') => res.push(try!(self.parse_unicode_escape(4))),
+                        Some('U') => res.push(try!(self.parse_unicode_escape(8))),
+                        // A backslash at the end of a line trims the
+                        // whitespace up to the next non-whitespace character.
+                        Some(' ') | Some('\t') | Some('\r') | Some('\n') if multiline => {
+                            loop {
+                                self.parse_whitespace();
+                                if !try!(self.parse_newline()) {
+                                    break;
+                                }
+                            }
+                            continue;
+                        }
+                        None => return self.error(EOFWhileParsingString),
+                        _ => return self.error(InvalidEscape),
+                    }
+                    self.bump();
+                }
+                Some(c) => {
+                    res.push(c);
+                    self.bump();
+                }
+            }
+        }
+    }
+
+    // Parses the hex digits after `\u` or `\U`, leaving the last one as the
+    // current character.
+    fn parse_unicode_escape(&mut self, digits: uint) -> Result<char, ParserError> {
+        let mut n = 0u32;
+        for _ in range(0, digits) {
+            self.bump();
+            match self.ch.and_then(|c| c.to_digit(16)) {
+                Some(d) => n = n * 16 + d as u32,
+                None => return self.error(InvalidEscape),
+            }
+        }
+        match char::from_u32(n) {
+            Some(c) => Ok(c),
+            None => self.error(InvalidUnicodeCodePoint),
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Toml, ParserError> {
+        self.bump();
+        let mut values: Array = Vec::new();
+        loop {
+            try!(self.parse_blank());
+            if self.eat(']') {
+                break;
+            }
+            if self.eof() {
+                return self.error(EOFWhileParsingArray);
+            }
+            let (line, col) = (self.line, self.col);
+            let value = try!(self.parse_value());
+            match values.first() {
+                Some(first) if first.type_str() != value.type_str() => {
+                    return self.error_at(MixedArray, line, col);
+                }
+                _ => {}
+            }
+            values.push(value);
+            try!(self.parse_blank());
+            if self.eat(',') {
+                continue;
+            }
+            if self.eat(']') {
+                break;
+            }
+            return if self.eof() {
+                self.error(EOFWhileParsingArray)
+            } else {
+                self.error(ExpectedComma)
+            };
+        }
+        Ok(Toml::Array(values))
+    }
+
+    fn parse_inline_table(&mut self) -> Result<Toml, ParserError> {
+        self.bump();
+        let mut table = BTreeMap::new();
+        self.parse_whitespace();
+        if self.eat('}') {
+            return Ok(Toml::Table(table));
+        }
+        loop {
+            self.parse_whitespace();
+            let (line, col) = (self.line, self.col);
+            let key = try!(self.parse_key());
+            self.parse_whitespace();
+            if !self.eat('=') {
+                return self.error(ExpectedEquals);
+            }
+            self.parse_whitespace();
+            let value = try!(self.parse_value());
+            if table.contains_key(&key) {
+                return self.error_at(DuplicateKey, line, col);
+            }
+            table.insert(key, value);
+            self.parse_whitespace();
+            if self.eat(',') {
+                continue;
+            }
+            if self.eat('}') {
+                return Ok(Toml::Table(table));
+            }
+            return if self.eof() {
+                self.error(EOFWhileParsingTable)
+            } else {
+                self.error(ExpectedComma)
+            };
+        }
+    }
+
+    // Parses a number, a boolean or a date and time.
+    fn parse_scalar(&mut self) -> Result<Toml, ParserError> {
+        let (line, col) = (self.line, self.col);
+        let mut token = string::String::new();
+        while let Some(c) = self.ch {
+            if !c.is_alphanumeric() && !"+-.:_".contains_char(c) {
+                break;
+            }
+            token.push(c);
+            self.bump();
+        }
+        let value = match token.as_slice() {
+            "true" => Some(Toml::Boolean(true)),
+            "false" => Some(Toml::Boolean(false)),
+            "inf" | "+inf" => Some(Toml::Float(Float::infinity())),
+            "-inf" => Some(Toml::Float(Float::neg_infinity())),
+            "nan" | "+nan" | "-nan" => Some(Toml::Float(Float::nan())),
+            s if is_datetime(s) => Some(Toml::Datetime(s.to_string())),
+            s => parse_number(s),
+        };
+        match value {
+            Some(value) => Ok(value),
+            None => {
+                let b = token.as_bytes();
+                let code = if b.len() > 4 && b[4] == b'-' &&
+                              b[..4].iter().all(|&c| c >= b'0' && c <= b'9') {
+                    InvalidDatetime
+                } else if !b.is_empty() && (b[0] == b'+' || b[0] == b'-' ||
+                                            (b[0] >= b'0' && b[0] <= b'9')) {
+                    InvalidNumber
+                } else {
+                    ExpectedValue
+                };
+                self.error_at(code, line, col)
+            }
+        }
+    }
+}
+
+/// A structure for implementing serialization to TOML, which builds a `Toml`
+/// value.
+pub struct Encoder {
+    // The tables and arrays being filled.
+    stack: Vec<Toml>,
+    // The last value that was encoded, or None if it was `()` or `None`.
+    value: Option<Toml>,
+    // The keys of the map entries being encoded.
+    keys: Vec<string::String>,
+}
+
+impl Encoder {
+    /// Creates a new TOML encoder.
+    pub fn new() -> Encoder {
+        Encoder { stack: Vec::new(), value: None, keys: Vec::new() }
+    }
+
+    /// Returns the value that was encoded, or None if it was `()` or `None`,
+    /// which TOML can't represent.
+    pub fn unwrap(self) -> Option<Toml> {
+        self.value
+    }
+
+    fn emit_value(&mut self, value: Toml) -> EncodeResult {
+        self.value = Some(value);
+        Ok(())
+    }
+
+    // Encodes a table or an array, which `f` fills.
+    fn emit_container<F>(&mut self, container: Toml, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.stack.push(container);
+        let res = f(self);
+        let container = self.stack.pop().unwrap();
+        try!(res);
+        self.emit_value(container)
+    }
+
+    // Returns the value `f` encodes.
+    fn take_value<F>(&mut self, f: F) -> Result<Option<Toml>, EncoderError> where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.value = None;
+        try!(f(self));
+        Ok(self.value.take())
+    }
+
+    // Adds the value `f` encodes to the array being filled.
+    fn push_element<F>(&mut self, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        let value = match try!(self.take_value(f)) {
+            Some(value) => value,
+            None => return Err(EncoderError::MissingValue),
+        };
+        match *self.stack.last_mut().unwrap() {
+            Toml::Array(ref mut values) => {
+                match values.first() {
+                    Some(first) if first.type_str() != value.type_str() => {
+                        return Err(EncoderError::MixedArray);
+                    }
+                    _ => {}
+                }
+                values.push(value);
+            }
+            _ => panic!("not encoding an array"),
+        }
+        Ok(())
+    }
+
+    // Adds the value `f` encodes to the table being filled, unless it is
+    // `()` or `None`.
+    fn insert<F>(&mut self, key: string::String, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        let value = match try!(self.take_value(f)) {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+        match *self.stack.last_mut().unwrap() {
+            Toml::Table(ref mut table) => { table.insert(key, value); }
+            _ => panic!("not encoding a table"),
+        }
+        Ok(())
+    }
+}
+
+impl ::Encoder<EncoderError> for Encoder {
+    fn emit_nil(&mut self) -> EncodeResult {
+        self.value = None;
+        Ok(())
+    }
+
+    fn emit_uint(&mut self, v: uint) -> EncodeResult { self.emit_u64(v as u64) }
+    fn emit_u64(&mut self, v: u64) -> EncodeResult {
+        match num::cast(v) {
+            Some(v) => self.emit_value(Toml::Integer(v)),
+            None => Err(EncoderError::IntegerOutOfRange),
+        }
+    }
+    fn emit_u32(&mut self, v: u32) -> EncodeResult { self.emit_i64(v as i64) }
+    fn emit_u16(&mut self, v: u16) -> EncodeResult { self.emit_i64(v as i64) }
+    fn emit_u8(&mut self, v: u8) -> EncodeResult { self.emit_i64(v as i64) }
+
+    fn emit_int(&mut self, v: int) -> EncodeResult { self.emit_i64(v as i64) }
+    fn emit_i64(&mut self, v: i64) -> EncodeResult { self.emit_value(Toml::Integer(v)) }
+    fn emit_i32(&mut self, v: i32) -> EncodeResult { self.emit_i64(v as i64) }
+    fn emit_i16(&mut self, v: i16) -> EncodeResult { self.emit_i64(v as i64) }
+    fn emit_i8(&mut self, v: i8) -> EncodeResult { self.emit_i64(v as i64) }
+
+    fn emit_bool(&mut self, v: bool) -> EncodeResult { self.emit_value(Toml::Boolean(v)) }
+
+    fn emit_f64(&mut self, v: f64) -> EncodeResult { self.emit_value(Toml::Float(v)) }
+    fn emit_f32(&mut self, v: f32) -> EncodeResult { self.emit_f64(v as f64) }
+
+    fn emit_char(&mut self, v: char) -> EncodeResult {
+        self.emit_value(Toml::String(string::String::from_char(1, v)))
+    }
+    fn emit_str(&mut self, v: &str) -> EncodeResult {
+        self.emit_value(Toml::String(v.to_string()))
+    }
+
+    fn emit_enum<F>(&mut self, _name: &str, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_enum_variant<F>(&mut self,
+                            name: &str,
+                            _id: uint,
+                            cnt: uint,
+                            f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        // enums are encoded as strings or tables
+        // Bunny => "Bunny"
+        // Kangaroo(34,27) => { variant = "Kangaroo", fields = [34, 27] }
+        if cnt == 0 {
+            return self.emit_str(name);
+        }
+        let mut table = BTreeMap::new();
+        table.insert("variant".to_string(), Toml::String(name.to_string()));
+        self.emit_container(Toml::Table(table), move |e| {
+            e.insert("fields".to_string(), move |e| e.emit_container(Toml::Array(Vec::new()), f))
+        })
+    }
+
+    fn emit_enum_variant_arg<F>(&mut self, _idx: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.push_element(f)
+    }
+
+    fn emit_enum_struct_variant<F>(&mut self,
+                                   name: &str,
+                                   id: uint,
+                                   cnt: uint,
+                                   f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.emit_enum_variant(name, id, cnt, f)
+    }
+
+    fn emit_enum_struct_variant_field<F>(&mut self,
+                                         _: &str,
+                                         idx: uint,
+                                         f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.emit_enum_variant_arg(idx, f)
+    }
+
+    fn emit_struct<F>(&mut self, _: &str, _: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.emit_container(Toml::Table(BTreeMap::new()), f)
+    }
+
+    fn emit_struct_field<F>(&mut self, name: &str, _: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.insert(name.to_string(), f)
+    }
+
+    fn emit_tuple<F>(&mut self, len: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.emit_seq(len, f)
+    }
+    fn emit_tuple_arg<F>(&mut self, idx: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.emit_seq_elt(idx, f)
+    }
+
+    fn emit_tuple_struct<F>(&mut self, _: &str, len: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.emit_seq(len, f)
+    }
+    fn emit_tuple_struct_arg<F>(&mut self, idx: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.emit_seq_elt(idx, f)
+    }
+
+    fn emit_option<F>(&mut self, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        f(self)
+    }
+    fn emit_option_none(&mut self) -> EncodeResult { self.emit_nil() }
+    fn emit_option_some<F>(&mut self, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        f(self)
+    }
+
+    fn emit_seq<F>(&mut self, _len: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.emit_container(Toml::Array(Vec::new()), f)
+    }
+
+    fn emit_seq_elt<F>(&mut self, _idx: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.push_element(f)
+    }
+
+    fn emit_map<F>(&mut self, _len: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        self.emit_container(Toml::Table(BTreeMap::new()), f)
+    }
+
+    fn emit_map_elt_key<F>(&mut self, _idx: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        // Keys that aren't strings are written as they would be as values,
+        // like `HashMap<uint, V>` keys in the json module.
+        let key = match try!(self.take_value(f)) {
+            Some(Toml::String(s)) => s,
+            Some(value @ Toml::Integer(_)) |
+            Some(value @ Toml::Float(_)) |
+            Some(value @ Toml::Boolean(_)) => value.to_string(),
+            _ => return Err(EncoderError::KeyMustBeAString),
+        };
+        self.keys.push(key);
+        Ok(())
+    }
+
+    fn emit_map_elt_val<F>(&mut self, _idx: uint, f: F) -> EncodeResult where
+        F: FnOnce(&mut Encoder) -> EncodeResult,
+    {
+        let key = self.keys.pop().unwrap();
+        self.insert(key, f)
+    }
+}
+
+/// A structure to decode TOML values to values in rust.
+pub struct Decoder {
+    // None stands for a missing value, which decodes as `()` or `None`.
+    stack: Vec<Option<Toml>>,
+}
+
+impl Decoder {
+    /// Creates a new decoder instance for decoding the specified TOML value.
+    pub fn new(toml: Toml) -> Decoder {
+        Decoder { stack: vec![Some(toml)] }
+    }
+}
+
+impl Decoder {
+    fn pop(&mut self) -> Option<Toml> {
+        self.stack.pop().unwrap()
+    }
+}
+
+// Describes a value for error messages.
+fn describe(value: &Option<Toml>) -> string::String {
+    match *value {
+        Some(ref value) => {
+            let mut out = string::String::new();
+            write_inline(&mut out, value);
+            out
+        }
+        None => "nothing".to_string(),
+    }
+}
+
+macro_rules! expect {
+    ($e:expr, $t:ident) => ({
+        match $e {
+            Some(Toml::$t(v)) => Ok(v),
+            other => {
+                Err(ExpectedError(stringify!($t).to_string(), describe(&other)))
+            }
+        }
+    })
+}
+
+macro_rules! read_primitive {
+    ($name:ident, $ty:ty) => {
+        fn $name(&mut self) -> DecodeResult<$ty> {
+            match self.pop() {
+                Some(Toml::Integer(f)) => match num::cast(f) {
+                    Some(f) => Ok(f),
+                    None => Err(ExpectedError("Number".to_string(), format!("{}", f))),
+                },
+                // Map keys are strings, so numbers can be found there.
+                Some(Toml::String(s)) => match std::str::from_str(s.as_slice()) {
+                    Some(f) => Ok(f),
+                    None => Err(ExpectedError("Number".to_string(), s)),
+                },
+                value => Err(ExpectedError("Integer".to_string(), describe(&value))),
+            }
+        }
+    }
+}
+
+impl ::Decoder<DecoderError> for Decoder {
+    fn read_nil(&mut self) -> DecodeResult<()> {
+        match self.pop() {
+            None => Ok(()),
+            value => Err(ExpectedError("nothing".to_string(), describe(&value))),
+        }
+    }
+
+    read_primitive! { read_uint, uint }
+    read_primitive! { read_u8, u8 }
+    read_primitive! { read_u16, u16 }
+    read_primitive! { read_u32, u32 }
+    read_primitive! { read_u64, u64 }
+    read_primitive! { read_int, int }
+    read_primitive! { read_i8, i8 }
+    read_primitive! { read_i16, i16 }
+    read_primitive! { read_i32, i32 }
+    read_primitive! { read_i64, i64 }
+
+    fn read_f32(&mut self) -> DecodeResult<f32> { self.read_f64().map(|x| x as f32) }
+
+    fn read_f64(&mut self) -> DecodeResult<f64> {
+        match self.pop() {
+            Some(Toml::Integer(f)) => Ok(f as f64),
+            Some(Toml::Float(f)) => Ok(f),
+            Some(Toml::String(s)) => match std::str::from_str(s.as_slice()) {
+                Some(f) => Ok(f),
+                None => Err(ExpectedError("Number".to_string(), s)),
+            },
+            value => Err(ExpectedError("Number".to_string(), describe(&value))),
+        }
+    }
+
+    fn read_bool(&mut self) -> DecodeResult<bool> {
+        match self.pop() {
+            Some(Toml::Boolean(b)) => Ok(b),
+            // Map keys are strings, so booleans can be found there.
+            Some(Toml::String(ref s)) if s.as_slice() == "true" => Ok(true),
+            Some(Toml::String(ref s)) if s.as_slice() == "false" => Ok(false),
+            value => Err(ExpectedError("Boolean".to_string(), describe(&value))),
+        }
+    }
+
+    fn read_char(&mut self) -> DecodeResult<char> {
+        let s = try!(self.read_str());
+        {
+            let mut it = s.chars();
+            match (it.next(), it.next()) {
+                // exactly one character
+                (Some(c), None) => return Ok(c),
+                _ => ()
+            }
+        }
+        Err(ExpectedError("single character string".to_string(), s))
+    }
+
+    fn read_str(&mut self) -> DecodeResult<string::String> {
+        match self.pop() {
+            Some(Toml::String(s)) | Some(Toml::Datetime(s)) => Ok(s),
+            value => Err(ExpectedError("String".to_string(), describe(&value))),
+        }
+    }
+
+    fn read_enum<T, F>(&mut self, _name: &str, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_enum_variant<T, F>(&mut self, names: &[&str],
+                               mut f: F) -> DecodeResult<T>
+        where F: FnMut(&mut Decoder, uint) -> DecodeResult<T>,
+    {
+        let name = match self.pop() {
+            Some(Toml::String(s)) => s,
+            Some(Toml::Table(mut table)) => {
+                let name = match table.remove(&"variant".to_string()) {
+                    Some(Toml::String(s)) => s,
+                    Some(value) => {
+                        return Err(ExpectedError("String".to_string(), describe(&Some(value))))
+                    }
+                    None => return Err(MissingFieldError("variant".to_string())),
+                };
+                match table.remove(&"fields".to_string()) {
+                    Some(Toml::Array(fields)) => {
+                        for field in fields.into_iter().rev() {
+                            self.stack.push(Some(field));
+                        }
+                    }
+                    Some(value) => {
+                        return Err(ExpectedError("Array".to_string(), describe(&Some(value))))
+                    }
+                    None => return Err(MissingFieldError("fields".to_string())),
+                }
+                name
+            }
+            value => {
+                return Err(ExpectedError("String or Table".to_string(), describe(&value)))
+            }
+        };
+        let idx = match names.iter().position(|n| *n == name.as_slice()) {
+            Some(idx) => idx,
+            None => return Err(UnknownVariantError(name))
+        };
+        f(self, idx)
+    }
+
+    fn read_enum_variant_arg<T, F>(&mut self, _idx: uint, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_enum_struct_variant<T, F>(&mut self, names: &[&str], f: F) -> DecodeResult<T> where
+        F: FnMut(&mut Decoder, uint) -> DecodeResult<T>,
+    {
+        self.read_enum_variant(names, f)
+    }
+
+    fn read_enum_struct_variant_field<T, F>(&mut self,
+                                         _name: &str,
+                                         idx: uint,
+                                         f: F)
+                                         -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        self.read_enum_variant_arg(idx, f)
+    }
+
+    fn read_struct<T, F>(&mut self, _name: &str, _len: uint, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        let value = try!(f(self));
+        self.pop();
+        Ok(value)
+    }
+
+    fn read_struct_field<T, F>(&mut self,
+                               name: &str,
+                               _idx: uint,
+                               f: F)
+                               -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        let mut table = try!(expect!(self.pop(), Table));
+
+        let value = match table.remove(&name.to_string()) {
+            None => {
+                // Decode a missing value, to get None if the field is an
+                // Option<_> or () if it is a unit.
+                self.stack.push(None);
+                match f(self) {
+                    Ok(x) => x,
+                    Err(_) => return Err(MissingFieldError(name.to_string())),
+                }
+            },
+            Some(toml) => {
+                self.stack.push(Some(toml));
+                try!(f(self))
+            }
+        };
+        self.stack.push(Some(Toml::Table(table)));
+        Ok(value)
+    }
+
+    fn deny_unknown_fields(&mut self) -> DecodeResult<()> {
+        // The fields that were read have been removed from the table.
+        match self.stack.last() {
+            Some(&Some(Toml::Table(ref table))) => match table.keys().next() {
+                Some(key) => Err(UnknownFieldError(key.clone())),
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn read_tuple<T, F>(&mut self, tuple_len: uint, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        self.read_seq(move |d, len| {
+            if len == tuple_len {
+                f(d)
+            } else {
+                Err(ExpectedError(format!("Tuple{}", tuple_len), format!("Tuple{}", len)))
+            }
+        })
+    }
+
+    fn read_tuple_arg<T, F>(&mut self, idx: uint, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        self.read_seq_elt(idx, f)
+    }
+
+    fn read_tuple_struct<T, F>(&mut self,
+                               _name: &str,
+                               len: uint,
+                               f: F)
+                               -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        self.read_tuple(len, f)
+    }
+
+    fn read_tuple_struct_arg<T, F>(&mut self,
+                                   idx: uint,
+                                   f: F)
+                                   -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        self.read_tuple_arg(idx, f)
+    }
+
+    fn read_option<T, F>(&mut self, mut f: F) -> DecodeResult<T> where
+        F: FnMut(&mut Decoder, bool) -> DecodeResult<T>,
+    {
+        match self.pop() {
+            None => f(self, false),
+            value => { self.stack.push(value); f(self, true) }
+        }
+    }
+
+    fn read_seq<T, F>(&mut self, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder, uint) -> DecodeResult<T>,
+    {
+        let array = try!(expect!(self.pop(), Array));
+        let len = array.len();
+        for v in array.into_iter().rev() {
+            self.stack.push(Some(v));
+        }
+        f(self, len)
+    }
+
+    fn read_seq_elt<T, F>(&mut self, _idx: uint, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_map<T, F>(&mut self, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder, uint) -> DecodeResult<T>,
+    {
+        let table = try!(expect!(self.pop(), Table));
+        let len = table.len();
+        for (key, value) in table.into_iter() {
+            self.stack.push(Some(value));
+            self.stack.push(Some(Toml::String(key)));
+        }
+        f(self, len)
+    }
+
+    fn read_map_elt_key<T, F>(&mut self, _idx: uint, f: F) -> DecodeResult<T> where
+       F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_map_elt_val<T, F>(&mut self, _idx: uint, f: F) -> DecodeResult<T> where
+       F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn error(&mut self, err: &str) -> DecoderError {
+        ApplicationError(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use self::Animal::*;
+    use super::{Toml, from_str, decode, encode, EncoderError};
+    use super::ErrorCode::*;
+    use super::ParserError::*;
+    use super::DecoderError::*;
+    use std::collections::{BTreeMap, HashMap};
+    use std::num::Float;
+
+    fn table(entries: Vec<(&str, Toml)>) -> Toml {
+        let mut table = BTreeMap::new();
+        for (key, value) in entries.into_iter() {
+            table.insert(key.to_string(), value);
+        }
+        Toml::Table(table)
+    }
+
+    fn string(s: &str) -> Toml {
+        Toml::String(s.to_string())
+    }
+
+    fn check_err(s: &str, code: super::ErrorCode, line: uint, col: uint) {
+        assert_eq!(from_str(s), Err(SyntaxError(code, line, col)));
+    }
+
+    #[deriving(PartialEq, Encodable, Decodable, Show)]
+    enum Animal {
+        Dog,
+        Frog(String, String)
+    }
+
+    #[deriving(PartialEq, Encodable, Decodable, Show)]
+    struct Database {
+        path: String,
+        timeout: Option<f64>,
+        replicas: Vec<String>,
+    }
+
+    #[deriving(PartialEq, Encodable, Decodable, Show)]
+    struct User {
+        name: String,
+        admin: bool,
+        pet: Animal,
+    }
+
+    #[deriving(PartialEq, Encodable, Decodable, Show)]
+    struct Config {
+        port: u16,
+        ratio: f32,
+        tags: HashMap<String, uint>,
+        database: Database,
+        users: Vec<User>,
+        unit: (),
+    }
+
+    #[test]
+    fn test_parse_values() {
+        let toml = from_str(r#"
+# A comment.
+a = "basic \"string\"\t\u00e9\U0001F600"  # and another
+b = 'C:\path\no escapes'
+c = """
+multi
+line \
+      joined"""
+d = '''
+raw ''line'' \n'''
+e = -17
+f = +1_000
+g = 3.25e-2
+h = -0.0
+i = true
+j = 1979-05-27T07:32:00.999-07:00
+k = [ [1, 2], ["x", "y",],
+      # comments and new lines are allowed
+    ]
+l = { x = 1, "y z" = [] }
+m = inf
+'n.o' = 1
+"#).unwrap();
+        assert_eq!(toml.find("a"), Some(&string("basic \"string\"\té\U0001F600")));
+        assert_eq!(toml.find("b"), Some(&string("C:\\path\\no escapes")));
+        assert_eq!(toml.find("c"), Some(&string("multi\nline joined")));
+        assert_eq!(toml.find("d"), Some(&string("raw ''line'' \\n")));
+        assert_eq!(toml.find("e"), Some(&Toml::Integer(-17)));
+        assert_eq!(toml.find("f"), Some(&Toml::Integer(1000)));
+        assert_eq!(toml.find("g").and_then(|v| v.as_float()), Some(3.25e-2));
+        assert_eq!(toml.find("h"), Some(&Toml::Float(0.0)));
+        assert_eq!(toml.find("i"), Some(&Toml::Boolean(true)));
+        assert_eq!(toml.find("j").and_then(|v| v.as_datetime()),
+                   Some("1979-05-27T07:32:00.999-07:00"));
+        assert_eq!(toml.find("k"), Some(&Toml::Array(vec![
+            Toml::Array(vec![Toml::Integer(1), Toml::Integer(2)]),
+            Toml::Array(vec![string("x"), string("y")]),
+        ])));
+        assert_eq!(toml.find("l"), Some(&table(vec![
+            ("x", Toml::Integer(1)),
+            ("y z", Toml::Array(vec![])),
+        ])));
+        assert_eq!(toml.find("m").and_then(|v| v.as_float()), Some(Float::infinity()));
+        assert_eq!(toml.find("n.o"), Some(&Toml::Integer(1)));
+
+        let toml = from_str("a = \"\"\"\r\nx\r\ny\"\"\"\r\nb = 1\r\n").unwrap();
+        assert_eq!(toml.find("a"), Some(&string("x\r\ny")));
+        assert_eq!(toml.find("b"), Some(&Toml::Integer(1)));
+    }
+
+    #[test]
+    fn test_parse_tables() {
+        let toml = from_str("\
+a = 1
+[x.y]
+b = 2
+[x]
+c = 3
+[[fruit]]
+name = \"apple\"
+[fruit.physical]
+color = \"red\"
+[[fruit.variety]]
+name = \"red delicious\"
+[[fruit]]
+name = \"banana\"
+[fruit.physical]
+color = \"yellow\"
+[ \"quoted key\" . z ]
+").unwrap();
+        assert_eq!(toml.lookup("a"), Some(&Toml::Integer(1)));
+        assert_eq!(toml.lookup("x.y.b"), Some(&Toml::Integer(2)));
+        assert_eq!(toml.lookup("x.c"), Some(&Toml::Integer(3)));
+        assert_eq!(toml.lookup("fruit.0.physical.color"), Some(&string("red")));
+        assert_eq!(toml.lookup("fruit.0.variety.0.name"), Some(&string("red delicious")));
+        assert_eq!(toml.lookup("fruit.1.name"), Some(&string("banana")));
+        assert_eq!(toml.lookup("fruit.1.physical.color"), Some(&string("yellow")));
+        assert_eq!(toml.lookup("fruit.2"), None);
+        assert_eq!(toml.lookup("quoted key.z"), Some(&table(vec![])));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        check_err("a", ExpectedEquals, 1, 2);
+        check_err("= 1", ExpectedKey, 1, 1);
+        check_err("a =", EOFWhileParsingValue, 1, 4);
+        check_err("a = 1 b = 2", ExpectedNewline, 1, 7);
+        check_err("a = 1\nb = hello", ExpectedValue, 2, 5);
+        check_err("a = 01", InvalidNumber, 1, 5);
+        check_err("a = 1__0", InvalidNumber, 1, 5);
+        check_err("a = 1.", InvalidNumber, 1, 5);
+        check_err("a = 99999999999999999999", InvalidNumber, 1, 5);
+        check_err("a = 1979-05-27", InvalidDatetime, 1, 5);
+        check_err("a = \"\\q\"", InvalidEscape, 1, 7);
+        check_err("a = \"\\uD800\"", InvalidUnicodeCodePoint, 1, 11);
+        check_err("a = \"x\ny\"", NewlineInString, 1, 7);
+        check_err("a = \"abc", EOFWhileParsingString, 1, 9);
+        check_err("a = [1, 2", EOFWhileParsingArray, 1, 10);
+        check_err("a = [1 2]", ExpectedComma, 1, 8);
+        check_err("a = [1, \"x\"]", MixedArray, 1, 9);
+        check_err("a = { x = 1", EOFWhileParsingTable, 1, 12);
+        check_err("a = 1\na = 2", DuplicateKey, 2, 1);
+        check_err("[a]\n[b]\n[a]", DuplicateTable, 3, 1);
+        check_err("a = 1\n[a.b]", NotATable, 2, 1);
+        check_err("a = []\n[[a]]", NotAnArrayOfTables, 2, 1);
+        check_err("[a\nb = 1", ExpectedBracket, 1, 3);
+        check_err("[[a]\n", ExpectedBracket, 1, 5);
+        check_err("a = { b = 1 }\n[a]", DuplicateTable, 2, 1);
+        check_err("a = { b = { c = 1 } }\n[a.b]", DuplicateTable, 2, 1);
+        check_err("[x]\na = {}\n[x.a]", DuplicateTable, 3, 1);
+        check_err("a = 1\rb = 2", ExpectedNewline, 1, 6);
+        check_err("\r[a]", ExpectedNewline, 1, 1);
+        check_err("a = \"x\ry\"", NewlineInString, 1, 7);
+        check_err("a = '''x\ry'''", ExpectedNewline, 1, 9);
+    }
+
+    #[test]
+    fn test_write() {
+        let toml = table(vec![
+            ("title", string("TOML \"example\"")),
+            ("empty", table(vec![])),
+            ("owner", table(vec![
+                ("dob", Toml::Datetime("1979-05-27T07:32:00Z".to_string())),
+                ("ratio", Toml::Float(2.0)),
+            ])),
+            ("servers", table(vec![
+                ("alpha", table(vec![("ip", string("10.0.0.1"))])),
+            ])),
+            ("points", Toml::Array(vec![
+                table(vec![("x", Toml::Integer(1))]),
+                table(vec![("x", Toml::Integer(-2)), ("tags", Toml::Array(vec![]))]),
+            ])),
+            ("nested", Toml::Array(vec![
+                Toml::Array(vec![table(vec![("a b", Toml::Boolean(true))])]),
+            ])),
+        ]);
+        let expected = "\
+nested = [[{ \"a b\" = true }]]
+title = \"TOML \\\"example\\\"\"
+
+[empty]
+
+[owner]
+dob = 1979-05-27T07:32:00Z
+ratio = 2.0
+
+[[points]]
+x = 1
+
+[[points]]
+tags = []
+x = -2
+
+[servers.alpha]
+ip = \"10.0.0.1\"
+";
+        assert_eq!(toml.to_string(), expected.to_string());
+        assert_eq!(from_str(expected), Ok(toml));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut tags = HashMap::new();
+        tags.insert("blue".to_string(), 1u);
+        tags.insert("with space".to_string(), 2u);
+        let config = Config {
+            port: 8080,
+            ratio: 0.5,
+            tags: tags,
+            database: Database {
+                path: "/var/lib/db\n".to_string(),
+                timeout: None,
+                replicas: vec!["a".to_string(), "b".to_string()],
+            },
+            users: vec![
+                User { name: "Amy".to_string(), admin: true, pet: Dog },
+                User {
+                    name: "Bob".to_string(),
+                    admin: false,
+                    pet: Frog("Henry".to_string(), "green".to_string()),
+                },
+            ],
+            unit: (),
+        };
+        let text = encode(&config).unwrap();
+        assert_eq!(decode::<Config>(text.as_slice()), Ok(config));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        #[deriving(Decodable, PartialEq, Show)]
+        struct Point {
+            x: i8,
+            y: Option<Vec<uint>>,
+        }
+        assert_eq!(decode::<Point>("x = 1\ny = [2]"), Ok(Point { x: 1, y: Some(vec![2]) }));
+        assert_eq!(decode::<Point>("y = [2]"), Err(MissingFieldError("x".to_string())));
+        assert_eq!(decode::<Point>("x = 1000"),
+                   Err(ExpectedError("Number".to_string(), "1000".to_string())));
+        assert_eq!(decode::<Point>("x = 1\ny = 2"),
+                   Err(ExpectedError("Array".to_string(), "2".to_string())));
+        assert_eq!(decode::<Point>("x = 1\ny = [\"a\"]"),
+                   Err(ExpectedError("Number".to_string(), "a".to_string())));
+        assert_eq!(decode::<Point>("x = "),
+                   Err(ParseError(SyntaxError(EOFWhileParsingValue, 1, 5))));
+        assert_eq!(decode::<Animal>("variant = \"Cat\"\nfields = []"),
+                   Err(UnknownVariantError("Cat".to_string())));
+    }
+
+    #[test]
+    fn test_encode_errors() {
+        assert_eq!(encode(&1u), Err(EncoderError::NotATable));
+        assert_eq!(encode(&vec![(1u, "x".to_string())]), Err(EncoderError::NotATable));
+        let mut map = HashMap::new();
+        map.insert("a".to_string(), (1u, "x".to_string()));
+        assert_eq!(encode(&map), Err(EncoderError::MixedArray));
+        let mut map = HashMap::new();
+        map.insert("a".to_string(), vec![None, Some(1u)]);
+        assert_eq!(encode(&map), Err(EncoderError::MissingValue));
+        let mut map = HashMap::new();
+        map.insert("a".to_string(), ::std::u64::MAX);
+        assert_eq!(encode(&map), Err(EncoderError::IntegerOutOfRange));
+        let mut outer = HashMap::new();
+        outer.insert(vec![1u], 1u);
+        assert_eq!(encode(&outer), Err(EncoderError::KeyMustBeAString));
+    }
+}
@@ -16,7 +16,7 @@
 // FIXME: These functions take Durations but only pass ms to the backend impls.
 
 use comm::{Receiver, Sender, channel};
-use time::Duration;
+use time::{Duration, Instant};
 use io::IoResult;
 use sys::timer::Callback;
 use sys::timer::Timer as TimerImp;
@@ -80,6 +80,14 @@ pub fn sleep(duration: Duration) {
     timer.sleep(duration)
 }
 
+/// Sleep the current task until the specified instant.
+///
+/// When provided an instant in the past, the function will return
+/// immediately.
+pub fn sleep_until(deadline: Instant) {
+    sleep(until(deadline))
+}
+
 impl Timer {
     /// Creates a new timer which can be used to put the current task to sleep
     /// for a number of milliseconds, or to possibly create channels which will
@@ -102,6 +110,17 @@ impl Timer {
         self.inner.sleep(ms);
     }
 
+    /// Blocks the current task until the specified instant.
+    ///
+    /// Note that this function will cause any other receivers for this timer to
+    /// be invalidated (the other end will be closed).
+    ///
+    /// When provided an instant in the past, the function will return
+    /// immediately.
+    pub fn sleep_until(&mut self, deadline: Instant) {
+        self.sleep(until(deadline))
+    }
+
     /// Creates a oneshot receiver which will have a notification sent when
     /// the specified duration has elapsed.
     ///
@@ -152,6 +171,15 @@ impl Timer {
         return rx
     }
 
+    /// Creates a oneshot receiver which will have a notification sent at the
+    /// specified instant, as with `oneshot`.
+    ///
+    /// When provided an instant in the past, the message will be sent
+    /// immediately.
+    pub fn oneshot_at(&mut self, deadline: Instant) -> Receiver<()> {
+        self.oneshot(until(deadline))
+    }
+
     /// Creates a receiver which will have a continuous stream of notifications
     /// being sent each time the specified duration has elapsed.
     ///
@@ -217,6 +245,14 @@ impl Callback for TimerCallback {
     }
 }
 
+// The time from now until `deadline`, rounded up to whole milliseconds so
+// that the backends don't wake up early.
+fn until(deadline: Instant) -> Duration {
+    let remaining = deadline - Instant::now();
+    let ms = Duration::milliseconds(remaining.num_milliseconds());
+    if ms < remaining { ms + Duration::milliseconds(1) } else { remaining }
+}
+
 fn in_ms_u64(d: Duration) -> u64 {
     let ms = d.num_milliseconds();
     if ms < 0 { return 0 };
@@ -228,7 +264,7 @@ mod test {
     use prelude::*;
 
     use super::Timer;
-    use time::Duration;
+    use time::{Duration, Instant};
 
     #[test]
     fn test_io_timer_sleep_simple() {
@@ -286,6 +322,20 @@ mod test {
         super::sleep(Duration::milliseconds(1))
     }
 
+    #[test]
+    fn test_io_timer_sleep_until() {
+        let mut timer = Timer::new().unwrap();
+        let deadline = Instant::now() + Duration::milliseconds(10);
+        timer.sleep_until(deadline);
+        assert!(Instant::now() >= deadline);
+
+        timer.oneshot_at(Instant::now() + Duration::milliseconds(10)).recv();
+
+        // Instants in the past don't block.
+        super::sleep_until(Instant::now() - Duration::seconds(1));
+        timer.oneshot_at(Instant::now() - Duration::seconds(1)).recv();
+    }
+
     #[test]
     fn oneshot() {
         let mut timer = Timer::new().unwrap();
@@ -9,13 +9,283 @@
 // except according to those terms.
 
 //! Temporal quantification.
+//!
+//! An `Instant` is a reading of a monotonic clock, for measuring how long
+//! something takes, and a `SystemTime` is a reading of the wall clock, which
+//! may be set back or forward.
+//!
+//! ```rust
+//! use std::time::{Instant, SystemTime, UNIX_EPOCH};
+//!
+//! let start = Instant::now();
+//! let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+//! println!("{} seconds since 1970, found in {}", since_epoch.num_seconds(), start.elapsed());
+//! ```
 
-use libc;
+use {fmt, libc};
+use error::Error;
+use num::Int;
+use ops::{Add, Sub};
+use option::Option;
+use option::Option::{Some, None};
+use result::Result;
+use result::Result::{Ok, Err};
 
 pub use self::duration::Duration;
 
 pub mod duration;
 
+/// A reading of a monotonic clock, which never goes back.
+///
+/// Instants are only meaningful relative to each other, as in
+/// `Instant::elapsed` and `Instant::duration_since`.
+#[deriving(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Show)]
+pub struct Instant {
+    // Nanoseconds since an unspecified epoch.
+    ns: u64,
+}
+
+impl Instant {
+    /// Returns the current reading of the monotonic clock.
+    pub fn now() -> Instant {
+        Instant { ns: precise_time_ns() }
+    }
+
+    /// Returns the time elapsed from `earlier` to this instant, which is
+    /// negative if `earlier` is later.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        *self - earlier
+    }
+
+    /// Returns the time elapsed since this instant.
+    pub fn elapsed(&self) -> Duration {
+        Instant::now() - *self
+    }
+}
+
+impl Add<Duration, Instant> for Instant {
+    fn add(self, other: Duration) -> Instant {
+        let ns = other.num_nanoseconds().expect("overflow when adding duration to instant");
+        let ns = if ns >= 0 {
+            self.ns.checked_add(ns as u64)
+        } else {
+            self.ns.checked_sub(-ns as u64)
+        };
+        Instant { ns: ns.expect("overflow when adding duration to instant") }
+    }
+}
+
+impl Sub<Duration, Instant> for Instant {
+    fn sub(self, other: Duration) -> Instant {
+        self + -other
+    }
+}
+
+impl Sub<Instant, Duration> for Instant {
+    fn sub(self, other: Instant) -> Duration {
+        if self.ns >= other.ns {
+            Duration::nanoseconds((self.ns - other.ns) as i64)
+        } else {
+            -Duration::nanoseconds((other.ns - self.ns) as i64)
+        }
+    }
+}
+
+/// A stopwatch, which measures the time that it's been running for, on the
+/// monotonic clock.
+///
+/// ```rust
+/// use std::time::Stopwatch;
+///
+/// let mut stopwatch = Stopwatch::start_new();
+/// // do work
+/// stopwatch.stop();
+/// // don't count this
+/// stopwatch.start();
+/// // do more work
+/// println!("work took {}", stopwatch.elapsed());
+/// ```
+#[deriving(Clone, Copy, Show)]
+pub struct Stopwatch {
+    // The time elapsed before the stopwatch was last started.
+    elapsed: Duration,
+    // When the stopwatch was started, if it's running.
+    started: Option<Instant>,
+}
+
+impl Stopwatch {
+    /// Makes a stopwatch which isn't running.
+    pub fn new() -> Stopwatch {
+        Stopwatch { elapsed: Duration::zero(), started: None }
+    }
+
+    /// Makes a stopwatch and starts it.
+    pub fn start_new() -> Stopwatch {
+        let mut stopwatch = Stopwatch::new();
+        stopwatch.start();
+        stopwatch
+    }
+
+    /// Starts the stopwatch, if it isn't running.
+    pub fn start(&mut self) {
+        if self.started.is_none() {
+            self.started = Some(Instant::now());
+        }
+    }
+
+    /// Stops the stopwatch, keeping the time elapsed so far.
+    pub fn stop(&mut self) {
+        self.elapsed = self.elapsed();
+        self.started = None;
+    }
+
+    /// Stops the stopwatch, and sets the time elapsed to zero.
+    pub fn reset(&mut self) {
+        *self = Stopwatch::new();
+    }
+
+    /// Sets the time elapsed to zero, and starts the stopwatch.
+    pub fn restart(&mut self) {
+        *self = Stopwatch::start_new();
+    }
+
+    /// Returns whether the stopwatch is running.
+    pub fn is_running(&self) -> bool {
+        self.started.is_some()
+    }
+
+    /// Returns the total time that the stopwatch has been running for.
+    pub fn elapsed(&self) -> Duration {
+        match self.started {
+            Some(started) => self.elapsed + started.elapsed(),
+            None => self.elapsed,
+        }
+    }
+}
+
+/// A reading of the wall clock.
+///
+/// Unlike instants, system times can be compared with times of other
+/// processes and machines, but the wall clock may be set back, so a later
+/// reading may be earlier.
+#[deriving(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Show)]
+pub struct SystemTime {
+    // The time since `UNIX_EPOCH`, with 0 <= nanos < 1,000,000,000.
+    secs: i64,
+    nanos: i32,
+}
+
+/// The system time of January 1, 1970 at 00:00:00 UTC.
+pub const UNIX_EPOCH: SystemTime = SystemTime { secs: 0, nanos: 0 };
+
+/// The error returned by `SystemTime::duration_since` when the other time is
+/// later, as when the wall clock was set back between the two readings.
+#[deriving(Clone, Copy, PartialEq, Eq, Show)]
+pub struct SystemTimeError {
+    duration: Duration,
+}
+
+impl SystemTimeError {
+    /// Returns how much later the other time is.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+impl Error for SystemTimeError {
+    fn description(&self) -> &str {
+        "second time provided was later than self"
+    }
+}
+
+impl fmt::Show for SystemTimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} by {}", self.description(), self.duration)
+    }
+}
+
+impl SystemTime {
+    /// Returns the current reading of the wall clock.
+    pub fn now() -> SystemTime {
+        let (secs, nanos) = system_time();
+        SystemTime { secs: secs, nanos: nanos }
+    }
+
+    /// Returns the time elapsed from `earlier` to this time.
+    ///
+    /// Fails if `earlier` is later than this time, which may happen even if
+    /// it was read first.
+    pub fn duration_since(&self, earlier: SystemTime) -> Result<Duration, SystemTimeError> {
+        let duration = self.since_epoch() - earlier.since_epoch();
+        if duration < Duration::zero() {
+            Err(SystemTimeError { duration: -duration })
+        } else {
+            Ok(duration)
+        }
+    }
+
+    /// Returns the time elapsed since this time.
+    ///
+    /// Fails if the wall clock now reads earlier than this time.
+    pub fn elapsed(&self) -> Result<Duration, SystemTimeError> {
+        SystemTime::now().duration_since(*self)
+    }
+
+    fn since_epoch(&self) -> Duration {
+        Duration::seconds(self.secs) + Duration::nanoseconds(self.nanos as i64)
+    }
+
+    fn from_since_epoch(since_epoch: Duration) -> SystemTime {
+        let secs = since_epoch.num_seconds();
+        // There cannot be more than one second left.
+        let nanos = (since_epoch - Duration::seconds(secs)).num_nanoseconds().unwrap() as i32;
+        if nanos < 0 {
+            SystemTime { secs: secs - 1, nanos: nanos + 1_000_000_000 }
+        } else {
+            SystemTime { secs: secs, nanos: nanos }
+        }
+    }
+}
+
+impl Add<Duration, SystemTime> for SystemTime {
+    fn add(self, other: Duration) -> SystemTime {
+        SystemTime::from_since_epoch(self.since_epoch() + other)
+    }
+}
+
+impl Sub<Duration, SystemTime> for SystemTime {
+    fn sub(self, other: Duration) -> SystemTime {
+        SystemTime::from_since_epoch(self.since_epoch() - other)
+    }
+}
+
+/// Returns the number of days from January 1, 1970 to a date of the proleptic
+/// Gregorian calendar, with months from 1 to 12.
+pub fn days_from_civil(year: i64, month: i64, mday: i64) -> i64 {
+    // Count years from March, so that February 29 is the last day of a year,
+    // in eras of 400 years.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = (if year >= 0 { year } else { year - 399 }) / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + mday - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// The inverse of `days_from_civil`: returns the year, month and day of the
+/// month of a number of days since January 1, 1970.
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = (if days >= 0 { days } else { days - 146096 }) / 146097;
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let mday = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + if month <= 2 { 1 } else { 0 }, month, mday)
+}
+
 /// Returns the current value of a high-resolution performance counter
 /// in nanoseconds since an unspecified epoch.
 // NB: this is intentionally not public, this is not ready to stabilize its api.
@@ -63,6 +333,47 @@ fn precise_time_ns() -> u64 {
     }
 }
 
+/// Returns the seconds and nanoseconds since 1970-01-01T00:00:00Z.
+fn system_time() -> (i64, i32) {
+    return os_system_time();
+
+    #[cfg(windows)]
+    fn os_system_time() -> (i64, i32) {
+        const HECTONANOSECONDS_FROM_1601_TO_1970: i64 = 116444736000000000;
+
+        let mut time = libc::FILETIME { dwLowDateTime: 0, dwHighDateTime: 0 };
+        unsafe {
+            libc::GetSystemTimeAsFileTime(&mut time);
+        }
+        // A FILETIME counts hectonanoseconds (100 nanoseconds) since
+        // 1601-01-01T00:00:00Z.
+        let since_1601 = ((time.dwHighDateTime as u64 << 32) |
+                          (time.dwLowDateTime as u64 << 0)) as i64;
+        let since_1970 = since_1601 - HECTONANOSECONDS_FROM_1601_TO_1970;
+        (since_1970 / 10000000, (since_1970 % 10000000) as i32 * 100)
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    fn os_system_time() -> (i64, i32) {
+        use ptr;
+
+        let mut tv = libc::timeval { tv_sec: 0, tv_usec: 0 };
+        unsafe {
+            imp::gettimeofday(&mut tv, ptr::null_mut());
+        }
+        (tv.tv_sec as i64, tv.tv_usec as i32 * 1000)
+    }
+
+    #[cfg(not(any(windows, target_os = "macos", target_os = "ios")))]
+    fn os_system_time() -> (i64, i32) {
+        let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        unsafe {
+            imp::clock_gettime(libc::CLOCK_REALTIME, &mut ts);
+        }
+        (ts.tv_sec as i64, ts.tv_nsec as i32)
+    }
+}
+
 #[cfg(all(unix, not(target_os = "macos"), not(target_os = "ios")))]
 mod imp {
     use libc::{c_int, timespec};
@@ -79,10 +390,88 @@ mod imp {
 }
 #[cfg(any(target_os = "macos", target_os = "ios"))]
 mod imp {
-    use libc::{c_int, mach_timebase_info};
+    use libc::{c_int, mach_timebase_info, timeval, timezone};
 
     extern {
+        pub fn gettimeofday(tp: *mut timeval, tzp: *mut timezone) -> c_int;
         pub fn mach_absolute_time() -> u64;
         pub fn mach_timebase_info(info: *mut mach_timebase_info) -> c_int;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use prelude::*;
+
+    use super::{Duration, Instant, Stopwatch, SystemTime, UNIX_EPOCH};
+    use super::{civil_from_days, days_from_civil};
+    use io::timer;
+
+    #[test]
+    fn test_instant() {
+        let a = Instant::now();
+        let b = Instant::now();
+        assert!(b >= a);
+        assert!(b.duration_since(a) >= Duration::zero());
+        assert_eq!(a.duration_since(b), -b.duration_since(a));
+
+        let second = Duration::seconds(1);
+        assert_eq!((a + second) - a, second);
+        assert_eq!((a + second) - second, a);
+        assert_eq!(a - (a + second), -second);
+
+        timer::sleep(Duration::milliseconds(10));
+        assert!(a.elapsed() >= Duration::milliseconds(10));
+    }
+
+    #[test]
+    fn test_stopwatch() {
+        let mut stopwatch = Stopwatch::new();
+        assert!(!stopwatch.is_running());
+        assert_eq!(stopwatch.elapsed(), Duration::zero());
+
+        stopwatch.start();
+        assert!(stopwatch.is_running());
+        timer::sleep(Duration::milliseconds(10));
+        stopwatch.stop();
+        let elapsed = stopwatch.elapsed();
+        assert!(elapsed >= Duration::milliseconds(10));
+        timer::sleep(Duration::milliseconds(10));
+        assert_eq!(stopwatch.elapsed(), elapsed);
+
+        stopwatch.start();
+        assert!(stopwatch.elapsed() >= elapsed);
+        stopwatch.reset();
+        assert!(!stopwatch.is_running());
+        assert_eq!(stopwatch.elapsed(), Duration::zero());
+    }
+
+    #[test]
+    fn test_system_time() {
+        // 2014-01-01T00:00:00Z
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        assert!(since_epoch > Duration::seconds(1388534400));
+
+        let a = UNIX_EPOCH + Duration::seconds(100);
+        let b = a - Duration::milliseconds(1500);
+        assert_eq!(a.duration_since(b), Ok(Duration::milliseconds(1500)));
+        let err = b.duration_since(a).unwrap_err();
+        assert_eq!(err.duration(), Duration::milliseconds(1500));
+        assert_eq!(err.to_string(), "second time provided was later than self by PT1.500S");
+        assert!(a.elapsed().is_ok());
+    }
+
+    #[test]
+    fn test_civil() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(-719529), (-1, 12, 31));
+        for &days in [-1_000_000, -1, 0, 59, 10_000, 1_000_000].iter() {
+            let (year, month, mday) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, mday), days);
+        }
+    }
+}
@@ -15,6 +15,9 @@ use ast::{MetaItem, Item, Expr, MutMutable};
 use codemap::Span;
 use ext::base::ExtCtxt;
 use ext::build::AstBuilder;
+use ext::deriving::encodable::{FieldOptions, TypeOptions, EncoderDefined, External, Internal};
+use ext::deriving::encodable::{field_name, struct_field_options, type_options};
+use ext::deriving::encodable::{variant_field_options, variant_name};
 use ext::deriving::generic::*;
 use ext::deriving::generic::ty::*;
 use parse::token::InternedString;
@@ -28,6 +31,7 @@ pub fn expand_deriving_decodable<F>(cx: &mut ExtCtxt,
                                     push: F) where
     F: FnOnce(P<Item>),
 {
+    let options = type_options(cx, true, item);
     let trait_def = TraitDef {
         span: span,
         attributes: Vec::new(),
@@ -54,7 +58,7 @@ pub fn expand_deriving_decodable<F>(cx: &mut ExtCtxt,
                                                box Literal(Path::new_local("__E"))), true)),
                 attributes: Vec::new(),
                 combine_substructure: combine_substructure(|a, b, c| {
-                    decodable_substructure(a, b, c)
+                    decodable_substructure(a, b, c, &options)
                 }),
             })
     };
@@ -63,7 +67,7 @@ pub fn expand_deriving_decodable<F>(cx: &mut ExtCtxt,
 }
 
 fn decodable_substructure(cx: &mut ExtCtxt, trait_span: Span,
-                          substr: &Substructure) -> P<Expr> {
+                          substr: &Substructure, type_options: &TypeOptions) -> P<Expr> {
     let decoder = substr.nonself_args[0].clone();
     let recurse = vec!(cx.ident_of("serialize"),
                     cx.ident_of("Decodable"),
@@ -73,13 +77,12 @@ fn decodable_substructure(cx: &mut ExtCtxt, trait_span: Span,
     let blkdecoder = cx.expr_ident(trait_span, blkarg);
     let calldecode = cx.expr_call_global(trait_span, recurse, vec!(blkdecoder.clone()));
     let lambdadecode = cx.lambda_expr_1(trait_span, calldecode, blkarg);
+    let type_name = cx.expr_str(trait_span, token::get_ident(substr.type_ident));
 
     return match *substr.fields {
-        StaticStruct(_, ref summary) => {
-            let nfields = match *summary {
-                Unnamed(ref fields) => fields.len(),
-                Named(ref fields) => fields.len()
-            };
+        StaticStruct(struct_def, ref summary) => {
+            let options = struct_field_options(cx, true, struct_def);
+            let nfields = options.iter().filter(|options| !options.skip).count();
             let read_struct_field = cx.ident_of("read_struct_field");
 
             let path = cx.path_ident(trait_span, substr.type_ident);
@@ -87,6 +90,7 @@ fn decodable_substructure(cx: &mut ExtCtxt, trait_span: Span,
                                               trait_span,
                                               path,
                                               summary,
+                                              options.as_slice(),
                                               |cx, span, name, field| {
                 cx.expr_try(span,
                     cx.expr_method_call(span, blkdecoder.clone(), read_struct_field,
@@ -94,77 +98,298 @@ fn decodable_substructure(cx: &mut ExtCtxt, trait_span: Span,
                                           cx.expr_uint(span, field),
                                           lambdadecode.clone())))
             });
-            let result = cx.expr_ok(trait_span, result);
+            let result = if type_options.deny_unknown_fields {
+                // Read the fields before checking that there are no others.
+                let value = cx.ident_of("__value");
+                let check = cx.expr_method_call(trait_span, blkdecoder.clone(),
+                                                cx.ident_of("deny_unknown_fields"), vec!());
+                let stmts = vec!(cx.stmt_let(trait_span, false, value, result),
+                                 cx.stmt_expr(cx.expr_try(trait_span, check)));
+                let ok = cx.expr_ok(trait_span, cx.expr_ident(trait_span, value));
+                cx.expr_block(cx.block(trait_span, stmts, Some(ok)))
+            } else {
+                cx.expr_ok(trait_span, result)
+            };
             cx.expr_method_call(trait_span,
                                 decoder,
                                 cx.ident_of("read_struct"),
                                 vec!(
-                cx.expr_str(trait_span, token::get_ident(substr.type_ident)),
+                type_name,
                 cx.expr_uint(trait_span, nfields),
                 cx.lambda_expr_1(trait_span, result, blkarg)
             ))
         }
-        StaticEnum(_, ref fields) => {
+        StaticEnum(enum_def, ref fields) => {
             let variant = cx.ident_of("i");
+            let tag = cx.ident_of("__tag");
 
             let mut arms = Vec::new();
             let mut variants = Vec::new();
             let rvariant_arg = cx.ident_of("read_enum_variant_arg");
+            let read_struct_field = cx.ident_of("read_struct_field");
+            let read_tuple_arg = cx.ident_of("read_tuple_arg");
 
-            for (i, &(name, v_span, ref parts)) in fields.iter().enumerate() {
-                variants.push(cx.expr_str(v_span, token::get_ident(name)));
-
+            for (i, (&(name, v_span, ref parts), ast_variant)) in
+                    fields.iter().zip(enum_def.variants.iter()).enumerate() {
+                let options = variant_field_options(cx, true, &**ast_variant);
+                let nfields = options.iter().filter(|options| !options.skip).count();
+                let encoded_name = variant_name(cx, true, &**ast_variant);
                 let path = cx.path(trait_span, vec![substr.type_ident, name]);
-                let decoded = decode_static_fields(cx,
-                                                   v_span,
-                                                   path,
-                                                   parts,
-                                                   |cx, span, _, field| {
-                    let idx = cx.expr_uint(span, field);
-                    cx.expr_try(span,
-                        cx.expr_method_call(span, blkdecoder.clone(), rvariant_arg,
-                                            vec!(idx, lambdadecode.clone())))
-                });
-
-                arms.push(cx.arm(v_span,
-                                 vec!(cx.pat_lit(v_span, cx.expr_uint(v_span, i))),
-                                 decoded));
+
+                let (pat, decoded) = match type_options.repr {
+                    EncoderDefined => {
+                        variants.push(cx.expr_str(v_span, encoded_name));
+                        let decoded = decode_static_fields(cx,
+                                                           v_span,
+                                                           path,
+                                                           parts,
+                                                           options.as_slice(),
+                                                           |cx, span, _, field| {
+                            let idx = cx.expr_uint(span, field);
+                            cx.expr_try(span,
+                                cx.expr_method_call(span, blkdecoder.clone(), rvariant_arg,
+                                                    vec!(idx, lambdadecode.clone())))
+                        });
+                        (cx.pat_lit(v_span, cx.expr_uint(v_span, i)),
+                         cx.expr_ok(v_span, decoded))
+                    }
+
+                    // { "Frog": { "legs": 4 } }
+                    External => {
+                        let is_struct = match ast_variant.node.kind {
+                            ast::StructVariantKind(_) => true,
+                            ast::TupleVariantKind(_) => false,
+                        };
+                        let decoded = decode_static_fields(cx,
+                                                           v_span,
+                                                           path,
+                                                           parts,
+                                                           options.as_slice(),
+                                                           |cx, span, name, field| {
+                            let idx = cx.expr_uint(span, field);
+                            let call = if is_struct {
+                                cx.expr_method_call(span, blkdecoder.clone(), read_struct_field,
+                                                    vec!(cx.expr_str(span, name), idx,
+                                                         lambdadecode.clone()))
+                            } else {
+                                cx.expr_method_call(span, blkdecoder.clone(), read_tuple_arg,
+                                                    vec!(idx, lambdadecode.clone()))
+                            };
+                            cx.expr_try(span, call)
+                        });
+                        let lambda = cx.lambda_expr_1(v_span, cx.expr_ok(v_span, decoded),
+                                                      blkarg);
+                        let decoded = if is_struct {
+                            cx.expr_method_call(v_span, blkdecoder.clone(),
+                                                cx.ident_of("read_struct"),
+                                                vec!(cx.expr_str(v_span, encoded_name.clone()),
+                                                     cx.expr_uint(v_span, nfields),
+                                                     lambda))
+                        } else {
+                            cx.expr_method_call(v_span, blkdecoder.clone(),
+                                                cx.ident_of("read_tuple"),
+                                                vec!(cx.expr_uint(v_span, nfields), lambda))
+                        };
+                        (cx.pat_lit(v_span, cx.expr_str(v_span, encoded_name)), decoded)
+                    }
+
+                    // { "type": "Frog", "legs": 4 }
+                    Internal(_) => {
+                        let decoded = decode_static_fields(cx,
+                                                           v_span,
+                                                           path,
+                                                           parts,
+                                                           options.as_slice(),
+                                                           |cx, span, name, field| {
+                            // the tag is field 0
+                            cx.expr_try(span,
+                                cx.expr_method_call(span, blkdecoder.clone(), read_struct_field,
+                                                    vec!(cx.expr_str(span, name),
+                                                         cx.expr_uint(span, field + 1),
+                                                         lambdadecode.clone())))
+                        });
+                        (cx.pat_lit(v_span, cx.expr_str(v_span, encoded_name)),
+                         cx.expr_ok(v_span, decoded))
+                    }
+                };
+                arms.push(cx.arm(v_span, vec!(pat), decoded));
             }
 
-            arms.push(cx.arm_unreachable(trait_span));
-
-            let result = cx.expr_ok(trait_span,
-                                    cx.expr_match(trait_span,
-                                                  cx.expr_ident(trait_span, variant), arms));
-            let lambda = cx.lambda_expr(trait_span, vec!(blkarg, variant), result);
-            let variant_vec = cx.expr_vec(trait_span, variants);
-            let variant_vec = cx.expr_addr_of(trait_span, variant_vec);
-            let result = cx.expr_method_call(trait_span, blkdecoder,
-                                             cx.ident_of("read_enum_variant"),
-                                             vec!(variant_vec, lambda));
-            cx.expr_method_call(trait_span,
-                                decoder,
-                                cx.ident_of("read_enum"),
-                                vec!(
-                cx.expr_str(trait_span, token::get_ident(substr.type_ident)),
-                cx.lambda_expr_1(trait_span, result, blkarg)
-            ))
+            match type_options.repr {
+                EncoderDefined => {
+                    arms.push(cx.arm_unreachable(trait_span));
+
+                    let result = cx.expr_match(trait_span,
+                                               cx.expr_ident(trait_span, variant), arms);
+                    let lambda = cx.lambda_expr(trait_span, vec!(blkarg, variant), result);
+                    let variant_vec = cx.expr_vec(trait_span, variants);
+                    let variant_vec = cx.expr_addr_of(trait_span, variant_vec);
+                    let result = cx.expr_method_call(trait_span, blkdecoder,
+                                                     cx.ident_of("read_enum_variant"),
+                                                     vec!(variant_vec, lambda));
+                    cx.expr_method_call(trait_span,
+                                        decoder,
+                                        cx.ident_of("read_enum"),
+                                        vec!(
+                        type_name,
+                        cx.lambda_expr_1(trait_span, result, blkarg)
+                    ))
+                }
+
+                External => {
+                    arms.push(unknown_variant_arm(cx, trait_span, blkdecoder.clone(), tag));
+
+                    // The map must have exactly one entry: read_map_has_elt
+                    // tells for decoders that don't know the length upfront.
+                    let len = cx.ident_of("__len");
+                    let has_elt = |cx: &mut ExtCtxt, idx: uint| {
+                        cx.expr_try(trait_span,
+                            cx.expr_method_call(trait_span, blkdecoder.clone(),
+                                                cx.ident_of("read_map_has_elt"),
+                                                vec!(cx.expr_uint(trait_span, idx),
+                                                     cx.expr_ident(trait_span, len))))
+                    };
+                    let too_long = cx.expr_binary(trait_span, ast::BiGt,
+                                                  cx.expr_ident(trait_span, len),
+                                                  cx.expr_uint(trait_span, 1));
+                    let first = has_elt(cx, 0);
+                    let empty = cx.expr_unary(trait_span, ast::UnNot, first);
+                    let check_first = cx.expr_if(trait_span,
+                                                 cx.expr_binary(trait_span, ast::BiOr,
+                                                                too_long, empty),
+                                                 single_variant_error(cx, trait_span,
+                                                                      blkdecoder.clone()),
+                                                 None);
+                    let second = has_elt(cx, 1);
+                    let check_second = cx.expr_if(trait_span, second,
+                                                  single_variant_error(cx, trait_span,
+                                                                       blkdecoder.clone()),
+                                                  None);
+
+                    let read_key = cx.expr_method_call(trait_span, blkdecoder.clone(),
+                                                       cx.ident_of("read_map_elt_key"),
+                                                       vec!(cx.expr_uint(trait_span, 0),
+                                                            lambdadecode.clone()));
+                    let value = cx.ident_of("__value");
+                    let payload = cx.expr_match(trait_span, tag_slice(cx, trait_span, tag),
+                                                arms);
+                    let read_value = cx.expr_method_call(trait_span, blkdecoder.clone(),
+                                                         cx.ident_of("read_map_elt_val"),
+                                                         vec!(cx.expr_uint(trait_span, 0),
+                                                              cx.lambda_expr_1(trait_span,
+                                                                               payload,
+                                                                               blkarg)));
+                    let stmts = vec!(
+                        cx.stmt_expr(check_first),
+                        read_tag(cx, trait_span, tag, read_key),
+                        cx.stmt_let(trait_span, false, value,
+                                    cx.expr_try(trait_span, read_value)),
+                        cx.stmt_expr(check_second));
+                    let result = cx.expr_ok(trait_span, cx.expr_ident(trait_span, value));
+                    let lambda = cx.lambda(trait_span, vec!(blkarg, len),
+                                           cx.block(trait_span, stmts, Some(result)));
+                    cx.expr_method_call(trait_span,
+                                        decoder,
+                                        cx.ident_of("read_map"),
+                                        vec!(lambda))
+                }
+
+                Internal(ref tag_name) => {
+                    arms.push(unknown_variant_arm(cx, trait_span, blkdecoder.clone(), tag));
+
+                    let read_tag_field = cx.expr_method_call(trait_span, blkdecoder.clone(),
+                                                             read_struct_field,
+                                                             vec!(cx.expr_str(trait_span,
+                                                                              tag_name.clone()),
+                                                                  cx.expr_uint(trait_span, 0),
+                                                                  lambdadecode.clone()));
+                    let result = cx.expr_match(trait_span, tag_slice(cx, trait_span, tag), arms);
+                    let blk = cx.block(trait_span,
+                                       vec!(read_tag(cx, trait_span, tag, read_tag_field)),
+                                       Some(result));
+                    cx.expr_method_call(trait_span,
+                                        decoder,
+                                        cx.ident_of("read_struct"),
+                                        vec!(
+                        type_name,
+                        cx.expr_uint(trait_span, 0),
+                        cx.lambda1(trait_span, blk, blkarg)
+                    ))
+                }
+            }
         }
         _ => cx.bug("expected StaticEnum or StaticStruct in deriving(Decodable)")
     };
 }
 
+/// `let __tag: String = try!(<read>);`
+fn read_tag(cx: &mut ExtCtxt, span: Span, tag: ast::Ident, read: P<Expr>) -> P<ast::Stmt> {
+    let string = cx.path_global(span, vec!(cx.ident_of("std"),
+                                           cx.ident_of("string"),
+                                           cx.ident_of("String")));
+    cx.stmt_let_typed(span, false, tag, cx.ty_path(string), cx.expr_try(span, read))
+}
+
+/// `__tag.as_slice()`
+fn tag_slice(cx: &mut ExtCtxt, span: Span, tag: ast::Ident) -> P<Expr> {
+    cx.expr_method_call(span, cx.expr_ident(span, tag), cx.ident_of("as_slice"), vec!())
+}
+
+/// `_ => Err(_d.error(["unknown variant `", __tag.as_slice(), "`"].concat().as_slice()))`
+fn unknown_variant_arm(cx: &mut ExtCtxt, span: Span, decoder: P<Expr>,
+                       tag: ast::Ident) -> ast::Arm {
+    let parts = vec!(cx.expr_str(span, InternedString::new("unknown variant `")),
+                     tag_slice(cx, span, tag),
+                     cx.expr_str(span, InternedString::new("`")));
+    let msg = cx.expr_method_call(span, cx.expr_vec_slice(span, parts),
+                                  cx.ident_of("concat"), vec!());
+    let msg = cx.expr_method_call(span, msg, cx.ident_of("as_slice"), vec!());
+    let err = cx.expr_method_call(span, decoder, cx.ident_of("error"), vec!(msg));
+    cx.arm(span, vec!(cx.pat_wild(span)), cx.expr_err(span, err))
+}
+
+/// `return Err(_d.error("expected a map with a single variant"))`
+fn single_variant_error(cx: &mut ExtCtxt, span: Span, decoder: P<Expr>) -> P<Expr> {
+    let msg = cx.expr_str(span, InternedString::new("expected a map with a single variant"));
+    let err = cx.expr_method_call(span, decoder, cx.ident_of("error"), vec!(msg));
+    cx.expr(span, ast::ExprRet(Some(cx.expr_err(span, err))))
+}
+
 /// Create a decoder for a single enum variant/struct:
 /// - `outer_pat_path` is the path to this enum variant/struct
-/// - `getarg` should retrieve the `uint`-th field with name `@str`.
+/// - `options` are the `#[serialize(...)]` options of the fields
+/// - `getarg` should retrieve the `uint`-th field with name `@str`, where
+///   the skipped fields aren't counted.
 fn decode_static_fields<F>(cx: &mut ExtCtxt,
                            trait_span: Span,
                            outer_pat_path: ast::Path,
                            fields: &StaticFields,
+                           options: &[FieldOptions],
                            mut getarg: F)
                            -> P<Expr> where
     F: FnMut(&mut ExtCtxt, Span, InternedString, uint) -> P<Expr>,
 {
+    let mut nread = 0u;
+    let mut decode_field = |cx: &mut ExtCtxt, span: Span, name: Option<ast::Ident>, i: uint| {
+        let options = &options[i];
+        if options.skip {
+            return default_value(cx, span);
+        }
+        let arg = getarg(cx, span, field_name(options, name, i), nread);
+        nread += 1;
+        if options.default {
+            // The field is decoded as an Option, which is None when it's
+            // missing.
+            let value = cx.ident_of("__value");
+            let some = cx.arm(span, vec!(cx.pat_some(span, cx.pat_ident(span, value))),
+                              cx.expr_ident(span, value));
+            let none = cx.arm(span, vec!(cx.pat_none(span)), default_value(cx, span));
+            cx.expr_match(span, arg, vec!(some, none))
+        } else {
+            arg
+        }
+    };
+
     match *fields {
         Unnamed(ref fields) => {
             let path_expr = cx.expr_path(outer_pat_path);
@@ -172,10 +397,7 @@ fn decode_static_fields<F>(cx: &mut ExtCtxt,
                 path_expr
             } else {
                 let fields = fields.iter().enumerate().map(|(i, &span)| {
-                    getarg(cx, span,
-                           token::intern_and_get_ident(format!("_field{}",
-                                                               i).as_slice()),
-                           i)
+                    decode_field(cx, span, None, i)
                 }).collect();
 
                 cx.expr_call(trait_span, path_expr, fields)
@@ -184,10 +406,19 @@ fn decode_static_fields<F>(cx: &mut ExtCtxt,
         Named(ref fields) => {
             // use the field's span to get nicer error messages.
             let fields = fields.iter().enumerate().map(|(i, &(name, span))| {
-                let arg = getarg(cx, span, token::get_ident(name), i);
+                let arg = decode_field(cx, span, Some(name), i);
                 cx.field_imm(span, name, arg)
             }).collect();
             cx.expr_struct(trait_span, outer_pat_path, fields)
         }
     }
 }
+
+/// `::std::default::Default::default()`
+fn default_value(cx: &mut ExtCtxt, span: Span) -> P<Expr> {
+    let path = vec!(cx.ident_of("std"),
+                    cx.ident_of("default"),
+                    cx.ident_of("Default"),
+                    cx.ident_of("default"));
+    cx.expr_call_global(span, path, Vec::new())
+}
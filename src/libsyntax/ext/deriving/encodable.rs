@@ -87,16 +87,264 @@
 //!     }
 //! }
 //! ```
+//!
+//! # Options
+//!
+//! The representation can be adjusted with `#[serialize(...)]` attributes,
+//! so that a persisted format can evolve without hand-written impls.
+//!
+//! On fields:
+//!
+//! * `rename = "name"` encodes and decodes the field under another name.
+//! * `default` decodes a missing field as `Default::default()`. The field is
+//!   decoded as an `Option` of its type, so a null value also gives the
+//!   default.
+//! * `skip` leaves the field out, and decodes it as `Default::default()`.
+//!
+//! On enum variants, `rename = "name"` changes the name of the variant.
+//!
+//! On structs, `deny_unknown_fields` makes decoding fail when the encoded
+//! struct has fields that the struct doesn't, for decoders that can tell.
+//!
+//! On enums, the representation of the variants is left to the encoder
+//! (`emit_enum_variant`) unless one of these is given:
+//!
+//! * `external` encodes a variant as a map with one entry, from the name of
+//!   the variant to its fields: a struct for struct variants, and a tuple
+//!   otherwise. In JSON, `Frog { legs: 4 }` is `{"Frog":{"legs":4}}`.
+//! * `tag = "key"` encodes a variant as a struct with the name of the
+//!   variant under `key`, followed by its fields. In JSON, this is
+//!   `{"key":"Frog","legs":4}`. Only unit and struct variants can be
+//!   represented this way.
+//!
+//! For example:
+//!
+//! ```ignore
+//! #[deriving(Encodable, Decodable)]
+//! #[serialize(deny_unknown_fields)]
+//! struct Config {
+//!     #[serialize(rename = "type")]
+//!     kind: String,
+//!     #[serialize(default)]
+//!     retries: uint,
+//!     #[serialize(skip)]
+//!     cache: Vec<u8>,
+//! }
+//! ```
 
-use ast::{MetaItem, Item, Expr, ExprRet, MutMutable};
+use ast;
+use ast::{MetaItem, MetaNameValue, MetaWord, Item, Expr, ExprRet, Ident, MutMutable};
+use attr::AttrMetaMethods;
 use codemap::Span;
 use ext::base::ExtCtxt;
 use ext::build::AstBuilder;
 use ext::deriving::generic::*;
 use ext::deriving::generic::ty::*;
+use parse::token::InternedString;
 use parse::token;
 use ptr::P;
 
+use std::default::Default;
+
+pub use self::EnumRepr::*;
+
+/// The `#[serialize(...)]` options of a field.
+#[deriving(Default)]
+pub struct FieldOptions {
+    /// The name to use instead of the field's.
+    pub rename: Option<InternedString>,
+    /// Whether a missing field is decoded as `Default::default()`.
+    pub default: bool,
+    /// Whether the field is left out, and decoded as `Default::default()`.
+    pub skip: bool,
+}
+
+/// How the variants of an enum are represented.
+pub enum EnumRepr {
+    /// As the encoder sees fit, through `emit_enum_variant`.
+    EncoderDefined,
+    /// As a map from the name of the variant to its fields.
+    External,
+    /// As a struct with the name of the variant under the given key.
+    Internal(InternedString),
+}
+
+/// The `#[serialize(...)]` options of a struct or an enum.
+pub struct TypeOptions {
+    /// Whether decoding a struct fails on fields the struct doesn't have.
+    pub deny_unknown_fields: bool,
+    pub repr: EnumRepr,
+    /// Whether errors in the options of the type, its fields and its variants
+    /// are reported by this expansion.
+    pub report_errors: bool,
+}
+
+fn option_error(cx: &mut ExtCtxt, report_errors: bool, span: Span, msg: &str) {
+    if report_errors {
+        cx.span_err(span, msg);
+    }
+}
+
+// Collects the options of the `#[serialize(...)]` attributes in `attrs`.
+fn serialize_options(cx: &mut ExtCtxt, report_errors: bool,
+                     attrs: &[ast::Attribute]) -> Vec<P<MetaItem>> {
+    let mut options = Vec::new();
+    for attr in attrs.iter() {
+        if !attr.check_name("serialize") {
+            continue;
+        }
+        match attr.meta_item_list() {
+            Some(items) => options.extend(items.iter().map(|item| item.clone())),
+            None => option_error(cx, report_errors, attr.span,
+                                 "expected a list of options, like `#[serialize(skip)]`"),
+        }
+    }
+    options
+}
+
+fn unsupported_option(cx: &mut ExtCtxt, report_errors: bool, option: &MetaItem) {
+    option_error(cx, report_errors, option.span,
+                 format!("unsupported `serialize` option `{}` here",
+                         option.name()).as_slice());
+}
+
+fn string_option(cx: &mut ExtCtxt, report_errors: bool,
+                 option: &MetaItem) -> Option<InternedString> {
+    let value = option.value_str();
+    if value.is_none() {
+        option_error(cx, report_errors, option.span,
+                     format!("expected a string, like `{} = \"...\"`",
+                             option.name()).as_slice());
+    }
+    value
+}
+
+/// Reads the `#[serialize(...)]` options of a field.
+pub fn field_options(cx: &mut ExtCtxt, report_errors: bool,
+                     attrs: &[ast::Attribute]) -> FieldOptions {
+    let mut options: FieldOptions = Default::default();
+    for option in serialize_options(cx, report_errors, attrs).iter() {
+        match option.node {
+            MetaWord(ref name) if name.get() == "default" => options.default = true,
+            MetaWord(ref name) if name.get() == "skip" => options.skip = true,
+            MetaNameValue(ref name, _) if name.get() == "rename" => {
+                options.rename = string_option(cx, report_errors, &**option);
+            }
+            _ => unsupported_option(cx, report_errors, &**option),
+        }
+    }
+    options
+}
+
+/// Reads the options of the fields of a struct.
+pub fn struct_field_options(cx: &mut ExtCtxt, report_errors: bool,
+                            struct_def: &ast::StructDef) -> Vec<FieldOptions> {
+    struct_def.fields.iter().map(|field| {
+        field_options(cx, report_errors, field.node.attrs.as_slice())
+    }).collect()
+}
+
+/// Reads the options of the fields of an enum variant. The fields of tuple
+/// variants can't have attributes, so they have none.
+pub fn variant_field_options(cx: &mut ExtCtxt, report_errors: bool,
+                             variant: &ast::Variant) -> Vec<FieldOptions> {
+    match variant.node.kind {
+        ast::TupleVariantKind(ref args) => args.iter().map(|_| Default::default()).collect(),
+        ast::StructVariantKind(ref struct_def) => {
+            struct_field_options(cx, report_errors, &**struct_def)
+        }
+    }
+}
+
+/// Returns the name a variant is encoded as, from its `rename` option if it
+/// has one.
+pub fn variant_name(cx: &mut ExtCtxt, report_errors: bool,
+                    variant: &ast::Variant) -> InternedString {
+    let mut name = token::get_ident(variant.node.name);
+    for option in serialize_options(cx, report_errors, variant.node.attrs.as_slice()).iter() {
+        match option.node {
+            MetaNameValue(ref key, _) if key.get() == "rename" => {
+                match string_option(cx, report_errors, &**option) {
+                    Some(rename) => name = rename,
+                    None => {}
+                }
+            }
+            _ => unsupported_option(cx, report_errors, &**option),
+        }
+    }
+    name
+}
+
+/// Returns the name a field is encoded as: its `rename` option, its name,
+/// or `_fieldN` for the `N`th field of a tuple struct or variant.
+pub fn field_name(options: &FieldOptions, name: Option<Ident>, idx: uint) -> InternedString {
+    match (&options.rename, name) {
+        (&Some(ref rename), _) => rename.clone(),
+        (&None, Some(id)) => token::get_ident(id),
+        (&None, None) => token::intern_and_get_ident(format!("_field{}", idx).as_slice()),
+    }
+}
+
+/// Returns whether `item` derives Decodable as well as Encodable.
+pub fn derives_decodable(item: &Item) -> bool {
+    item.attrs.iter().any(|attr| {
+        attr.check_name("deriving") && match attr.meta_item_list() {
+            Some(traits) => traits.iter().any(|t| {
+                t.name().get() == "Decodable" || t.name().get() == "RustcDecodable"
+            }),
+            None => false,
+        }
+    })
+}
+
+/// Reads the `#[serialize(...)]` options of the struct or enum being derived
+/// upon. Both derives read the options, so only one of them should report
+/// their errors.
+pub fn type_options(cx: &mut ExtCtxt, report_errors: bool, item: &Item) -> TypeOptions {
+    let mut options = TypeOptions {
+        deny_unknown_fields: false,
+        repr: EncoderDefined,
+        report_errors: report_errors,
+    };
+    let enum_def = match item.node {
+        ast::ItemEnum(ref enum_def, _) => Some(enum_def),
+        _ => None,
+    };
+    for option in serialize_options(cx, report_errors, item.attrs.as_slice()).iter() {
+        match option.node {
+            MetaWord(ref name) if name.get() == "deny_unknown_fields" && enum_def.is_none() => {
+                options.deny_unknown_fields = true;
+            }
+            MetaWord(ref name) if name.get() == "external" && enum_def.is_some() => {
+                options.repr = External;
+            }
+            MetaNameValue(ref name, _) if name.get() == "tag" && enum_def.is_some() => {
+                match string_option(cx, report_errors, &**option) {
+                    Some(tag) => options.repr = Internal(tag),
+                    None => {}
+                }
+            }
+            _ => unsupported_option(cx, report_errors, &**option),
+        }
+    }
+    match (&options.repr, enum_def) {
+        (&Internal(_), Some(enum_def)) => {
+            for variant in enum_def.variants.iter() {
+                match variant.node.kind {
+                    ast::TupleVariantKind(ref args) if !args.is_empty() => {
+                        option_error(cx, report_errors, variant.span,
+                                     "only unit and struct variants can be in an enum \
+                                      with a `tag` option");
+                    }
+                    _ => {}
+                }
+            }
+        }
+        _ => {}
+    }
+    options
+}
+
 pub fn expand_deriving_encodable<F>(cx: &mut ExtCtxt,
                                     span: Span,
                                     mitem: &MetaItem,
@@ -104,6 +352,8 @@ pub fn expand_deriving_encodable<F>(cx: &mut ExtCtxt,
                                     push: F) where
     F: FnOnce(P<Item>),
 {
+    // When the type derives Decodable too, its expansion reports the errors.
+    let options = type_options(cx, !derives_decodable(item), item);
     let trait_def = TraitDef {
         span: span,
         attributes: Vec::new(),
@@ -132,7 +382,7 @@ pub fn expand_deriving_encodable<F>(cx: &mut ExtCtxt,
                                            true)),
                 attributes: Vec::new(),
                 combine_substructure: combine_substructure(|a, b, c| {
-                    encodable_substructure(a, b, c)
+                    encodable_substructure(a, b, c, item, &options)
                 }),
             })
     };
@@ -141,64 +391,32 @@ pub fn expand_deriving_encodable<F>(cx: &mut ExtCtxt,
 }
 
 fn encodable_substructure(cx: &mut ExtCtxt, trait_span: Span,
-                          substr: &Substructure) -> P<Expr> {
+                          substr: &Substructure, item: &Item,
+                          type_options: &TypeOptions) -> P<Expr> {
     let encoder = substr.nonself_args[0].clone();
     // throw an underscore in front to suppress unused variable warnings
     let blkarg = cx.ident_of("_e");
     let blkencoder = cx.expr_ident(trait_span, blkarg);
-    let encode = cx.ident_of("encode");
+    let type_name = cx.expr_str(trait_span, token::get_ident(substr.type_ident));
 
     return match *substr.fields {
         Struct(ref fields) => {
-            let emit_struct_field = cx.ident_of("emit_struct_field");
-            let mut stmts = Vec::new();
-            let last = fields.len() - 1;
-            for (i, &FieldInfo {
-                    name,
-                    ref self_,
-                    span,
-                    ..
-                }) in fields.iter().enumerate() {
-                let name = match name {
-                    Some(id) => token::get_ident(id),
-                    None => {
-                        token::intern_and_get_ident(format!("_field{}",
-                                                            i).as_slice())
-                    }
-                };
-                let enc = cx.expr_method_call(span, self_.clone(),
-                                              encode, vec!(blkencoder.clone()));
-                let lambda = cx.lambda_expr_1(span, enc, blkarg);
-                let call = cx.expr_method_call(span, blkencoder.clone(),
-                                               emit_struct_field,
-                                               vec!(cx.expr_str(span, name),
-                                                 cx.expr_uint(span, i),
-                                                 lambda));
-
-                // last call doesn't need a try!
-                let call = if i != last {
-                    cx.expr_try(span, call)
-                } else {
-                    cx.expr(span, ExprRet(Some(call)))
-                };
-                stmts.push(cx.stmt_expr(call));
-            }
-
-            // unit structs have no fields and need to return Ok()
-            if stmts.is_empty() {
-                let ret_ok = cx.expr(trait_span,
-                                     ExprRet(Some(cx.expr_ok(trait_span,
-                                                             cx.expr_tuple(trait_span, vec![])))));
-                stmts.push(cx.stmt_expr(ret_ok));
-            }
-
-            let blk = cx.lambda_stmts_1(trait_span, stmts, blkarg);
+            let options = match item.node {
+                ast::ItemStruct(ref struct_def, _) => {
+                    struct_field_options(cx, type_options.report_errors, &**struct_def)
+                }
+                _ => cx.bug("expected a struct in deriving(Encodable)"),
+            };
+            let calls = encode_fields(cx, blkarg, fields.as_slice(), options.as_slice(),
+                                      0, "emit_struct_field", true);
+            let nfields = calls.len();
+            let blk = cx.lambda_stmts_1(trait_span, sequence(cx, trait_span, calls), blkarg);
             cx.expr_method_call(trait_span,
                                 encoder,
                                 cx.ident_of("emit_struct"),
                                 vec!(
-                cx.expr_str(trait_span, token::get_ident(substr.type_ident)),
-                cx.expr_uint(trait_span, fields.len()),
+                type_name,
+                cx.expr_uint(trait_span, nfields),
                 blk
             ))
         }
@@ -210,52 +428,162 @@ fn encodable_substructure(cx: &mut ExtCtxt, trait_span: Span,
             // actually exist.
             let me = cx.stmt_let(trait_span, false, blkarg, encoder);
             let encoder = cx.expr_ident(trait_span, blkarg);
-            let emit_variant_arg = cx.ident_of("emit_enum_variant_arg");
-            let mut stmts = Vec::new();
-            let last = fields.len() - 1;
-            for (i, &FieldInfo { ref self_, span, .. }) in fields.iter().enumerate() {
-                let enc = cx.expr_method_call(span, self_.clone(),
-                                              encode, vec!(blkencoder.clone()));
-                let lambda = cx.lambda_expr_1(span, enc, blkarg);
-                let call = cx.expr_method_call(span, blkencoder.clone(),
-                                               emit_variant_arg,
-                                               vec!(cx.expr_uint(span, i),
-                                                 lambda));
-                let call = if i != last {
-                    cx.expr_try(span, call)
-                } else {
-                    cx.expr(span, ExprRet(Some(call)))
-                };
-                stmts.push(cx.stmt_expr(call));
-            }
+            let options = variant_field_options(cx, type_options.report_errors, variant);
+            let name = variant_name(cx, type_options.report_errors, variant);
+            let name = cx.expr_str(trait_span, name);
+            let is_struct = match variant.node.kind {
+                ast::StructVariantKind(_) => true,
+                ast::TupleVariantKind(_) => false,
+            };
 
-            // enums with no fields need to return Ok()
-            if stmts.len() == 0 {
-                let ret_ok = cx.expr(trait_span,
-                                     ExprRet(Some(cx.expr_ok(trait_span,
-                                                             cx.expr_tuple(trait_span, vec![])))));
-                stmts.push(cx.stmt_expr(ret_ok));
-            }
+            let ret = match type_options.repr {
+                EncoderDefined => {
+                    let calls = encode_fields(cx, blkarg, fields.as_slice(), options.as_slice(),
+                                              0, "emit_enum_variant_arg", false);
+                    let nfields = calls.len();
+                    let blk = cx.lambda_stmts_1(trait_span, sequence(cx, trait_span, calls),
+                                                blkarg);
+                    let call = cx.expr_method_call(trait_span, blkencoder,
+                                                   cx.ident_of("emit_enum_variant"),
+                                                   vec!(name,
+                                                     cx.expr_uint(trait_span, idx),
+                                                     cx.expr_uint(trait_span, nfields),
+                                                     blk));
+                    let blk = cx.lambda_expr_1(trait_span, call, blkarg);
+                    cx.expr_method_call(trait_span,
+                                        encoder,
+                                        cx.ident_of("emit_enum"),
+                                        vec!(type_name, blk))
+                }
 
-            let blk = cx.lambda_stmts_1(trait_span, stmts, blkarg);
-            let name = cx.expr_str(trait_span, token::get_ident(variant.node.name));
-            let call = cx.expr_method_call(trait_span, blkencoder,
-                                           cx.ident_of("emit_enum_variant"),
-                                           vec!(name,
-                                             cx.expr_uint(trait_span, idx),
-                                             cx.expr_uint(trait_span, fields.len()),
-                                             blk));
-            let blk = cx.lambda_expr_1(trait_span, call, blkarg);
-            let ret = cx.expr_method_call(trait_span,
-                                          encoder,
-                                          cx.ident_of("emit_enum"),
-                                          vec!(
-                cx.expr_str(trait_span, token::get_ident(substr.type_ident)),
-                blk
-            ));
+                // { "Frog": { "legs": 4 } }
+                External => {
+                    let payload = if is_struct {
+                        let calls = encode_fields(cx, blkarg, fields.as_slice(),
+                                                  options.as_slice(), 0,
+                                                  "emit_struct_field", true);
+                        let nfields = calls.len();
+                        let blk = cx.lambda_stmts_1(trait_span, sequence(cx, trait_span, calls),
+                                                    blkarg);
+                        cx.expr_method_call(trait_span, blkencoder.clone(),
+                                            cx.ident_of("emit_struct"),
+                                            vec!(name.clone(),
+                                                 cx.expr_uint(trait_span, nfields),
+                                                 blk))
+                    } else {
+                        let calls = encode_fields(cx, blkarg, fields.as_slice(),
+                                                  options.as_slice(), 0,
+                                                  "emit_tuple_arg", false);
+                        let nfields = calls.len();
+                        let blk = cx.lambda_stmts_1(trait_span, sequence(cx, trait_span, calls),
+                                                    blkarg);
+                        cx.expr_method_call(trait_span, blkencoder.clone(),
+                                            cx.ident_of("emit_tuple"),
+                                            vec!(cx.expr_uint(trait_span, nfields), blk))
+                    };
+                    let key = cx.expr_method_call(trait_span, blkencoder.clone(),
+                                                  cx.ident_of("emit_str"), vec!(name));
+                    let key = cx.expr_method_call(trait_span, blkencoder.clone(),
+                                                  cx.ident_of("emit_map_elt_key"),
+                                                  vec!(cx.expr_uint(trait_span, 0),
+                                                       cx.lambda_expr_1(trait_span, key, blkarg)));
+                    let value = cx.expr_method_call(trait_span, blkencoder,
+                                                    cx.ident_of("emit_map_elt_val"),
+                                                    vec!(cx.expr_uint(trait_span, 0),
+                                                         cx.lambda_expr_1(trait_span, payload,
+                                                                          blkarg)));
+                    let blk = cx.lambda_stmts_1(trait_span,
+                                                sequence(cx, trait_span, vec!(key, value)),
+                                                blkarg);
+                    cx.expr_method_call(trait_span,
+                                        encoder,
+                                        cx.ident_of("emit_map"),
+                                        vec!(cx.expr_uint(trait_span, 1), blk))
+                }
+
+                // { "type": "Frog", "legs": 4 }
+                Internal(ref tag) => {
+                    let tag_value = cx.expr_method_call(trait_span, blkencoder.clone(),
+                                                        cx.ident_of("emit_str"), vec!(name));
+                    let mut calls = vec!(cx.expr_method_call(
+                        trait_span, blkencoder,
+                        cx.ident_of("emit_struct_field"),
+                        vec!(cx.expr_str(trait_span, tag.clone()),
+                             cx.expr_uint(trait_span, 0),
+                             cx.lambda_expr_1(trait_span, tag_value, blkarg))));
+                    calls.extend(encode_fields(cx, blkarg, fields.as_slice(), options.as_slice(),
+                                               1, "emit_struct_field", true).into_iter());
+                    let nfields = calls.len();
+                    let blk = cx.lambda_stmts_1(trait_span, sequence(cx, trait_span, calls),
+                                                blkarg);
+                    cx.expr_method_call(trait_span,
+                                        encoder,
+                                        cx.ident_of("emit_struct"),
+                                        vec!(type_name, cx.expr_uint(trait_span, nfields), blk))
+                }
+            };
             cx.expr_block(cx.block(trait_span, vec!(me), Some(ret)))
         }
 
         _ => cx.bug("expected Struct or EnumMatching in deriving(Encodable)")
     };
 }
+
+/// Creates the calls to `method` that encode the fields which aren't
+/// skipped, numbering them from `first`. `method` is given the name of the
+/// field first if `named` is set, like `emit_struct_field`.
+fn encode_fields(cx: &mut ExtCtxt,
+                 blkarg: Ident,
+                 fields: &[FieldInfo],
+                 options: &[FieldOptions],
+                 first: uint,
+                 method: &str,
+                 named: bool)
+                 -> Vec<P<Expr>> {
+    let mut calls = Vec::new();
+    for (i, (&FieldInfo { name, ref self_, span, .. }, options)) in
+            fields.iter().zip(options.iter()).enumerate() {
+        if options.skip {
+            continue;
+        }
+        let blkencoder = cx.expr_ident(span, blkarg);
+        let enc = cx.expr_method_call(span, self_.clone(),
+                                      cx.ident_of("encode"), vec!(blkencoder.clone()));
+        let lambda = cx.lambda_expr_1(span, enc, blkarg);
+        let idx = cx.expr_uint(span, first + calls.len());
+        let args = if named {
+            vec!(cx.expr_str(span, field_name(options, name, i)), idx, lambda)
+        } else {
+            vec!(idx, lambda)
+        };
+        calls.push(cx.expr_method_call(span, blkencoder, cx.ident_of(method), args));
+    }
+    calls
+}
+
+/// Turns calls returning `Result<(), E>` into the statements of a closure
+/// that makes them in order until one fails, and returns the result of the
+/// last one.
+fn sequence(cx: &mut ExtCtxt, trait_span: Span, calls: Vec<P<Expr>>) -> Vec<P<ast::Stmt>> {
+    let last = calls.len();
+    let mut stmts = Vec::new();
+    for (i, call) in calls.into_iter().enumerate() {
+        let span = call.span;
+        // last call doesn't need a try!
+        let call = if i + 1 != last {
+            cx.expr_try(span, call)
+        } else {
+            cx.expr(span, ExprRet(Some(call)))
+        };
+        stmts.push(cx.stmt_expr(call));
+    }
+
+    // with nothing to encode, we need to return Ok()
+    if stmts.is_empty() {
+        let ret_ok = cx.expr(trait_span,
+                             ExprRet(Some(cx.expr_ok(trait_span,
+                                                     cx.expr_tuple(trait_span, vec![])))));
+        stmts.push(cx.stmt_expr(ret_ok));
+    }
+    stmts
+}
@@ -33,7 +33,7 @@ use owned_slice::OwnedSlice;
 use parse::token::InternedString;
 use parse::{token, ParseSess};
 use print::pprust;
-use {ast, ast_util};
+use {abi, ast, ast_util};
 use ptr::P;
 use util::small_vector::SmallVector;
 
@@ -47,7 +47,26 @@ struct Test {
     path: Vec<ast::Ident> ,
     bench: bool,
     ignore: bool,
-    should_fail: ShouldFail
+    should_fail: ShouldFail,
+    timeout: Option<u64>,
+    // The function that runs the test with its module's fixtures, if it
+    // needs one; it is run in place of the test function itself.
+    wrapper: Option<ast::Ident>,
+}
+
+// A `#[fixture]` function, and the static its value is kept in.
+struct Fixture {
+    name: ast::Ident,
+    cell: ast::Ident,
+    used: bool,
+}
+
+// The fixtures, setup and teardown functions of a module, which apply to the
+// tests of that module but not to those of its submodules.
+struct ModuleFixtures {
+    setup: Option<ast::Ident>,
+    teardown: Option<ast::Ident>,
+    fixtures: Vec<Fixture>,
 }
 
 struct TestCtxt<'a> {
@@ -62,6 +81,11 @@ struct TestCtxt<'a> {
 
     // top-level re-export submodule, filled out after folding is finished
     toplevel_reexport: Option<ast::Ident>,
+
+    // the name the test crate is linked as at the top level, for the
+    // functions that run tests with their fixtures or check properties
+    test_crate: ast::Ident,
+    uses_test_crate: bool,
 }
 
 // Traverse the crate, collecting all the test functions, eliding any
@@ -93,6 +117,7 @@ pub fn modify_for_testing(sess: &ParseSess,
 struct TestHarnessGenerator<'a> {
     cx: TestCtxt<'a>,
     tests: Vec<ast::Ident>,
+    fixtures: ModuleFixtures,
 
     // submodule name, gensym'd identifier for re-exports
     tested_submods: Vec<(ast::Ident, ast::Ident)>,
@@ -110,6 +135,10 @@ impl<'a> fold::Folder for TestHarnessGenerator<'a> {
             Some(re) => folded.module.view_items.push(re),
             None => {}
         }
+        if self.cx.uses_test_crate {
+            let vi = mk_test_crate(&self.cx);
+            folded.module.view_items.insert(0, vi);
+        }
         folded
     }
 
@@ -121,7 +150,9 @@ impl<'a> fold::Folder for TestHarnessGenerator<'a> {
         debug!("current path: {}",
                ast_util::path_name_i(self.cx.path.as_slice()));
 
-        if is_test_fn(&self.cx, &*i) || is_bench_fn(&self.cx, &*i) {
+        let mut wrapper = None;
+        let property = is_property_fn(&self.cx, &*i);
+        if is_test_fn(&self.cx, &*i) || is_bench_fn(&self.cx, &*i) || property {
             match i.node {
                 ast::ItemFn(_, ast::Unsafety::Unsafe, _, _, _) => {
                     let diag = self.cx.span_diagnostic;
@@ -131,15 +162,24 @@ impl<'a> fold::Folder for TestHarnessGenerator<'a> {
                 }
                 _ => {
                     debug!("this is a test function");
+                    let bench = is_bench_fn(&self.cx, &*i);
+                    if property {
+                        wrapper = Some(mk_property_wrapper(&mut self.cx, &self.fixtures, &*i));
+                    } else if !bench {
+                        wrapper = mk_fixture_wrapper(&mut self.cx, &mut self.fixtures, &*i);
+                    }
+                    let wrapper_ident = wrapper.as_ref().map(|w| w.ident);
                     let test = Test {
                         span: i.span,
                         path: self.cx.path.clone(),
-                        bench: is_bench_fn(&self.cx, &*i),
+                        bench: bench,
                         ignore: is_ignored(&*i),
-                        should_fail: should_fail(&*i)
+                        should_fail: should_fail(&*i),
+                        timeout: timeout(&self.cx, &*i),
+                        wrapper: wrapper_ident,
                     };
                     self.cx.testfns.push(test);
-                    self.tests.push(i.ident);
+                    self.tests.push(wrapper_ident.unwrap_or(i.ident));
                     // debug!("have {} test/bench functions",
                     //        cx.testfns.len());
                 }
@@ -148,10 +188,14 @@ impl<'a> fold::Folder for TestHarnessGenerator<'a> {
 
         // We don't want to recurse into anything other than mods, since
         // mods or tests inside of functions will break things
-        let res = match i.node {
+        let mut res = match i.node {
             ast::ItemMod(..) => fold::noop_fold_item(i, self),
             _ => SmallVector::one(i),
         };
+        match wrapper {
+            Some(wrapper) => res.push(wrapper),
+            None => {}
+        }
         if ident.name != token::special_idents::invalid.name {
             self.cx.path.pop();
         }
@@ -161,9 +205,17 @@ impl<'a> fold::Folder for TestHarnessGenerator<'a> {
     fn fold_mod(&mut self, m: ast::Mod) -> ast::Mod {
         let tests = mem::replace(&mut self.tests, Vec::new());
         let tested_submods = mem::replace(&mut self.tested_submods, Vec::new());
+        let fixtures = mem::replace(&mut self.fixtures, module_fixtures(&self.cx, &m));
         let mut mod_folded = fold::noop_fold_mod(m, self);
         let tests = mem::replace(&mut self.tests, tests);
         let tested_submods = mem::replace(&mut self.tested_submods, tested_submods);
+        let fixtures = mem::replace(&mut self.fixtures, fixtures);
+
+        // The values of the fixtures that tests use are kept in statics
+        // next to them.
+        for fixture in fixtures.fixtures.iter().filter(|f| f.used) {
+            mod_folded.items.push(mk_fixture_cell(&self.cx, fixture));
+        }
 
         // Remove any #[main] from the AST so it doesn't clash with
         // the one we're going to add. Only if compiling an executable.
@@ -203,6 +255,232 @@ impl<'a> fold::Folder for TestHarnessGenerator<'a> {
     }
 }
 
+// Finds the `#[setup]`, `#[teardown]` and `#[fixture]` functions of a module.
+fn module_fixtures(cx: &TestCtxt, m: &ast::Mod) -> ModuleFixtures {
+    let mut fixtures = ModuleFixtures {
+        setup: None,
+        teardown: None,
+        fixtures: Vec::new(),
+    };
+    let diag = cx.span_diagnostic;
+    for i in m.items.iter() {
+        let is_setup = attr::contains_name(i.attrs.as_slice(), "setup");
+        let is_teardown = attr::contains_name(i.attrs.as_slice(), "teardown");
+        let is_fixture = attr::contains_name(i.attrs.as_slice(), "fixture");
+        if !is_setup && !is_teardown && !is_fixture {
+            continue;
+        }
+        let returns_nil = match i.node {
+            ast::ItemFn(ref decl, _, _, ref generics, _)
+                    if decl.inputs.is_empty() && !generics.is_parameterized() => {
+                match decl.output {
+                    ast::Return(ref ret_ty) => match ret_ty.node {
+                        ast::TyTup(ref tys) => tys.is_empty(),
+                        _ => false,
+                    },
+                    ast::NoReturn(_) => false,
+                }
+            }
+            ast::ItemFn(..) => {
+                diag.span_err(i.span, "functions used as setup, teardown or fixtures \
+                                       can't take arguments or type parameters");
+                continue;
+            }
+            _ => {
+                diag.span_err(i.span, "only functions may be used as setup, teardown \
+                                       or fixtures");
+                continue;
+            }
+        };
+
+        if is_setup || is_teardown {
+            if !returns_nil {
+                diag.span_err(i.span, "functions used as setup or teardown must have \
+                                       signature fn() -> ()");
+            }
+            let (slot, name) = if is_setup {
+                (&mut fixtures.setup, "setup")
+            } else {
+                (&mut fixtures.teardown, "teardown")
+            };
+            if slot.is_some() {
+                diag.span_err(i.span, format!("a module can only have one `#[{}]` \
+                                               function", name).as_slice());
+            }
+            *slot = Some(i.ident);
+        } else if returns_nil {
+            diag.span_err(i.span, "functions used as fixtures must return a value");
+        } else {
+            fixtures.fixtures.push(Fixture {
+                name: i.ident,
+                cell: token::gensym_ident("__TEST_FIXTURE"),
+                used: false,
+            });
+        }
+    }
+    fixtures
+}
+
+// The name of a fixture a test takes as an argument, which it has to take by
+// reference.
+fn fixture_arg(arg: &ast::Arg) -> Option<ast::Ident> {
+    match (&arg.pat.node, &arg.ty.node) {
+        (&ast::PatIdent(_, ref ident, None),
+         &ast::TyRptr(_, ast::MutTy { mutbl: ast::MutImmutable, .. })) => Some(ident.node),
+        _ => None,
+    }
+}
+
+// Builds the function that runs a test with the setup, teardown and fixtures
+// of its module, unless the test needs none of them:
+//
+//     fn __test_wrapper() {
+//         setup();
+//         let _teardown = ::test::fixture::Teardown(teardown);
+//         test(unsafe { __TEST_FIXTURE.get(name) }, ...)
+//     }
+fn mk_fixture_wrapper(cx: &mut TestCtxt, fixtures: &mut ModuleFixtures,
+                      i: &ast::Item) -> Option<P<ast::Item>> {
+    let args = match i.node {
+        ast::ItemFn(ref decl, _, _, _, _) => decl.inputs.as_slice(),
+        _ => return None,
+    };
+    if args.is_empty() && fixtures.setup.is_none() && fixtures.teardown.is_none() {
+        return None;
+    }
+    cx.uses_test_crate = true;
+
+    let span = i.span;
+    let ecx = &cx.ext_cx;
+    let stmts = mk_setup_and_teardown(cx, fixtures, span);
+
+    let args = args.iter().map(|arg| {
+        let name = fixture_arg(arg).unwrap();
+        match fixtures.fixtures.iter_mut().find(|f| f.name.name == name.name) {
+            Some(fixture) => {
+                fixture.used = true;
+                let get = ecx.expr_method_call(span, ecx.expr_ident(span, fixture.cell),
+                                               ecx.ident_of("get"),
+                                               vec![ecx.expr_ident(span, fixture.name)]);
+                // Each fixture is only ever given its own function, and torn
+                // down once all tests are over, which makes `get` safe here.
+                let block = ecx.block_expr(get).map(|block| ast::Block {
+                    rules: ast::UnsafeBlock(ast::CompilerGenerated),
+                    ..block
+                });
+                ecx.expr_block(block)
+            }
+            None => {
+                cx.span_diagnostic.span_err(arg.pat.span,
+                    format!("there is no `#[fixture]` function named `{}` in \
+                             this module", token::get_ident(name)).as_slice());
+                ecx.expr_unreachable(span)
+            }
+        }
+    }).collect();
+    let call = ecx.expr_call_ident(span, i.ident, args);
+
+    Some(ecx.item_fn(span,
+                     token::gensym_ident("__test_wrapper"),
+                     Vec::new(),
+                     ecx.ty(span, ast::TyTup(Vec::new())),
+                     ecx.block(span, stmts, Some(call))))
+}
+
+// The statements that run the setup of a module, and its teardown once the
+// function they are in returns.
+fn mk_setup_and_teardown(cx: &TestCtxt, fixtures: &ModuleFixtures,
+                         span: Span) -> Vec<P<ast::Stmt>> {
+    let ecx = &cx.ext_cx;
+    let mut stmts = Vec::new();
+    match fixtures.setup {
+        Some(setup) => stmts.push(ecx.stmt_expr(ecx.expr_call_ident(span, setup, Vec::new()))),
+        None => {}
+    }
+    match fixtures.teardown {
+        // Bound to a variable so that it's dropped, and runs the teardown, only
+        // once the test is over, even if it panics.
+        Some(teardown) => {
+            let path = ecx.path_global(span, vec![cx.test_crate, ecx.ident_of("fixture"),
+                                                  ecx.ident_of("Teardown")]);
+            let guard = ecx.expr_call(span, ecx.expr_path(path),
+                                      vec![ecx.expr_ident(span, teardown)]);
+            stmts.push(ecx.stmt_let(span, false, ecx.ident_of("_teardown"), guard));
+        }
+        None => {}
+    }
+    stmts
+}
+
+// Builds the function that checks a `#[quickcheck]` property with random
+// arguments, with the setup and teardown of its module around the whole
+// check:
+//
+//     fn __test_wrapper() {
+//         setup();
+//         let _teardown = ::test::fixture::Teardown(teardown);
+//         ::test::quickcheck::quickcheck(property as fn(A, B) -> R)
+//     }
+fn mk_property_wrapper(cx: &mut TestCtxt, fixtures: &ModuleFixtures,
+                       i: &ast::Item) -> P<ast::Item> {
+    let decl = match i.node {
+        ast::ItemFn(ref decl, _, _, _, _) => decl,
+        _ => panic!("only functions may be used as properties"),
+    };
+    cx.uses_test_crate = true;
+
+    let span = i.span;
+    let ecx = &cx.ext_cx;
+    let stmts = mk_setup_and_teardown(cx, fixtures, span);
+
+    // The function has to be cast to a function pointer, which is what the
+    // property traits are implemented for.
+    let inputs = decl.inputs.iter().map(|arg| {
+        ast::Arg {
+            ty: arg.ty.clone(),
+            pat: ecx.pat_wild(span),
+            id: ast::DUMMY_NODE_ID,
+        }
+    }).collect();
+    let fn_ty = ecx.ty(span, ast::TyBareFn(P(ast::BareFnTy {
+        unsafety: ast::Unsafety::Normal,
+        abi: abi::Rust,
+        lifetimes: Vec::new(),
+        decl: P(ast::FnDecl {
+            inputs: inputs,
+            output: decl.output.clone(),
+            variadic: false,
+        }),
+    })));
+    let property = ecx.expr_cast(span, ecx.expr_ident(span, i.ident), fn_ty);
+    let check = ecx.expr_call_global(span,
+                                     vec![cx.test_crate, ecx.ident_of("quickcheck"),
+                                          ecx.ident_of("quickcheck")],
+                                     vec![property]);
+
+    ecx.item_fn(span,
+                token::gensym_ident("__test_wrapper"),
+                Vec::new(),
+                ecx.ty(span, ast::TyTup(Vec::new())),
+                ecx.block(span, stmts, Some(check)))
+}
+
+// Builds the static that keeps the value of a fixture:
+//
+//     static __TEST_FIXTURE: ::test::fixture::Fixture = ::test::fixture::FIXTURE_INIT;
+fn mk_fixture_cell(cx: &TestCtxt, fixture: &Fixture) -> P<ast::Item> {
+    let ecx = &cx.ext_cx;
+    let fixture_path = |name| {
+        ecx.path_global(DUMMY_SP, vec![cx.test_crate, ecx.ident_of("fixture"),
+                                       ecx.ident_of(name)])
+    };
+    ecx.item_static(DUMMY_SP,
+                    fixture.cell,
+                    ecx.ty_path(fixture_path("Fixture")),
+                    ast::MutImmutable,
+                    ecx.expr_path(fixture_path("FIXTURE_INIT")))
+}
+
 fn mk_reexport_mod(cx: &mut TestCtxt, tests: Vec<ast::Ident>,
                    tested_submods: Vec<(ast::Ident, ast::Ident)>) -> (P<ast::Item>, ast::Ident) {
     let mut view_items = Vec::new();
@@ -252,6 +530,8 @@ fn generate_test_harness(sess: &ParseSess,
         is_test_crate: is_test_crate(&krate),
         config: krate.config.clone(),
         toplevel_reexport: None,
+        test_crate: token::gensym_ident("__test_crate"),
+        uses_test_crate: false,
     };
 
     cx.ext_cx.bt_push(ExpnInfo {
@@ -266,6 +546,11 @@ fn generate_test_harness(sess: &ParseSess,
     let mut fold = TestHarnessGenerator {
         cx: cx,
         tests: Vec::new(),
+        fixtures: ModuleFixtures {
+            setup: None,
+            teardown: None,
+            fixtures: Vec::new(),
+        },
         tested_submods: Vec::new(),
     };
     let res = fold.fold_crate(krate);
@@ -278,7 +563,11 @@ fn strip_test_functions(krate: ast::Crate) -> ast::Crate {
     // #[test] functions
     config::strip_items(krate, |attrs| {
         !attr::contains_name(attrs.as_slice(), "test") &&
-        !attr::contains_name(attrs.as_slice(), "bench")
+        !attr::contains_name(attrs.as_slice(), "bench") &&
+        !attr::contains_name(attrs.as_slice(), "quickcheck") &&
+        !attr::contains_name(attrs.as_slice(), "setup") &&
+        !attr::contains_name(attrs.as_slice(), "teardown") &&
+        !attr::contains_name(attrs.as_slice(), "fixture")
     })
 }
 
@@ -303,7 +592,8 @@ fn is_test_fn(cx: &TestCtxt, i: &ast::Item) -> bool {
                 },
                 ast::NoReturn(_) => false
             };
-            if decl.inputs.is_empty()
+            // Tests may take fixtures of their module by reference.
+            if decl.inputs.iter().all(|arg| fixture_arg(arg).is_some())
                    && no_output
                    && !generics.is_parameterized() {
                 Yes
@@ -361,6 +651,25 @@ fn is_bench_fn(cx: &TestCtxt, i: &ast::Item) -> bool {
     return has_bench_attr && has_test_signature(i);
 }
 
+fn is_property_fn(cx: &TestCtxt, i: &ast::Item) -> bool {
+    if !attr::contains_name(i.attrs.as_slice(), "quickcheck") {
+        return false;
+    }
+    let diag = cx.span_diagnostic;
+    match i.node {
+        ast::ItemFn(_, _, _, ref generics, _) if generics.is_parameterized() => {
+            diag.span_err(i.span, "functions used as properties can't have type \
+                                   parameters");
+            false
+        }
+        ast::ItemFn(..) => true,
+        _ => {
+            diag.span_err(i.span, "only functions may be used as properties");
+            false
+        }
+    }
+}
+
 fn is_ignored(i: &ast::Item) -> bool {
     i.attrs.iter().any(|attr| attr.check_name("ignore"))
 }
@@ -377,6 +686,24 @@ fn should_fail(i: &ast::Item) -> ShouldFail {
     }
 }
 
+fn timeout(cx: &TestCtxt, i: &ast::Item) -> Option<u64> {
+    let attr = match i.attrs.iter().find(|attr| attr.check_name("timeout")) {
+        Some(attr) => attr,
+        None => return None,
+    };
+    match attr.node.value.node {
+        ast::MetaNameValue(_, ref lit) => match lit.node {
+            ast::LitInt(ms, ast::UnsuffixedIntLit(ast::Plus)) |
+            ast::LitInt(ms, ast::UnsignedIntLit(_)) => return Some(ms),
+            _ => {}
+        },
+        _ => {}
+    }
+    cx.span_diagnostic.span_err(attr.span, "the test timeout must be a number of \
+                                            milliseconds, like `#[timeout = 5000]`");
+    None
+}
+
 /*
 
 We're going to be building a module that looks more or less like:
@@ -414,6 +741,28 @@ fn mk_std(cx: &TestCtxt) -> ast::ViewItem {
     }
 }
 
+// Links the test crate at the top level for the fixtures and properties, as
+// `mk_std` does for the test module.
+fn mk_test_crate(cx: &TestCtxt) -> ast::ViewItem {
+    let id_test = token::str_to_ident("test");
+    let vi = if cx.is_test_crate {
+        ast::ViewItemUse(
+            P(nospan(ast::ViewPathSimple(cx.test_crate,
+                                         path_node(vec!(id_test)),
+                                         ast::DUMMY_NODE_ID))))
+    } else {
+        ast::ViewItemExternCrate(cx.test_crate,
+                                 Some((InternedString::new("test"), ast::CookedStr)),
+                                 ast::DUMMY_NODE_ID)
+    };
+    ast::ViewItem {
+        node: vi,
+        attrs: Vec::new(),
+        vis: ast::Inherited,
+        span: DUMMY_SP
+    }
+}
+
 fn mk_test_module(cx: &mut TestCtxt) -> (P<ast::Item>, Option<ast::ViewItem>) {
     // Link to test crate
     let view_items = vec!(mk_std(cx));
@@ -578,13 +927,22 @@ fn mk_test_desc_and_fn_rec(cx: &TestCtxt, test: &Test) -> P<ast::Expr> {
         }
     };
 
+    let timeout_expr = match test.timeout {
+        Some(ms) => {
+            let ms = ecx.expr_lit(span, ast::LitInt(ms, ast::UnsignedIntLit(ast::TyU64)));
+            ecx.expr_some(span, ms)
+        }
+        None => ecx.expr_none(span),
+    };
+
     // self::test::TestDesc { ... }
     let desc_expr = ecx.expr_struct(
         span,
         test_path("TestDesc"),
         vec![field("name", name_expr),
              field("ignore", ignore_expr),
-             field("should_fail", fail_expr)]);
+             field("should_fail", fail_expr),
+             field("timeout", timeout_expr)]);
 
 
     let mut visible_path = match cx.toplevel_reexport {
@@ -595,6 +953,10 @@ fn mk_test_desc_and_fn_rec(cx: &TestCtxt, test: &Test) -> P<ast::Expr> {
         }
     };
     visible_path.extend(path.into_iter());
+    match test.wrapper {
+        Some(wrapper) => *visible_path.last_mut().unwrap() = wrapper,
+        None => {}
+    }
 
     let fn_expr = ecx.expr_path(ecx.path_global(span, visible_path));
 
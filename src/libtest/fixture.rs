@@ -0,0 +1,202 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for the fixtures of the tests of a module.
+//!
+//! A module can have a `#[setup]` function and a `#[teardown]` function,
+//! which the harness generated by `rustc --test` runs before and after each
+//! test of the module, and `#[fixture]` functions whose values are shared by
+//! the tests of the module:
+//!
+//! ```rust,ignore
+//! #[fixture]
+//! fn dir() -> TempDir { TempDir::new("test").unwrap() }
+//!
+//! #[test]
+//! fn writes_a_file(dir: &TempDir) { ... }
+//! ```
+//!
+//! Tests take fixtures by reference, as arguments named after the fixture
+//! function. A fixture is created by the first test that needs it, and
+//! dropped once all tests have run. The teardown function runs even if the
+//! test panics.
+
+use std::cell::UnsafeCell;
+use std::mem;
+use std::sync::{StaticMutex, MUTEX_INIT};
+
+/// The value of a `#[fixture]` function, created the first time it is needed.
+pub struct Fixture {
+    lock: StaticMutex,
+    // The address of the boxed value, or 0 until it is created.
+    value: UnsafeCell<uint>,
+    // Drops the boxed value, given its address.
+    drop: UnsafeCell<Option<fn(uint)>>,
+    // The fixture that was created before this one, which is torn down after
+    // it.
+    next: UnsafeCell<uint>,
+}
+
+/// Static initialization of a fixture with no value yet.
+pub const FIXTURE_INIT: Fixture = Fixture {
+    lock: MUTEX_INIT,
+    value: UnsafeCell { value: 0 },
+    drop: UnsafeCell { value: None },
+    next: UnsafeCell { value: 0 },
+};
+
+static CREATED_LOCK: StaticMutex = MUTEX_INIT;
+// The address of the last fixture to be created, which is torn down first.
+static mut CREATED: uint = 0;
+
+impl Fixture {
+    /// Returns the value of the fixture, calling `init` to create it if no
+    /// test has yet.
+    ///
+    /// `init` runs with the fixture locked. If it panics, the lock is left
+    /// poisoned, so `init` isn't called again and every later call panics:
+    /// every test that uses the fixture fails.
+    ///
+    /// This is only meant to be called by the harness that `rustc --test`
+    /// generates. It is unsafe because the value is kept untyped: a fixture
+    /// must always be given the same `init`, and the reference returned must
+    /// not be used after `tear_down` is called.
+    #[doc(hidden)]
+    pub unsafe fn get<T: Send + Sync>(&'static self, init: fn() -> T) -> &'static T {
+        // The lock of a fixture is always taken before `CREATED_LOCK`.
+        let _guard = self.lock.lock();
+        if *self.value.get() == 0 {
+            let value: Box<T> = box init();
+            *self.value.get() = mem::transmute(value);
+            *self.drop.get() = Some(drop_box::<T>);
+
+            let _guard = CREATED_LOCK.lock();
+            *self.next.get() = CREATED;
+            CREATED = self as *const Fixture as uint;
+        }
+        &*(*self.value.get() as *const T)
+    }
+}
+
+fn drop_box<T>(value: uint) {
+    let _: Box<T> = unsafe { mem::transmute(value) };
+}
+
+/// Drops the values of all the fixtures that have been created, the most
+/// recently created first.
+///
+/// The test runner calls this once the tests are done with their fixtures.
+/// It is unsafe because the references that `Fixture::get` returned are left
+/// dangling: no test may still be running.
+#[doc(hidden)]
+pub unsafe fn tear_down() {
+    loop {
+        // Take the fixture off the list before locking it, so that the locks
+        // are taken in the same order as in `get`.
+        let fixture: &'static Fixture = {
+            let _guard = CREATED_LOCK.lock();
+            if CREATED == 0 {
+                break;
+            }
+            let fixture: &'static Fixture = mem::transmute(CREATED);
+            CREATED = *fixture.next.get();
+            fixture
+        };
+        let _guard = fixture.lock.lock();
+        let value = mem::replace(&mut *fixture.value.get(), 0);
+        (*fixture.drop.get()).unwrap()(value);
+    }
+}
+
+/// Runs a module's `#[teardown]` function when dropped, so that it runs after
+/// a test whether the test passes or panics.
+pub struct Teardown(pub fn());
+
+impl Drop for Teardown {
+    fn drop(&mut self) {
+        let Teardown(teardown) = *self;
+        teardown()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fixture, FIXTURE_INIT, Teardown, tear_down};
+    use std::sync::atomic::{AtomicUint, INIT_ATOMIC_UINT, SeqCst};
+    use std::thread::Thread;
+
+    static CREATED: AtomicUint = INIT_ATOMIC_UINT;
+    static DROPPED: AtomicUint = INIT_ATOMIC_UINT;
+    static TORN_DOWN: AtomicUint = INIT_ATOMIC_UINT;
+
+    struct Value(uint);
+
+    impl Drop for Value {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(1, SeqCst);
+        }
+    }
+
+    fn value() -> Value {
+        Value(CREATED.fetch_add(1, SeqCst) + 1)
+    }
+
+    static FIXTURE: Fixture = FIXTURE_INIT;
+    static PANICKY: Fixture = FIXTURE_INIT;
+    static PANICKY_CALLS: AtomicUint = INIT_ATOMIC_UINT;
+
+    #[test]
+    fn fixtures_are_created_once_and_torn_down() {
+        let guards: Vec<_> = range(0u, 8).map(|_| {
+            Thread::spawn(move || {
+                let Value(n) = *unsafe { FIXTURE.get(value) };
+                n
+            })
+        }).collect();
+        for guard in guards.into_iter() {
+            assert_eq!(guard.join().ok().unwrap(), 1);
+        }
+        assert_eq!(CREATED.load(SeqCst), 1);
+        assert_eq!(DROPPED.load(SeqCst), 0);
+
+        unsafe { tear_down() };
+        assert_eq!(DROPPED.load(SeqCst), 1);
+    }
+
+    fn panicky() -> Value {
+        PANICKY_CALLS.fetch_add(1, SeqCst);
+        panic!("the fixture can't be created")
+    }
+
+    #[test]
+    fn fixtures_that_panic_fail_every_test() {
+        for _ in range(0u, 3) {
+            let result = Thread::spawn(move || {
+                unsafe { PANICKY.get(panicky) };
+            }).join();
+            assert!(result.is_err());
+        }
+        assert_eq!(PANICKY_CALLS.load(SeqCst), 1);
+    }
+
+    fn teardown() {
+        TORN_DOWN.fetch_add(1, SeqCst);
+    }
+
+    #[test]
+    fn teardown_runs_when_a_test_panics() {
+        let result = Thread::spawn(move || {
+            let _teardown = Teardown(teardown);
+            panic!();
+        }).join();
+        assert!(result.is_err());
+        assert_eq!(TORN_DOWN.load(SeqCst), 1);
+    }
+}
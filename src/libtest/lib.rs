@@ -42,6 +42,7 @@ extern crate term;
 pub use self::TestFn::*;
 pub use self::MetricChange::*;
 pub use self::ColorConfig::*;
+pub use self::OutputFormat::*;
 pub use self::TestResult::*;
 pub use self::TestName::*;
 use self::TestEvent::*;
@@ -54,6 +55,7 @@ use stats::Stats;
 use getopts::{OptGroup, optflag, optopt};
 use regex::Regex;
 use serialize::{json, Decodable, Encodable};
+use serialize::json::{Json, ToJson};
 use term::Terminal;
 use term::color::{Color, RED, YELLOW, GREEN, CYAN};
 
@@ -70,20 +72,22 @@ use std::os;
 use std::str::FromStr;
 use std::string::String;
 use std::thread::{mod, Thread};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::thunk::{Thunk, Invoke};
 
 // to be used by rustc to compile tests in libtest
 pub mod test {
     pub use {Bencher, TestName, TestResult, TestDesc,
-             TestDescAndFn, TestOpts, TrFailed, TrIgnored, TrOk,
+             TestDescAndFn, TestOpts, TrFailed, TrIgnored, TrOk, TrTimedOut,
              Metric, MetricMap, MetricAdded, MetricRemoved,
              MetricChange, Improvement, Regression, LikelyNoise,
              StaticTestFn, StaticTestName, DynTestName, DynTestFn,
              run_test, test_main, test_main_static, filter_tests,
-             parse_opts, StaticBenchFn, ShouldFail};
+             parse_opts, StaticBenchFn, ShouldFail, fixture, quickcheck};
 }
 
+pub mod fixture;
+pub mod quickcheck;
 pub mod stats;
 
 // The name of a test. By convention this follows the rules for rust
@@ -205,6 +209,9 @@ pub struct TestDesc {
     pub name: TestName,
     pub ignore: bool,
     pub should_fail: ShouldFail,
+    // How many milliseconds the test may run before it is failed as timed
+    // out, overriding the runner's default. Benchmarks never time out.
+    pub timeout: Option<u64>,
 }
 
 #[deriving(Show)]
@@ -247,6 +254,32 @@ pub enum MetricChange {
 
 pub type MetricDiff = BTreeMap<String,MetricChange>;
 
+/// The samples, in ns/iter, of each benchmark of a run. A baseline is saved
+/// with `--baseline NAME`, and later runs compare against it with
+/// `--compare NAME`.
+#[deriving(Clone, PartialEq)]
+pub struct Baseline(BTreeMap<String,Vec<f64>>);
+
+/// The level below which the p-value of a comparison is considered
+/// significant: a change is reported when the chance that the benchmark did
+/// not actually change is less than 5%.
+pub const BENCH_SIGNIFICANCE: f64 = 0.05;
+
+/// How the samples of a benchmark compare with those in a baseline.
+#[deriving(Copy, Clone, PartialEq, Show)]
+pub struct BenchComparison {
+    /// The median ns/iter in the baseline.
+    pub old_median: f64,
+    /// The median ns/iter in this run.
+    pub new_median: f64,
+    /// The bounds of the 95% confidence interval of the change of the median,
+    /// in ns/iter.
+    pub low: f64,
+    pub high: f64,
+    /// The p-value of the Mann-Whitney U test of the two sets of samples.
+    pub p_value: f64,
+}
+
 // The default console test runner. It accepts the command line
 // arguments and a vector of test_descs.
 pub fn test_main(args: &[String], tests: Vec<TestDescAndFn> ) {
@@ -288,6 +321,15 @@ pub enum ColorConfig {
     NeverColor,
 }
 
+#[deriving(Copy, PartialEq)]
+pub enum OutputFormat {
+    /// Human readable progress and summary, as printed to a terminal.
+    HumanFormat,
+    /// One JSON object per line for each event of the run, for tools that
+    /// aggregate test results.
+    JsonFormat,
+}
+
 pub struct TestOpts {
     pub filter: Option<Regex>,
     pub run_ignored: bool,
@@ -296,10 +338,16 @@ pub struct TestOpts {
     pub ratchet_metrics: Option<Path>,
     pub ratchet_noise_percent: Option<f64>,
     pub save_metrics: Option<Path>,
+    pub baseline: Option<String>,
+    pub compare: Option<String>,
     pub test_shard: Option<(uint,uint)>,
     pub logfile: Option<Path>,
+    pub junit: Option<Path>,
+    pub timeout: Option<u64>,
+    pub slow_threshold: Option<u64>,
     pub nocapture: bool,
     pub color: ColorConfig,
+    pub format: OutputFormat,
     pub show_boxplot: bool,
     pub boxplot_width: uint,
     pub show_all_stats: bool,
@@ -316,10 +364,16 @@ impl TestOpts {
             ratchet_metrics: None,
             ratchet_noise_percent: None,
             save_metrics: None,
+            baseline: None,
+            compare: None,
             test_shard: None,
             logfile: None,
+            junit: None,
+            timeout: None,
+            slow_threshold: None,
             nocapture: false,
             color: AutoColor,
+            format: HumanFormat,
             show_boxplot: false,
             boxplot_width: 50,
             show_all_stats: false,
@@ -344,16 +398,31 @@ fn optgroups() -> Vec<getopts::OptGroup> {
       getopts::optopt("", "ratchet-noise-percent",
                      "Tests within N% of the recorded metrics will be \
                       considered as passing", "PERCENTAGE"),
+      getopts::optopt("", "baseline", "Save the samples of the benchmarks as the \
+                                       baseline NAME", "NAME"),
+      getopts::optopt("", "compare", "Compare the benchmarks with the baseline NAME, \
+                                      failing if any is significantly slower", "NAME"),
       getopts::optopt("", "logfile", "Write logs to the specified file instead \
                           of stdout", "PATH"),
+      getopts::optopt("", "junit", "Write a JUnit XML report of the run to the \
+                                    specified file", "PATH"),
       getopts::optopt("", "test-shard", "run shard A, of B shards, worth of the testsuite",
                      "A.B"),
+      getopts::optopt("", "timeout", "Fail tests that run for longer than MS \
+                                      milliseconds, unless they have a \
+                                      #[timeout] of their own. Tests that time \
+                                      out are left running in the background", "MS"),
+      getopts::optopt("", "slow", "Report tests that are still running after MS \
+                                   milliseconds", "MS"),
       getopts::optflag("", "nocapture", "don't capture stdout/stderr of each \
                                          task, allow printing directly"),
       getopts::optopt("", "color", "Configure coloring of output:
             auto   = colorize if stdout is a tty and tests are run on serially (default);
             always = always colorize output;
             never  = never colorize output;", "auto|always|never"),
+      getopts::optopt("", "format", "Configure the output format:
+            human = human readable progress and summary (default);
+            json  = one JSON event per line;", "human|json"),
       getopts::optflag("", "boxplot", "Display a boxplot of the benchmark statistics"),
       getopts::optopt("", "boxplot-width", "Set the boxplot width (default 50)", "WIDTH"),
       getopts::optflag("", "stats", "Display the benchmark min, max, and quartiles"))
@@ -373,6 +442,17 @@ All tests have their standard output and standard error captured by default.
 This can be overridden with the --nocapture flag or the RUST_TEST_NOCAPTURE=1
 environment variable. Logging is not captured by default.
 
+Benchmark baselines saved with --baseline NAME are written to NAME.json in the
+directory named by the RUST_BENCH_BASELINES environment variable, or in
+bench-baselines by default. Running with --compare NAME reports the change of
+each benchmark since the baseline, and fails the run if a benchmark got slower
+by more than can be put down to noise.
+
+Properties marked #[quickcheck] are checked with 100 random arguments, or as
+many as the RUST_QUICKCHECK_TESTS environment variable says. When a property
+fails, the arguments are shrunk to the simplest ones it still fails with, and
+the seed of the run is printed; set RUST_QUICKCHECK_SEED to it to replay it.
+
 Test Attributes:
 
     #[test]        - Indicates a function is a test to be run. This function
@@ -383,10 +463,23 @@ Test Attributes:
                      the code causes a failure (an assertion failure or panic!)
                      A message may be provided, which the failure string must
                      contain: #[should_fail(expected = "foo")].
+    #[quickcheck]  - Indicates a function is a property to be checked with
+                     random arguments, which must implement
+                     test::quickcheck::Arbitrary. It returns whether the
+                     property holds, as a bool, or panics when it does not.
     #[ignore]      - When applied to a function which is already attributed as a
                      test, then the test runner will ignore these tests during
                      normal test runs. Running with --ignored will run these
-                     tests."#,
+                     tests.
+    #[timeout = MS] - This test (also labeled with #[test]) fails if it runs
+                     for more than MS milliseconds, whatever --timeout is.
+                     Its task keeps running until the test runner exits.
+    #[setup]       - This function runs before each test of its module.
+    #[teardown]    - This function runs after each test of its module, even
+                     when the test fails.
+    #[fixture]     - The value this function returns is created once, and
+                     passed by reference to the tests of its module that take
+                     an argument named after the function."#,
              usage = getopts::usage(message.as_slice(),
                                     optgroups().as_slice()));
 }
@@ -417,6 +510,9 @@ pub fn parse_opts(args: &[String]) -> Option<OptRes> {
     let logfile = matches.opt_str("logfile");
     let logfile = logfile.map(|s| Path::new(s));
 
+    let junit = matches.opt_str("junit");
+    let junit = junit.map(|s| Path::new(s));
+
     let run_benchmarks = matches.opt_present("bench");
     let run_tests = ! run_benchmarks ||
         matches.opt_present("test");
@@ -434,6 +530,24 @@ pub fn parse_opts(args: &[String]) -> Option<OptRes> {
     let test_shard = matches.opt_str("test-shard");
     let test_shard = opt_shard(test_shard);
 
+    let timeout = match matches.opt_str("timeout") {
+        Some(ms) => match FromStr::from_str(ms.as_slice()) {
+            Some(ms) => Some(ms),
+            None => return Some(Err(format!("argument for --timeout must be a \
+                                             number of milliseconds (was {})", ms))),
+        },
+        None => None,
+    };
+
+    let slow_threshold = match matches.opt_str("slow") {
+        Some(ms) => match FromStr::from_str(ms.as_slice()) {
+            Some(ms) => Some(ms),
+            None => return Some(Err(format!("argument for --slow must be a \
+                                             number of milliseconds (was {})", ms))),
+        },
+        None => None,
+    };
+
     let mut nocapture = matches.opt_present("nocapture");
     if !nocapture {
         nocapture = os::getenv("RUST_TEST_NOCAPTURE").is_some();
@@ -449,6 +563,15 @@ pub fn parse_opts(args: &[String]) -> Option<OptRes> {
                                             v))),
     };
 
+    let format = match matches.opt_str("format").as_ref().map(|s| s.as_slice()) {
+        Some("human") | None => HumanFormat,
+        Some("json") => JsonFormat,
+
+        Some(v) => return Some(Err(format!("argument for --format must be \
+                                            human or json (was {})",
+                                            v))),
+    };
+
     let show_boxplot = matches.opt_present("boxplot");
     let boxplot_width = match matches.opt_str("boxplot-width") {
         Some(width) => {
@@ -472,10 +595,16 @@ pub fn parse_opts(args: &[String]) -> Option<OptRes> {
         ratchet_metrics: ratchet_metrics,
         ratchet_noise_percent: ratchet_noise_percent,
         save_metrics: save_metrics,
+        baseline: matches.opt_str("baseline"),
+        compare: matches.opt_str("compare"),
         test_shard: test_shard,
         logfile: logfile,
+        junit: junit,
+        timeout: timeout,
+        slow_threshold: slow_threshold,
         nocapture: nocapture,
         color: color,
+        format: format,
         show_boxplot: show_boxplot,
         boxplot_width: boxplot_width,
         show_all_stats: show_all_stats,
@@ -508,6 +637,7 @@ pub fn opt_shard(maybestr: Option<String>) -> Option<(uint,uint)> {
 #[deriving(Clone, PartialEq)]
 pub struct BenchSamples {
     ns_iter_summ: stats::Summary<f64>,
+    ns_iter_samples: Vec<f64>,
     mb_s: uint,
 }
 
@@ -516,6 +646,7 @@ pub enum TestResult {
     TrOk,
     TrFailed,
     TrIgnored,
+    TrTimedOut,
     TrMetrics(MetricMap),
     TrBench(BenchSamples),
 }
@@ -525,19 +656,29 @@ enum OutputLocation<T> {
     Raw(T),
 }
 
+/// The outcome of a test as recorded for the JUnit report: what was run, its
+/// result, its captured output and how long it took.
+pub type JunitRecord = (TestDesc, TestResult, Vec<u8>, Duration);
+
 struct ConsoleTestState<T> {
     log_out: Option<File>,
+    junit_out: Option<File>,
+    junit_records: Vec<JunitRecord>,
     out: OutputLocation<T>,
     use_color: bool,
+    format: OutputFormat,
     show_boxplot: bool,
     boxplot_width: uint,
     show_all_stats: bool,
+    start: Instant,
     total: uint,
     passed: uint,
     failed: uint,
     ignored: uint,
     measured: uint,
     metrics: MetricMap,
+    bench_samples: Baseline,
+    regressed_benches: uint,
     failures: Vec<(TestDesc, Vec<u8> )> ,
     max_name_len: uint, // number of columns to fill when aligning names
 }
@@ -549,6 +690,10 @@ impl<T: Writer> ConsoleTestState<T> {
             Some(ref path) => Some(try!(File::create(path))),
             None => None
         };
+        let junit_out = match opts.junit {
+            Some(ref path) => Some(try!(File::create(path))),
+            None => None
+        };
         let out = match term::stdout() {
             None => Raw(io::stdio::stdout_raw()),
             Some(t) => Pretty(t)
@@ -557,16 +702,22 @@ impl<T: Writer> ConsoleTestState<T> {
         Ok(ConsoleTestState {
             out: out,
             log_out: log_out,
+            junit_out: junit_out,
+            junit_records: Vec::new(),
             use_color: use_color(opts),
+            format: opts.format,
             show_boxplot: opts.show_boxplot,
             boxplot_width: opts.boxplot_width,
             show_all_stats: opts.show_all_stats,
+            start: Instant::now(),
             total: 0u,
             passed: 0u,
             failed: 0u,
             ignored: 0u,
             measured: 0u,
             metrics: MetricMap::new(),
+            bench_samples: Baseline::new(),
+            regressed_benches: 0u,
             failures: Vec::new(),
             max_name_len: 0u,
         })
@@ -584,6 +735,10 @@ impl<T: Writer> ConsoleTestState<T> {
         self.write_pretty("ignored", term::color::YELLOW)
     }
 
+    pub fn write_timed_out(&mut self) -> io::IoResult<()> {
+        self.write_pretty("timed out", term::color::RED)
+    }
+
     pub fn write_metric(&mut self) -> io::IoResult<()> {
         self.write_pretty("metric", term::color::CYAN)
     }
@@ -633,23 +788,62 @@ impl<T: Writer> ConsoleTestState<T> {
         }
     }
 
+    /// Writes a single line JSON object for an event of the run. `kind` is
+    /// what the event is about (the suite, a test, a benchmark...) and
+    /// `event` is what happened to it.
+    pub fn write_event(&mut self, kind: &str, event: &str,
+                       fields: Vec<(&str, Json)>) -> io::IoResult<()> {
+        let mut obj = BTreeMap::new();
+        obj.insert("type".to_string(), kind.to_json());
+        obj.insert("event".to_string(), event.to_json());
+        for (k, v) in fields.into_iter() {
+            obj.insert(k.to_string(), v);
+        }
+        self.write_plain(format!("{}\n", Json::Object(obj)).as_slice())
+    }
+
     pub fn write_run_start(&mut self, len: uint) -> io::IoResult<()> {
         self.total = len;
+        self.start = Instant::now();
+        if self.format == JsonFormat {
+            return self.write_event("suite", "started",
+                                    vec![("test_count", len.to_json())]);
+        }
         let noun = if len != 1 { "tests" } else { "test" };
         self.write_plain(format!("\nrunning {} {}\n", len, noun).as_slice())
     }
 
     pub fn write_test_start(&mut self, test: &TestDesc,
                             align: NamePadding) -> io::IoResult<()> {
+        if self.format == JsonFormat {
+            return self.write_event("test", "started",
+                                    vec![("name", test.name.to_string().to_json())]);
+        }
         let name = test.padded_name(self.max_name_len, align);
         self.write_plain(format!("test {} ... ", name).as_slice())
     }
 
-    pub fn write_result(&mut self, result: &TestResult) -> io::IoResult<()> {
+    pub fn write_slow(&mut self, test: &TestDesc, elapsed: Duration) -> io::IoResult<()> {
+        if self.format == JsonFormat {
+            let elapsed = elapsed.num_nanoseconds().unwrap() as u64;
+            return self.write_event("test", "slow",
+                                    vec![("name", test.name.to_string().to_json()),
+                                         ("duration_ns", elapsed.to_json())]);
+        }
+        self.write_plain(format!("test {} has been running for over {} seconds\n",
+                                 test.name, elapsed.num_seconds()).as_slice())
+    }
+
+    pub fn write_result(&mut self, test: &TestDesc, result: &TestResult,
+                        stdout: &[u8], dur: Duration) -> io::IoResult<()> {
+        if self.format == JsonFormat {
+            return self.write_json_result(test, result, stdout, dur);
+        }
         try!(match *result {
             TrOk => self.write_ok(),
             TrFailed => self.write_failed(),
             TrIgnored => self.write_ignored(),
+            TrTimedOut => self.write_timed_out(),
             TrMetrics(ref mm) => {
                 try!(self.write_metric());
                 self.write_plain(format!(": {}", fmt_metrics(mm)).as_slice())
@@ -686,6 +880,46 @@ impl<T: Writer> ConsoleTestState<T> {
         self.write_plain("\n")
     }
 
+    fn write_json_result(&mut self, test: &TestDesc, result: &TestResult,
+                         stdout: &[u8], dur: Duration) -> io::IoResult<()> {
+        let name = test.name.to_string().to_json();
+        if stdout.len() > 0 {
+            let output = String::from_utf8_lossy(stdout).into_owned();
+            try!(self.write_event("test", "output",
+                                  vec![("name", name.clone()),
+                                       ("stdout", output.to_json())]));
+        }
+        let duration = ("duration_ns", (dur.num_nanoseconds().unwrap() as u64).to_json());
+        match *result {
+            TrOk => self.write_event("test", "ok", vec![("name", name), duration]),
+            TrFailed => self.write_event("test", "failed", vec![("name", name), duration]),
+            TrIgnored => self.write_event("test", "ignored", vec![("name", name), duration]),
+            TrTimedOut => self.write_event("test", "timed_out", vec![("name", name), duration]),
+            TrMetrics(MetricMap(ref mm)) => {
+                let mut metrics = BTreeMap::new();
+                for (k, v) in mm.iter() {
+                    let mut metric = BTreeMap::new();
+                    metric.insert("value".to_string(), v.value.to_json());
+                    metric.insert("noise".to_string(), v.noise.to_json());
+                    metrics.insert(k.clone(), Json::Object(metric));
+                }
+                self.write_event("metric", "ok",
+                                 vec![("name", name),
+                                      ("metrics", Json::Object(metrics)),
+                                      duration])
+            }
+            TrBench(ref bs) => {
+                let summ = &bs.ns_iter_summ;
+                self.write_event("bench", "ok",
+                                 vec![("name", name),
+                                      ("median_ns", summ.median.to_json()),
+                                      ("deviation_ns", (summ.max - summ.min).to_json()),
+                                      ("mb_s", bs.mb_s.to_json()),
+                                      duration])
+            }
+        }
+    }
+
     pub fn write_log(&mut self, test: &TestDesc,
                      result: &TestResult) -> io::IoResult<()> {
         match self.log_out {
@@ -695,6 +929,7 @@ impl<T: Writer> ConsoleTestState<T> {
                         TrOk => "ok".to_string(),
                         TrFailed => "failed".to_string(),
                         TrIgnored => "ignored".to_string(),
+                        TrTimedOut => "timed out".to_string(),
                         TrMetrics(ref mm) => fmt_metrics(mm),
                         TrBench(ref bs) => fmt_bench_samples(bs)
                     }, test.name.as_slice());
@@ -703,6 +938,18 @@ impl<T: Writer> ConsoleTestState<T> {
         }
     }
 
+    pub fn write_junit_report(&mut self) -> io::IoResult<()> {
+        let secs = self.start.elapsed().num_milliseconds() as f64 / 1e3;
+        let suite = os::self_exe_name()
+            .and_then(|p| p.filename_str().map(|s| s.to_string()))
+            .unwrap_or("test".to_string());
+        match self.junit_out {
+            None => Ok(()),
+            Some(ref mut o) => write_junit_report(o, suite.as_slice(),
+                                                  self.junit_records.as_slice(), secs)
+        }
+    }
+
     pub fn write_failures(&mut self) -> io::IoResult<()> {
         try!(self.write_plain("\nfailures:\n"));
         let mut failures = Vec::new();
@@ -741,6 +988,18 @@ impl<T: Writer> ConsoleTestState<T> {
         for (k, v) in diff.iter() {
             match *v {
                 LikelyNoise => noise += 1,
+                _ if self.format == JsonFormat => {
+                    let (event, pct) = match *v {
+                        MetricAdded => { added += 1; ("added", None) }
+                        MetricRemoved => { removed += 1; ("removed", None) }
+                        Improvement(pct) => { improved += 1; ("improved", Some(pct)) }
+                        Regression(pct) => { regressed += 1; ("regressed", Some(pct)) }
+                        LikelyNoise => unreachable!(),
+                    };
+                    try!(self.write_event("ratchet", event,
+                                          vec![("name", k.to_json()),
+                                               ("percent", pct.to_json())]));
+                }
                 MetricAdded => {
                     added += 1;
                     try!(self.write_added());
@@ -767,6 +1026,15 @@ impl<T: Writer> ConsoleTestState<T> {
                 }
             }
         }
+        if self.format == JsonFormat {
+            return self.write_event("ratchet", "finished",
+                                    vec![("added", added.to_json()),
+                                         ("removed", removed.to_json()),
+                                         ("improved", improved.to_json()),
+                                         ("regressed", regressed.to_json()),
+                                         ("noise", noise.to_json()),
+                                         ("updated", (regressed == 0).to_json())]);
+        }
         try!(self.write_plain(format!("result of ratchet: {} metrics added, \
                                         {} removed, {} improved, {} regressed, \
                                         {} noise\n",
@@ -780,6 +1048,87 @@ impl<T: Writer> ConsoleTestState<T> {
         Ok(())
     }
 
+    pub fn write_baseline_comparison(&mut self, baseline: &str,
+                                     old: &Baseline) -> io::IoResult<()> {
+        let json = self.format == JsonFormat;
+        if json {
+            try!(self.write_event("compare", "started",
+                                  vec![("baseline", baseline.to_json())]));
+        } else {
+            try!(self.write_plain(format!("\ncomparing with baseline `{}`:\n",
+                                          baseline).as_slice()));
+        }
+
+        let mut improved = 0u;
+        let mut regressed = 0u;
+        let mut unchanged = 0u;
+        let mut added = 0u;
+        let Baseline(ref old) = *old;
+        let Baseline(new) = self.bench_samples.clone();
+        for (k, samples) in new.iter() {
+            let c = match old.get(k) {
+                Some(old) => BenchComparison::new(old.as_slice(), samples.as_slice()),
+                None => {
+                    added += 1;
+                    if json {
+                        try!(self.write_event("compare", "added", vec![("name", k.to_json())]));
+                    } else {
+                        try!(self.write_plain(format!("bench {}: ", *k).as_slice()));
+                        try!(self.write_added());
+                        try!(self.write_plain("\n"));
+                    }
+                    continue
+                }
+            };
+            let event = if c.is_regression() {
+                regressed += 1;
+                "regressed"
+            } else if c.is_improvement() {
+                improved += 1;
+                "improved"
+            } else {
+                unchanged += 1;
+                "unchanged"
+            };
+            if json {
+                try!(self.write_event("compare", event,
+                                      vec![("name", k.to_json()),
+                                           ("old_median_ns", c.old_median.to_json()),
+                                           ("new_median_ns", c.new_median.to_json()),
+                                           ("percent", c.percent(c.new_median - c.old_median)
+                                                        .to_json()),
+                                           ("low_percent", c.percent(c.low).to_json()),
+                                           ("high_percent", c.percent(c.high).to_json()),
+                                           ("p_value", c.p_value.to_json())]));
+                continue
+            }
+            try!(self.write_plain(format!("bench {}: {} -> {} ns/iter ({:+.2}%, \
+                                           95% CI {:+.2}% to {:+.2}%, p = {:.4}) ",
+                                          *k, c.old_median as u64, c.new_median as u64,
+                                          c.percent(c.new_median - c.old_median),
+                                          c.percent(c.low), c.percent(c.high),
+                                          c.p_value).as_slice()));
+            try!(match event {
+                "regressed" => self.write_regressed(),
+                "improved" => self.write_improved(),
+                _ => self.write_plain("no significant change"),
+            });
+            try!(self.write_plain("\n"));
+        }
+        self.regressed_benches = regressed;
+
+        if json {
+            return self.write_event("compare", "finished",
+                                    vec![("improved", improved.to_json()),
+                                         ("regressed", regressed.to_json()),
+                                         ("unchanged", unchanged.to_json()),
+                                         ("added", added.to_json())]);
+        }
+        self.write_plain(format!("result of comparison: {} improved, {} regressed, \
+                                  {} unchanged, {} added\n",
+                                 improved, regressed, unchanged, added).as_slice())
+    }
+
     pub fn write_run_finish(&mut self,
                             ratchet_metrics: &Option<Path>,
                             ratchet_pct: Option<f64>) -> io::IoResult<bool> {
@@ -787,6 +1136,14 @@ impl<T: Writer> ConsoleTestState<T> {
 
         let ratchet_success = match *ratchet_metrics {
             None => true,
+            Some(ref pth) if self.format == JsonFormat => {
+                let (diff, ok) = self.metrics.ratchet(pth, ratchet_pct);
+                try!(self.write_event("ratchet", "started",
+                                      vec![("path", pth.display().to_string().to_json()),
+                                           ("noise_percent", ratchet_pct.to_json())]));
+                try!(self.write_metric_diff(&diff));
+                ok
+            }
             Some(ref pth) => {
                 try!(self.write_plain(format!("\nusing metrics ratchet: {}\n",
                                               pth.display()).as_slice()));
@@ -804,12 +1161,23 @@ impl<T: Writer> ConsoleTestState<T> {
         };
 
         let test_success = self.failed == 0u;
+        let success = ratchet_success && test_success && self.regressed_benches == 0;
+
+        if self.format == JsonFormat {
+            let dur = self.start.elapsed().num_nanoseconds().unwrap();
+            try!(self.write_event("suite", if success { "ok" } else { "failed" },
+                                  vec![("passed", self.passed.to_json()),
+                                       ("failed", self.failed.to_json()),
+                                       ("ignored", self.ignored.to_json()),
+                                       ("measured", self.measured.to_json()),
+                                       ("duration_ns", dur.to_json())]));
+            return Ok(success);
+        }
+
         if !test_success {
             try!(self.write_failures());
         }
 
-        let success = ratchet_success && test_success;
-
         try!(self.write_plain("\ntest result: "));
         if success {
             // There's no parallelism at this point so it's safe to use color
@@ -824,6 +1192,86 @@ impl<T: Writer> ConsoleTestState<T> {
     }
 }
 
+/// Writes a JUnit XML report for a run of the tests in `records`, which took
+/// `secs` seconds in total.
+///
+/// Test names are split on their last `::` into the class and the name of the
+/// test case. Captured output goes in `system-out`, since a test's standard
+/// output and standard error are captured together.
+pub fn write_junit_report<W: Writer>(w: &mut W, suite: &str, records: &[JunitRecord],
+                                     secs: f64) -> io::IoResult<()> {
+    let failures = records.iter().filter(|&&(_, ref r, _, _)| {
+        *r == TrFailed || *r == TrTimedOut
+    }).count();
+    let skipped = records.iter().filter(|&&(_, ref r, _, _)| *r == TrIgnored).count();
+
+    try!(writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#));
+    try!(writeln!(w, r#"<testsuite name="{}" tests="{}" failures="{}" errors="0" skipped="{}" time="{:.3}">"#,
+                  xml_escape(suite), records.len(), failures, skipped, secs));
+    for &(ref desc, ref result, ref stdout, dur) in records.iter() {
+        let name = desc.name.to_string();
+        let mut path: Vec<&str> = name.as_slice().split_str("::").collect();
+        let case = path.pop().unwrap();
+        // Tests outside of any module belong to the suite itself.
+        let class = if path.is_empty() { suite.to_string() } else { path.connect("::") };
+        let secs = dur.num_nanoseconds().unwrap() as f64 / 1e9;
+        try!(writeln!(w, r#"  <testcase classname="{}" name="{}" time="{:.3}">"#,
+                      xml_escape(class.as_slice()), xml_escape(case), secs));
+
+        let output = String::from_utf8_lossy(stdout.as_slice()).into_owned();
+        let mut output = xml_escape(output.as_slice());
+        match *result {
+            TrOk => {}
+            TrFailed => {
+                let message = failure_message(desc, output.as_slice());
+                try!(writeln!(w, r#"    <failure message="{}"/>"#, message));
+            }
+            TrIgnored => try!(writeln!(w, "    <skipped/>")),
+            TrTimedOut => try!(writeln!(w, r#"    <failure message="test timed out"/>"#)),
+            TrMetrics(ref mm) => output.push_str(xml_escape(fmt_metrics(mm).as_slice()).as_slice()),
+            TrBench(ref bs) => {
+                output.push_str(xml_escape(fmt_bench_samples(bs).as_slice()).as_slice())
+            }
+        }
+        if output.len() > 0 {
+            try!(writeln!(w, "    <system-out>{}</system-out>", output));
+        }
+        try!(writeln!(w, "  </testcase>"));
+    }
+    writeln!(w, "</testsuite>")
+}
+
+// The message of a failure is the panic message printed in the test's output,
+// which has already been escaped for XML.
+fn failure_message(desc: &TestDesc, output: &str) -> String {
+    match output.lines().filter(|l| l.contains("panicked at")).last() {
+        Some(line) => line.to_string(),
+        None => match desc.should_fail {
+            ShouldFail::No => "test failed".to_string(),
+            ShouldFail::Yes(_) => "test did not panic as expected".to_string(),
+        }
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Other control characters can't appear in an XML document at all,
+            // not even as character references.
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c < ' ' => escaped.push_str(format!("\\x{:02x}", c as uint).as_slice()),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 pub fn fmt_metrics(mm: &MetricMap) -> String {
     let MetricMap(ref mm) = *mm;
     let v : Vec<String> = mm.iter()
@@ -853,9 +1301,13 @@ pub fn run_tests_console(opts: &TestOpts, tests: Vec<TestDescAndFn> ) -> io::IoR
         match (*event).clone() {
             TeFiltered(ref filtered_tests) => st.write_run_start(filtered_tests.len()),
             TeWait(ref test, padding) => st.write_test_start(test, padding),
-            TeResult(test, result, stdout) => {
+            TeSlow(ref test, elapsed) => st.write_slow(test, elapsed),
+            TeResult(test, result, stdout, dur) => {
                 try!(st.write_log(&test, &result));
-                try!(st.write_result(&result));
+                if st.junit_out.is_some() {
+                    st.junit_records.push((test.clone(), result.clone(), stdout.clone(), dur));
+                }
+                try!(st.write_result(&test, &result, stdout.as_slice(), dur));
                 match result {
                     TrOk => st.passed += 1,
                     TrIgnored => st.ignored += 1,
@@ -876,9 +1328,11 @@ pub fn run_tests_console(opts: &TestOpts, tests: Vec<TestDescAndFn> ) -> io::IoR
                         st.metrics.insert_metric(test.name.as_slice(),
                                                  bs.ns_iter_summ.median,
                                                  bs.ns_iter_summ.max - bs.ns_iter_summ.min);
+                        st.bench_samples.insert_samples(test.name.as_slice(),
+                                                        bs.ns_iter_samples);
                         st.measured += 1
                     }
-                    TrFailed => {
+                    TrFailed | TrTimedOut => {
                         st.failed += 1;
                         st.failures.push((test, stdout));
                     }
@@ -907,11 +1361,40 @@ pub fn run_tests_console(opts: &TestOpts, tests: Vec<TestDescAndFn> ) -> io::IoR
         None => (),
         Some(ref pth) => {
             try!(st.metrics.save(pth));
-            try!(st.write_plain(format!("\nmetrics saved to: {}",
-                                          pth.display()).as_slice()));
+            if opts.format == HumanFormat {
+                try!(st.write_plain(format!("\nmetrics saved to: {}",
+                                              pth.display()).as_slice()));
+            }
+        }
+    }
+    match opts.compare {
+        None => (),
+        Some(ref name) => {
+            let old = try!(Baseline::load(&try!(Baseline::path(name.as_slice()))));
+            try!(st.write_baseline_comparison(name.as_slice(), &old));
+        }
+    }
+    match opts.baseline {
+        None => (),
+        Some(ref name) => {
+            let pth = try!(Baseline::path(name.as_slice()));
+            let mut baseline = if pth.exists() {
+                try!(Baseline::load(&pth))
+            } else {
+                Baseline::new()
+            };
+            baseline.update(&st.bench_samples);
+            try!(baseline.save(&pth));
+            if opts.format == HumanFormat {
+                try!(st.write_plain(format!("\nbaseline `{}` saved to: {}\n",
+                                            name, pth.display()).as_slice()));
+            }
         }
     }
-    return st.write_run_finish(&opts.ratchet_metrics, opts.ratchet_noise_percent);
+    let success = try!(st.write_run_finish(&opts.ratchet_metrics,
+                                           opts.ratchet_noise_percent));
+    try!(st.write_junit_report());
+    return Ok(success);
 }
 
 #[test]
@@ -919,22 +1402,28 @@ fn should_sort_failures_before_printing_them() {
     let test_a = TestDesc {
         name: StaticTestName("a"),
         ignore: false,
-        should_fail: ShouldFail::No
+        should_fail: ShouldFail::No,
+        timeout: None
     };
 
     let test_b = TestDesc {
         name: StaticTestName("b"),
         ignore: false,
-        should_fail: ShouldFail::No
+        should_fail: ShouldFail::No,
+        timeout: None
     };
 
     let mut st = ConsoleTestState {
         log_out: None,
+        junit_out: None,
+        junit_records: Vec::new(),
         out: Raw(Vec::new()),
         use_color: false,
+        format: HumanFormat,
         show_boxplot: false,
         boxplot_width: 0,
         show_all_stats: false,
+        start: Instant::now(),
         total: 0u,
         passed: 0u,
         failed: 0u,
@@ -942,6 +1431,8 @@ fn should_sort_failures_before_printing_them() {
         measured: 0u,
         max_name_len: 10u,
         metrics: MetricMap::new(),
+        bench_samples: Baseline::new(),
+        regressed_benches: 0u,
         failures: vec!((test_b, Vec::new()), (test_a, Vec::new()))
     };
 
@@ -956,6 +1447,63 @@ fn should_sort_failures_before_printing_them() {
     assert!(apos < bpos);
 }
 
+#[test]
+fn json_format_writes_one_event_per_line() {
+    let test = TestDesc {
+        name: StaticTestName("a"),
+        ignore: false,
+        should_fail: ShouldFail::No,
+        timeout: None
+    };
+
+    let mut st = ConsoleTestState {
+        log_out: None,
+        junit_out: None,
+        junit_records: Vec::new(),
+        out: Raw(Vec::new()),
+        use_color: false,
+        format: JsonFormat,
+        show_boxplot: false,
+        boxplot_width: 0,
+        show_all_stats: false,
+        start: Instant::now(),
+        total: 0u,
+        passed: 0u,
+        failed: 0u,
+        ignored: 0u,
+        measured: 0u,
+        max_name_len: 10u,
+        metrics: MetricMap::new(),
+        bench_samples: Baseline::new(),
+        regressed_benches: 0u,
+        failures: Vec::new()
+    };
+
+    st.write_run_start(1).unwrap();
+    st.write_test_start(&test, PadNone).unwrap();
+    st.write_result(&test, &TrFailed, "oops".as_bytes(), Duration::nanoseconds(42)).unwrap();
+    st.failed += 1;
+    assert!(!st.write_run_finish(&None, None).unwrap());
+
+    let s = match st.out {
+        Raw(ref m) => String::from_utf8(m.clone()).unwrap(),
+        Pretty(_) => unreachable!()
+    };
+    let events: Vec<Json> = s.lines().map(|l| json::from_str(l).unwrap()).collect();
+    let kinds: Vec<(&str, &str)> = events.iter().map(|e| {
+        (e.find("type").unwrap().as_string().unwrap(),
+         e.find("event").unwrap().as_string().unwrap())
+    }).collect();
+    assert_eq!(kinds, vec![("suite", "started"), ("test", "started"),
+                           ("test", "output"), ("test", "failed"),
+                           ("suite", "failed")]);
+    assert_eq!(events[0].find("test_count").unwrap().as_u64(), Some(1));
+    assert_eq!(events[2].find("stdout").unwrap().as_string(), Some("oops"));
+    assert_eq!(events[3].find("name").unwrap().as_string(), Some("a"));
+    assert_eq!(events[3].find("duration_ns").unwrap().as_u64(), Some(42));
+    assert_eq!(events[4].find("failed").unwrap().as_u64(), Some(1));
+}
+
 fn use_color(opts: &TestOpts) -> bool {
     match opts.color {
         AutoColor => get_concurrency() == 1 && io::stdout().get_ref().isatty(),
@@ -968,10 +1516,35 @@ fn use_color(opts: &TestOpts) -> bool {
 enum TestEvent {
     TeFiltered(Vec<TestDesc> ),
     TeWait(TestDesc, NamePadding),
-    TeResult(TestDesc, TestResult, Vec<u8> ),
+    TeSlow(TestDesc, Duration),
+    TeResult(TestDesc, TestResult, Vec<u8>, Duration),
+}
+
+/// The result of a test, sent back with the id it was run with, since test
+/// names need not be unique.
+pub type MonitorMsg = (uint, TestDesc, TestResult, Vec<u8> );
+
+// A test that `run_tests` has started and that hasn't finished yet.
+struct RunningTest {
+    desc: TestDesc,
+    start: Instant,
+    reported_slow: bool,
 }
 
-pub type MonitorMsg = (TestDesc, TestResult, Vec<u8> );
+impl RunningTest {
+    // When the test times out.
+    fn deadline(&self, opts: &TestOpts) -> Option<Instant> {
+        self.desc.timeout.or(opts.timeout).map(|ms| self.start + Duration::milliseconds(ms as i64))
+    }
+
+    // When the test is to be reported as slow, unless it already was.
+    fn slow_deadline(&self, opts: &TestOpts) -> Option<Instant> {
+        if self.reported_slow {
+            return None;
+        }
+        opts.slow_threshold.map(|ms| self.start + Duration::milliseconds(ms as i64))
+    }
+}
 
 fn run_tests<F>(opts: &TestOpts,
                 tests: Vec<TestDescAndFn> ,
@@ -997,40 +1570,121 @@ fn run_tests<F>(opts: &TestOpts,
     // many tests that run in other processes we would be making a big mess.
     let concurrency = get_concurrency();
 
+    // When we are doing one test at a time we can print the name of the test
+    // before we run it. Useful for debugging tests that hang forever. The
+    // JSON output reports each start as an event of its own, so it doesn't
+    // have to wait for the result to be on the same line.
+    let announce_start = concurrency == 1 || opts.format == JsonFormat;
+
     let mut remaining = filtered_tests;
     remaining.reverse();
     let mut pending = 0;
+    let mut next_id = 0u;
+    let mut running = BTreeMap::new();
+    let mut any_timed_out = false;
+    let mut timer = try!(io::Timer::new());
 
     let (tx, rx) = channel::<MonitorMsg>();
 
     while pending > 0 || !remaining.is_empty() {
         while pending < concurrency && !remaining.is_empty() {
             let test = remaining.pop().unwrap();
-            if concurrency == 1 {
-                // We are doing one test at a time so we can print the name
-                // of the test before we run it. Useful for debugging tests
-                // that hang forever.
+            if announce_start {
                 try!(callback(TeWait(test.desc.clone(), test.testfn.padding())));
             }
-            run_test(opts, !opts.run_tests, test, tx.clone());
+            running.insert(next_id, RunningTest {
+                desc: test.desc.clone(),
+                start: Instant::now(),
+                reported_slow: false,
+            });
+            run_test(opts, !opts.run_tests, test, next_id, tx.clone());
+            next_id += 1;
             pending += 1;
         }
 
-        let (desc, result, stdout) = rx.recv();
-        if concurrency != 1 {
-            try!(callback(TeWait(desc.clone(), PadNone)));
+        // Wait for a test to finish, but only until the next test has to be
+        // reported as slow or failed as timed out.
+        let next_check = running.values().flat_map(|t| {
+            t.deadline(opts).into_iter().chain(t.slow_deadline(opts).into_iter())
+        }).min();
+        let msg = match next_check {
+            None => Some(rx.recv()),
+            Some(deadline) => {
+                let timeout = timer.oneshot_at(deadline);
+                select! {
+                    msg = rx.recv() => Some(msg),
+                    () = timeout.recv() => None
+                }
+            }
+        };
+
+        match msg {
+            Some((id, desc, result, stdout)) => {
+                // A test that timed out has already been reported, and there's
+                // nothing to do with its result if it finishes after all.
+                let start = match running.remove(&id) {
+                    Some(test) => test.start,
+                    None => continue,
+                };
+                let dur = start.elapsed();
+                if !announce_start {
+                    try!(callback(TeWait(desc.clone(), PadNone)));
+                }
+                try!(callback(TeResult(desc, result, stdout, dur)));
+                pending -= 1;
+            }
+            None => {
+                let now = Instant::now();
+                let mut timed_out = Vec::new();
+                for (&id, test) in running.iter_mut() {
+                    if test.deadline(opts).map_or(false, |d| d <= now) {
+                        timed_out.push(id);
+                    } else if test.slow_deadline(opts).map_or(false, |d| d <= now) {
+                        test.reported_slow = true;
+                        let elapsed = now.duration_since(test.start);
+                        try!(callback(TeSlow(test.desc.clone(), elapsed)));
+                    }
+                }
+                // The tasks of tests that timed out can't be killed, so they
+                // are left running while the other tests take their place.
+                for id in timed_out.into_iter() {
+                    any_timed_out = true;
+                    let test = running.remove(&id).unwrap();
+                    let dur = now.duration_since(test.start);
+                    if !announce_start {
+                        try!(callback(TeWait(test.desc.clone(), PadNone)));
+                    }
+                    try!(callback(TeResult(test.desc, TrTimedOut, Vec::new(), dur)));
+                    pending -= 1;
+                }
+            }
         }
-        try!(callback(TeResult(desc, result, stdout)));
-        pending -= 1;
     }
 
     // All benchmarks run at the end, in serial.
     // (this includes metric fns)
     for b in filtered_benchs_and_metrics.into_iter() {
         try!(callback(TeWait(b.desc.clone(), b.testfn.padding())));
-        run_test(opts, !opts.run_benchmarks, b, tx.clone());
-        let (test, result, stdout) = rx.recv();
-        try!(callback(TeResult(test, result, stdout)));
+        let id = next_id;
+        next_id += 1;
+        let start = Instant::now();
+        run_test(opts, !opts.run_benchmarks, b, id, tx.clone());
+        loop {
+            // Tests that timed out may still report back in the meantime.
+            let (test_id, test, result, stdout) = rx.recv();
+            if test_id != id {
+                continue;
+            }
+            try!(callback(TeResult(test, result, stdout, start.elapsed())));
+            break;
+        }
+    }
+
+    // Tests that timed out may still be using the fixtures, which are leaked
+    // rather than dropped from under them. Otherwise all tests are over, and
+    // nothing uses the fixtures anymore.
+    if !any_timed_out {
+        unsafe { fixture::tear_down() };
     }
     Ok(())
 }
@@ -1101,16 +1755,18 @@ pub fn filter_tests(opts: &TestOpts, tests: Vec<TestDescAndFn>) -> Vec<TestDescA
 pub fn run_test(opts: &TestOpts,
                 force_ignore: bool,
                 test: TestDescAndFn,
+                id: uint,
                 monitor_ch: Sender<MonitorMsg>) {
 
     let TestDescAndFn {desc, testfn} = test;
 
     if force_ignore || desc.ignore {
-        monitor_ch.send((desc, TrIgnored, Vec::new()));
+        monitor_ch.send((id, desc, TrIgnored, Vec::new()));
         return;
     }
 
     fn run_test_inner(desc: TestDesc,
+                      id: uint,
                       monitor_ch: Sender<MonitorMsg>,
                       nocapture: bool,
                       testfn: Thunk) {
@@ -1133,35 +1789,37 @@ pub fn run_test(opts: &TestOpts,
             let result_guard = cfg.spawn(move || { testfn.invoke(()) });
             let stdout = reader.read_to_end().unwrap().into_iter().collect();
             let test_result = calc_result(&desc, result_guard.join());
-            monitor_ch.send((desc.clone(), test_result, stdout));
+            // Nobody is listening anymore if the test timed out and the run is
+            // over by now.
+            let _ = monitor_ch.send_opt((id, desc.clone(), test_result, stdout));
         }).detach();
     }
 
     match testfn {
         DynBenchFn(bencher) => {
             let bs = ::bench::benchmark(|harness| bencher.run(harness));
-            monitor_ch.send((desc, TrBench(bs), Vec::new()));
+            monitor_ch.send((id, desc, TrBench(bs), Vec::new()));
             return;
         }
         StaticBenchFn(benchfn) => {
             let bs = ::bench::benchmark(|harness| (benchfn.clone())(harness));
-            monitor_ch.send((desc, TrBench(bs), Vec::new()));
+            monitor_ch.send((id, desc, TrBench(bs), Vec::new()));
             return;
         }
         DynMetricFn(f) => {
             let mut mm = MetricMap::new();
             f.invoke(&mut mm);
-            monitor_ch.send((desc, TrMetrics(mm), Vec::new()));
+            monitor_ch.send((id, desc, TrMetrics(mm), Vec::new()));
             return;
         }
         StaticMetricFn(f) => {
             let mut mm = MetricMap::new();
             f(&mut mm);
-            monitor_ch.send((desc, TrMetrics(mm), Vec::new()));
+            monitor_ch.send((id, desc, TrMetrics(mm), Vec::new()));
             return;
         }
-        DynTestFn(f) => run_test_inner(desc, monitor_ch, opts.nocapture, f),
-        StaticTestFn(f) => run_test_inner(desc, monitor_ch, opts.nocapture,
+        DynTestFn(f) => run_test_inner(desc, id, monitor_ch, opts.nocapture, f),
+        StaticTestFn(f) => run_test_inner(desc, id, monitor_ch, opts.nocapture,
                                           Thunk::new(move|| f()))
     }
 }
@@ -1318,6 +1976,104 @@ impl MetricMap {
     }
 }
 
+impl Baseline {
+    pub fn new() -> Baseline {
+        Baseline(BTreeMap::new())
+    }
+
+    /// The file where the baseline `name` is saved: `name.json`, in the
+    /// directory named by `RUST_BENCH_BASELINES` or in `bench-baselines`.
+    ///
+    /// Fails with `InvalidInput` if the name is empty or could refer to a file
+    /// outside of that directory.
+    pub fn path(name: &str) -> io::IoResult<Path> {
+        if name.is_empty() || name.contains("..") ||
+           name.chars().any(|c| c == '/' || c == '\\' || c == ':') {
+            return Err(io::IoError {
+                kind: io::InvalidInput,
+                desc: "invalid benchmark baseline name",
+                detail: Some(format!("`{}` must not be empty or contain `..`, \
+                                      `:` or path separators", name)),
+            });
+        }
+        let dir = os::getenv("RUST_BENCH_BASELINES").unwrap_or("bench-baselines".to_string());
+        Ok(Path::new(dir).join(format!("{}.json", name)))
+    }
+
+    /// Load a baseline from a file, failing with `InvalidInput` if the file
+    /// does not contain a valid baseline.
+    pub fn load(p: &Path) -> io::IoResult<Baseline> {
+        fn invalid<E: Show>(p: &Path, e: E) -> io::IoError {
+            io::IoError {
+                kind: io::InvalidInput,
+                desc: "invalid benchmark baseline",
+                detail: Some(format!("{}: {}", p.display(), e)),
+            }
+        }
+        let mut f = try!(File::open(p));
+        let value = try!(json::from_reader(&mut f as &mut io::Reader)
+                             .map_err(|e| invalid(p, e)));
+        let mut decoder = json::Decoder::new(value);
+        Decodable::decode(&mut decoder).map(Baseline).map_err(|e| invalid(p, e))
+    }
+
+    /// Write the baseline to a file, creating its directory if needed.
+    pub fn save(&self, p: &Path) -> io::IoResult<()> {
+        try!(io::fs::mkdir_recursive(&p.dir_path(), io::USER_RWX));
+        let mut file = try!(File::create(p));
+        let Baseline(ref map) = *self;
+        let mut enc = json::PrettyEncoder::new(&mut file);
+        map.encode(&mut enc)
+    }
+
+    pub fn insert_samples(&mut self, name: &str, samples: Vec<f64>) {
+        let Baseline(ref mut map) = *self;
+        map.insert(name.to_string(), samples);
+    }
+
+    /// Replace the samples of the benchmarks that are in `other`, keeping
+    /// those of the benchmarks that were not run this time.
+    pub fn update(&mut self, other: &Baseline) {
+        let Baseline(ref mut map) = *self;
+        let Baseline(ref other) = *other;
+        for (k, v) in other.iter() {
+            map.insert(k.clone(), v.clone());
+        }
+    }
+}
+
+impl BenchComparison {
+    /// Compare the samples of a benchmark in this run with those in a
+    /// baseline.
+    pub fn new(old: &[f64], new: &[f64]) -> BenchComparison {
+        let (low, high) = stats::bootstrap_median_difference(old, new, 1000, 95.0);
+        BenchComparison {
+            old_median: old.median(),
+            new_median: new.median(),
+            low: low,
+            high: high,
+            p_value: stats::mann_whitney_u(old, new),
+        }
+    }
+
+    /// Whether the benchmark got significantly slower since the baseline.
+    /// Changes that are within the noise of the samples are not regressions,
+    /// however large they are.
+    pub fn is_regression(&self) -> bool {
+        self.p_value < BENCH_SIGNIFICANCE && self.new_median > self.old_median
+    }
+
+    /// Whether the benchmark got significantly faster since the baseline.
+    pub fn is_improvement(&self) -> bool {
+        self.p_value < BENCH_SIGNIFICANCE && self.new_median < self.old_median
+    }
+
+    /// `ns` as a percentage of the median in the baseline.
+    pub fn percent(&self, ns: f64) -> f64 {
+        ns / self.old_median.max(f64::EPSILON) * 100.0
+    }
+}
+
 
 // Benchmarking
 
@@ -1362,7 +2118,14 @@ impl Bencher {
     }
 
     // This is a more statistics-driven benchmark algorithm
-    pub fn auto_bench<F>(&mut self, mut f: F) -> stats::Summary<f64> where F: FnMut(&mut Bencher) {
+    pub fn auto_bench<F>(&mut self, f: F) -> stats::Summary<f64> where F: FnMut(&mut Bencher) {
+        let (summ, _) = self.auto_bench_samples(f);
+        summ
+    }
+
+    // Like auto_bench, but also returns the samples the summary was made of.
+    fn auto_bench_samples<F>(&mut self, mut f: F) -> (stats::Summary<f64>, Vec<f64>)
+        where F: FnMut(&mut Bencher) {
         // Initial bench run to get ballpark figure.
         let mut n = 1_u64;
         self.bench_n(n, |x| f(x));
@@ -1386,6 +2149,9 @@ impl Bencher {
         loop {
             let mut summ = None;
             let mut summ5 = None;
+            // The samples before they are winsorized, which is what baselines
+            // are compared with.
+            let mut raw5 = Vec::new();
 
             let loop_run = Duration::span(|| {
 
@@ -1402,6 +2168,7 @@ impl Bencher {
                     *p = self.ns_per_iter() as f64;
                 };
 
+                raw5 = samples.to_vec();
                 stats::winsorize(samples, 5.0);
                 summ5 = Some(stats::Summary::new(samples));
             });
@@ -1413,13 +2180,13 @@ impl Bencher {
             if loop_run.num_milliseconds() > 100 &&
                 summ.median_abs_dev_pct < 1.0 &&
                 summ.median - summ5.median < summ5.median_abs_dev {
-                return summ5;
+                return (summ5, raw5);
             }
 
             total_run = total_run + loop_run;
             // Longest we ever run for is 3s.
             if total_run.num_seconds() > 3 {
-                return summ5;
+                return (summ5, raw5);
             }
 
             n *= 2;
@@ -1439,7 +2206,7 @@ pub mod bench {
             bytes: 0
         };
 
-        let ns_iter_summ = bs.auto_bench(f);
+        let (ns_iter_summ, ns_iter_samples) = bs.auto_bench_samples(f);
 
         let ns_iter = cmp::max(ns_iter_summ.median as u64, 1);
         let iter_s = 1_000_000_000 / ns_iter;
@@ -1447,6 +2214,7 @@ pub mod bench {
 
         BenchSamples {
             ns_iter_summ: ns_iter_summ,
+            ns_iter_samples: ns_iter_samples,
             mb_s: mb_s as uint
         }
     }
@@ -1454,13 +2222,16 @@ pub mod bench {
 
 #[cfg(test)]
 mod tests {
-    use test::{TrFailed, TrIgnored, TrOk, filter_tests, parse_opts,
-               TestDesc, TestDescAndFn, TestOpts, run_test,
+    use test::{TrFailed, TrIgnored, TrOk, TrTimedOut, filter_tests, parse_opts,
+               TestDesc, TestDescAndFn, TestFn, TestOpts, run_test,
                Metric, MetricMap, MetricAdded, MetricRemoved,
                Improvement, Regression, LikelyNoise,
                StaticTestName, DynTestName, DynTestFn, ShouldFail};
-    use std::io::TempDir;
+    use super::{run_tests, write_junit_report, Baseline, BenchComparison, JsonFormat};
+    use super::TestEvent::{TeResult, TeSlow};
+    use std::io::{File, InvalidInput, TempDir};
     use std::thunk::Thunk;
+    use std::time::Duration;
 
     #[test]
     pub fn do_not_run_ignored_tests() {
@@ -1470,12 +2241,13 @@ mod tests {
                 name: StaticTestName("whatever"),
                 ignore: true,
                 should_fail: ShouldFail::No,
+                timeout: None,
             },
             testfn: DynTestFn(Thunk::new(move|| f())),
         };
         let (tx, rx) = channel();
-        run_test(&TestOpts::new(), false, desc, tx);
-        let (_, res, _) = rx.recv();
+        run_test(&TestOpts::new(), false, desc, 0, tx);
+        let (_, _, res, _) = rx.recv();
         assert!(res != TrOk);
     }
 
@@ -1487,12 +2259,13 @@ mod tests {
                 name: StaticTestName("whatever"),
                 ignore: true,
                 should_fail: ShouldFail::No,
+                timeout: None,
             },
             testfn: DynTestFn(Thunk::new(move|| f())),
         };
         let (tx, rx) = channel();
-        run_test(&TestOpts::new(), false, desc, tx);
-        let (_, res, _) = rx.recv();
+        run_test(&TestOpts::new(), false, desc, 0, tx);
+        let (_, _, res, _) = rx.recv();
         assert!(res == TrIgnored);
     }
 
@@ -1503,13 +2276,14 @@ mod tests {
             desc: TestDesc {
                 name: StaticTestName("whatever"),
                 ignore: false,
-                should_fail: ShouldFail::Yes(None)
+                should_fail: ShouldFail::Yes(None),
+                timeout: None
             },
             testfn: DynTestFn(Thunk::new(move|| f())),
         };
         let (tx, rx) = channel();
-        run_test(&TestOpts::new(), false, desc, tx);
-        let (_, res, _) = rx.recv();
+        run_test(&TestOpts::new(), false, desc, 0, tx);
+        let (_, _, res, _) = rx.recv();
         assert!(res == TrOk);
     }
 
@@ -1520,13 +2294,14 @@ mod tests {
             desc: TestDesc {
                 name: StaticTestName("whatever"),
                 ignore: false,
-                should_fail: ShouldFail::Yes(Some("error message"))
+                should_fail: ShouldFail::Yes(Some("error message")),
+                timeout: None
             },
             testfn: DynTestFn(Thunk::new(move|| f())),
         };
         let (tx, rx) = channel();
-        run_test(&TestOpts::new(), false, desc, tx);
-        let (_, res, _) = rx.recv();
+        run_test(&TestOpts::new(), false, desc, 0, tx);
+        let (_, _, res, _) = rx.recv();
         assert!(res == TrOk);
     }
 
@@ -1537,13 +2312,14 @@ mod tests {
             desc: TestDesc {
                 name: StaticTestName("whatever"),
                 ignore: false,
-                should_fail: ShouldFail::Yes(Some("foobar"))
+                should_fail: ShouldFail::Yes(Some("foobar")),
+                timeout: None
             },
             testfn: DynTestFn(Thunk::new(move|| f())),
         };
         let (tx, rx) = channel();
-        run_test(&TestOpts::new(), false, desc, tx);
-        let (_, res, _) = rx.recv();
+        run_test(&TestOpts::new(), false, desc, 0, tx);
+        let (_, _, res, _) = rx.recv();
         assert!(res == TrFailed);
     }
 
@@ -1554,16 +2330,97 @@ mod tests {
             desc: TestDesc {
                 name: StaticTestName("whatever"),
                 ignore: false,
-                should_fail: ShouldFail::Yes(None)
+                should_fail: ShouldFail::Yes(None),
+                timeout: None
             },
             testfn: DynTestFn(Thunk::new(move|| f())),
         };
         let (tx, rx) = channel();
-        run_test(&TestOpts::new(), false, desc, tx);
-        let (_, res, _) = rx.recv();
+        run_test(&TestOpts::new(), false, desc, 0, tx);
+        let (_, _, res, _) = rx.recv();
         assert!(res == TrFailed);
     }
 
+    #[test]
+    fn tests_with_the_same_name_all_report() {
+        let mut opts = TestOpts::new();
+        opts.run_tests = true;
+        opts.format = JsonFormat;
+        let tests = range(0u, 3).map(|_| TestDescAndFn {
+            desc: TestDesc {
+                name: StaticTestName("same"),
+                ignore: false,
+                should_fail: ShouldFail::No,
+                timeout: None,
+            },
+            testfn: DynTestFn(Thunk::new(move|| {})),
+        }).collect();
+
+        let mut results = Vec::new();
+        run_tests(&opts, tests, |event| {
+            match event {
+                TeResult(desc, result, _, _) => results.push((desc.name.to_string(), result)),
+                _ => {}
+            }
+            Ok(())
+        }).unwrap();
+
+        assert!(results == Vec::from_elem(3, ("same".to_string(), TrOk)));
+    }
+
+    #[test]
+    fn tests_that_run_too_long_time_out() {
+        fn desc(name: &'static str, timeout: Option<u64>) -> TestDesc {
+            TestDesc {
+                name: StaticTestName(name),
+                ignore: false,
+                should_fail: ShouldFail::No,
+                timeout: timeout,
+            }
+        }
+        // A test that runs until the sender it returns is dropped.
+        fn blocked() -> (Sender<()>, TestFn) {
+            let (tx, rx) = channel::<()>();
+            (tx, DynTestFn(Thunk::new(move|| { let _ = rx.recv_opt(); })))
+        }
+
+        // The thresholds are far above the time it takes to spawn a test, so
+        // that a quick test is never reported as slow.
+        let mut opts = TestOpts::new();
+        opts.run_tests = true;
+        opts.timeout = Some(100);
+        opts.slow_threshold = Some(1000);
+        let (release_a, default_a) = blocked();
+        let (release_b, default_b) = blocked();
+        let (release_own, own) = blocked();
+        let tests = vec![
+            TestDescAndFn { desc: desc("default", None), testfn: default_a },
+            TestDescAndFn { desc: desc("default", None), testfn: default_b },
+            TestDescAndFn { desc: desc("own", Some(1500)), testfn: own },
+            TestDescAndFn { desc: desc("quick", Some(10_000)),
+                            testfn: DynTestFn(Thunk::new(move|| {})) },
+        ];
+
+        let mut results = Vec::new();
+        let mut slow = Vec::new();
+        run_tests(&opts, tests, |event| {
+            match event {
+                TeResult(desc, result, _, _) => results.push((desc.name.to_string(), result)),
+                TeSlow(desc, _) => slow.push(desc.name.to_string()),
+                _ => {}
+            }
+            Ok(())
+        }).unwrap();
+        drop((release_a, release_b, release_own));
+        results.sort_by(|&(ref a, _), &(ref b, _)| a.cmp(b));
+
+        assert!(results == vec![("default".to_string(), TrTimedOut),
+                                ("default".to_string(), TrTimedOut),
+                                ("own".to_string(), TrTimedOut),
+                                ("quick".to_string(), TrOk)]);
+        assert!(slow == vec!["own".to_string()]);
+    }
+
     #[test]
     fn first_free_arg_should_be_a_filter() {
         let args = vec!("progname".to_string(), "some_regex_filter".to_string());
@@ -1586,6 +2443,25 @@ mod tests {
         assert!((opts.run_ignored));
     }
 
+    #[test]
+    fn parse_slow_flag() {
+        let args = vec!("progname".to_string());
+        let opts = match parse_opts(args.as_slice()) {
+            Some(Ok(o)) => o,
+            _ => panic!("Malformed arg in parse_slow_flag")
+        };
+        assert_eq!(opts.slow_threshold, None);
+
+        let args = vec!("progname".to_string(),
+                        "--slow".to_string(),
+                        "500".to_string());
+        let opts = match parse_opts(args.as_slice()) {
+            Some(Ok(o)) => o,
+            _ => panic!("Malformed arg in parse_slow_flag")
+        };
+        assert_eq!(opts.slow_threshold, Some(500));
+    }
+
     #[test]
     pub fn filter_for_ignored_option() {
         // When we run ignored tests the test filter should filter out all the
@@ -1601,6 +2477,7 @@ mod tests {
                     name: StaticTestName("1"),
                     ignore: true,
                     should_fail: ShouldFail::No,
+                    timeout: None,
                 },
                 testfn: DynTestFn(Thunk::new(move|| {})),
             },
@@ -1609,6 +2486,7 @@ mod tests {
                     name: StaticTestName("2"),
                     ignore: false,
                     should_fail: ShouldFail::No,
+                    timeout: None,
                 },
                 testfn: DynTestFn(Thunk::new(move|| {})),
             });
@@ -1645,6 +2523,7 @@ mod tests {
                         name: DynTestName((*name).clone()),
                         ignore: false,
                         should_fail: ShouldFail::No,
+                        timeout: None,
                     },
                     testfn: DynTestFn(Thunk::new(testfn)),
                 };
@@ -1686,6 +2565,7 @@ mod tests {
                     name: DynTestName(name.to_string()),
                     ignore: false,
                     should_fail: ShouldFail::No,
+                    timeout: None,
                 },
                 testfn: DynTestFn(Thunk::new(test_fn))
             }
@@ -1799,4 +2679,105 @@ mod tests {
         assert_eq!(*(m4.get(&"runtime".to_string()).unwrap()), Metric::new(1100.0, 2.0));
         assert_eq!(*(m4.get(&"throughput".to_string()).unwrap()), Metric::new(50.0, 2.0));
     }
+
+    #[test]
+    pub fn baseline_save_and_update() {
+        let dpth = TempDir::new("test-baseline").ok().expect("missing test for baseline");
+        let pth = dpth.path().join("baselines").join("main.json");
+
+        let mut b1 = Baseline::new();
+        b1.insert_samples("a", vec![1.0, 2.0, 3.0]);
+        b1.insert_samples("b", vec![4.0, 5.0]);
+        b1.save(&pth).unwrap();
+
+        let mut b2 = Baseline::new();
+        b2.insert_samples("b", vec![6.0]);
+        let mut loaded = Baseline::load(&pth).unwrap();
+        assert!(loaded == b1);
+        loaded.update(&b2);
+        let Baseline(map) = loaded;
+        assert_eq!(map.len(), 2);
+        assert_eq!(*map.get(&"a".to_string()).unwrap(), vec![1.0, 2.0, 3.0]);
+        assert_eq!(*map.get(&"b".to_string()).unwrap(), vec![6.0]);
+
+        File::create(&pth).write_str("{\"a\": 1}").unwrap();
+        assert_eq!(Baseline::load(&pth).err().unwrap().kind, InvalidInput);
+    }
+
+    #[test]
+    pub fn baseline_names_stay_in_the_baseline_directory() {
+        assert!(Baseline::path("main").unwrap().filename_str() == Some("main.json"));
+        for name in ["", "..", "../main", "a/b", "a\\b", "/etc/passwd", "C:evil"].iter() {
+            assert_eq!(Baseline::path(*name).err().unwrap().kind, InvalidInput);
+        }
+    }
+
+    #[test]
+    pub fn bench_comparison_ignores_noise() {
+        // Noisy samples between 1000 and 1190 ns/iter.
+        let old: Vec<f64> = range(0u, 50).map(|i| 1000.0 + (i * 7 % 20) as f64 * 10.0).collect();
+
+        // The same distribution with a median 1% higher is within the noise...
+        let shifted: Vec<f64> = range(0u, 50).map(|i| {
+            1010.0 + (i * 3 % 20) as f64 * 10.0
+        }).collect();
+        let c = BenchComparison::new(old.as_slice(), shifted.as_slice());
+        assert!(c.new_median > c.old_median);
+        assert!(!c.is_regression() && !c.is_improvement());
+        assert!(c.low <= 0.0 && 0.0 <= c.high);
+
+        // ...but a consistent slowdown of 30% is not.
+        let slower: Vec<f64> = old.iter().map(|x| x * 1.3).collect();
+        let c = BenchComparison::new(old.as_slice(), slower.as_slice());
+        assert!(c.is_regression());
+        assert!(c.p_value < 1.0e-6);
+        assert!(c.percent(c.low) > 20.0 && c.percent(c.high) < 40.0);
+
+        let c = BenchComparison::new(slower.as_slice(), old.as_slice());
+        assert!(c.is_improvement());
+    }
+
+    #[test]
+    pub fn junit_report() {
+        fn desc(name: &'static str, should_fail: ShouldFail) -> TestDesc {
+            TestDesc {
+                name: StaticTestName(name),
+                ignore: false,
+                should_fail: should_fail,
+                timeout: None,
+            }
+        }
+        let records = vec![
+            (desc("a::passes", ShouldFail::No), TrOk, Vec::new(), Duration::milliseconds(1500)),
+            (desc("a::b::fails", ShouldFail::No), TrFailed,
+             "x < y\nthread 'a::b::fails' panicked at 'boom', a.rs:1\n".as_bytes().to_vec(),
+             Duration::milliseconds(2)),
+            (desc("does_not_fail", ShouldFail::Yes(None)), TrFailed, Vec::new(),
+             Duration::milliseconds(0)),
+            (desc("ignored", ShouldFail::No), TrIgnored, Vec::new(), Duration::milliseconds(0)),
+        ];
+
+        let mut out = Vec::new();
+        write_junit_report(&mut out, "suite", records.as_slice(), 2.0).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let expected = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuite name="suite" tests="4" failures="2" errors="0" skipped="1" time="2.000">
+  <testcase classname="a" name="passes" time="1.500">
+  </testcase>
+  <testcase classname="a::b" name="fails" time="0.002">
+    <failure message="thread &apos;a::b::fails&apos; panicked at &apos;boom&apos;, a.rs:1"/>
+    <system-out>x &lt; y
+thread &apos;a::b::fails&apos; panicked at &apos;boom&apos;, a.rs:1
+</system-out>
+  </testcase>
+  <testcase classname="suite" name="does_not_fail" time="0.000">
+    <failure message="test did not panic as expected"/>
+  </testcase>
+  <testcase classname="suite" name="ignored" time="0.000">
+    <skipped/>
+  </testcase>
+</testsuite>
+"#;
+        assert_eq!(out.as_slice(), expected);
+    }
 }
@@ -0,0 +1,665 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Property based testing, in the style of QuickCheck.
+//!
+//! A property is a function whose arguments implement `Arbitrary`, and which
+//! returns whether the property holds for them, as a `bool`, or panics when it
+//! does not. The harness generated by `rustc --test` checks the functions
+//! marked `#[quickcheck]` with many random arguments:
+//!
+//! ```rust,ignore
+//! #[quickcheck]
+//! fn reversing_twice_is_the_identity(v: Vec<int>) -> bool {
+//!     let mut w = v.clone();
+//!     w.reverse();
+//!     w.reverse();
+//!     v == w
+//! }
+//! ```
+//!
+//! When the property fails, its arguments are shrunk to the simplest ones
+//! for which it still fails, and the test fails with them and with the seed
+//! of the random arguments, which replays the same run when given in the
+//! `RUST_QUICKCHECK_SEED` environment variable. `RUST_QUICKCHECK_TESTS` sets
+//! how many arguments are tried, 100 by default, and `RUST_QUICKCHECK_SIZE`
+//! how large they get, also 100 by default.
+
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet, DList, HashMap, HashSet, RingBuf};
+use std::fmt;
+use std::fmt::Show;
+use std::hash::Hash;
+use std::io::{ChanReader, ChanWriter};
+use std::num::{Float, Int};
+use std::os;
+use std::rand::{mod, Rng, SeedableRng, XorShiftRng};
+use std::thread;
+
+/// A source of random values, which also bounds the size of the values
+/// generated from it.
+pub trait Gen: Rng {
+    /// How large generated values should be: the length of collections, or
+    /// the magnitude of numbers, should not exceed it much.
+    fn size(&self) -> uint;
+}
+
+/// A `Gen` made of a random number generator and a size.
+pub struct StdGen<R> {
+    rng: R,
+    size: uint,
+}
+
+impl<R: Rng> StdGen<R> {
+    pub fn new(rng: R, size: uint) -> StdGen<R> {
+        StdGen { rng: rng, size: size }
+    }
+}
+
+impl<R: Rng> Rng for StdGen<R> {
+    fn next_u32(&mut self) -> u32 { self.rng.next_u32() }
+    fn next_u64(&mut self) -> u64 { self.rng.next_u64() }
+}
+
+impl<R: Rng> Gen for StdGen<R> {
+    fn size(&self) -> uint { self.size }
+}
+
+/// Types of which random values can be generated to check properties with,
+/// and simplified when they make a property fail.
+pub trait Arbitrary: Clone + Send + Show {
+    fn arbitrary<G: Gen>(g: &mut G) -> Self;
+
+    /// Values simpler than `self`, simplest first. Shrinking a value must
+    /// eventually give none, so no value may be among the values it shrinks
+    /// to, directly or not.
+    fn shrink(&self) -> Vec<Self> {
+        Vec::new()
+    }
+}
+
+impl Arbitrary for () {
+    fn arbitrary<G: Gen>(_: &mut G) -> () { () }
+}
+
+impl Arbitrary for bool {
+    fn arbitrary<G: Gen>(g: &mut G) -> bool { g.gen() }
+
+    fn shrink(&self) -> Vec<bool> {
+        if *self { vec![false] } else { Vec::new() }
+    }
+}
+
+impl Arbitrary for char {
+    // Mostly printable ASCII characters, which make the clearest
+    // counterexamples, but any character now and then.
+    fn arbitrary<G: Gen>(g: &mut G) -> char {
+        if g.gen_weighted_bool(10) {
+            g.gen()
+        } else {
+            g.gen_range(b' ', b'~' + 1) as char
+        }
+    }
+
+    fn shrink(&self) -> Vec<char> {
+        ['a', 'b', 'c', 'A', 'B', 'C', '0', '1', '2', ' ', '\n'].iter()
+            .map(|&c| c)
+            .filter(|&c| c < *self)
+            .collect()
+    }
+}
+
+// Integers are mostly between minus and plus the size, and sometimes any
+// value of their type.
+macro_rules! signed_arbitrary {
+    ($($t:ty),+) => ($(
+        impl Arbitrary for $t {
+            fn arbitrary<G: Gen>(g: &mut G) -> $t {
+                if g.gen_weighted_bool(10) {
+                    g.gen()
+                } else {
+                    let s = g.size() as i64;
+                    g.gen_range(-s, s + 1) as $t
+                }
+            }
+
+            fn shrink(&self) -> Vec<$t> {
+                let x = *self;
+                let mut shrunk = Vec::new();
+                if x == 0 {
+                    return shrunk;
+                }
+                shrunk.push(0);
+                if x < 0 && x != Int::min_value() {
+                    shrunk.push(-x);
+                }
+                let mut i = x / 2;
+                while i != 0 {
+                    shrunk.push(x - i);
+                    i = i / 2;
+                }
+                shrunk
+            }
+        }
+    )+)
+}
+
+macro_rules! unsigned_arbitrary {
+    ($($t:ty),+) => ($(
+        impl Arbitrary for $t {
+            fn arbitrary<G: Gen>(g: &mut G) -> $t {
+                if g.gen_weighted_bool(10) {
+                    g.gen()
+                } else {
+                    let s = g.size() as u64;
+                    g.gen_range(0, s + 1) as $t
+                }
+            }
+
+            fn shrink(&self) -> Vec<$t> {
+                let x = *self;
+                let mut shrunk = Vec::new();
+                if x == 0 {
+                    return shrunk;
+                }
+                shrunk.push(0);
+                let mut i = x / 2;
+                while i != 0 {
+                    shrunk.push(x - i);
+                    i = i / 2;
+                }
+                shrunk
+            }
+        }
+    )+)
+}
+
+signed_arbitrary!(int, i8, i16, i32, i64)
+unsigned_arbitrary!(uint, u8, u16, u32, u64)
+
+macro_rules! float_arbitrary {
+    ($($t:ty),+) => ($(
+        impl Arbitrary for $t {
+            fn arbitrary<G: Gen>(g: &mut G) -> $t {
+                let s = cmp::max(g.size(), 1) as $t;
+                g.gen_range(-s, s)
+            }
+
+            // Shrinks towards zero, through integers.
+            fn shrink(&self) -> Vec<$t> {
+                let x = *self;
+                let mut shrunk = Vec::new();
+                if x == 0.0 || x.is_nan() {
+                    return shrunk;
+                }
+                shrunk.push(0.0);
+                if x < 0.0 {
+                    shrunk.push(-x);
+                }
+                if x.trunc() != x {
+                    shrunk.push(x.trunc());
+                } else if x.is_finite() && x.abs() > 1.0 {
+                    shrunk.push((x / 2.0).trunc());
+                }
+                shrunk
+            }
+        }
+    )+)
+}
+
+float_arbitrary!(f32, f64)
+
+impl<A: Arbitrary> Arbitrary for Option<A> {
+    fn arbitrary<G: Gen>(g: &mut G) -> Option<A> {
+        if g.gen_weighted_bool(4) {
+            None
+        } else {
+            Some(Arbitrary::arbitrary(g))
+        }
+    }
+
+    fn shrink(&self) -> Vec<Option<A>> {
+        match *self {
+            None => Vec::new(),
+            Some(ref x) => {
+                let mut shrunk = vec![None];
+                shrunk.extend(x.shrink().into_iter().map(Some));
+                shrunk
+            }
+        }
+    }
+}
+
+impl<A: Arbitrary, B: Arbitrary> Arbitrary for Result<A, B> {
+    fn arbitrary<G: Gen>(g: &mut G) -> Result<A, B> {
+        if g.gen() {
+            Ok(Arbitrary::arbitrary(g))
+        } else {
+            Err(Arbitrary::arbitrary(g))
+        }
+    }
+
+    fn shrink(&self) -> Vec<Result<A, B>> {
+        match *self {
+            Ok(ref x) => x.shrink().into_iter().map(Ok).collect(),
+            Err(ref x) => x.shrink().into_iter().map(Err).collect(),
+        }
+    }
+}
+
+impl<A: Arbitrary> Arbitrary for Box<A> {
+    fn arbitrary<G: Gen>(g: &mut G) -> Box<A> {
+        box Arbitrary::arbitrary(g)
+    }
+
+    fn shrink(&self) -> Vec<Box<A>> {
+        (**self).shrink().into_iter().map(|x| box x).collect()
+    }
+}
+
+impl<A: Arbitrary> Arbitrary for (A,) {
+    fn arbitrary<G: Gen>(g: &mut G) -> (A,) {
+        (Arbitrary::arbitrary(g),)
+    }
+
+    fn shrink(&self) -> Vec<(A,)> {
+        let (ref a,) = *self;
+        a.shrink().into_iter().map(|a| (a,)).collect()
+    }
+}
+
+impl<A: Arbitrary, B: Arbitrary> Arbitrary for (A, B) {
+    fn arbitrary<G: Gen>(g: &mut G) -> (A, B) {
+        let a = Arbitrary::arbitrary(g);
+        (a, Arbitrary::arbitrary(g))
+    }
+
+    fn shrink(&self) -> Vec<(A, B)> {
+        let (ref a, ref b) = *self;
+        let mut shrunk: Vec<(A, B)> = a.shrink().into_iter().map(|a| (a, b.clone())).collect();
+        shrunk.extend(b.shrink().into_iter().map(|b| (a.clone(), b)));
+        shrunk
+    }
+}
+
+impl<A: Arbitrary, B: Arbitrary, C: Arbitrary> Arbitrary for (A, B, C) {
+    fn arbitrary<G: Gen>(g: &mut G) -> (A, B, C) {
+        let a = Arbitrary::arbitrary(g);
+        let b = Arbitrary::arbitrary(g);
+        (a, b, Arbitrary::arbitrary(g))
+    }
+
+    fn shrink(&self) -> Vec<(A, B, C)> {
+        let (ref a, ref b, ref c) = *self;
+        let mut shrunk: Vec<(A, B, C)> = a.shrink().into_iter().map(|a| {
+            (a, b.clone(), c.clone())
+        }).collect();
+        shrunk.extend(b.shrink().into_iter().map(|b| (a.clone(), b, c.clone())));
+        shrunk.extend(c.shrink().into_iter().map(|c| (a.clone(), b.clone(), c)));
+        shrunk
+    }
+}
+
+impl<A: Arbitrary, B: Arbitrary, C: Arbitrary, D: Arbitrary> Arbitrary for (A, B, C, D) {
+    fn arbitrary<G: Gen>(g: &mut G) -> (A, B, C, D) {
+        let a = Arbitrary::arbitrary(g);
+        let b = Arbitrary::arbitrary(g);
+        let c = Arbitrary::arbitrary(g);
+        (a, b, c, Arbitrary::arbitrary(g))
+    }
+
+    fn shrink(&self) -> Vec<(A, B, C, D)> {
+        let (ref a, ref b, ref c, ref d) = *self;
+        let mut shrunk: Vec<(A, B, C, D)> = a.shrink().into_iter().map(|a| {
+            (a, b.clone(), c.clone(), d.clone())
+        }).collect();
+        shrunk.extend(b.shrink().into_iter().map(|b| (a.clone(), b, c.clone(), d.clone())));
+        shrunk.extend(c.shrink().into_iter().map(|c| (a.clone(), b.clone(), c, d.clone())));
+        shrunk.extend(d.shrink().into_iter().map(|d| (a.clone(), b.clone(), c.clone(), d)));
+        shrunk
+    }
+}
+
+impl<A: Arbitrary> Arbitrary for Vec<A> {
+    fn arbitrary<G: Gen>(g: &mut G) -> Vec<A> {
+        let size = g.size();
+        let len = g.gen_range(0, size + 1);
+        range(0, len).map(|_| Arbitrary::arbitrary(g)).collect()
+    }
+
+    // Removes runs of elements, starting with all of them and down to single
+    // elements, then shrinks each element in turn.
+    fn shrink(&self) -> Vec<Vec<A>> {
+        let mut shrunk = Vec::new();
+        let mut k = self.len();
+        while k > 0 {
+            let mut start = 0;
+            while start + k <= self.len() {
+                let mut v = self[..start].to_vec();
+                v.push_all(self[start + k..]);
+                shrunk.push(v);
+                start += k;
+            }
+            k /= 2;
+        }
+        for (i, x) in self.iter().enumerate() {
+            for y in x.shrink().into_iter() {
+                let mut v = self.clone();
+                v[i] = y;
+                shrunk.push(v);
+            }
+        }
+        shrunk
+    }
+}
+
+impl Arbitrary for String {
+    fn arbitrary<G: Gen>(g: &mut G) -> String {
+        let chars: Vec<char> = Arbitrary::arbitrary(g);
+        chars.into_iter().collect()
+    }
+
+    fn shrink(&self) -> Vec<String> {
+        let chars: Vec<char> = self.chars().collect();
+        chars.shrink().into_iter().map(|cs| cs.into_iter().collect()).collect()
+    }
+}
+
+// Collections are generated and shrunk like vectors of their elements.
+macro_rules! seq_arbitrary {
+    ($t:ident $(, $bound:ident)*) => (
+        impl<A: Arbitrary $(+ $bound)*> Arbitrary for $t<A> {
+            fn arbitrary<G: Gen>(g: &mut G) -> $t<A> {
+                let v: Vec<A> = Arbitrary::arbitrary(g);
+                v.into_iter().collect()
+            }
+
+            fn shrink(&self) -> Vec<$t<A>> {
+                let v: Vec<A> = self.iter().map(|x| x.clone()).collect();
+                v.shrink().into_iter().map(|v| v.into_iter().collect()).collect()
+            }
+        }
+    )
+}
+
+seq_arbitrary!(RingBuf)
+seq_arbitrary!(DList)
+seq_arbitrary!(HashSet, Eq, Hash)
+seq_arbitrary!(BTreeSet, Ord)
+
+macro_rules! map_arbitrary {
+    ($t:ident $(, $bound:ident)*) => (
+        impl<K: Arbitrary $(+ $bound)*, V: Arbitrary> Arbitrary for $t<K, V> {
+            fn arbitrary<G: Gen>(g: &mut G) -> $t<K, V> {
+                let v: Vec<(K, V)> = Arbitrary::arbitrary(g);
+                v.into_iter().collect()
+            }
+
+            fn shrink(&self) -> Vec<$t<K, V>> {
+                let v: Vec<(K, V)> = self.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+                v.shrink().into_iter().map(|v| v.into_iter().collect()).collect()
+            }
+        }
+    )
+}
+
+map_arbitrary!(HashMap, Eq, Hash)
+map_arbitrary!(BTreeMap, Ord)
+
+/// What a property returns: whether it holds.
+pub trait Property {
+    fn holds(self) -> bool;
+}
+
+impl Property for bool {
+    fn holds(self) -> bool { self }
+}
+
+// Properties that return nothing hold unless they panic.
+impl Property for () {
+    fn holds(self) -> bool { true }
+}
+
+/// The arguments of a property that does not hold.
+pub struct Counterexample {
+    /// The shrunk arguments, as printed by `Show`.
+    pub args: String,
+    /// How many times the arguments were shrunk.
+    pub shrinks: uint,
+    /// What the property printed for the shrunk arguments, including its
+    /// panic message if it panicked.
+    pub output: String,
+}
+
+/// Functions that can be checked as properties.
+pub trait Testable: Send {
+    /// Checks the property with arguments generated by `g`, and returns the
+    /// shrunk arguments if it does not hold.
+    fn check_once<G: Gen>(&self, g: &mut G) -> Option<Counterexample>;
+}
+
+macro_rules! testable_fn {
+    ($($name:ident),*) => (
+        impl<T: Property $(, $name: Arbitrary)*> Testable for fn($($name),*) -> T {
+            fn check_once<G: Gen>(&self, g: &mut G) -> Option<Counterexample> {
+                #[allow(non_snake_case)]
+                fn call<T: Property $(, $name: Arbitrary)*>(f: fn($($name),*) -> T,
+                                                            args: ($($name,)*)) -> bool {
+                    let ($($name,)*) = args;
+                    f($($name),*).holds()
+                }
+                check_args(g, *self, call::<T $(, $name)*>)
+            }
+        }
+    )
+}
+
+testable_fn!()
+testable_fn!(A)
+testable_fn!(A, B)
+testable_fn!(A, B, C)
+testable_fn!(A, B, C, D)
+
+// Checks a property `prop` with arguments generated by `g`, calling it with
+// `call`, and shrinks the arguments as long as it still fails with them.
+fn check_args<A, P, G>(g: &mut G, prop: P, call: fn(P, A) -> bool) -> Option<Counterexample>
+    where A: Arbitrary, P: Copy + Send, G: Gen {
+    let mut args: A = Arbitrary::arbitrary(g);
+    let mut output = match run(prop, call, args.clone()) {
+        Ok(()) => return None,
+        Err(output) => output,
+    };
+    let mut shrinks = 0u;
+    'shrinking: loop {
+        for candidate in args.shrink().into_iter() {
+            match run(prop, call, candidate.clone()) {
+                Ok(()) => {}
+                Err(out) => {
+                    args = candidate;
+                    output = out;
+                    shrinks += 1;
+                    continue 'shrinking;
+                }
+            }
+        }
+        break;
+    }
+    Some(Counterexample {
+        args: format!("{}", args),
+        shrinks: shrinks,
+        output: output,
+    })
+}
+
+// Runs a property in a task of its own, so that it may panic, and returns
+// what it printed if it does not hold.
+fn run<A: Send, P: Send>(prop: P, call: fn(P, A) -> bool, args: A) -> Result<(), String> {
+    let (tx, rx) = channel();
+    let mut reader = ChanReader::new(rx);
+    let stdout = ChanWriter::new(tx.clone());
+    let stderr = ChanWriter::new(tx);
+    let result_guard = thread::Builder::new()
+        .stdout(box stdout as Box<Writer + Send>)
+        .stderr(box stderr as Box<Writer + Send>)
+        .spawn(move || call(prop, args));
+    let output = reader.read_to_end().unwrap();
+    match result_guard.join() {
+        Ok(true) => Ok(()),
+        _ => Err(String::from_utf8_lossy(output.as_slice()).into_owned()),
+    }
+}
+
+/// A property that does not hold, and how to replay its check.
+pub struct Failure {
+    /// How many arguments were tried before the property failed.
+    pub tests: uint,
+    /// The seed of the random arguments.
+    pub seed: u64,
+    pub counterexample: Counterexample,
+}
+
+impl Show for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let c = &self.counterexample;
+        try!(writeln!(f, "[quickcheck] property falsified after {} tests and {} shrinks \
+                          with arguments: {}", self.tests, c.shrinks, c.args));
+        try!(write!(f, "[quickcheck] replay with RUST_QUICKCHECK_SEED={}", self.seed));
+        if c.output.len() > 0 {
+            try!(write!(f, "\n{}", c.output.as_slice().trim_right()));
+        }
+        Ok(())
+    }
+}
+
+/// How properties are checked.
+#[deriving(Copy, Clone)]
+pub struct QuickCheck {
+    /// How many arguments to try.
+    pub tests: uint,
+    /// The size of the last arguments; the first ones are smaller.
+    pub size: uint,
+    /// The seed of the random arguments.
+    pub seed: u64,
+}
+
+impl QuickCheck {
+    /// The configuration given by the `RUST_QUICKCHECK_TESTS`,
+    /// `RUST_QUICKCHECK_SIZE` and `RUST_QUICKCHECK_SEED` environment
+    /// variables, with a random seed if none is given.
+    pub fn new() -> QuickCheck {
+        fn env(name: &str) -> Option<u64> {
+            os::getenv(name).map(|s| match from_str(s.as_slice()) {
+                Some(n) => n,
+                None => panic!("{} must be a number (was {})", name, s),
+            })
+        }
+        QuickCheck {
+            tests: env("RUST_QUICKCHECK_TESTS").unwrap_or(100) as uint,
+            size: env("RUST_QUICKCHECK_SIZE").unwrap_or(100) as uint,
+            seed: env("RUST_QUICKCHECK_SEED").unwrap_or_else(|| rand::random()),
+        }
+    }
+
+    /// Checks `prop` with `self.tests` random arguments, and returns how many
+    /// were tried, or the shrunk arguments the property fails with.
+    pub fn check<T: Testable>(&self, prop: T) -> Result<uint, Failure> {
+        let seed = [self.seed as u32, (self.seed >> 32) as u32, 0x9e3779b9, 0x2545f491];
+        let rng: XorShiftRng = SeedableRng::from_seed(seed);
+        let mut g = StdGen::new(rng, 0);
+        for i in range(0, self.tests) {
+            g.size = cmp::max((i + 1) * self.size / self.tests, 1);
+            match prop.check_once(&mut g) {
+                None => {}
+                Some(c) => return Err(Failure {
+                    tests: i + 1,
+                    seed: self.seed,
+                    counterexample: c,
+                }),
+            }
+        }
+        Ok(self.tests)
+    }
+
+    /// Checks `prop`, and panics if it does not hold.
+    pub fn quickcheck<T: Testable>(&self, prop: T) {
+        match self.check(prop) {
+            Ok(_) => {}
+            Err(failure) => panic!("{}", failure),
+        }
+    }
+}
+
+/// Checks `prop` as configured by the environment, and panics if it does not
+/// hold. This is what the tests marked `#[quickcheck]` run.
+pub fn quickcheck<T: Testable>(prop: T) {
+    QuickCheck::new().quickcheck(prop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Arbitrary, QuickCheck};
+
+    fn config() -> QuickCheck {
+        QuickCheck { tests: 100, size: 100, seed: 42 }
+    }
+
+    #[test]
+    fn shrinks_integers_towards_zero() {
+        assert_eq!(5i.shrink(), vec![0, 3, 4]);
+        assert_eq!((-4i).shrink(), vec![0, 4, -2, -3]);
+        assert_eq!(0u.shrink(), vec![]);
+    }
+
+    #[test]
+    fn shrinks_vectors_by_removing_then_shrinking_elements() {
+        assert_eq!(vec![1u, 2].shrink(), vec![vec![], vec![2], vec![1], vec![0, 2],
+                                              vec![1, 0], vec![1, 1]]);
+    }
+
+    #[test]
+    fn properties_that_hold_pass() {
+        fn prop(v: Vec<int>) -> bool {
+            let mut w = v.clone();
+            w.reverse();
+            w.reverse();
+            v == w
+        }
+        assert_eq!(config().check(prop as fn(Vec<int>) -> bool).ok(), Some(100));
+    }
+
+    #[test]
+    fn counterexamples_are_shrunk() {
+        fn prop(x: u32) -> bool { x < 1000 }
+        let failure = config().check(prop as fn(u32) -> bool).err().unwrap();
+        assert_eq!(failure.seed, 42);
+        assert_eq!(failure.counterexample.args.as_slice(), "(1000,)");
+
+        fn no_sevens(v: Vec<u8>, _: bool) -> bool { !v.contains(&7) }
+        let failure = config().check(no_sevens as fn(Vec<u8>, bool) -> bool).err().unwrap();
+        assert_eq!(failure.counterexample.args.as_slice(), "([7], false)");
+    }
+
+    #[test]
+    fn panics_are_failures() {
+        fn prop(x: uint) { assert!(x < 10, "too large"); }
+        let failure = config().check(prop as fn(uint)).err().unwrap();
+        assert_eq!(failure.counterexample.args.as_slice(), "(10,)");
+        assert!(failure.counterexample.output.as_slice().contains("too large"));
+    }
+
+    #[test]
+    fn the_seed_replays_the_same_check() {
+        fn prop(v: Vec<int>) -> bool { v.len() < 5 }
+        let a = config().check(prop as fn(Vec<int>) -> bool).err().unwrap();
+        let b = config().check(prop as fn(Vec<int>) -> bool).err().unwrap();
+        assert_eq!(a.tests, b.tests);
+        assert_eq!(a.counterexample.args, b.counterexample.args);
+    }
+}
@@ -0,0 +1,353 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Dates of the proleptic Gregorian calendar, and arithmetic on them.
+//!
+//! ```
+//! use time::date::Date;
+//!
+//! let date = Date::from_ymd(2014, 1, 31).unwrap();
+//! // Adding months keeps the day of the month, or clamps it to the end of
+//! // the month.
+//! assert_eq!(date.checked_add_months(1), Date::from_ymd(2014, 2, 28));
+//! assert_eq!(date.iso_week(), (2014, 5));
+//! assert_eq!((Date::from_ymd(2014, 3, 1).unwrap() - date).num_days(), 29);
+//! ```
+
+use std::cmp;
+use std::fmt;
+use std::num::Int;
+use std::str::FromStr;
+use std::time::{Duration, civil_from_days, days_from_civil};
+
+use super::{Tm, div_floor, empty_tm, mod_floor};
+
+/// The earliest year of a `Date`.
+pub const MIN_YEAR: i32 = -262144;
+
+/// The latest year of a `Date`.
+pub const MAX_YEAR: i32 = 262143;
+
+/// A date of the proleptic Gregorian calendar, between the years `MIN_YEAR`
+/// and `MAX_YEAR`.
+#[deriving(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    year: i32,
+    month: i32,
+    mday: i32,
+}
+
+/// Returns whether `year` is a leap year.
+pub fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+/// Returns the number of days in a month of a year, with months from 1 to 12.
+pub fn days_in_month(year: i32, month: i32) -> i32 {
+    match month {
+        2 => if is_leap_year(year as i64) { 29 } else { 28 },
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl Date {
+    /// Makes a date from a year, a month from 1 to 12 and a day of the month
+    /// from 1.
+    ///
+    /// Returns `None` if there's no such date.
+    pub fn from_ymd(year: i32, month: i32, mday: i32) -> Option<Date> {
+        if year < MIN_YEAR || year > MAX_YEAR || month < 1 || month > 12 ||
+           mday < 1 || mday > days_in_month(year, month) {
+            return None;
+        }
+        Some(Date { year: year, month: month, mday: mday })
+    }
+
+    /// Makes a date from a year and a day of the year from 0, as in
+    /// `Tm::tm_yday`.
+    ///
+    /// Returns `None` if there's no such date.
+    pub fn from_yday(year: i32, yday: i32) -> Option<Date> {
+        let days_in_year = if is_leap_year(year as i64) { 366 } else { 365 };
+        if yday < 0 || yday >= days_in_year {
+            return None;
+        }
+        Date::from_ymd(year, 1, 1).and_then(|jan1| jan1.checked_add_days(yday as i64))
+    }
+
+    /// Makes a date from an ISO 8601 year, a week from 1 and a day of the week
+    /// from 1 (Monday) to 7 (Sunday).
+    ///
+    /// Returns `None` if there's no such date.
+    pub fn from_iso_week(year: i32, week: i32, wday: i32) -> Option<Date> {
+        if year < MIN_YEAR || year > MAX_YEAR || week < 1 || wday < 1 || wday > 7 {
+            return None;
+        }
+        // Week 1 is the week of January 4.
+        let jan4 = days_from_civil(year as i64, 1, 4);
+        let monday = jan4 - mod_floor(jan4 + 3, 7);
+        let date = try_opt!(Date::from_days(monday + (week as i64 - 1) * 7 + wday as i64 - 1));
+        let (iso_year, _) = date.iso_week();
+        if iso_year != year { None } else { Some(date) }
+    }
+
+    /// Makes a date from the number of days since January 1, 1970.
+    ///
+    /// Returns `None` if the date is out of range.
+    pub fn from_days(days: i64) -> Option<Date> {
+        if days < days_from_civil(MIN_YEAR as i64, 1, 1) ||
+           days > days_from_civil(MAX_YEAR as i64, 12, 31) {
+            return None;
+        }
+        let (year, month, mday) = civil_from_days(days);
+        Some(Date { year: year as i32, month: month as i32, mday: mday as i32 })
+    }
+
+    /// Returns the date of `tm`, or `None` if it isn't a valid date.
+    pub fn from_tm(tm: &Tm) -> Option<Date> {
+        let year = tm.tm_year as i64 + 1900;
+        if year < MIN_YEAR as i64 || year > MAX_YEAR as i64 {
+            return None;
+        }
+        Date::from_ymd(year as i32, tm.tm_mon + 1, tm.tm_mday)
+    }
+
+    /// Returns the number of days since January 1, 1970.
+    pub fn to_days(&self) -> i64 {
+        days_from_civil(self.year as i64, self.month as i64, self.mday as i64)
+    }
+
+    /// Returns midnight UTC at the start of this date.
+    pub fn to_tm(&self) -> Tm {
+        let mut tm = empty_tm();
+        tm.tm_year = self.year - 1900;
+        tm.tm_mon = self.month - 1;
+        tm.tm_mday = self.mday;
+        tm.tm_wday = self.wday();
+        tm.tm_yday = self.yday();
+        tm
+    }
+
+    /// The year.
+    pub fn year(&self) -> i32 {
+        self.year
+    }
+
+    /// The month, from 1 to 12.
+    pub fn month(&self) -> i32 {
+        self.month
+    }
+
+    /// The day of the month, from 1.
+    pub fn mday(&self) -> i32 {
+        self.mday
+    }
+
+    /// The day of the week, from 0 (Sunday) to 6, as in `Tm::tm_wday`.
+    pub fn wday(&self) -> i32 {
+        // January 1, 1970 was a Thursday.
+        mod_floor(self.to_days() + 4, 7) as i32
+    }
+
+    /// The day of the year, from 0, as in `Tm::tm_yday`.
+    pub fn yday(&self) -> i32 {
+        (self.to_days() - days_from_civil(self.year as i64, 1, 1)) as i32
+    }
+
+    /// Returns the ISO 8601 year and week of the date.
+    ///
+    /// Weeks start on Monday, and the first week of a year is the one with
+    /// its first Thursday, so the first days of January may be in the last
+    /// week of the year before, and the last days of December in the first
+    /// week of the year after.
+    pub fn iso_week(&self) -> (i32, i32) {
+        let days = self.to_days();
+        // The Thursday of the same week decides its year.
+        let thursday = days - mod_floor(days + 3, 7) + 3;
+        let (year, _, _) = civil_from_days(thursday);
+        let week = (thursday - days_from_civil(year, 1, 1)) / 7 + 1;
+        (year as i32, week as i32)
+    }
+
+    /// Adds a number of days.
+    ///
+    /// Returns `None` if the result is out of range.
+    pub fn checked_add_days(&self, days: i64) -> Option<Date> {
+        Date::from_days(try_opt!(self.to_days().checked_add(days)))
+    }
+
+    /// Adds a number of months, keeping the day of the month unless the
+    /// month is shorter, in which case it is the last day of the month.
+    ///
+    /// Returns `None` if the result is out of range.
+    pub fn checked_add_months(&self, months: i32) -> Option<Date> {
+        self.add_months(months as i64)
+    }
+
+    /// Adds a number of years, keeping the day of the month unless the date
+    /// is February 29 and the year isn't a leap year, in which case it is
+    /// February 28.
+    ///
+    /// Returns `None` if the result is out of range.
+    pub fn checked_add_years(&self, years: i32) -> Option<Date> {
+        self.add_months(years as i64 * 12)
+    }
+
+    fn add_months(&self, months: i64) -> Option<Date> {
+        let month = self.year as i64 * 12 + (self.month - 1) as i64 + months;
+        let year = div_floor(month, 12);
+        if year < MIN_YEAR as i64 || year > MAX_YEAR as i64 {
+            return None;
+        }
+        let (year, month) = (year as i32, mod_floor(month, 12) as i32 + 1);
+        Date::from_ymd(year, month, cmp::min(self.mday, days_in_month(year, month)))
+    }
+}
+
+impl Sub<Date, Duration> for Date {
+    fn sub(self, other: Date) -> Duration {
+        Duration::days(self.to_days() - other.to_days())
+    }
+}
+
+impl fmt::Show for Date {
+    /// Formats the date as in ISO 8601: `2014-03-09`. Years before 0 or after
+    /// 9999 have a sign.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.year < 0 {
+            try!(write!(f, "-{:04}", -self.year));
+        } else if self.year > 9999 {
+            try!(write!(f, "+{}", self.year));
+        } else {
+            try!(write!(f, "{:04}", self.year));
+        }
+        write!(f, "-{:02}-{:02}", self.month, self.mday)
+    }
+}
+
+impl FromStr for Date {
+    /// Parses a date in any of the formats of ISO 8601, as with
+    /// `parse_iso8601`.
+    fn from_str(s: &str) -> Option<Date> {
+        let mut pos = 0;
+        match super::parse_iso8601_date(s.as_bytes(), &mut pos) {
+            Ok(date) if pos == s.len() => Some(date),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Date, MIN_YEAR, MAX_YEAR, days_in_month, is_leap_year};
+
+    fn ymd(year: i32, month: i32, mday: i32) -> Date {
+        Date::from_ymd(year, month, mday).unwrap()
+    }
+
+    #[test]
+    fn test_from_ymd() {
+        assert!(Date::from_ymd(2016, 2, 29).is_some());
+        assert!(Date::from_ymd(2014, 2, 29).is_none());
+        assert!(Date::from_ymd(1900, 2, 29).is_none());
+        assert!(Date::from_ymd(2000, 2, 29).is_some());
+        assert!(Date::from_ymd(2014, 0, 1).is_none());
+        assert!(Date::from_ymd(2014, 13, 1).is_none());
+        assert!(Date::from_ymd(2014, 4, 31).is_none());
+        assert!(Date::from_ymd(2014, 4, 0).is_none());
+        assert!(Date::from_ymd(MAX_YEAR + 1, 1, 1).is_none());
+        assert!(is_leap_year(-4));
+        assert!(!is_leap_year((1 << 32) + 4));
+        assert_eq!(days_in_month(2014, 2), 28);
+        assert_eq!(days_in_month(2014, 12), 31);
+    }
+
+    #[test]
+    fn test_days() {
+        assert_eq!(ymd(1970, 1, 1).to_days(), 0);
+        assert_eq!(ymd(1969, 12, 31).to_days(), -1);
+        assert_eq!(ymd(2000, 2, 29).to_days(), 11016);
+        for &days in [-719162i64, -1, 0, 11016, 20520, 100000].iter() {
+            assert_eq!(Date::from_days(days).unwrap().to_days(), days);
+        }
+        assert_eq!(Date::from_days(ymd(MIN_YEAR, 1, 1).to_days() - 1), None);
+        assert_eq!(Date::from_days(ymd(MAX_YEAR, 12, 31).to_days() + 1), None);
+
+        let date = ymd(2012, 2, 22);
+        assert_eq!((date.wday(), date.yday()), (3, 52));
+        assert_eq!(Date::from_yday(2012, 52), Some(date));
+        assert_eq!(Date::from_yday(2012, 365), Some(ymd(2012, 12, 31)));
+        assert_eq!(Date::from_yday(2014, 365), None);
+
+        let tm = date.to_tm();
+        assert_eq!((tm.tm_year, tm.tm_mon, tm.tm_mday), (112, 1, 22));
+        assert_eq!((tm.tm_wday, tm.tm_yday, tm.tm_hour), (3, 52, 0));
+        assert_eq!(Date::from_tm(&tm), Some(date));
+    }
+
+    #[test]
+    fn test_iso_week() {
+        assert_eq!(ymd(2008, 12, 29).iso_week(), (2009, 1));
+        assert_eq!(ymd(2010, 1, 3).iso_week(), (2009, 53));
+        assert_eq!(ymd(2012, 2, 22).iso_week(), (2012, 8));
+        assert_eq!(ymd(2026, 1, 1).iso_week(), (2026, 1));
+        assert_eq!(ymd(2025, 12, 29).iso_week(), (2026, 1));
+        assert_eq!(ymd(2026, 12, 31).iso_week(), (2026, 53));
+
+        assert_eq!(Date::from_iso_week(2009, 1, 1), Some(ymd(2008, 12, 29)));
+        assert_eq!(Date::from_iso_week(2009, 53, 7), Some(ymd(2010, 1, 3)));
+        assert_eq!(Date::from_iso_week(2026, 53, 7), Some(ymd(2027, 1, 3)));
+        assert_eq!(Date::from_iso_week(2025, 53, 1), None);
+        assert_eq!(Date::from_iso_week(2025, 0, 1), None);
+        assert_eq!(Date::from_iso_week(2025, 1, 8), None);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(ymd(2014, 12, 31).checked_add_days(1), Some(ymd(2015, 1, 1)));
+        assert_eq!(ymd(2016, 3, 1).checked_add_days(-1), Some(ymd(2016, 2, 29)));
+        assert_eq!(ymd(MAX_YEAR, 12, 31).checked_add_days(1), None);
+
+        assert_eq!(ymd(2014, 1, 31).checked_add_months(1), Some(ymd(2014, 2, 28)));
+        assert_eq!(ymd(2016, 1, 31).checked_add_months(1), Some(ymd(2016, 2, 29)));
+        assert_eq!(ymd(2014, 12, 15).checked_add_months(1), Some(ymd(2015, 1, 15)));
+        assert_eq!(ymd(2014, 3, 31).checked_add_months(-13), Some(ymd(2013, 2, 28)));
+        assert_eq!(ymd(0, 1, 1).checked_add_months(-1), Some(ymd(-1, 12, 1)));
+        assert_eq!(ymd(MIN_YEAR, 1, 1).checked_add_months(-1), None);
+
+        assert_eq!(ymd(2016, 2, 29).checked_add_years(1), Some(ymd(2017, 2, 28)));
+        assert_eq!(ymd(2016, 2, 29).checked_add_years(4), Some(ymd(2020, 2, 29)));
+        assert_eq!(ymd(2014, 6, 1).checked_add_years(-2014), Some(ymd(0, 6, 1)));
+        assert_eq!(ymd(2014, 6, 1).checked_add_years(::std::i32::MAX), None);
+
+        assert_eq!((ymd(2014, 3, 1) - ymd(2014, 1, 31)).num_days(), 29);
+        assert_eq!((ymd(2014, 1, 31) - ymd(2014, 3, 1)).num_days(), -29);
+        assert!(ymd(2014, 1, 31) < ymd(2014, 2, 1));
+    }
+
+    #[test]
+    fn test_show_and_from_str() {
+        assert_eq!(ymd(2014, 3, 9).to_string(), "2014-03-09");
+        assert_eq!(ymd(-44, 3, 15).to_string(), "-0044-03-15");
+        assert_eq!(ymd(12345, 1, 1).to_string(), "+12345-01-01");
+
+        for s in ["2014-03-09", "20140309", "2014-068", "2014068", "2014-W10-7",
+                  "2014W107"].iter() {
+            assert_eq!(from_str::<Date>(*s), Some(ymd(2014, 3, 9)));
+        }
+        assert_eq!(from_str::<Date>("-0044-03-15"), Some(ymd(-44, 3, 15)));
+        assert_eq!(from_str::<Date>("+12345-01-01"), Some(ymd(12345, 1, 1)));
+        for s in ["", "2014", "2014-3-9", "2014-03-09T", "2014-02-30", "2014-W54-1",
+                  "12345-01-01", "2014-0309"].iter() {
+            assert_eq!(from_str::<Date>(*s), None);
+        }
+    }
+}
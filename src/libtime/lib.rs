@@ -20,7 +20,7 @@
        html_favicon_url = "http://www.rust-lang.org/favicon.ico",
        html_root_url = "http://doc.rust-lang.org/nightly/",
        html_playground_url = "http://play.rust-lang.org/")]
-#![feature(phase, globs)]
+#![feature(macro_rules, phase, globs)]
 
 #[cfg(test)] #[phase(plugin, link)] extern crate log;
 
@@ -32,11 +32,19 @@ use self::Fmt::*;
 
 use std::fmt::Show;
 use std::fmt;
-use std::num::SignedInt;
+use std::num::{Int, SignedInt};
 use std::string::String;
 use std::time::Duration;
 
 static NSEC_PER_SEC: i32 = 1_000_000_000_i32;
+static SECS_PER_DAY: i64 = 86400;
+
+macro_rules! try_opt {
+    ($e:expr) => (match $e { Some(x) => x, None => return None })
+}
+
+pub mod date;
+pub mod zone;
 
 mod rustrt {
     use super::Tm;
@@ -46,7 +54,6 @@ mod rustrt {
         pub fn rust_gmtime(sec: i64, nsec: i32, result: &mut Tm);
         pub fn rust_localtime(sec: i64, nsec: i32, result: &mut Tm);
         pub fn rust_timegm(tm: &Tm) -> i64;
-        pub fn rust_mktime(tm: &Tm) -> i64;
     }
 }
 
@@ -289,6 +296,15 @@ pub fn empty_tm() -> Tm {
     }
 }
 
+fn div_floor(a: i64, b: i64) -> i64 {
+    (a - mod_floor(a, b)) / b
+}
+
+fn mod_floor(a: i64, b: i64) -> i64 {
+    let r = a % b;
+    if r < 0 { r + b } else { r }
+}
+
 /// Returns the specified time in UTC
 pub fn at_utc(clock: Timespec) -> Tm {
     unsafe {
@@ -321,12 +337,13 @@ pub fn now() -> Tm {
 
 impl Tm {
     /// Convert time to the seconds from January 1, 1970
+    ///
+    /// The time is taken to be `tm_gmtoff` seconds east of UTC, so that times
+    /// in any zone, such as those from `zone::TimeZone::at`, convert back to
+    /// the same instant.
     pub fn to_timespec(&self) -> Timespec {
         unsafe {
-            let sec = match self.tm_gmtoff {
-                0_i32 => rustrt::rust_timegm(self),
-                _     => rustrt::rust_mktime(self)
-            };
+            let sec = rustrt::rust_timegm(self) - self.tm_gmtoff as i64;
 
             Timespec::new(sec, self.tm_nsec)
         }
@@ -342,6 +359,73 @@ impl Tm {
         at_utc(self.to_timespec())
     }
 
+    /// Returns the date, or `None` if the fields of the date aren't valid.
+    pub fn date(&self) -> Option<date::Date> {
+        date::Date::from_tm(self)
+    }
+
+    /// Adds a number of days, keeping the time of day and the offset from UTC.
+    ///
+    /// Returns `None` if the date isn't valid or the result is out of range.
+    /// The offset in effect in a time zone may differ on the new date; use
+    /// `zone::TimeZone::from_local` to find it.
+    pub fn checked_add_days(&self, days: i64) -> Option<Tm> {
+        self.date().and_then(|date| date.checked_add_days(days))
+                   .map(|date| self.with_date(date))
+    }
+
+    /// Adds a number of months as with `date::Date::checked_add_months`,
+    /// keeping the time of day and the offset from UTC.
+    ///
+    /// Returns `None` if the date isn't valid or the result is out of range.
+    pub fn checked_add_months(&self, months: i32) -> Option<Tm> {
+        self.date().and_then(|date| date.checked_add_months(months))
+                   .map(|date| self.with_date(date))
+    }
+
+    /// Adds a number of years as with `date::Date::checked_add_years`,
+    /// keeping the time of day and the offset from UTC.
+    ///
+    /// Returns `None` if the date isn't valid or the result is out of range.
+    pub fn checked_add_years(&self, years: i32) -> Option<Tm> {
+        self.date().and_then(|date| date.checked_add_years(years))
+                   .map(|date| self.with_date(date))
+    }
+
+    /// Adds a duration, keeping the offset from UTC.
+    ///
+    /// Returns `None` if the date isn't valid or the result is out of range.
+    pub fn checked_add(&self, duration: Duration) -> Option<Tm> {
+        let date = try_opt!(self.date());
+        let secs = duration.num_seconds();
+        // There cannot be more than one second left, as in `Timespec::add`.
+        let nsec = self.tm_nsec as i64 +
+                   (duration - Duration::seconds(secs)).num_nanoseconds().unwrap();
+        let local = date.to_days() * SECS_PER_DAY + self.tm_hour as i64 * 3600 +
+                    self.tm_min as i64 * 60 + self.tm_sec as i64 +
+                    div_floor(nsec, NSEC_PER_SEC as i64);
+        let local = try_opt!(local.checked_add(secs));
+
+        let mut tm = self.with_date(try_opt!(date::Date::from_days(div_floor(local,
+                                                                             SECS_PER_DAY))));
+        let secs = mod_floor(local, SECS_PER_DAY) as i32;
+        tm.tm_hour = secs / 3600;
+        tm.tm_min = secs / 60 % 60;
+        tm.tm_sec = secs % 60;
+        tm.tm_nsec = mod_floor(nsec, NSEC_PER_SEC as i64) as i32;
+        Some(tm)
+    }
+
+    fn with_date(&self, date: date::Date) -> Tm {
+        let mut tm = *self;
+        tm.tm_year = date.year() - 1900;
+        tm.tm_mon = date.month() - 1;
+        tm.tm_mday = date.mday();
+        tm.tm_wday = date.wday();
+        tm.tm_yday = date.yday();
+        tm
+    }
+
     /// Returns a TmFmt that outputs according to the `asctime` format in ISO
     /// C, in the local timezone.
     ///
@@ -414,6 +498,12 @@ impl Tm {
     }
 }
 
+impl Sub<Tm, Duration> for Tm {
+    fn sub(self, other: Tm) -> Duration {
+        self.to_timespec() - other.to_timespec()
+    }
+}
+
 #[deriving(Copy, PartialEq)]
 pub enum ParseError {
     InvalidSecond,
@@ -425,6 +515,7 @@ pub enum ParseError {
     InvalidDayOfWeek,
     InvalidDayOfMonth,
     InvalidDayOfYear,
+    InvalidWeek,
     InvalidZoneOffset,
     InvalidTime,
     MissingFormatConverter,
@@ -444,6 +535,7 @@ impl Show for ParseError {
             InvalidDayOfWeek => write!(f, "Invalid day of the week."),
             InvalidDayOfMonth => write!(f, "Invalid day of the month."),
             InvalidDayOfYear => write!(f, "Invalid day of the year."),
+            InvalidWeek => write!(f, "Invalid week."),
             InvalidZoneOffset => write!(f, "Invalid zone offset."),
             InvalidTime => write!(f, "Invalid time."),
             MissingFormatConverter => write!(f, "Missing format converter after `%`"),
@@ -1253,6 +1345,166 @@ pub fn strptime(s: &str, format: &str) -> Result<Tm, ParseError> {
     } else { result }
 }
 
+/// Parses a date and time in any of the formats of ISO 8601 and RFC 3339,
+/// such as those of `Tm::rfc3339`, without a format string.
+///
+/// The date is in the calendar (`2014-03-09`), ordinal (`2014-068`) or week
+/// (`2014-W10-7`) format, or the basic format without hyphens of any of
+/// those. Years before 0 or after 9999 have a sign and at least four digits,
+/// in the extended format only (`+12345-01-01`). The date may be followed by
+/// `T` or a space and a time of day, such as `02:30`, `02:30:15` or
+/// `02:30:15.123456789`, and then by `Z` or an offset from UTC such as
+/// `-05:00`, which sets `tm_gmtoff`. The offset is zero if it's missing; use
+/// `zone::TimeZone::from_local` for times in a zone.
+///
+/// ```
+/// let tm = time::parse_iso8601("2012-02-22T07:53:18-07:00").unwrap();
+/// assert_eq!(tm.tm_hour, 7);
+/// assert_eq!(tm.tm_gmtoff, -7 * 3600);
+/// assert_eq!(tm.rfc3339().to_string(), "2012-02-22T07:53:18-07:00");
+/// ```
+pub fn parse_iso8601(s: &str) -> Result<Tm, ParseError> {
+    let s = s.as_bytes();
+    let mut pos = 0;
+    let mut tm = try!(parse_iso8601_date(s, &mut pos)).to_tm();
+    if pos == s.len() {
+        return Ok(tm);
+    }
+    match s[pos] {
+        b'T' | b't' | b' ' => pos += 1,
+        c => return Err(UnexpectedCharacter('T', c as char)),
+    }
+
+    tm.tm_hour = try!(parse_digits(s, &mut pos, 2, 0, 23).ok_or(InvalidHour));
+    let extended = skip_char(s, &mut pos, b':');
+    tm.tm_min = try!(parse_digits(s, &mut pos, 2, 0, 59).ok_or(InvalidMinute));
+    if (extended && skip_char(s, &mut pos, b':')) || (!extended && is_digit(s, pos)) {
+        tm.tm_sec = try!(parse_digits(s, &mut pos, 2, 0, 60).ok_or(InvalidSecond));
+        if skip_char(s, &mut pos, b'.') || skip_char(s, &mut pos, b',') {
+            if !is_digit(s, pos) {
+                return Err(InvalidSecond);
+            }
+            // Digits after the ninth are below a nanosecond.
+            let mut scale = NSEC_PER_SEC;
+            while is_digit(s, pos) {
+                scale /= 10;
+                tm.tm_nsec += (s[pos] - b'0') as i32 * scale;
+                pos += 1;
+            }
+        }
+    }
+
+    if pos < s.len() {
+        let sign = match s[pos] {
+            b'Z' | b'z' => 0,
+            b'+' => 1,
+            b'-' => -1,
+            _ => return Err(InvalidTime),
+        };
+        pos += 1;
+        if sign != 0 {
+            let hours = try!(parse_digits(s, &mut pos, 2, 0, 23).ok_or(InvalidZoneOffset));
+            let mut minutes = 0;
+            if skip_char(s, &mut pos, b':') || is_digit(s, pos) {
+                minutes = try!(parse_digits(s, &mut pos, 2, 0, 59).ok_or(InvalidZoneOffset));
+            }
+            tm.tm_gmtoff = sign * (hours * 3600 + minutes * 60);
+        }
+    }
+    if pos != s.len() {
+        return Err(InvalidTime);
+    }
+    Ok(tm)
+}
+
+// Parses the date at the start of an ISO 8601 date and time.
+fn parse_iso8601_date(s: &[u8], pos: &mut uint) -> Result<date::Date, ParseError> {
+    // Years before 0 or after 9999 have a sign, and then at least four
+    // digits, and the date is in the extended format.
+    let (year, extended) = match s.get(*pos) {
+        Some(&b'+') | Some(&b'-') => {
+            let sign = if s[*pos] == b'-' { -1 } else { 1 };
+            *pos += 1;
+            let start = *pos;
+            while is_digit(s, *pos) {
+                *pos += 1;
+            }
+            let digits = *pos - start;
+            *pos = start;
+            if digits < 4 || digits > 6 {
+                return Err(InvalidYear);
+            }
+            let year = try!(parse_digits(s, pos, digits, 0, -date::MIN_YEAR).ok_or(InvalidYear));
+            if !skip_char(s, pos, b'-') {
+                return Err(UnexpectedCharacter('-', s.get(*pos).map_or(' ', |&c| c as char)));
+            }
+            (sign * year, true)
+        }
+        _ => {
+            let year = try!(parse_digits(s, pos, 4, 0, 9999).ok_or(InvalidYear));
+            (year, skip_char(s, pos, b'-'))
+        }
+    };
+
+    let date = if skip_char(s, pos, b'W') {
+        let week = try!(parse_digits(s, pos, 2, 1, 53).ok_or(InvalidWeek));
+        if extended && !skip_char(s, pos, b'-') {
+            return Err(InvalidDayOfWeek);
+        }
+        let wday = try!(parse_digits(s, pos, 1, 1, 7).ok_or(InvalidDayOfWeek));
+        try!(date::Date::from_iso_week(year, week, wday).ok_or(InvalidWeek))
+    } else {
+        let start = *pos;
+        while is_digit(s, *pos) {
+            *pos += 1;
+        }
+        let digits = *pos - start;
+        *pos = start;
+        if digits == 3 {
+            let yday = try!(parse_digits(s, pos, 3, 1, 366).ok_or(InvalidDayOfYear));
+            try!(date::Date::from_yday(year, yday - 1).ok_or(InvalidDayOfYear))
+        } else {
+            let month = try!(parse_digits(s, pos, 2, 1, 12).ok_or(InvalidMonth));
+            if extended && !skip_char(s, pos, b'-') {
+                return Err(InvalidDayOfMonth);
+            }
+            let mday = try!(parse_digits(s, pos, 2, 1, 31).ok_or(InvalidDayOfMonth));
+            try!(date::Date::from_ymd(year, month, mday).ok_or(InvalidDayOfMonth))
+        }
+    };
+    // Digits after the date would make it some other date.
+    if is_digit(s, *pos) {
+        return Err(InvalidTime);
+    }
+    Ok(date)
+}
+
+fn is_digit(s: &[u8], pos: uint) -> bool {
+    pos < s.len() && s[pos] >= b'0' && s[pos] <= b'9'
+}
+
+fn skip_char(s: &[u8], pos: &mut uint, c: u8) -> bool {
+    if *pos < s.len() && s[*pos] == c {
+        *pos += 1;
+        true
+    } else {
+        false
+    }
+}
+
+// Exactly `digits` digits, for a number from `min` to `max`.
+fn parse_digits(s: &[u8], pos: &mut uint, digits: uint, min: i32, max: i32) -> Option<i32> {
+    let mut n = 0i64;
+    for _ in range(0, digits) {
+        if !is_digit(s, *pos) {
+            return None;
+        }
+        n = n * 10 + (s[*pos] - b'0') as i64;
+        *pos += 1;
+    }
+    if n < min as i64 || n > max as i64 { None } else { Some(n as i32) }
+}
+
 /// Formats the time according to the format string.
 pub fn strftime(format: &str, tm: &Tm) -> Result<String, ParseError> {
     tm.strftime(format).map(|fmt| fmt.to_string())
@@ -1263,7 +1515,9 @@ mod tests {
     extern crate test;
     use super::{Timespec, InvalidTime, InvalidYear, get_time, precise_time_ns,
                 precise_time_s, tzset, at_utc, at, strptime, MissingFormatConverter,
-                InvalidFormatSpecifier};
+                InvalidFormatSpecifier, parse_iso8601, InvalidHour, InvalidMonth,
+                InvalidDayOfMonth, InvalidWeek, InvalidZoneOffset, UnexpectedCharacter};
+    use super::zone::TimeZone;
 
     use std::f64;
     use std::result::Result::{Err, Ok};
@@ -1756,6 +2010,71 @@ mod tests {
         test_timespec_sub();
     }
 
+    #[test]
+    fn test_parse_iso8601() {
+        for s in ["2012-02-22T07:53:18-07:00", "2012-02-22t07:53:18-0700",
+                  "20120222T075318-07", "2012-053 07:53:18-07:00",
+                  "2012-W08-3T07:53:18-07:00"].iter() {
+            let tm = parse_iso8601(*s).unwrap();
+            assert_eq!((tm.tm_year, tm.tm_mon, tm.tm_mday, tm.tm_wday, tm.tm_yday),
+                       (112, 1, 22, 3, 52));
+            assert_eq!((tm.tm_hour, tm.tm_min, tm.tm_sec, tm.tm_nsec), (7, 53, 18, 0));
+            assert_eq!(tm.tm_gmtoff, -25200);
+        }
+
+        let tm = parse_iso8601("2012-02-22T14:53Z").unwrap();
+        assert_eq!((tm.tm_hour, tm.tm_min, tm.tm_sec, tm.tm_gmtoff), (14, 53, 0, 0));
+        let tm = parse_iso8601("2012-02-22").unwrap();
+        assert_eq!((tm.tm_mday, tm.tm_hour, tm.tm_min, tm.tm_sec), (22, 0, 0, 0));
+        for &(s, nsec) in [("2012-02-22T14:53:18.123456789Z", 123456789),
+                           ("2012-02-22T14:53:18,25Z", 250000000),
+                           ("2012-02-22T14:53:18.1234567891Z", 123456789)].iter() {
+            assert_eq!(parse_iso8601(s).unwrap().tm_nsec, nsec);
+        }
+
+        // The inverse of `Tm::rfc3339`.
+        let time = Timespec::new(1234567890, 0);
+        for tm in [at_utc(time), TimeZone::posix("MST7").unwrap().at(time)].iter() {
+            assert_eq!(parse_iso8601(tm.rfc3339().to_string().as_slice()), Ok(*tm));
+        }
+
+        assert_eq!(parse_iso8601(""), Err(InvalidYear));
+        assert_eq!(parse_iso8601("2012-13-01"), Err(InvalidMonth));
+        assert_eq!(parse_iso8601("2012-02-30"), Err(InvalidDayOfMonth));
+        assert_eq!(parse_iso8601("2012-W54-1"), Err(InvalidWeek));
+        assert_eq!(parse_iso8601("2012-02-22X07:53"), Err(UnexpectedCharacter('T', 'X')));
+        assert_eq!(parse_iso8601("2012-02-22T24:00:00Z"), Err(InvalidHour));
+        assert_eq!(parse_iso8601("2012-02-22T07:53:18+25:00"), Err(InvalidZoneOffset));
+        assert_eq!(parse_iso8601("2012-02-22T07:53:18Zjunk"), Err(InvalidTime));
+    }
+
+    #[test]
+    fn test_tm_arithmetic() {
+        let tm = parse_iso8601("2014-01-31T23:30:00.5-05:00").unwrap();
+
+        let next = tm.checked_add_months(1).unwrap();
+        assert_eq!(next.rfc3339().to_string(), "2014-02-28T23:30:00-05:00");
+        assert_eq!((next.tm_wday, next.tm_yday, next.tm_nsec), (5, 58, 500_000_000));
+        assert_eq!(next - tm, Duration::days(28));
+        assert_eq!(tm.checked_add_years(-1).unwrap().rfc3339().to_string(),
+                   "2013-01-31T23:30:00-05:00");
+        assert_eq!(tm.checked_add_days(1).unwrap().rfc3339().to_string(),
+                   "2014-02-01T23:30:00-05:00");
+
+        let later = tm.checked_add(Duration::minutes(45)).unwrap();
+        assert_eq!(later.rfc3339().to_string(), "2014-02-01T00:15:00-05:00");
+        assert_eq!(later.tm_nsec, 500_000_000);
+        let earlier = tm.checked_add(Duration::milliseconds(-1500)).unwrap();
+        assert_eq!(earlier.rfc3339().to_string(), "2014-01-31T23:29:59-05:00");
+        assert_eq!(earlier.tm_nsec, 0);
+        assert_eq!(earlier - tm, Duration::milliseconds(-1500));
+
+        let mut invalid = tm;
+        invalid.tm_mday = 32;
+        assert!(invalid.checked_add_days(1).is_none());
+        assert!(invalid.checked_add(Duration::seconds(1)).is_none());
+    }
+
     #[bench]
     fn bench_precise_time_ns(b: &mut Bencher) {
         b.iter(|| precise_time_ns())
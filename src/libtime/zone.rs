@@ -0,0 +1,829 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Time zones from the IANA time zone database.
+//!
+//! A `TimeZone` is read from a zoneinfo (TZif) file, as found in
+//! `/usr/share/zoneinfo`, or from a POSIX `TZ` string. Unlike `at` and
+//! `Tm::to_local`, which use the zone of the process, any number of zones can
+//! be used at once.
+//!
+//! ```no_run
+//! use time::zone::{TimeZone, LocalResult};
+//!
+//! let new_york = TimeZone::load("America/New_York").unwrap();
+//! let tm = new_york.at(time::get_time());
+//! println!("{} in {}", tm.rfc3339(), new_york.name());
+//!
+//! // 2:30 AM doesn't exist on the day DST starts.
+//! let tm = time::strptime("2014-03-09 02:30", "%Y-%m-%d %H:%M").unwrap();
+//! match new_york.from_local(&tm) {
+//!     LocalResult::Single(tm) => println!("{}", tm.to_timespec()),
+//!     LocalResult::Ambiguous(earlier, later) => {
+//!         println!("either {} or {}", earlier.rfc3339(), later.rfc3339())
+//!     }
+//!     LocalResult::Nonexistent => println!("no such time"),
+//! }
+//! ```
+
+use std::io::{mod, BufReader, File, IoError, IoResult};
+use std::num::Int;
+use std::os;
+use std::time::{civil_from_days, days_from_civil};
+
+use super::{SECS_PER_DAY, Tm, Timespec, at_utc, div_floor, mod_floor};
+use super::date::is_leap_year;
+
+/// The directories searched for zoneinfo files, unless `TZDIR` is set.
+static ZONEINFO_DIRS: &'static [&'static str] = &["/usr/share/zoneinfo",
+                                                  "/usr/lib/zoneinfo",
+                                                  "/usr/share/lib/zoneinfo"];
+
+/// An offset from UTC in effect in a time zone, such as EDT in
+/// America/New_York.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct LocalTimeType {
+    /// The number of seconds east of UTC, as in `Tm::tm_gmtoff`.
+    pub gmtoff: i32,
+    /// Whether this is daylight saving time.
+    pub isdst: bool,
+    /// The abbreviation of the offset, such as "EDT".
+    pub abbrev: String,
+}
+
+/// The result of converting a local time to an instant in a time zone.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum LocalResult {
+    /// The local time happens once.
+    Single(Tm),
+    /// The local time happens twice, as when clocks are set back at the end
+    /// of daylight saving time. The earlier and the later times.
+    Ambiguous(Tm, Tm),
+    /// The local time is skipped, as when clocks are set forward at the start
+    /// of daylight saving time.
+    Nonexistent,
+}
+
+impl LocalResult {
+    /// Returns the time if the local time happens once.
+    pub fn single(self) -> Option<Tm> {
+        match self {
+            LocalResult::Single(tm) => Some(tm),
+            _ => None,
+        }
+    }
+
+    /// Returns the earliest time that the local time happens.
+    pub fn earliest(self) -> Option<Tm> {
+        match self {
+            LocalResult::Single(tm) | LocalResult::Ambiguous(tm, _) => Some(tm),
+            LocalResult::Nonexistent => None,
+        }
+    }
+
+    /// Returns the latest time that the local time happens.
+    pub fn latest(self) -> Option<Tm> {
+        match self {
+            LocalResult::Single(tm) | LocalResult::Ambiguous(_, tm) => Some(tm),
+            LocalResult::Nonexistent => None,
+        }
+    }
+}
+
+// A day of the year in the rules of a POSIX `TZ` string.
+#[deriving(Clone, Copy, PartialEq, Eq, Show)]
+enum RuleDay {
+    // `Jn`: the day of the year, 1 to 365, not counting February 29.
+    Julian1(i64),
+    // `n`: the day of the year, 0 to 365, counting February 29.
+    Julian0(i64),
+    // `Mm.w.d`: the day `d` (0 is Sunday) of week `w` (5 is the last) of
+    // month `m`.
+    MonthWeekDay(i64, i64, i64),
+}
+
+// A change of the offset from UTC at `time`, to `types[idx]`.
+#[deriving(Clone, Copy, PartialEq, Eq, Show)]
+struct Transition {
+    time: i64,
+    idx: uint,
+}
+
+// A transition to or from daylight saving time: the day, and the local time
+// of day in seconds.
+#[deriving(Clone, Copy, PartialEq, Eq, Show)]
+struct Rule {
+    day: RuleDay,
+    time: i64,
+}
+
+// The zone described by a POSIX `TZ` string, such as `EST5EDT,M3.2.0,M11.1.0`.
+#[deriving(Clone, PartialEq, Eq, Show)]
+struct PosixTz {
+    std: LocalTimeType,
+    dst: Option<(LocalTimeType, Rule, Rule)>,
+}
+
+/// A time zone, with its history of offsets from UTC.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct TimeZone {
+    name: String,
+    // The transitions in order of time.
+    transitions: Vec<Transition>,
+    types: Vec<LocalTimeType>,
+    // The rule for times after the last transition.
+    rule: Option<PosixTz>,
+    // The distinct offsets of all the types and of the rule.
+    offsets: Vec<i32>,
+}
+
+impl TimeZone {
+    /// Loads the time zone with the given name, such as "America/New_York",
+    /// from the zoneinfo directory given by the `TZDIR` environment variable,
+    /// or from the system's zoneinfo directory. An absolute path names a
+    /// zoneinfo file.
+    pub fn load(name: &str) -> IoResult<TimeZone> {
+        if name.starts_with("/") {
+            return TimeZone::from_file(name, &Path::new(name));
+        }
+        if name.is_empty() || name.split('/').any(|c| c == "..") {
+            return Err(IoError {
+                kind: io::InvalidInput,
+                desc: "invalid time zone name",
+                detail: Some(name.to_string()),
+            });
+        }
+
+        let dirs = match os::getenv("TZDIR") {
+            Some(dir) => vec!(Path::new(dir)),
+            None => ZONEINFO_DIRS.iter().map(|dir| Path::new(*dir)).collect(),
+        };
+        let mut err = None;
+        for dir in dirs.iter() {
+            match TimeZone::from_file(name, &dir.join(name)) {
+                Err(IoError { kind: io::FileNotFound, .. }) => {}
+                Err(e) => err = Some(e),
+                Ok(tz) => return Ok(tz),
+            }
+        }
+        Err(err.unwrap_or(IoError {
+            kind: io::FileNotFound,
+            desc: "unknown time zone",
+            detail: Some(name.to_string()),
+        }))
+    }
+
+    /// Loads the zone of the process: the zone named by the `TZ` environment
+    /// variable, which may also be a POSIX `TZ` string, or the zone of
+    /// `/etc/localtime`.
+    pub fn local() -> IoResult<TimeZone> {
+        match os::getenv("TZ") {
+            Some(ref tz) if tz.is_empty() => Ok(TimeZone::utc()),
+            Some(tz) => {
+                let name = if tz.starts_with(":") { tz.slice_from(1) } else { tz.as_slice() };
+                match TimeZone::load(name) {
+                    Ok(zone) => Ok(zone),
+                    Err(e) => TimeZone::posix(name).ok_or(e),
+                }
+            }
+            None => TimeZone::from_file("localtime", &Path::new("/etc/localtime")),
+        }
+    }
+
+    /// Reads a zoneinfo file, naming the zone `name`.
+    pub fn from_file(name: &str, path: &Path) -> IoResult<TimeZone> {
+        let data = try!(File::open(path).read_to_end());
+        TimeZone::parse(name, data.as_slice()).map_err(|e| {
+            let detail = e.detail.clone().unwrap_or(String::new());
+            IoError { detail: Some(format!("{}: {}", path.display(), detail)), ..e }
+        })
+    }
+
+    /// Parses the contents of a zoneinfo file, in version 1 to 4 of the TZif
+    /// format, naming the zone `name`.
+    ///
+    /// Leap second records, as in the `right/` zones, aren't supported.
+    pub fn parse(name: &str, data: &[u8]) -> IoResult<TimeZone> {
+        let mut r = BufReader::new(data);
+        let mut header = try!(read_header(&mut r, data.len(), 4));
+        let wide = header.version >= b'2';
+        if wide {
+            // Skip the version 1 data, whose times are 32 bits.
+            try!(r.read_exact(header.data_len(4).unwrap()));
+            header = try!(read_header(&mut r, data.len(), 8));
+        }
+        if header.typecnt == 0 {
+            return Err(invalid("no local time types"));
+        }
+        if header.leapcnt != 0 {
+            return Err(invalid("leap seconds are not supported"));
+        }
+
+        let mut times = Vec::with_capacity(header.timecnt);
+        for _ in range(0, header.timecnt) {
+            times.push(if wide { try!(r.read_be_i64()) } else { try!(r.read_be_i32()) as i64 });
+        }
+        let mut transitions = Vec::with_capacity(header.timecnt);
+        for &time in times.iter() {
+            let idx = try!(r.read_u8()) as uint;
+            if idx >= header.typecnt {
+                return Err(invalid("local time type index out of range"));
+            }
+            transitions.push(Transition { time: time, idx: idx });
+        }
+        if transitions.windows(2).any(|w| w[0].time >= w[1].time) {
+            return Err(invalid("transition times are not in order"));
+        }
+
+        let mut ttinfos = Vec::with_capacity(header.typecnt);
+        for _ in range(0, header.typecnt) {
+            let gmtoff = try!(r.read_be_i32());
+            let isdst = try!(r.read_u8()) != 0;
+            let abbrind = try!(r.read_u8()) as uint;
+            ttinfos.push((gmtoff, isdst, abbrind));
+        }
+        let chars = try!(r.read_exact(header.charcnt));
+        let mut types = Vec::with_capacity(header.typecnt);
+        for &(gmtoff, isdst, abbrind) in ttinfos.iter() {
+            if abbrind >= chars.len() {
+                return Err(invalid("abbreviation index out of range"));
+            }
+            let abbrev = chars.slice_from(abbrind);
+            let end = abbrev.iter().position(|&b| b == 0).unwrap_or(abbrev.len());
+            let abbrev = match String::from_utf8(abbrev.slice_to(end).to_vec()) {
+                Ok(abbrev) => abbrev,
+                Err(..) => return Err(invalid("abbreviation is not UTF-8")),
+            };
+            types.push(LocalTimeType { gmtoff: gmtoff, isdst: isdst, abbrev: abbrev });
+        }
+        // The standard/wall and UT/local indicators are only used for POSIX
+        // `TZ` strings without rules, and are ignored.
+        try!(r.read_exact(header.isstdcnt + header.isutcnt));
+
+        let mut rule = None;
+        if wide {
+            if try!(r.read_u8()) != b'\n' {
+                return Err(invalid("missing footer"));
+            }
+            let mut footer = try!(r.read_until(b'\n'));
+            if footer.pop() != Some(b'\n') {
+                return Err(invalid("unterminated footer"));
+            }
+            if !footer.is_empty() {
+                rule = match parse_posix_tz(footer.as_slice()) {
+                    Some(rule) => Some(rule),
+                    None => return Err(invalid("invalid TZ string in footer")),
+                };
+            }
+        }
+
+        Ok(TimeZone::new(name, transitions, types, rule))
+    }
+
+    /// Makes a zone from a POSIX `TZ` string, such as `EST5EDT,M3.2.0,M11.1.0`
+    /// or `<+0530>-5:30`, which names the zone.
+    ///
+    /// Returns `None` if the string is invalid.
+    pub fn posix(tz: &str) -> Option<TimeZone> {
+        parse_posix_tz(tz.as_bytes()).map(|rule| {
+            TimeZone::new(tz, Vec::new(), Vec::new(), Some(rule))
+        })
+    }
+
+    /// The UTC zone.
+    pub fn utc() -> TimeZone {
+        let utc = LocalTimeType { gmtoff: 0, isdst: false, abbrev: "UTC".to_string() };
+        TimeZone::new("UTC", Vec::new(), vec!(utc), None)
+    }
+
+    fn new(name: &str, transitions: Vec<Transition>, types: Vec<LocalTimeType>,
+           rule: Option<PosixTz>) -> TimeZone {
+        let mut offsets: Vec<i32> = types.iter().map(|ty| ty.gmtoff).collect();
+        match rule {
+            Some(ref rule) => {
+                offsets.push(rule.std.gmtoff);
+                match rule.dst {
+                    Some((ref dst, _, _)) => offsets.push(dst.gmtoff),
+                    None => {}
+                }
+            }
+            None => {}
+        }
+        offsets.sort();
+        offsets.dedup();
+        TimeZone {
+            name: name.to_string(),
+            transitions: transitions,
+            types: types,
+            rule: rule,
+            offsets: offsets,
+        }
+    }
+
+    /// The name of the zone.
+    pub fn name(&self) -> &str {
+        self.name.as_slice()
+    }
+
+    /// Returns the offset from UTC in effect at the given time.
+    pub fn local_time_type(&self, clock: Timespec) -> &LocalTimeType {
+        let t = clock.sec;
+        let n = self.transitions.len();
+        match self.rule {
+            Some(ref rule) if n == 0 || t >= self.transitions[n - 1].time => {
+                return rule.local_time_type(t)
+            }
+            _ => {}
+        }
+        // Times before the first transition use the first type.
+        if n == 0 || t < self.transitions[0].time {
+            return &self.types[0];
+        }
+        // Find the last transition at or before `t`.
+        let (mut lo, mut hi) = (0, n);
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if self.transitions[mid].time <= t { lo = mid } else { hi = mid }
+        }
+        &self.types[self.transitions[lo].idx]
+    }
+
+    /// Returns the specified time in this zone, with `tm_gmtoff` and
+    /// `tm_isdst` set.
+    pub fn at(&self, clock: Timespec) -> Tm {
+        let ty = self.local_time_type(clock);
+        let mut tm = at_utc(Timespec::new(clock.sec + ty.gmtoff as i64, clock.nsec));
+        tm.tm_gmtoff = ty.gmtoff;
+        tm.tm_isdst = if ty.isdst { 1 } else { 0 };
+        tm
+    }
+
+    /// Returns the current time in this zone.
+    pub fn now(&self) -> Tm {
+        self.at(super::get_time())
+    }
+
+    /// Converts the local time in this zone given by the date and time of day
+    /// of `tm` to the times that it happens at. Its `tm_gmtoff`, `tm_isdst`,
+    /// `tm_wday` and `tm_yday` are ignored.
+    pub fn from_local(&self, tm: &Tm) -> LocalResult {
+        let days = days_from_civil(tm.tm_year as i64 + 1900, tm.tm_mon as i64 + 1,
+                                   tm.tm_mday as i64);
+        let local = days * SECS_PER_DAY + tm.tm_hour as i64 * 3600 + tm.tm_min as i64 * 60 +
+                    tm.tm_sec as i64;
+
+        // The local time happens at `local - gmtoff` for each offset in
+        // effect at that time.
+        let mut times: Vec<i64> = self.offsets.iter().filter_map(|&gmtoff| {
+            let t = local - gmtoff as i64;
+            if self.local_time_type(Timespec::new(t, 0)).gmtoff == gmtoff {
+                Some(t)
+            } else {
+                None
+            }
+        }).collect();
+        times.sort();
+        times.dedup();
+
+        let at = |t: i64| self.at(Timespec::new(t, tm.tm_nsec));
+        match times.len() {
+            0 => LocalResult::Nonexistent,
+            1 => LocalResult::Single(at(times[0])),
+            n => LocalResult::Ambiguous(at(times[0]), at(times[n - 1])),
+        }
+    }
+}
+
+impl PosixTz {
+    fn local_time_type(&self, t: i64) -> &LocalTimeType {
+        let (dst, start, end) = match self.dst {
+            Some((ref dst, start, end)) => (dst, start, end),
+            None => return &self.std,
+        };
+        // The rules of the year of `t` in standard time apply, since
+        // transitions don't cross the end of the year in standard time.
+        let (year, _, _) = civil_from_days(div_floor(t + self.std.gmtoff as i64, SECS_PER_DAY));
+        let start = start.utc(year, self.std.gmtoff);
+        let end = end.utc(year, dst.gmtoff);
+        let isdst = if start <= end {
+            start <= t && t < end
+        } else {
+            // Daylight saving time in the southern hemisphere spans the end
+            // of the year.
+            t < end || start <= t
+        };
+        if isdst { dst } else { &self.std }
+    }
+}
+
+impl Rule {
+    // The time of the transition in the given year, when the offset in effect
+    // before it is `gmtoff`.
+    fn utc(&self, year: i64, gmtoff: i32) -> i64 {
+        let leap = is_leap_year(year);
+        let jan1 = days_from_civil(year, 1, 1);
+        let yday = match self.day {
+            RuleDay::Julian1(n) => if leap && n >= 60 { n } else { n - 1 },
+            RuleDay::Julian0(n) => n,
+            RuleDay::MonthWeekDay(m, w, d) => {
+                let first = days_from_civil(year, m, 1);
+                let len = days_from_civil(if m == 12 { year + 1 } else { year },
+                                          if m == 12 { 1 } else { m + 1 }, 1) - first;
+                // January 1, 1970 was a Thursday.
+                let wday = mod_floor(first + 4, 7);
+                let mut mday = mod_floor(d - wday, 7) + (w - 1) * 7;
+                while mday >= len {
+                    mday -= 7;
+                }
+                first + mday - jan1
+            }
+        };
+        (jan1 + yday) * SECS_PER_DAY + self.time - gmtoff as i64
+    }
+}
+
+fn invalid(detail: &str) -> IoError {
+    IoError {
+        kind: io::InvalidInput,
+        desc: "invalid zoneinfo data",
+        detail: Some(detail.to_string()),
+    }
+}
+
+struct Header {
+    version: u8,
+    isutcnt: uint,
+    isstdcnt: uint,
+    leapcnt: uint,
+    timecnt: uint,
+    typecnt: uint,
+    charcnt: uint,
+}
+
+impl Header {
+    // The length of the data following the header, for times of `time_len`
+    // bytes, or `None` if it overflows.
+    fn data_len(&self, time_len: uint) -> Option<uint> {
+        let sizes = [(self.timecnt, time_len + 1), (self.typecnt, 6), (self.charcnt, 1),
+                     (self.leapcnt, time_len + 4), (self.isstdcnt, 1), (self.isutcnt, 1)];
+        let mut len = 0u;
+        for &(count, size) in sizes.iter() {
+            len = try_opt!(count.checked_mul(size).and_then(|n| len.checked_add(n)));
+        }
+        Some(len)
+    }
+}
+
+// Reads the header of the data for times of `time_len` bytes, from a file of
+// `file_len` bytes. The counts are checked against what is left of the file
+// before they size any allocation.
+fn read_header(r: &mut BufReader, file_len: uint, time_len: uint) -> IoResult<Header> {
+    if try!(r.read_exact(4)).as_slice() != b"TZif" {
+        return Err(invalid("not a zoneinfo file"));
+    }
+    let version = try!(r.read_u8());
+    try!(r.read_exact(15));
+    let mut counts = [0u, ..6];
+    for count in counts.iter_mut() {
+        *count = try!(r.read_be_u32()) as uint;
+    }
+    let header = Header {
+        version: version,
+        isutcnt: counts[0],
+        isstdcnt: counts[1],
+        leapcnt: counts[2],
+        timecnt: counts[3],
+        typecnt: counts[4],
+        charcnt: counts[5],
+    };
+    let remaining = file_len - try!(r.tell()) as uint;
+    match header.data_len(time_len) {
+        Some(len) if len <= remaining => Ok(header),
+        _ => Err(invalid("counts exceed the length of the data")),
+    }
+}
+
+// Parses a POSIX `TZ` string, with the extensions of version 3 of the TZif
+// format: hours of rule times from -167 to 167.
+fn parse_posix_tz(s: &[u8]) -> Option<PosixTz> {
+    let mut pos = 0;
+    let std_abbrev = try_opt!(parse_abbrev(s, &mut pos));
+    let std_gmtoff = -try_opt!(parse_offset(s, &mut pos, 24));
+    let std = LocalTimeType { gmtoff: std_gmtoff as i32, isdst: false, abbrev: std_abbrev };
+    if pos == s.len() {
+        return Some(PosixTz { std: std, dst: None });
+    }
+
+    let dst_abbrev = try_opt!(parse_abbrev(s, &mut pos));
+    let dst_gmtoff = if pos < s.len() && s[pos] != b',' {
+        -try_opt!(parse_offset(s, &mut pos, 24))
+    } else {
+        std_gmtoff + 3600
+    };
+    let dst = LocalTimeType { gmtoff: dst_gmtoff as i32, isdst: true, abbrev: dst_abbrev };
+    let (start, end) = if pos == s.len() {
+        // The rules of the United States, as in most implementations.
+        (Rule { day: RuleDay::MonthWeekDay(3, 2, 0), time: 7200 },
+         Rule { day: RuleDay::MonthWeekDay(11, 1, 0), time: 7200 })
+    } else {
+        let start = try_opt!(parse_rule(s, &mut pos));
+        let end = try_opt!(parse_rule(s, &mut pos));
+        (start, end)
+    };
+    if pos != s.len() {
+        return None;
+    }
+    Some(PosixTz { std: std, dst: Some((dst, start, end)) })
+}
+
+// An abbreviation of at least three letters, or of at least three
+// alphanumeric characters, `+` and `-` between `<` and `>`.
+fn parse_abbrev(s: &[u8], pos: &mut uint) -> Option<String> {
+    let (start, end) = if *pos < s.len() && s[*pos] == b'<' {
+        let start = *pos + 1;
+        let len = try_opt!(s.slice_from(start).iter().position(|&b| b == b'>'));
+        if !s.slice(start, start + len).iter().all(|&b| {
+            (b as char).is_alphanumeric() || b == b'+' || b == b'-'
+        }) {
+            return None;
+        }
+        *pos = start + len + 1;
+        (start, start + len)
+    } else {
+        let start = *pos;
+        while *pos < s.len() && (s[*pos] as char).is_alphabetic() {
+            *pos += 1;
+        }
+        (start, *pos)
+    };
+    if end - start < 3 {
+        return None;
+    }
+    String::from_utf8(s.slice(start, end).to_vec()).ok()
+}
+
+// `[+-]hh[:mm[:ss]]`, in seconds, with hours at most `max_hours`.
+fn parse_offset(s: &[u8], pos: &mut uint, max_hours: i64) -> Option<i64> {
+    let sign = match s.get(*pos) {
+        Some(&b'-') => { *pos += 1; -1 }
+        Some(&b'+') => { *pos += 1; 1 }
+        _ => 1,
+    };
+    let hours = try_opt!(parse_num(s, pos, 0, max_hours));
+    let mut secs = hours * 3600;
+    if s.get(*pos) == Some(&b':') {
+        *pos += 1;
+        secs += try_opt!(parse_num(s, pos, 0, 59)) * 60;
+        if s.get(*pos) == Some(&b':') {
+            *pos += 1;
+            secs += try_opt!(parse_num(s, pos, 0, 59));
+        }
+    }
+    Some(sign * secs)
+}
+
+// `,date[/time]`.
+fn parse_rule(s: &[u8], pos: &mut uint) -> Option<Rule> {
+    if s.get(*pos) != Some(&b',') {
+        return None;
+    }
+    *pos += 1;
+    let day = match s.get(*pos) {
+        Some(&b'J') => {
+            *pos += 1;
+            RuleDay::Julian1(try_opt!(parse_num(s, pos, 1, 365)))
+        }
+        Some(&b'M') => {
+            *pos += 1;
+            let m = try_opt!(parse_num(s, pos, 1, 12));
+            if s.get(*pos) != Some(&b'.') { return None }
+            *pos += 1;
+            let w = try_opt!(parse_num(s, pos, 1, 5));
+            if s.get(*pos) != Some(&b'.') { return None }
+            *pos += 1;
+            let d = try_opt!(parse_num(s, pos, 0, 6));
+            RuleDay::MonthWeekDay(m, w, d)
+        }
+        _ => RuleDay::Julian0(try_opt!(parse_num(s, pos, 0, 365))),
+    };
+    let time = if s.get(*pos) == Some(&b'/') {
+        *pos += 1;
+        try_opt!(parse_offset(s, pos, 167))
+    } else {
+        7200
+    };
+    Some(Rule { day: day, time: time })
+}
+
+fn parse_num(s: &[u8], pos: &mut uint, min: i64, max: i64) -> Option<i64> {
+    let start = *pos;
+    let mut n = 0i64;
+    while *pos < s.len() && s[*pos] >= b'0' && s[*pos] <= b'9' && *pos - start < 3 {
+        n = n * 10 + (s[*pos] - b'0') as i64;
+        *pos += 1;
+    }
+    if *pos == start || n < min || n > max { None } else { Some(n) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TimeZone, LocalTimeType, LocalResult};
+    use super::super::{Timespec, strptime};
+
+    use std::io::{mod, MemWriter};
+
+    static EST: i32 = -18000;
+    static EDT: i32 = -14400;
+
+    // A version 2 zoneinfo file with a transition to EDT and back in 2014,
+    // and the rules of America/New_York after that.
+    fn new_york_tzif(version: u8, footer: &str) -> Vec<u8> {
+        let transitions = [(1394348400i64, 1u8), (1414908000, 0)];
+        let types = [(EST, 0u8, 0u8), (EDT, 1, 4)];
+        let chars = b"EST\0EDT\0";
+
+        let mut w = MemWriter::new();
+        let write_data = |w: &mut MemWriter, wide: bool| {
+            w.write(b"TZif").unwrap();
+            w.write_u8(version).unwrap();
+            w.write(&[0u8, ..15]).unwrap();
+            for &count in [0u32, 0, 0, transitions.len() as u32, types.len() as u32,
+                           chars.len() as u32].iter() {
+                w.write_be_u32(count).unwrap();
+            }
+            for &(t, _) in transitions.iter() {
+                if wide { w.write_be_i64(t).unwrap() } else { w.write_be_i32(t as i32).unwrap() }
+            }
+            for &(_, idx) in transitions.iter() {
+                w.write_u8(idx).unwrap();
+            }
+            for &(gmtoff, isdst, abbrind) in types.iter() {
+                w.write_be_i32(gmtoff).unwrap();
+                w.write_u8(isdst).unwrap();
+                w.write_u8(abbrind).unwrap();
+            }
+            w.write(chars).unwrap();
+        };
+        write_data(&mut w, false);
+        if version >= b'2' {
+            write_data(&mut w, true);
+            w.write_str(format!("\n{}\n", footer).as_slice()).unwrap();
+        }
+        w.into_inner()
+    }
+
+    fn new_york() -> TimeZone {
+        TimeZone::parse("America/New_York",
+                        new_york_tzif(b'2', "EST5EDT,M3.2.0,M11.1.0").as_slice()).unwrap()
+    }
+
+    fn local(s: &str) -> super::super::Tm {
+        strptime(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn test_transitions() {
+        let tz = new_york();
+        assert_eq!(tz.name(), "America/New_York");
+
+        let tm = tz.at(Timespec::new(1394348399, 0));
+        assert_eq!((tm.tm_hour, tm.tm_min, tm.tm_sec), (1, 59, 59));
+        assert_eq!((tm.tm_gmtoff, tm.tm_isdst), (EST, 0));
+
+        let tm = tz.at(Timespec::new(1394348400, 0));
+        assert_eq!((tm.tm_hour, tm.tm_min, tm.tm_sec), (3, 0, 0));
+        assert_eq!((tm.tm_gmtoff, tm.tm_isdst), (EDT, 1));
+        assert_eq!(tz.local_time_type(Timespec::new(1394348400, 0)).abbrev, "EDT");
+
+        let tm = tz.at(Timespec::new(1414908000, 0));
+        assert_eq!((tm.tm_hour, tm.tm_gmtoff), (1, EST));
+
+        // Times before the first transition use the first type.
+        assert_eq!(tz.at(Timespec::new(0, 0)).tm_gmtoff, EST);
+    }
+
+    #[test]
+    fn test_footer() {
+        let tz = new_york();
+        // DST starts on March 8, 2026 and ends on November 1, 2026.
+        assert_eq!(tz.at(Timespec::new(1772953199, 0)).tm_gmtoff, EST);
+        assert_eq!(tz.at(Timespec::new(1772953200, 0)).tm_gmtoff, EDT);
+        assert_eq!(tz.at(Timespec::new(1793512799, 0)).tm_gmtoff, EDT);
+        assert_eq!(tz.at(Timespec::new(1793512800, 0)).tm_gmtoff, EST);
+
+        // Without a footer, the last type stays in effect.
+        let tz = TimeZone::parse("EST", new_york_tzif(b'2', "").as_slice()).unwrap();
+        assert_eq!(tz.at(Timespec::new(1772953200, 0)).tm_gmtoff, EST);
+        let tz = TimeZone::parse("EST", new_york_tzif(0, "").as_slice()).unwrap();
+        assert_eq!(tz.at(Timespec::new(1772953200, 0)).tm_gmtoff, EST);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let tz = new_york();
+        for &sec in [0i64, 1394348399, 1394348400, 1404489600, 1414906200, 1414909800,
+                     1793511000, 1793514600].iter() {
+            let time = Timespec::new(sec, 54321);
+            let tm = tz.at(time);
+            assert_eq!(tm.to_timespec(), time);
+            assert_eq!(tm.tm_nsec, 54321);
+        }
+    }
+
+    #[test]
+    fn test_from_local() {
+        let tz = new_york();
+
+        let tm = tz.from_local(&local("2014-07-04 12:00:00")).single().unwrap();
+        assert_eq!(tm.to_timespec(), Timespec::new(1404489600, 0));
+        assert_eq!(tm.tm_gmtoff, EDT);
+
+        assert_eq!(tz.from_local(&local("2014-03-09 02:30:00")), LocalResult::Nonexistent);
+        assert_eq!(tz.from_local(&local("2026-03-08 02:00:00")), LocalResult::Nonexistent);
+
+        match tz.from_local(&local("2014-11-02 01:30:00")) {
+            LocalResult::Ambiguous(earlier, later) => {
+                assert_eq!(earlier.to_timespec(), Timespec::new(1414906200, 0));
+                assert_eq!((earlier.tm_gmtoff, earlier.tm_isdst), (EDT, 1));
+                assert_eq!(later.to_timespec(), Timespec::new(1414909800, 0));
+                assert_eq!((later.tm_gmtoff, later.tm_isdst), (EST, 0));
+            }
+            r => panic!("{}", r),
+        }
+        let r = tz.from_local(&local("2026-11-01 01:30:00"));
+        assert_eq!(r.clone().earliest().unwrap().to_timespec(), Timespec::new(1793511000, 0));
+        assert_eq!(r.latest().unwrap().to_timespec(), Timespec::new(1793514600, 0));
+    }
+
+    #[test]
+    fn test_posix() {
+        let tz = TimeZone::posix("IST-5:30").unwrap();
+        let tm = tz.at(Timespec::new(0, 0));
+        assert_eq!((tm.tm_hour, tm.tm_min, tm.tm_gmtoff), (5, 30, 19800));
+        assert_eq!(*tz.local_time_type(Timespec::new(0, 0)),
+                   LocalTimeType { gmtoff: 19800, isdst: false, abbrev: "IST".to_string() });
+
+        let tz = TimeZone::posix("<+0330>-3:30").unwrap();
+        assert_eq!(tz.local_time_type(Timespec::new(0, 0)).abbrev, "+0330");
+
+        // DST in the southern hemisphere spans the end of the year.
+        let tz = TimeZone::posix("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(tz.at(Timespec::new(1768435200, 0)).tm_gmtoff, 39600);
+        assert_eq!(tz.at(Timespec::new(1775318399, 0)).tm_gmtoff, 39600);
+        assert_eq!(tz.at(Timespec::new(1775318400, 0)).tm_gmtoff, 36000);
+        assert_eq!(tz.at(Timespec::new(1784073600, 0)).tm_gmtoff, 36000);
+        assert_eq!(tz.at(Timespec::new(1791043199, 0)).tm_gmtoff, 36000);
+        assert_eq!(tz.at(Timespec::new(1791043200, 0)).tm_gmtoff, 39600);
+
+        // Julian days, and a DST offset and times of day given explicitly.
+        let tz = TimeZone::posix("EST5EDT4,J67/2:00:00,304/2").unwrap();
+        assert_eq!(tz.at(Timespec::new(1772953199, 0)).tm_gmtoff, EST);
+        assert_eq!(tz.at(Timespec::new(1772953200, 0)).tm_gmtoff, EDT);
+
+        for s in ["", "E5", "EST", "EST5EDT,M3.2.0", "EST5EDT,M13.2.0,M11.1.0",
+                  "<+05>-5x", "EST25"].iter() {
+            assert!(TimeZone::posix(*s).is_none(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        let data = new_york_tzif(b'2', "EST5EDT,M3.2.0,M11.1.0");
+        for data in [b"TZjf".to_vec(),
+                     data.slice_to(60).to_vec(),
+                     data.slice_to(data.len() - 1).to_vec(),
+                     new_york_tzif(b'2', "EST5EDT,M3.2.0,")].iter() {
+            assert!(TimeZone::parse("America/New_York", data.as_slice()).is_err());
+        }
+
+        // Counts larger than the file are rejected before anything is
+        // allocated, including counts whose total overflows.
+        let mut huge = data.clone();
+        for b in huge.slice_mut(32, 36).iter_mut() {
+            *b = 0xff;
+        }
+        let e = TimeZone::parse("America/New_York", huge.as_slice()).unwrap_err();
+        assert_eq!(e.detail, Some("counts exceed the length of the data".to_string()));
+        for b in huge.slice_mut(20, 44).iter_mut() {
+            *b = 0xff;
+        }
+        assert!(TimeZone::parse("America/New_York", huge.as_slice()).is_err());
+
+        assert_eq!(TimeZone::load("../../etc/passwd").unwrap_err().kind, io::InvalidInput);
+    }
+
+    #[test]
+    fn test_utc() {
+        let tz = TimeZone::utc();
+        let tm = tz.at(Timespec::new(1234567890, 0));
+        assert_eq!((tm.tm_hour, tm.tm_gmtoff, tm.tm_isdst), (23, 0, 0));
+        assert_eq!(tz.local_time_type(Timespec::new(0, 0)).abbrev, "UTC");
+    }
+}
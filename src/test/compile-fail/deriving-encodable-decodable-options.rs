@@ -0,0 +1,36 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+extern crate serialize;
+
+#[deriving(Encodable)]
+#[serialize(external)] //~ ERROR unsupported `serialize` option `external` here
+struct A {
+    #[serialize(rename = 1)] //~ ERROR expected a string, like `rename = "..."`
+    a: int,
+    #[serialize(flatten)] //~ ERROR unsupported `serialize` option `flatten` here
+    b: int,
+}
+
+#[deriving(Encodable)]
+#[serialize(tag = "type")]
+enum B {
+    C(int), //~ ERROR only unit and struct variants can be in an enum with a `tag` option
+    D { x: int },
+}
+
+// Both derives read the options, but only one reports their errors.
+#[deriving(Encodable, Decodable)]
+struct E {
+    #[serialize(skip, flatten)] //~ ERROR unsupported `serialize` option `flatten` here
+    a: int,
+}
+
+fn main() {}
@@ -0,0 +1,29 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// compile-flags: --test
+
+#[setup]
+fn setup() {}
+
+#[setup]
+fn setup_again() {} //~ ERROR a module can only have one `#[setup]` function
+
+#[teardown]
+fn teardown() -> uint { 0 } //~ ERROR functions used as setup or teardown must have signature
+
+#[fixture]
+fn nothing() {} //~ ERROR functions used as fixtures must return a value
+
+#[fixture]
+fn number(n: uint) -> uint { n } //~ ERROR can't take arguments or type parameters
+
+#[test]
+fn test_unknown(missing: &uint) {} //~ ERROR there is no `#[fixture]` function named `missing`
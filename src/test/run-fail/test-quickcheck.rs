@@ -0,0 +1,22 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// check-stdout
+// error-pattern:property falsified after
+// error-pattern:with arguments: ([5], 10)
+// error-pattern:replay with RUST_QUICKCHECK_SEED=1234
+// compile-flags: --test
+// exec-env:RUST_QUICKCHECK_SEED=1234
+// ignore-pretty: does not work well with `--test`
+
+#[quickcheck]
+fn test_foo(v: Vec<u8>, n: uint) -> bool {
+    n < 10 || !v.iter().any(|&x| x >= 5)
+}
@@ -0,0 +1,99 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Test the `#[serialize(...)]` options of `#[deriving(Encodable, Decodable)]`.
+
+extern crate serialize;
+
+use serialize::{Encodable, Decodable};
+use serialize::json;
+
+#[deriving(Encodable, Decodable, PartialEq, Show)]
+struct Config {
+    #[serialize(rename = "type")]
+    kind: String,
+    #[serialize(default)]
+    retries: uint,
+    #[serialize(skip)]
+    cache: Vec<u8>,
+}
+
+#[deriving(Encodable, Decodable, PartialEq, Show)]
+#[serialize(deny_unknown_fields)]
+struct Strict {
+    a: int,
+    #[serialize(default)]
+    b: Option<int>,
+}
+
+#[deriving(Encodable, Decodable, PartialEq, Show)]
+#[serialize(external)]
+enum External {
+    Unit,
+    #[serialize(rename = "pair")]
+    Pair(int, String),
+    Named { x: int, #[serialize(skip)] y: int },
+}
+
+#[deriving(Encodable, Decodable, PartialEq, Show)]
+#[serialize(tag = "kind")]
+enum Internal {
+    Empty,
+    Point { x: int, #[serialize(rename = "Y")] y: int },
+}
+
+fn tree_decode<T: Decodable<json::Decoder, json::DecoderError>>(s: &str)
+                                                               -> json::DecodeResult<T> {
+    let mut decoder = json::Decoder::new(json::from_str(s).unwrap());
+    Decodable::decode(&mut decoder)
+}
+
+fn check<T>(value: T, s: &str)
+    where T: Encodable<json::Encoder<'static>, std::io::IoError> + PartialEq + std::fmt::Show,
+          T: Decodable<json::Decoder, json::DecoderError>,
+          T: for<'a> Decodable<json::StreamDecoder<std::str::Chars<'a>>, json::DecoderError>
+{
+    assert_eq!(json::encode(&value), s.to_string());
+    assert_eq!(json::decode_stream::<T>(s).unwrap(), value);
+    assert_eq!(tree_decode::<T>(s).unwrap(), value);
+}
+
+fn main() {
+    let config = Config { kind: "x".to_string(), retries: 3, cache: vec![1, 2] };
+    assert_eq!(json::encode(&config), "{\"type\":\"x\",\"retries\":3}".to_string());
+    let decoded: Config = json::decode_stream("{\"type\":\"x\"}").unwrap();
+    assert_eq!(decoded, Config { kind: "x".to_string(), retries: 0, cache: vec![] });
+    let decoded: Config = tree_decode("{\"type\":\"x\",\"retries\":null,\"cache\":[1]}").unwrap();
+    assert_eq!(decoded, Config { kind: "x".to_string(), retries: 0, cache: vec![] });
+
+    check(Strict { a: 1, b: None }, "{\"a\":1,\"b\":null}");
+    let decoded: Strict = json::decode_stream("{\"a\":1}").unwrap();
+    assert_eq!(decoded, Strict { a: 1, b: None });
+    let err = json::decode_stream::<Strict>("{\"c\":0,\"a\":1}").unwrap_err();
+    assert_eq!(err, json::DecoderError::UnknownFieldError("c".to_string()));
+    let err = json::decode_stream::<Strict>("{\"a\":1,\"b\":2,\"c\":0}").unwrap_err();
+    assert_eq!(err, json::DecoderError::UnknownFieldError("c".to_string()));
+    assert_eq!(tree_decode::<Strict>("{\"a\":1,\"c\":0}"),
+               Err(json::DecoderError::UnknownFieldError("c".to_string())));
+
+    check(External::Unit, "{\"Unit\":[]}");
+    check(External::Pair(1, "a".to_string()), "{\"pair\":[1,\"a\"]}");
+    check(External::Named { x: 1, y: 0 }, "{\"Named\":{\"x\":1}}");
+    assert!(json::decode_stream::<External>("{\"Other\":[]}").is_err());
+    assert!(json::decode_stream::<External>("{\"Unit\":[],\"pair\":[1,\"a\"]}").is_err());
+    assert!(tree_decode::<External>("{}").is_err());
+
+    check(Internal::Empty, "{\"kind\":\"Empty\"}");
+    check(Internal::Point { x: 1, y: 2 }, "{\"kind\":\"Point\",\"x\":1,\"Y\":2}");
+    let decoded: Internal = json::decode_stream("{\"Y\":2,\"x\":1,\"kind\":\"Point\"}").unwrap();
+    assert_eq!(decoded, Internal::Point { x: 1, y: 2 });
+    assert_eq!(tree_decode::<Internal>("{\"kind\":\"Line\"}"),
+               Err(json::DecoderError::ApplicationError("unknown variant `Line`".to_string())));
+}
@@ -0,0 +1,73 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// ignore-android (FIXME #11419)
+// exec-env:RUST_LOG=warn
+
+#![feature(phase)]
+
+#[phase(plugin, link)]
+extern crate log;
+extern crate regex;
+
+use log::{Logger, LogRecord, Sink};
+use regex::Regex;
+
+struct ChannelLogger(Sender<String>);
+
+impl Logger for ChannelLogger {
+    fn log(&mut self, record: &LogRecord) {
+        let ChannelLogger(ref tx) = *self;
+        tx.send(format!("{}:{}", record.level, record.args));
+    }
+}
+
+fn main() {
+    let (all_tx, all_rx) = channel();
+    let (errors_tx, errors_rx) = channel();
+    log::set_sinks(vec![
+        Sink::new(box ChannelLogger(all_tx)),
+        Sink::new(box ChannelLogger(errors_tx)).level(log::ERROR),
+    ]);
+
+    warn!("one");
+    info!("two");
+    error!("three");
+
+    // Enable info for this crate and keep only messages starting with 'f'.
+    log::set_directives("logging-sinks=info/^f");
+    info!("four");
+    info!("not five");
+    error!("five");
+
+    // Another task without a logger of its own also logs to the sinks.
+    let (done_tx, done_rx) = channel();
+    spawn(move|| {
+        warn!("fancy six");
+        done_tx.send(());
+    });
+    done_rx.recv();
+
+    let (tx, rx) = channel();
+    log::add_sink(Sink::new(box ChannelLogger(tx)).filter(Regex::new("six").unwrap()));
+    warn!("from seven to six");
+
+    // Dropping the sinks closes the channels.
+    log::set_sinks(Vec::new());
+
+    let all = all_rx.iter().collect::<Vec<String>>();
+    assert_eq!(all, vec!["WARN:one".to_string(), "ERROR:three".to_string(),
+                         "INFO:four".to_string(), "ERROR:five".to_string(),
+                         "WARN:fancy six".to_string(), "WARN:from seven to six".to_string()]);
+    assert_eq!(errors_rx.iter().collect::<Vec<String>>(),
+               vec!["ERROR:three".to_string(), "ERROR:five".to_string()]);
+    assert_eq!(rx.iter().collect::<Vec<String>>(),
+               vec!["WARN:from seven to six".to_string()]);
+}
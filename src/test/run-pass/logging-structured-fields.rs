@@ -0,0 +1,44 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// ignore-android (FIXME #11419)
+// exec-env:RUST_LOG=info
+
+#![feature(phase)]
+
+#[phase(plugin, link)]
+extern crate log;
+
+use log::{JsonLogger, Logger, TextLogger};
+use std::io::{ChanReader, ChanWriter};
+
+fn main() {
+    let (tx, rx) = channel();
+    let (mut r, w) = (ChanReader::new(rx), ChanWriter::new(tx));
+    spawn(move|| {
+        log::set_logger(box TextLogger::new(w) as Box<Logger+Send>);
+        let _request = log::push_context("request", "r1");
+        debug!({ skipped = true }, "debug");
+        info!({ status = 200u, path = "/a b" }, "served {}", 3u);
+    });
+    let s = r.read_to_string().unwrap();
+    assert!(s.as_slice().ends_with(": served 3 request=r1 status=200 path=\"/a b\"\n"));
+    assert!(!s.as_slice().contains("debug"));
+
+    let (tx, rx) = channel();
+    let (mut r, w) = (ChanReader::new(rx), ChanWriter::new(tx));
+    spawn(move|| {
+        log::set_logger(box JsonLogger::new(w) as Box<Logger+Send>);
+        warn!({ attempt = 2i }, "retrying");
+    });
+    let s = r.read_to_string().unwrap();
+    assert!(s.as_slice().starts_with("{\"time\":\""));
+    assert!(s.as_slice().ends_with("\"message\":\"retrying\",\"attempt\":\"2\"}\n"));
+}
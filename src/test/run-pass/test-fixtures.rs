@@ -0,0 +1,71 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// ignore-pretty: does not work well with `--test`
+// compile-flags:--test
+// exec-env:RUST_TEST_TASKS=1
+
+// Tests run in alphabetical order with a single task, so each test can check
+// what the previous ones did.
+
+use std::sync::atomic::{AtomicUint, INIT_ATOMIC_UINT, SeqCst};
+
+static SETUPS: AtomicUint = INIT_ATOMIC_UINT;
+static TEARDOWNS: AtomicUint = INIT_ATOMIC_UINT;
+static COUNTERS: AtomicUint = INIT_ATOMIC_UINT;
+
+struct Counter {
+    id: uint,
+}
+
+#[setup]
+fn setup() {
+    SETUPS.fetch_add(1, SeqCst);
+}
+
+#[teardown]
+fn teardown() {
+    TEARDOWNS.fetch_add(1, SeqCst);
+}
+
+#[fixture]
+fn counter() -> Counter {
+    Counter { id: COUNTERS.fetch_add(1, SeqCst) }
+}
+
+#[test]
+fn a_setup_runs_before_the_test() {
+    assert_eq!(SETUPS.load(SeqCst), 1);
+    assert_eq!(TEARDOWNS.load(SeqCst), 0);
+}
+
+#[test]
+#[should_fail]
+fn b_teardown_runs_when_a_test_panics(counter: &Counter) {
+    assert_eq!(counter.id, 0);
+    panic!();
+}
+
+#[test]
+fn c_fixtures_are_created_once(counter: &Counter) {
+    assert_eq!(TEARDOWNS.load(SeqCst), 2);
+    assert_eq!(counter.id, 0);
+    assert_eq!(COUNTERS.load(SeqCst), 1);
+}
+
+mod submodule {
+    use super::SETUPS;
+    use std::sync::atomic::SeqCst;
+
+    #[test]
+    fn setup_is_not_run_for_submodules() {
+        assert_eq!(SETUPS.load(SeqCst), 3);
+    }
+}
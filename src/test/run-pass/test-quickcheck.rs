@@ -0,0 +1,50 @@
+// Copyright 2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// ignore-pretty: does not work well with `--test`
+// compile-flags:--test
+
+use std::cmp;
+use std::collections::{BTreeMap, HashSet};
+
+#[quickcheck]
+fn reversing_twice_is_the_identity(v: Vec<int>) -> bool {
+    let mut w = v.clone();
+    w.reverse();
+    w.reverse();
+    v == w
+}
+
+#[quickcheck]
+fn max_is_an_upper_bound(a: int, b: int) -> bool {
+    let m = cmp::max(a, b);
+    m >= a && m >= b && (m == a || m == b)
+}
+
+#[quickcheck]
+fn sets_have_no_duplicates(s: HashSet<u8>) {
+    let mut v: Vec<u8> = s.into_iter().collect();
+    let len = v.len();
+    v.sort();
+    v.dedup();
+    assert_eq!(v.len(), len);
+}
+
+#[quickcheck]
+fn maps_are_sorted(m: BTreeMap<String, Option<char>>) -> bool {
+    let keys: Vec<&String> = m.keys().collect();
+    keys.windows(2).all(|w| w[0] < w[1])
+}
+
+#[quickcheck]
+#[should_fail]
+fn failing_properties_fail(x: uint) -> bool {
+    x < 10
+}